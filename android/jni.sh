@@ -9,6 +9,9 @@ USAGE: $(basename "$0") [-d|-r] [-v]
 Builds the necessary Rust JNI dependencies for Android.
 
 OPTIONS:
+  -d, --debug             debug build, which also enables test-only entry
+                          points such as clientCreateWithSeed
+  -r, --release           release build (default)
   -v, --verbose           verbose build
 
   -h, --help              show this help information
@@ -16,9 +19,16 @@ END
 }
 
 VERBOSE=
+PROFILE=release
 
 while [ "${1:-}" != "" ]; do
   case $1 in
+    -d | --debug )
+      PROFILE=debug
+      ;;
+    -r | --release )
+      PROFILE=release
+      ;;
     -v | --verbose )
       VERBOSE=1
       ;;
@@ -82,12 +92,17 @@ for target in "${TARGETS[@]}"; do
         exit 1
     fi
 
-    echo cargo build -p juicebox_sdk_jni --release ${VERBOSE:+--verbose} --target ${CARGO_BUILD_TARGET}
-    cargo build -p juicebox_sdk_jni --release ${VERBOSE:+--verbose} --target ${CARGO_BUILD_TARGET}
+    RELEASE_FLAG=
+    if [ "${PROFILE}" == "release" ]; then
+      RELEASE_FLAG=--release
+    fi
+
+    echo cargo build -p juicebox_sdk_jni ${RELEASE_FLAG} ${VERBOSE:+--verbose} --target ${CARGO_BUILD_TARGET}
+    cargo build -p juicebox_sdk_jni ${RELEASE_FLAG} ${VERBOSE:+--verbose} --target ${CARGO_BUILD_TARGET}
 
     echo mkdir -p "${ANDROID_LIB_DIR}/${ANDROID_BUILD_TARGET}"
     mkdir -p "${ANDROID_LIB_DIR}/${ANDROID_BUILD_TARGET}"
 
-    echo mv "${CARGO_TARGET_DIR:-target}/${CARGO_BUILD_TARGET}/release/libjuicebox_sdk_jni.so" "${ANDROID_LIB_DIR}/${ANDROID_BUILD_TARGET}"
-    mv "${CARGO_TARGET_DIR:-target}/${CARGO_BUILD_TARGET}/release/libjuicebox_sdk_jni.so" "${ANDROID_LIB_DIR}/${ANDROID_BUILD_TARGET}"
+    echo mv "${CARGO_TARGET_DIR:-target}/${CARGO_BUILD_TARGET}/${PROFILE}/libjuicebox_sdk_jni.so" "${ANDROID_LIB_DIR}/${ANDROID_BUILD_TARGET}"
+    mv "${CARGO_TARGET_DIR:-target}/${CARGO_BUILD_TARGET}/${PROFILE}/libjuicebox_sdk_jni.so" "${ANDROID_LIB_DIR}/${ANDROID_BUILD_TARGET}"
 done
@@ -8,6 +8,7 @@ import xyz.juicebox.sdk.PinHashingMode;
 import xyz.juicebox.sdk.Realm;
 import xyz.juicebox.sdk.RealmId;
 import xyz.juicebox.sdk.RecoverException;
+import xyz.juicebox.sdk.RecoverResult;
 import xyz.juicebox.sdk.RegisterException;
 
 public final class Native {
@@ -20,12 +21,33 @@ public final class Native {
             long configuration,
             @NotNull long[] previousConfigurations,
             @NotNull GetAuthTokenFn getAuthToken,
-            @NotNull HttpSendFn httpSend);
+            @NotNull HttpSendFn httpSend,
+            long runtime,
+            int authTokenRetries);
+
+    /**
+     * Only exported by debug builds of the native library. Release builds
+     * throw {@link UnsatisfiedLinkError}.
+     */
+    public static native long clientCreateWithSeed(
+            long configuration,
+            @NotNull long[] previousConfigurations,
+            @NotNull GetAuthTokenFn getAuthToken,
+            @NotNull HttpSendFn httpSend,
+            long runtime,
+            int authTokenRetries,
+            @NotNull byte[] seed);
 
     public static native void clientDestroy(long client);
 
+    public static native long runtimeCreate();
+
+    public static native void runtimeDestroy(long runtime);
+
     public static native String sdkVersion();
 
+    public static native void enableLogging(int priority);
+
     public static native long configurationCreate(
             @NotNull Realm[] realms,
             int registerThreshold,
@@ -50,9 +72,16 @@ public final class Native {
             @NotNull byte[] pin,
             @NotNull byte[] info) throws RecoverException;
 
+    public static native @NotNull RecoverResult clientRecoverWithMeta(
+            long client,
+            @NotNull byte[] pin,
+            @NotNull byte[] info) throws RecoverException;
+
     public static native void clientDelete(
             long client) throws DeleteException;
 
+    public static native void clientCancel(long client);
+
     public static native void httpClientRequestComplete(
             long httpClient,
             @NotNull HttpResponse response);
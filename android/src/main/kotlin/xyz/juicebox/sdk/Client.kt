@@ -1,4 +1,5 @@
 package xyz.juicebox.sdk
+import android.util.Log
 import kotlinx.coroutines.Dispatchers
 import kotlinx.coroutines.withContext
 import xyz.juicebox.sdk.internal.Native
@@ -30,13 +31,21 @@ class Client private constructor (
      * realm and should be valid for the lifetime of the [Client]. Alternatively, you
      * may omit this argument and implement [Client.fetchAuthTokenCallback]
      * to fetch and refresh tokens as needed.
+     * @param runtime The native threads to run operations on, which can be shared
+     * with other clients. By default, each client starts its own.
+     * @param authTokenRetries How many more times to ask for an auth token, after a
+     * short delay, when none is available. This covers tokens that are being refreshed
+     * asynchronously. An operation fails with `INVALID_AUTH` if every attempt returns
+     * no token.
      */
     constructor(
         configuration: Configuration,
         previousConfigurations: Array<Configuration> = emptyArray(),
-        authTokens: Map<RealmId, AuthToken>? = null
+        authTokens: Map<RealmId, AuthToken>? = null,
+        runtime: ClientRuntime? = null,
+        authTokenRetries: Int = 1
     ) : this(
-        createNative(configuration, previousConfigurations, authTokens)
+        createNative(configuration, previousConfigurations, authTokens, runtime, authTokenRetries)
     )
 
     /**
@@ -51,9 +60,10 @@ class Client private constructor (
      * reasonable choice, but even the name of the company or service could be viable
      * if nothing else is available.
      * @param numGuesses The number of guesses allowed before the secret can no longer
-     * be accessed.
+     * be accessed. Must not be negative.
      *
      * @throws [RegisterException] if registration could not be completed successfully.
+     * @throws IllegalArgumentException if [numGuesses] is negative.
      */
     @Throws(RegisterException::class)
     suspend fun register(pin: ByteArray, secret: ByteArray, info: ByteArray, numGuesses: Short) {
@@ -85,6 +95,25 @@ class Client private constructor (
         }
     }
 
+    /**
+     * Retrieves a PIN-protected secret like [recover], and also reports how many
+     * guesses remained after this attempt.
+     *
+     * @param pin A user provided PIN.
+     * @param info Additional data added to the salt for the configured [PinHashingMode].
+     * This must match the data provided during [register].
+     *
+     * @return result The recovered secret and the number of guesses remaining.
+     *
+     * @throws [RecoverException] if recovery could not be completed successfully.
+     */
+    @Throws(RecoverException::class)
+    suspend fun recoverWithMeta(pin: ByteArray, info: ByteArray): RecoverResult {
+        return withContext(Dispatchers.IO) {
+            Native.clientRecoverWithMeta(native, pin, info)
+        }
+    }
+
     /**
      * Deletes the registered secret for this user, if any.
      *
@@ -97,6 +126,19 @@ class Client private constructor (
         }
     }
 
+    /**
+     * Cancels any [register], [recover], [recoverWithMeta] or [delete] in progress on
+     * this client.
+     *
+     * Each operation stops before its next request to a realm and throws an exception
+     * with a `CANCELLED` error. Requests already sent are allowed to finish, so a
+     * cancelled [recover] may still have used a guess. Operations started after this
+     * call are not affected.
+     */
+    fun cancel() {
+        Native.clientCancel(native)
+    }
+
     protected fun finalize() {
         Native.clientDestroy(native)
     }
@@ -120,7 +162,38 @@ class Client private constructor (
          */
         var fetchAuthTokenCallback: ((RealmId) -> AuthToken?)? = null
 
-        private fun createNative(configuration: Configuration, previousConfigurations: Array<Configuration>, authTokens: Map<RealmId, AuthToken>?): Long {
+        /**
+         * Sends the SDK's native logs at or above [priority] to logcat, under the
+         * `JuiceboxSdk` tag. Secrets and PINs are redacted from the logs.
+         *
+         * Calling this again changes the priority. Use [Log.ASSERT] to stop logging.
+         *
+         * @param priority The least severe priority to log, such as [Log.DEBUG].
+         */
+        fun enableLogging(priority: Int = Log.INFO) {
+            Native.enableLogging(priority)
+        }
+
+        /**
+         * Creates a client whose registrations draw their randomness from [seed], a
+         * 32-byte value, so that instrumented tests can assert exact shares and
+         * ciphertexts. Never use this outside of tests.
+         *
+         * @throws UnsatisfiedLinkError if the native library is a release build, which
+         * doesn't include this entry point.
+         */
+        internal fun createWithSeed(
+            configuration: Configuration,
+            authTokens: Map<RealmId, AuthToken>?,
+            seed: ByteArray
+        ): Client {
+            require(seed.size == 32) { "seed must be 32 bytes" }
+            return Client(createNative(configuration, emptyArray(), authTokens, null, 1, seed))
+        }
+
+        private fun createNative(configuration: Configuration, previousConfigurations: Array<Configuration>, authTokens: Map<RealmId, AuthToken>?, runtime: ClientRuntime?, authTokenRetries: Int, seed: ByteArray? = null): Long {
+            require(authTokenRetries >= 0) { "authTokenRetries must not be negative" }
+
             val httpSend = Native.HttpSendFn { httpClient, request ->
                 thread {
                     try {
@@ -207,11 +280,25 @@ class Client private constructor (
                 }
             }
 
+            if (seed != null) {
+                return Native.clientCreateWithSeed(
+                    configuration.native,
+                    previousConfigurations.map { it.native }.toLongArray(),
+                    getAuthToken,
+                    httpSend,
+                    runtime?.native ?: 0,
+                    authTokenRetries,
+                    seed
+                )
+            }
+
             return Native.clientCreate(
                 configuration.native,
                 previousConfigurations.map { it.native }.toLongArray(),
                 getAuthToken,
-                httpSend
+                httpSend,
+                runtime?.native ?: 0,
+                authTokenRetries
             )
         }
     }
@@ -0,0 +1,19 @@
+package xyz.juicebox.sdk
+
+import xyz.juicebox.sdk.internal.Native
+
+/**
+ * A pool of native threads that several [Client]s can share, instead of each
+ * client starting its own.
+ *
+ * Each client created with a runtime keeps it running, so the runtime stays
+ * alive until it and every client using it have been garbage collected.
+ * Collecting one client never stops a runtime another client still uses.
+ */
+class ClientRuntime private constructor(val native: Long) {
+    constructor() : this(Native.runtimeCreate())
+
+    protected fun finalize() {
+        Native.runtimeDestroy(native)
+    }
+}
@@ -27,6 +27,9 @@ class Configuration private constructor(val native: Long) {
      * before register and recover operations. Changing modes will make previous
      * secrets stored on the realms inaccessible with the same PIN and should not
      * be done without re-registering secrets.
+     *
+     * @throws IllegalArgumentException if a realm address is malformed or the
+     * realms and thresholds are inconsistent.
      */
     constructor(
         realms: Array<Realm>,
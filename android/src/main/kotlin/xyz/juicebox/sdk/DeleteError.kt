@@ -33,11 +33,17 @@ enum class DeleteError {
      * This request may succeed by trying again with the same parameters.
      */
     TRANSIENT,
+
+    /**
+     * The operation was stopped by [Client.cancel].
+     */
+    CANCELLED,
 }
 
 /**
  * An exception thrown from [Client.delete]
  *
  * @property error The underlying error that triggered this exception.
+ * @param message A description of what failed, for logs and crash reports.
  */
-class DeleteException(val error: DeleteError) : Exception(error.name)
+class DeleteException(val error: DeleteError, message: String? = null) : Exception(message ?: error.name)
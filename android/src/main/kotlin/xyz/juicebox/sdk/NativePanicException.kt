@@ -0,0 +1,8 @@
+package xyz.juicebox.sdk
+
+/**
+ * Thrown when the SDK's native code hits an unexpected condition, instead of
+ * aborting the process. This indicates a bug in the SDK or invalid input that
+ * was not otherwise rejected.
+ */
+class NativePanicException(message: String) : RuntimeException(message)
@@ -8,11 +8,14 @@ package xyz.juicebox.sdk
  * @property publicKey A long-lived public key for which a hardware
  * backed service maintains a matching private key. Software realms
  * do not require public keys.
+ * @property role Which operations the client should perform against
+ * this realm.
  */
 data class Realm(
     val id: RealmId,
     val address: String,
-    val publicKey: ByteArray? = null
+    val publicKey: ByteArray? = null,
+    val role: RealmRole = RealmRole.READ_WRITE
 ) {
     override fun equals(other: Any?): Boolean {
         if (this === other) return true
@@ -23,6 +26,7 @@ data class Realm(
         if (id != other.id) return false
         if (address != other.address) return false
         if (!publicKey.contentEquals(other.publicKey)) return false
+        if (role != other.role) return false
 
         return true
     }
@@ -31,6 +35,7 @@ data class Realm(
         var result = id.hashCode()
         result = 31 * result + address.hashCode()
         result = 31 * result + publicKey.contentHashCode()
+        result = 31 * result + role.hashCode()
         return result
     }
 }
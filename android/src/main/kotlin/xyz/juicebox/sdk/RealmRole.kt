@@ -0,0 +1,17 @@
+package xyz.juicebox.sdk
+
+/**
+ * Describes how a [Realm] participates in client operations.
+ */
+enum class RealmRole {
+    /**
+     * The realm receives new registrations and participates in recovery.
+     */
+    READ_WRITE,
+
+    /**
+     * The realm participates in recovery but does not receive new
+     * registrations, such as while it is being decommissioned.
+     */
+    RECOVER_ONLY,
+}
@@ -46,6 +46,21 @@ enum class RecoverError {
      * This request may succeed by trying again with the same parameters.
      */
     TRANSIENT,
+
+    /**
+     * The operation was stopped by [Client.cancel].
+     */
+    CANCELLED,
+    /**
+     * No guesses remained before this attempt, so the PIN was not checked.
+     * The secret is locked and inaccessible.
+     */
+    LOCKED,
+    /**
+     * The secret was registered with a different set of realms than the
+     * configuration lists. No guess was used.
+     */
+    REALM_SET_MISMATCH,
 }
 
 /**
@@ -54,5 +69,10 @@ enum class RecoverError {
  * @property error The underlying error that triggered this exception.
  * @property guessesRemaining The guesses remaining, if the underlying
  * error is [RecoverError.INVALID_PIN].
+ * @param message A description of what failed, for logs and crash reports.
  */
-class RecoverException(val error: RecoverError, val guessesRemaining: Short?) : Exception(error.name)
+class RecoverException(
+    val error: RecoverError,
+    val guessesRemaining: Short?,
+    message: String? = null
+) : Exception(message ?: error.name)
@@ -0,0 +1,11 @@
+package xyz.juicebox.sdk
+
+/**
+ * The result of a successful [Client.recoverWithMeta].
+ *
+ * @property secret The recovered user provided secret.
+ * @property guessesRemaining The fewest guesses any realm had remaining after
+ * this attempt. Realms reset the count after a successful recovery, so this
+ * reports how close earlier wrong guesses came to locking the secret.
+ */
+class RecoverResult(val secret: ByteArray, val guessesRemaining: Short)
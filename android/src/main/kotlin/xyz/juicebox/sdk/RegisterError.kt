@@ -33,11 +33,17 @@ enum class RegisterError {
      * This request may succeed by trying again with the same parameters.
      */
     TRANSIENT,
+
+    /**
+     * The operation was stopped by [Client.cancel].
+     */
+    CANCELLED,
 }
 
 /**
  * An exception thrown from [Client.register]
  *
  * @property error The underlying error that triggered this exception.
+ * @param message A description of what failed, for logs and crash reports.
  */
-class RegisterException(val error: RegisterError) : Exception(error.name)
+class RegisterException(val error: RegisterError, message: String? = null) : Exception(message ?: error.name)
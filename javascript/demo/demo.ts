@@ -106,8 +106,7 @@ async function main() {
         const secret = await client.recover(encoder.encode("1234"), encoder.encode("artemis"));
         assert.fail("[JavaScript] Recover unexpectedly succeeded with secret: " + decoder.decode(secret));
     } catch (error) {
-        if (error instanceof RecoverError && error.reason === RecoverErrorReason.InvalidPin) {
-            assert.strictEqual(error.guesses_remaining, 0);
+        if (error instanceof RecoverError && error.reason === RecoverErrorReason.Locked) {
             console.log("[JavaScript] Recover expectedly unsuccessful");
         } else {
             assert.fail("[JavaScript] Recover failed with unexpected error: " + error);
@@ -145,8 +145,7 @@ async fn main() {
         )
         .await
     {
-        Err(RecoverError::InvalidPin { guesses_remaining }) => {
-            assert_eq!(guesses_remaining, 0);
+        Err(RecoverError::Locked) => {
             println!("[Rust] Recover expectedly unsuccessful")
         }
         result => panic!("Unexpected result from recover: {result:?}"),
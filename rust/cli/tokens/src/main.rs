@@ -7,7 +7,7 @@ use juicebox_realm_auth::{
     AuthKey, AuthKeyAlgorithm, AuthKeyVersion, Claims, Scope,
 };
 
-use clap::{command, Parser, Subcommand};
+use clap::{Parser, Subcommand};
 use jwt_simple::{
     algorithms::{Ed25519KeyPair, HS256Key, RS256KeyPair},
     JWTError,
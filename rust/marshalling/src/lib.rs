@@ -1,13 +1,16 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
 extern crate alloc;
 
+use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::fmt::Display;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 pub mod bytes;
+mod limit;
+pub mod sorted_list;
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct SerializationError(pub String);
@@ -18,25 +21,325 @@ impl Display for SerializationError {
     }
 }
 
+#[cfg(any(test, feature = "std"))]
+impl std::error::Error for SerializationError {}
+
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
-pub struct DeserializationError(pub String);
+pub struct DeserializationError {
+    message: String,
+    offset: Option<usize>,
+}
+
+impl DeserializationError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            offset: None,
+        }
+    }
+
+    pub fn with_offset(message: impl Into<String>, offset: usize) -> Self {
+        Self {
+            message: message.into(),
+            offset: Some(offset),
+        }
+    }
+
+    /// A description of what went wrong, without the offset.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The byte offset into the input where the error was detected, if
+    /// known.
+    ///
+    /// For syntax errors, this is the position of the malformed bytes. For
+    /// errors converting a parsed value to the target type (for example, a
+    /// field with the wrong type), it's how far [`from_slice`] had read,
+    /// which is just past the header of the offending item. For truncated
+    /// input, it's the length of the input.
+    ///
+    /// The CBOR decoder does not track the path of fields leading to an
+    /// error, so the offset is the best way to locate one.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// Converts a decoder error, returning any I/O error for the caller to
+    /// describe. `consumed` is the number of bytes read before the error, if
+    /// known.
+    fn from_ciborium<E>(error: ciborium::de::Error<E>, consumed: Option<usize>) -> Result<Self, E> {
+        use ciborium::de::Error;
+        Ok(match error {
+            Error::Io(e) => return Err(e),
+            Error::Syntax(offset) => Self::with_offset("invalid CBOR syntax", offset),
+            Error::Semantic(offset, message) => Self {
+                message,
+                offset: offset.or(consumed),
+            },
+            Error::RecursionLimitExceeded => Self::new("recursion limit exceeded"),
+        })
+    }
+}
 
 impl Display for DeserializationError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "Deserialization error: {}", self.0)
+        write!(f, "Deserialization error: {}", self.message)?;
+        if let Some(offset) = self.offset {
+            write!(f, " at byte {offset}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl std::error::Error for DeserializationError {}
+
+/// A binary format for the `Serialize` and `Deserialize` types in this
+/// project.
+///
+/// The wire types don't depend on the format, so callers that talk to
+/// something other than a realm, such as an embedded HSM with its own
+/// encoding, can implement this to encode the same types directly instead of
+/// re-encoding CBOR. [`to_vec`] and [`from_slice`] use [`CborCodec`].
+///
+/// The methods are generic over the value, so `dyn Codec` isn't possible.
+/// To choose a codec at runtime, implement this for an enum of the
+/// supported codecs that dispatches to the selected one.
+pub trait Codec {
+    fn encode<T: Serialize>(&self, val: &T) -> Result<Vec<u8>, SerializationError>;
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, DeserializationError>;
+}
+
+/// The CBOR encoding used by realms.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CborCodec;
+
+impl Codec for CborCodec {
+    fn encode<T: Serialize>(&self, val: &T) -> Result<Vec<u8>, SerializationError> {
+        let mut bytes = Vec::new();
+        match ciborium::ser::into_writer(val, &mut bytes) {
+            Ok(_) => Ok(bytes),
+            Err(e) => Err(SerializationError(e.to_string())),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, DeserializationError> {
+        decode_prefix(bytes).map(|(value, _)| value)
+    }
+}
+
+/// Deserializes the first CBOR data item in `bytes`, returning it with the
+/// number of bytes it took up.
+fn decode_prefix<T: DeserializeOwned>(bytes: &[u8]) -> Result<(T, usize), DeserializationError> {
+    let mut remaining = bytes;
+    let result = ciborium::de::from_reader(&mut remaining);
+    let consumed = bytes.len() - remaining.len();
+    match result {
+        Ok(value) => Ok((value, consumed)),
+        // Reading from a slice only fails when it runs out of bytes.
+        Err(e) => Err(
+            DeserializationError::from_ciborium(e, Some(consumed)).unwrap_or_else(|_| {
+                DeserializationError::with_offset("unexpected end of input", bytes.len())
+            }),
+        ),
     }
 }
 
 pub fn to_vec<T: Serialize>(val: &T) -> Result<Vec<u8>, SerializationError> {
-    let mut bytes = Vec::new();
-    match ciborium::ser::into_writer(val, &mut bytes) {
-        Ok(_) => Ok(bytes),
+    CborCodec.encode(val)
+}
+
+/// Serializes `val` to deterministically encoded CBOR, as defined in
+/// [RFC 8949 section 4.2](https://www.rfc-editor.org/rfc/rfc8949#section-4.2).
+///
+/// Map entries, including struct fields, are sorted by the bytewise
+/// lexicographic order of their encoded keys, and every length is definite.
+/// Integers and floats are already encoded in their shortest form by
+/// [`to_vec`]. Equal values therefore always produce the same bytes, which
+/// makes the output suitable for signing.
+///
+/// This is slower than [`to_vec`], since it builds an intermediate tree of
+/// the value. Returns an error if a map has two equal keys.
+pub fn to_vec_canonical<T: Serialize>(val: &T) -> Result<Vec<u8>, SerializationError> {
+    let mut value =
+        ciborium::Value::serialized(val).map_err(|e| SerializationError(e.to_string()))?;
+    canonicalize(&mut value)?;
+    to_vec(&value)
+}
+
+fn canonicalize(value: &mut ciborium::Value) -> Result<(), SerializationError> {
+    use ciborium::Value;
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                canonicalize(item)?;
+            }
+        }
+        Value::Tag(_, inner) => canonicalize(inner)?,
+        Value::Map(entries) => {
+            let mut encoded = Vec::with_capacity(entries.len());
+            for (mut key, mut value) in entries.drain(..) {
+                canonicalize(&mut key)?;
+                canonicalize(&mut value)?;
+                encoded.push((to_vec(&key)?, key, value));
+            }
+            encoded.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+            if encoded.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                return Err(SerializationError(String::from("duplicate map key")));
+            }
+            entries.extend(encoded.into_iter().map(|(_, key, value)| (key, value)));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns the number of bytes [`to_vec`] would produce for `val`, without
+/// allocating the output.
+pub fn serialized_size<T: Serialize>(val: &T) -> Result<usize, SerializationError> {
+    let mut size = 0;
+    match ciborium::ser::into_writer(val, CountingWriter(&mut size)) {
+        Ok(_) => Ok(size),
         Err(e) => Err(SerializationError(e.to_string())),
     }
 }
 
+/// Discards everything written to it, keeping only the total length.
+struct CountingWriter<'a>(&'a mut usize);
+
+impl ciborium_io::Write for CountingWriter<'_> {
+    type Error = core::convert::Infallible;
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        *self.0 += data.len();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Deserializes the first CBOR data item in `bytes`.
+///
+/// Any bytes after that item are ignored, which existing callers may depend
+/// on. Use [`from_slice_exact`] for untrusted input, so that data appended to
+/// a message isn't silently accepted.
 pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, DeserializationError> {
-    ciborium::de::from_reader(bytes).map_err(|e| DeserializationError(e.to_string()))
+    CborCodec.decode(bytes)
+}
+
+/// Like [`from_slice`], but returns an error if any bytes follow the
+/// deserialized value. The error's offset is where the trailing bytes start.
+pub fn from_slice_exact<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, DeserializationError> {
+    let (value, consumed) = decode_prefix(bytes)?;
+    if consumed == bytes.len() {
+        Ok(value)
+    } else {
+        Err(DeserializationError::with_offset(
+            format!("{} trailing bytes", bytes.len() - consumed),
+            consumed,
+        ))
+    }
+}
+
+/// The maximum nesting of arrays, maps, and tags accepted by
+/// [`from_slice_limited`].
+pub const MAX_DEPTH: usize = 32;
+
+/// Like [`from_slice`], but for untrusted input.
+///
+/// This rejects input longer than `max_len` bytes or nested more than
+/// [`MAX_DEPTH`] levels deep. It also rejects any CBOR length header that
+/// claims more elements or bytes than the input contains, so that the
+/// deserializer never allocates more than `max_len` entries for a collection.
+pub fn from_slice_limited<T: DeserializeOwned>(
+    bytes: &[u8],
+    max_len: usize,
+) -> Result<T, DeserializationError> {
+    if bytes.len() > max_len {
+        return Err(DeserializationError::new(format!(
+            "input of {} bytes exceeds limit of {max_len} bytes",
+            bytes.len()
+        )));
+    }
+    limit::check(bytes, MAX_DEPTH).map_err(DeserializationError::new)?;
+    from_slice(bytes)
+}
+
+/// Serializes `val` directly into `writer`, without an intermediate buffer.
+///
+/// The writer is not buffered or flushed here. Wrap unbuffered writers such as
+/// sockets in a [`std::io::BufWriter`], since the encoder issues many small
+/// writes.
+#[cfg(feature = "std")]
+pub fn to_writer<T: Serialize, W: std::io::Write>(
+    val: &T,
+    writer: W,
+) -> Result<(), SerializationError> {
+    ciborium::ser::into_writer(val, writer).map_err(|e| SerializationError(e.to_string()))
+}
+
+/// Deserializes a value directly from `reader`, without first reading it into
+/// a buffer.
+///
+/// As with [`to_writer`], wrap unbuffered readers in a [`std::io::BufReader`].
+#[cfg(feature = "std")]
+pub fn from_reader<T: DeserializeOwned, R: std::io::Read>(
+    reader: R,
+) -> Result<T, DeserializationError> {
+    ciborium::de::from_reader(reader).map_err(|e| {
+        DeserializationError::from_ciborium(e, None)
+            .unwrap_or_else(|e| DeserializationError::new(e.to_string()))
+    })
+}
+
+/// Writes `val` to `writer` as one frame: its CBOR encoding, preceded by
+/// the encoding's length as a 4-byte big-endian integer.
+///
+/// Frames let several messages share one stream. Read them back with
+/// [`read_framed`].
+#[cfg(feature = "std")]
+pub fn write_framed<W: std::io::Write, T: Serialize>(
+    mut writer: W,
+    val: &T,
+) -> Result<(), SerializationError> {
+    let bytes = to_vec(val)?;
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| SerializationError(format!("frame of {} bytes is too long", bytes.len())))?;
+    writer
+        .write_all(&len.to_be_bytes())
+        .and_then(|()| writer.write_all(&bytes))
+        .map_err(|e| SerializationError(e.to_string()))
+}
+
+/// Reads one frame written by [`write_framed`] from `reader`.
+///
+/// Frames longer than `max_len` bytes are rejected before any of their
+/// contents are read. As with [`from_slice_exact`], the frame must hold
+/// exactly one value, with no trailing bytes.
+#[cfg(feature = "std")]
+pub fn read_framed<R: std::io::Read, T: DeserializeOwned>(
+    mut reader: R,
+    max_len: usize,
+) -> Result<T, DeserializationError> {
+    let mut header = [0; 4];
+    reader
+        .read_exact(&mut header)
+        .map_err(|e| DeserializationError::new(e.to_string()))?;
+    let len = u32::from_be_bytes(header) as usize;
+    if len > max_len {
+        return Err(DeserializationError::new(format!(
+            "frame of {len} bytes exceeds limit of {max_len} bytes"
+        )));
+    }
+    let mut bytes = vec![0; len];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(|e| DeserializationError::new(e.to_string()))?;
+    from_slice_exact(&bytes)
 }
 
 /// Converts the provided integer into a 2 byte array in big-endian
@@ -143,4 +446,244 @@ mod tests {
             "integer larger than 8 bytes"
         );
     }
+
+    #[test]
+    fn test_to_vec_canonical() {
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Deserialize, PartialEq, Serialize)]
+        struct Signed {
+            zeta: BTreeMap<i32, String>,
+            alpha: Vec<u8>,
+        }
+
+        let value = Signed {
+            zeta: BTreeMap::from([
+                (-1, String::from("minus one")),
+                (100, String::from("hundred")),
+                (10, String::from("ten")),
+            ]),
+            alpha: vec![1, 2, 3],
+        };
+        let canonical = to_vec_canonical(&value).unwrap();
+        assert_eq!(from_slice::<Signed>(&canonical).unwrap(), value);
+
+        // Keys are sorted by their encoding, so "zeta" (0x64 ...) comes before
+        // "alpha" (0x65 ...), and 10 (0x0a) before 100 (0x18 0x64) before -1
+        // (0x20).
+        let mut expected = vec![0xa2, 0x64];
+        expected.extend(b"zeta");
+        expected.extend([0xa3, 0x0a, 0x63]);
+        expected.extend(b"ten");
+        expected.extend([0x18, 0x64, 0x67]);
+        expected.extend(b"hundred");
+        expected.extend([0x20, 0x69]);
+        expected.extend(b"minus one");
+        expected.push(0x65);
+        expected.extend(b"alpha");
+        expected.extend([0x83, 1, 2, 3]);
+        assert_eq!(canonical, expected);
+
+        assert_ne!(to_vec(&value).unwrap(), canonical);
+
+        // Values that are already canonical encode the same as `to_vec`.
+        let sorted = BTreeMap::from([(1u8, 1.5f64), (2, -0.0)]);
+        assert_eq!(to_vec_canonical(&sorted), to_vec(&sorted));
+    }
+
+    #[test]
+    fn test_serialized_size() {
+        fn check<T: Serialize>(val: &T) {
+            assert_eq!(serialized_size(val).unwrap(), to_vec(val).unwrap().len());
+        }
+        check(&());
+        check(&0u8);
+        check(&u64::MAX);
+        check(&String::from("artemis"));
+        check(&vec![0u32; 1000]);
+        check(&(Some(vec![1u8; 300]), None::<u8>, [-1i64; 3]));
+        assert_eq!(serialized_size(&vec![7u8; 30]).unwrap(), 32);
+    }
+
+    #[test]
+    fn test_from_slice_exact() {
+        let value = (String::from("artemis"), 7u8);
+        let mut bytes = to_vec(&value).unwrap();
+        assert_eq!(from_slice_exact::<(String, u8)>(&bytes), Ok(value.clone()));
+
+        let len = bytes.len();
+        bytes.extend([0xde, 0xad]);
+        assert_eq!(from_slice::<(String, u8)>(&bytes), Ok(value));
+        assert_eq!(
+            from_slice_exact::<(String, u8)>(&bytes),
+            Err(DeserializationError::with_offset("2 trailing bytes", len))
+        );
+        assert_eq!(
+            from_slice_exact::<(String, u8)>(&bytes[..len - 1])
+                .unwrap_err()
+                .message(),
+            "unexpected end of input"
+        );
+    }
+
+    #[test]
+    fn test_from_slice_limited() {
+        let value = (String::from("artemis"), vec![1u32, 2, 3], Some(7u8));
+        let bytes = to_vec(&value).unwrap();
+        assert_eq!(
+            from_slice_limited::<(String, Vec<u32>, Option<u8>)>(&bytes, bytes.len()),
+            Ok(value)
+        );
+        assert_eq!(
+            from_slice_limited::<(String, Vec<u32>, Option<u8>)>(&bytes, bytes.len() - 1),
+            Err(DeserializationError::new(format!(
+                "input of {} bytes exceeds limit of {} bytes",
+                bytes.len(),
+                bytes.len() - 1
+            )))
+        );
+
+        // Indefinite-length items are accepted too.
+        let indefinite = [0x9f, 0x01, 0x7f, 0x61, b'a', 0xff, 0xbf, 0xff, 0xff];
+        assert!(from_slice_limited::<ciborium::Value>(&indefinite, 100).is_ok());
+    }
+
+    #[test]
+    fn test_from_slice_limited_rejects_bombs() {
+        let error = DeserializationError::new;
+
+        // An array header claiming 2^32 elements, followed by a few.
+        let bytes = [0x9b, 0, 0, 0, 1, 0, 0, 0, 0, 1, 2, 3];
+        assert_eq!(
+            from_slice_limited::<Vec<u8>>(&bytes, 1024).unwrap_err(),
+            error("collection length exceeds input")
+        );
+        // A map header whose pairs need twice the remaining bytes.
+        assert_eq!(
+            from_slice_limited::<ciborium::Value>(&[0xa2, 1, 2], 1024).unwrap_err(),
+            error("collection length exceeds input")
+        );
+        // A byte string header longer than the input.
+        assert_eq!(
+            from_slice_limited::<Vec<u8>>(&[0x5a, 0xff, 0xff, 0xff, 0xff, 0], 1024).unwrap_err(),
+            error("truncated input")
+        );
+
+        let nested = |depth| {
+            let mut bytes = vec![0x81; depth];
+            bytes.push(0);
+            bytes
+        };
+        assert!(from_slice_limited::<ciborium::Value>(&nested(MAX_DEPTH), 1024).is_ok());
+        assert_eq!(
+            from_slice_limited::<ciborium::Value>(&nested(MAX_DEPTH + 1), 1024).unwrap_err(),
+            error("nesting depth exceeds limit")
+        );
+        assert_eq!(
+            from_slice_limited::<ciborium::Value>(&[0x9f, 0x01], 1024).unwrap_err(),
+            error("truncated input")
+        );
+        assert_eq!(
+            from_slice_limited::<ciborium::Value>(&[0x1c], 1024).unwrap_err(),
+            error("invalid CBOR header")
+        );
+    }
+
+    #[test]
+    fn test_deserialization_error_offset() {
+        let bytes = to_vec(&(String::from("artemis"), 7u8)).unwrap();
+
+        let truncated = from_slice::<(String, u8)>(&bytes[..5]).unwrap_err();
+        assert_eq!(truncated.message(), "unexpected end of input");
+        assert_eq!(truncated.offset(), Some(5));
+        assert_eq!(
+            truncated.to_string(),
+            "Deserialization error: unexpected end of input at byte 5"
+        );
+
+        // The second element, at offset 9, is a u8 where a string was
+        // expected.
+        let wrong_type = from_slice::<(String, String)>(&bytes).unwrap_err();
+        assert_eq!(wrong_type.offset(), Some(10));
+        assert!(wrong_type.message().starts_with("invalid type"));
+
+        // 0x1c is a reserved header.
+        let syntax = from_slice::<(String, u8)>(&[0x82, 0x1c]).unwrap_err();
+        assert_eq!(syntax.message(), "invalid CBOR syntax");
+        assert_eq!(syntax.offset(), Some(1));
+
+        let no_offset = DeserializationError::new("oops");
+        assert_eq!(no_offset.offset(), None);
+        assert_eq!(no_offset.to_string(), "Deserialization error: oops");
+    }
+
+    #[test]
+    fn test_errors_box_with_question_mark() {
+        fn round_trip() -> Result<u8, Box<dyn std::error::Error>> {
+            let bytes = to_vec(&String::from("artemis"))?;
+            Ok(from_slice(&bytes)?)
+        }
+        assert_eq!(
+            round_trip().unwrap_err().to_string(),
+            "Deserialization error: invalid type: string, expected integer at byte 1"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_writer_reader() {
+        let value = (String::from("artemis"), vec![1u32, 2, 3], Some(7u8));
+
+        let mut written = Vec::new();
+        to_writer(&value, &mut written).unwrap();
+        assert_eq!(written, to_vec(&value).unwrap());
+
+        let mut reader = std::io::Cursor::new(&written);
+        assert_eq!(
+            from_reader::<(String, Vec<u32>, Option<u8>), _>(&mut reader).unwrap(),
+            value
+        );
+        assert_eq!(reader.position(), written.len() as u64);
+
+        assert!(from_reader::<(String, Vec<u32>, Option<u8>), _>(&written[..4]).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_framed() {
+        let first = (String::from("artemis"), vec![1u32, 2, 3]);
+        let second = String::from("apollo");
+
+        let mut stream = Vec::new();
+        write_framed(&mut stream, &first).unwrap();
+        write_framed(&mut stream, &second).unwrap();
+        let first_len = to_vec(&first).unwrap().len();
+        assert_eq!(stream[..4], to_be4(first_len));
+
+        let mut reader = stream.as_slice();
+        assert_eq!(
+            read_framed::<_, (String, Vec<u32>)>(&mut reader, 100),
+            Ok(first)
+        );
+        assert_eq!(read_framed::<_, String>(&mut reader, 100), Ok(second));
+        assert!(reader.is_empty());
+        assert!(read_framed::<_, String>(&mut reader, 100).is_err());
+
+        assert_eq!(
+            read_framed::<_, (String, Vec<u32>)>(stream.as_slice(), first_len - 1),
+            Err(DeserializationError::new(format!(
+                "frame of {first_len} bytes exceeds limit of {} bytes",
+                first_len - 1
+            )))
+        );
+
+        // The frame's length must cover exactly one value.
+        let mut padded = to_be4(3).to_vec();
+        padded.extend([0x01, 0x02, 0x03]);
+        assert_eq!(
+            read_framed::<_, u8>(padded.as_slice(), 100),
+            Err(DeserializationError::with_offset("2 trailing bytes", 1))
+        );
+        assert!(read_framed::<_, u8>(&padded[..5], 100).is_err());
+    }
 }
@@ -0,0 +1,143 @@
+//! A structural pre-check of CBOR input that bounds the work the deserializer
+//! can be made to do.
+//!
+//! The check walks a single CBOR data item without allocating. It rejects
+//! items nested more deeply than a limit and any length header that claims
+//! more elements or bytes than remain in the input. After it passes, every
+//! collection the deserializer builds is at most as long as the input.
+
+/// Walks the first data item in `input`, returning an error if it is
+/// malformed, truncated, or nested more than `max_depth` levels deep.
+pub(crate) fn check(input: &[u8], max_depth: usize) -> Result<(), &'static str> {
+    let mut scanner = Scanner { input, max_depth };
+    scanner.item(0)
+}
+
+struct Scanner<'a> {
+    input: &'a [u8],
+    max_depth: usize,
+}
+
+/// Marks the end of an indefinite-length item.
+const BREAK: u8 = 0xff;
+
+enum Length {
+    Definite(u64),
+    Indefinite,
+}
+
+impl<'a> Scanner<'a> {
+    fn item(&mut self, depth: usize) -> Result<(), &'static str> {
+        let (major, length) = self.header()?;
+        match (major, length) {
+            // Unsigned and negative integers.
+            (0 | 1, Length::Definite(_)) => Ok(()),
+            // Byte and text strings.
+            (2 | 3, Length::Definite(len)) => self.skip(len),
+            (2 | 3, Length::Indefinite) => loop {
+                if self.at_break()? {
+                    break Ok(());
+                }
+                match self.header()? {
+                    (chunk_major, Length::Definite(len)) if chunk_major == major => {
+                        self.skip(len)?
+                    }
+                    _ => break Err("invalid string chunk"),
+                }
+            },
+            // Arrays and maps.
+            (4 | 5, length) => {
+                let depth = self.nest(depth)?;
+                let per_entry = if major == 4 { 1 } else { 2 };
+                match length {
+                    Length::Definite(count) => {
+                        // Every item takes at least one byte, so this bounds
+                        // the count by the input size before looping.
+                        let items = count
+                            .checked_mul(per_entry)
+                            .filter(|items| *items <= self.input.len() as u64)
+                            .ok_or("collection length exceeds input")?;
+                        for _ in 0..items {
+                            self.item(depth)?;
+                        }
+                        Ok(())
+                    }
+                    Length::Indefinite => {
+                        while !self.at_break()? {
+                            for _ in 0..per_entry {
+                                self.item(depth)?;
+                            }
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            // Tags, which apply to the following item.
+            (6, Length::Definite(_)) => {
+                let depth = self.nest(depth)?;
+                self.item(depth)
+            }
+            // Simple values and floats. The header already consumed their
+            // payload.
+            (7, Length::Definite(_)) => Ok(()),
+            _ => Err("invalid CBOR header"),
+        }
+    }
+
+    fn nest(&self, depth: usize) -> Result<usize, &'static str> {
+        if depth >= self.max_depth {
+            Err("nesting depth exceeds limit")
+        } else {
+            Ok(depth + 1)
+        }
+    }
+
+    /// Consumes a break marker if one is next.
+    fn at_break(&mut self) -> Result<bool, &'static str> {
+        match self.input.first() {
+            Some(&BREAK) => {
+                self.input = &self.input[1..];
+                Ok(true)
+            }
+            Some(_) => Ok(false),
+            None => Err("truncated input"),
+        }
+    }
+
+    fn header(&mut self) -> Result<(u8, Length), &'static str> {
+        let (&initial, rest) = self.input.split_first().ok_or("truncated input")?;
+        self.input = rest;
+        let major = initial >> 5;
+        let length = match initial & 0x1f {
+            info @ 0..=23 => Length::Definite(u64::from(info)),
+            24 => Length::Definite(self.uint::<1>()?),
+            25 => Length::Definite(self.uint::<2>()?),
+            26 => Length::Definite(self.uint::<4>()?),
+            27 => Length::Definite(self.uint::<8>()?),
+            31 if matches!(major, 2..=5) => Length::Indefinite,
+            _ => return Err("invalid CBOR header"),
+        };
+        Ok((major, length))
+    }
+
+    fn uint<const N: usize>(&mut self) -> Result<u64, &'static str> {
+        if self.input.len() < N {
+            return Err("truncated input");
+        }
+        let (bytes, rest) = self.input.split_at(N);
+        self.input = rest;
+        Ok(bytes
+            .iter()
+            .fold(0, |value, byte| (value << 8) | u64::from(*byte)))
+    }
+
+    fn skip(&mut self, len: u64) -> Result<(), &'static str> {
+        match usize::try_from(len) {
+            Ok(len) if len <= self.input.len() => {
+                self.input = &self.input[len..];
+                Ok(())
+            }
+            _ => Err("truncated input"),
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Compact encoding for sorted lists of integers, such as generation
+//! numbers.
+//!
+//! The list is written as the first value followed by the difference between
+//! each value and the previous one, with every number encoded as an unsigned
+//! LEB128 varint. Lists of nearby values encode to about one byte per entry,
+//! instead of the up to 5 bytes per entry that CBOR uses for a `u32`.
+//!
+//! Use with `#[serde(with = "sorted_list")]` on a `Vec<u32>` field, which is
+//! then serialized as a single byte string.
+extern crate alloc;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Encodes a list sorted in non-decreasing order.
+///
+/// Returns an error if the list is not sorted.
+pub fn encode(values: &[u32]) -> Result<Vec<u8>, &'static str> {
+    let mut encoded = Vec::with_capacity(values.len());
+    let mut previous = 0;
+    for &value in values {
+        let delta = value.checked_sub(previous).ok_or("list is not sorted")?;
+        write_varint(&mut encoded, delta);
+        previous = value;
+    }
+    Ok(encoded)
+}
+
+/// Decodes a list produced by [`encode`].
+pub fn decode(mut encoded: &[u8]) -> Result<Vec<u32>, &'static str> {
+    let mut values = Vec::new();
+    let mut previous: u32 = 0;
+    while !encoded.is_empty() {
+        let delta = read_varint(&mut encoded)?;
+        previous = previous.checked_add(delta).ok_or("value out of range")?;
+        values.push(previous);
+    }
+    Ok(values)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u32, &'static str> {
+    let mut value: u32 = 0;
+    for (i, &byte) in input.iter().enumerate() {
+        // A u32 takes at most 5 bytes, with only 4 bits used in the last.
+        if i == 4 && byte > 0x0f {
+            return Err("value out of range");
+        }
+        value |= u32::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            *input = &input[i + 1..];
+            return Ok(value);
+        }
+    }
+    Err("truncated varint")
+}
+
+pub fn serialize<Ser>(values: &[u32], serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+where
+    Ser: serde::ser::Serializer,
+{
+    let encoded = encode(values).map_err(serde::ser::Error::custom)?;
+    serializer.serialize_bytes(&encoded)
+}
+
+pub fn deserialize<'de, De>(deserializer: De) -> Result<Vec<u32>, De::Error>
+where
+    De: serde::de::Deserializer<'de>,
+{
+    struct Visitor;
+
+    impl<'de> serde::de::Visitor<'de> for Visitor {
+        type Value = Vec<u32>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("delta-encoded sorted list")
+        }
+
+        fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            decode(bytes).map_err(E::custom)
+        }
+    }
+
+    deserializer.deserialize_bytes(Visitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode};
+    use crate::{from_slice, sorted_list, to_vec};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+    struct Compact(#[serde(with = "sorted_list")] Vec<u32>);
+
+    #[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+    struct Naive(Vec<u32>);
+
+    #[test]
+    fn test_round_trip() {
+        for values in [
+            vec![],
+            vec![0],
+            vec![0, 0, 1],
+            vec![127, 128, 16_383, 16_384],
+            vec![1, 5, 1_000_000, u32::MAX - 1, u32::MAX],
+        ] {
+            let encoded = encode(&values).unwrap();
+            assert_eq!(decode(&encoded).unwrap(), values);
+
+            let compact = Compact(values);
+            assert_eq!(
+                from_slice::<Compact>(&to_vec(&compact).unwrap()).unwrap(),
+                compact
+            );
+        }
+    }
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(encode(&[1, 2, 130]).unwrap(), [0x01, 0x01, 0x80, 0x01]);
+        assert_eq!(encode(&[u32::MAX]).unwrap(), [0xff, 0xff, 0xff, 0xff, 0x0f]);
+    }
+
+    #[test]
+    fn test_size_vs_naive() {
+        let values: Vec<u32> = (0..1000).map(|i| 70_000 + i * 3).collect();
+        let compact = to_vec(&Compact(values.clone())).unwrap();
+        let naive = to_vec(&Naive(values)).unwrap();
+        assert_eq!(naive.len(), 3 + 1000 * 5);
+        assert_eq!(compact.len(), 3 + 3 + 999);
+        assert!(compact.len() < naive.len() / 4);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(encode(&[2, 1]), Err("list is not sorted"));
+        assert!(to_vec(&Compact(vec![2, 1])).is_err());
+        assert_eq!(decode(&[0x80]), Err("truncated varint"));
+        assert_eq!(
+            decode(&[0xff, 0xff, 0xff, 0xff, 0x10]),
+            Err("value out of range")
+        );
+        assert_eq!(
+            decode(&[0xff, 0xff, 0xff, 0xff, 0x0f, 0x01]),
+            Err("value out of range")
+        );
+    }
+}
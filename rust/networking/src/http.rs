@@ -67,5 +67,12 @@ pub trait Client: Sync {
     /// Called when the HTTP [`Client`] should perform a [`Request`]
     /// and return the [`Response`] or [`None`] if unable to
     /// perform the request.
+    ///
+    /// The caller may drop the returned future before it completes, for
+    /// example once enough other realms have responded. Whether that
+    /// cancels the HTTP request itself depends on the implementation: one
+    /// that performs the request within the future (like the `reqwest`
+    /// client) aborts it, while one that hands the request to another
+    /// thread or runtime lets it finish and discards the response.
     async fn send(&self, request: Request) -> Option<Response>;
 }
@@ -61,6 +61,11 @@ impl fmt::Debug for HandshakeResponse {
 }
 
 /// Clients and servers use this to communicate after the handshake.
+///
+/// Each direction keeps an implicit message counter that is used as the
+/// AEAD nonce. A message that is replayed, dropped, or delivered out of
+/// order will fail to decrypt, after which the `Transport` should be
+/// discarded since its counters are no longer in sync with the peer.
 pub struct Transport {
     inbound: CipherState,
     outbound: CipherState,
@@ -85,6 +90,51 @@ impl Transport {
             aad: &[],
         })
     }
+
+    /// Exports the transport's keys and message counters, so that the
+    /// session can be resumed later with [`Transport::from_state`], such as
+    /// after a process restart.
+    ///
+    /// Encrypting or decrypting with this transport afterwards makes the
+    /// exported state stale: resuming from it would reuse nonces or fall out
+    /// of step with the peer. Only resume from the latest export.
+    pub fn to_state(&self) -> TransportState {
+        TransportState {
+            inbound_key: self.inbound.key,
+            inbound_nonce: self.inbound.nonce,
+            outbound_key: self.outbound.key,
+            outbound_nonce: self.outbound.nonce,
+        }
+    }
+
+    /// Resumes a transport exported with [`Transport::to_state`].
+    pub fn from_state(state: &TransportState) -> Self {
+        Self {
+            inbound: CipherState::with_nonce(state.inbound_key, state.inbound_nonce),
+            outbound: CipherState::with_nonce(state.outbound_key, state.outbound_nonce),
+        }
+    }
+}
+
+/// The keys and message counters of a [`Transport`], from
+/// [`Transport::to_state`].
+///
+/// Anyone with this state can read and forge the session's messages, so it
+/// must be stored as carefully as the session itself.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct TransportState {
+    #[serde(with = "bytes")]
+    inbound_key: [u8; 32],
+    inbound_nonce: u64,
+    #[serde(with = "bytes")]
+    outbound_key: [u8; 32],
+    outbound_nonce: u64,
+}
+
+impl fmt::Debug for TransportState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransportState").finish_non_exhaustive()
+    }
 }
 
 /// This corresponds to a Noise protocol "CipherState" object for
@@ -101,14 +151,21 @@ impl Transport {
 /// > microcontrollers)."
 struct CipherState {
     cipher: ChaCha20Poly1305,
+    /// Kept only for [`Transport::to_state`].
+    key: [u8; 32],
     nonce: u64,
 }
 
 impl CipherState {
     pub fn new(key: [u8; 32]) -> Self {
+        Self::with_nonce(key, 0)
+    }
+
+    fn with_nonce(key: [u8; 32], nonce: u64) -> Self {
         Self {
             cipher: ChaCha20Poly1305::new(&key.into()),
-            nonce: 0,
+            key,
+            nonce,
         }
     }
 
@@ -234,4 +291,63 @@ mod tests {
             [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]
         );
     }
+
+    #[test]
+    fn test_transport_rejects_replay() {
+        let (mut client, mut server) = transports();
+        let first = server.encrypt(b"first").unwrap();
+        assert_eq!(client.decrypt(&first).unwrap(), b"first");
+        assert!(client.decrypt(&first).is_err());
+    }
+
+    #[test]
+    fn test_transport_rejects_reordering() {
+        let (mut client, mut server) = transports();
+        let _first = server.encrypt(b"first").unwrap();
+        let second = server.encrypt(b"second").unwrap();
+        assert!(client.decrypt(&second).is_err());
+    }
+
+    #[test]
+    fn test_transport_resumed_from_state() {
+        let (mut client, mut server) = transports();
+        let first = client.encrypt(b"first").unwrap();
+        assert_eq!(server.decrypt(&first).unwrap(), b"first");
+
+        let state: TransportState = juicebox_marshalling::from_slice(
+            &juicebox_marshalling::to_vec(&client.to_state()).unwrap(),
+        )
+        .unwrap();
+        let mut resumed = Transport::from_state(&state);
+        let second = resumed.encrypt(b"second").unwrap();
+        assert_eq!(server.decrypt(&second).unwrap(), b"second");
+        let reply = server.encrypt(b"reply").unwrap();
+        assert_eq!(resumed.decrypt(&reply).unwrap(), b"reply");
+
+        // The stale original would reuse the resumed transport's nonce.
+        assert_eq!(client.encrypt(b"second").unwrap(), second);
+    }
+
+    fn transports() -> (Transport, Transport) {
+        let server_static_secret = x25519::StaticSecret::from([1u8; 32]);
+        let server_static_public = x25519::PublicKey::from(&server_static_secret);
+
+        let (client, request) = client::Handshake::start_with_secret(
+            x25519::StaticSecret::from([2u8; 32]),
+            &server_static_public,
+            &[],
+            &[],
+        )
+        .unwrap();
+        let (server, _) = server::Handshake::start_with_secret(
+            x25519::StaticSecret::from([3u8; 32]),
+            (&server_static_secret, &server_static_public),
+            &[],
+            &request,
+        )
+        .unwrap();
+        let (server, response) = server.finish(&[]).unwrap();
+        let (client, _) = client.finish(&response).unwrap();
+        (client, server)
+    }
 }
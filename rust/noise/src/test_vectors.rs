@@ -219,10 +219,7 @@ fn test_vector(vector: &TestVector) -> bool {
     );
 
     let mut messages = vector.messages.iter().skip(2);
-    loop {
-        let Some(request) = messages.next() else {
-            break;
-        };
+    while let Some(request) = messages.next() {
         let ciphertext = client
             .encrypt(&hex_decode(&request.payload))
             .expect("client encrypt transport request");
@@ -7,20 +7,23 @@
 //! an optimization for proof size.
 
 use core::fmt;
+extern crate alloc;
+
+use alloc::vec::Vec;
 use curve25519_dalek::ristretto::{
     CompressedRistretto as CompressedPoint, RistrettoPoint as Point,
 };
 use curve25519_dalek::traits::MultiscalarMul;
 use curve25519_dalek::Scalar;
 use digest::Digest;
-use juicebox_marshalling::bytes;
+use juicebox_marshalling::{bytes, to_be4};
 use rand_core::CryptoRngCore;
 use serde::{Deserialize, Serialize};
 use sha2::Sha512;
-use subtle::ConstantTimeEq;
+use subtle::{Choice, ConstantTimeEq};
 use zeroize::ZeroizeOnDrop;
 
-use super::PrecompressedPoint;
+use super::{OprfError, PrecompressedPoint};
 
 /// Produced by the OPRF server as evidence that it evaluated the function
 /// correctly, then checked by the client with
@@ -34,6 +37,31 @@ pub struct Proof {
     pub(crate) beta_z: Scalar,
 }
 
+impl Proof {
+    /// Returns the 64-byte encoding of the proof: the canonical encoding of
+    /// the challenge `c` followed by that of the response `beta_z`.
+    pub fn to_bytes(&self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(self.c.as_bytes());
+        bytes[32..].copy_from_slice(self.beta_z.as_bytes());
+        bytes
+    }
+
+    /// Parses a proof previously produced by [`Proof::to_bytes`].
+    ///
+    /// Returns an error if either scalar is not canonically encoded.
+    pub fn from_bytes(bytes: &[u8; 64]) -> Result<Self, OprfError> {
+        let scalar = |bytes: &[u8]| {
+            Option::<Scalar>::from(Scalar::from_canonical_bytes(bytes.try_into().unwrap()))
+                .ok_or(OprfError::NonCanonicalScalar)
+        };
+        Ok(Self {
+            c: scalar(&bytes[..32])?,
+            beta_z: scalar(&bytes[32..])?,
+        })
+    }
+}
+
 impl fmt::Debug for Proof {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str("Proof(REDACTED)")
@@ -53,8 +81,20 @@ pub(crate) fn generate_proof(
     v: &CompressedPoint,    // OPRF public key
     w: &PrecompressedPoint, // OPRF blinded output
 ) -> Proof {
-    let beta_t = Scalar::random(rng);
-    let v_t = Point::mul_base(&beta_t);
+    generate_proof_with_nonce(&Scalar::random(rng), beta, u, v, w)
+}
+
+/// Like [`generate_proof`], but with the given nonce. Reusing a nonce for
+/// two proofs reveals the private key, so outside of test vectors it must
+/// be random.
+pub(crate) fn generate_proof_with_nonce(
+    beta_t: &Scalar,        // nonce
+    beta: &Scalar,          // OPRF private key
+    u: &PrecompressedPoint, // OPRF blinded input
+    v: &CompressedPoint,    // OPRF public key
+    w: &PrecompressedPoint, // OPRF blinded output
+) -> Proof {
+    let v_t = Point::mul_base(beta_t);
     let w_t = u.uncompressed * beta_t;
     let c = hash_to_challenge(
         &u.compressed,
@@ -72,7 +112,35 @@ pub(crate) fn verify_proof(
     v: &PrecompressedPoint, // OPRF public key
     w: &PrecompressedPoint, // OPRF blinded output
     proof: &Proof,
-) -> Result<(), &'static str> {
+) -> Result<(), OprfError> {
+    if bool::from(proof_matches(u, v, w, proof)) {
+        Ok(())
+    } else {
+        Err(OprfError::ProofVerificationFailed)
+    }
+}
+
+/// Like [`verify_proof`], but returns whether the proof is valid as a
+/// [`Choice`], for callers that must not branch on it.
+pub(crate) fn proof_matches(
+    u: &PrecompressedPoint, // OPRF blinded input
+    v: &PrecompressedPoint, // OPRF public key
+    w: &PrecompressedPoint, // OPRF blinded output
+    proof: &Proof,
+) -> Choice {
+    let (v_t, w_t) = verifier_commitments(u, v, w, proof);
+    let c = hash_to_challenge(&u.compressed, &v.compressed, &w.compressed, &v_t, &w_t);
+    c.ct_eq(&proof.c)
+}
+
+/// Recomputes the prover's commitments `(v_t, w_t)` from the proof. They
+/// match the prover's only if the proof is valid.
+fn verifier_commitments(
+    u: &PrecompressedPoint,
+    v: &PrecompressedPoint,
+    w: &PrecompressedPoint,
+    proof: &Proof,
+) -> (CompressedPoint, CompressedPoint) {
     let v_t = Point::mul_base(&proof.beta_z) - v.uncompressed * proof.c;
 
     // For `w_t` (but not `v_t`), the `multiscalar_mul` is faster on some
@@ -82,20 +150,62 @@ pub(crate) fn verify_proof(
         w_t,
         u.uncompressed * proof.beta_z - w.uncompressed * proof.c
     );
+    (v_t.compress(), w_t.compress())
+}
 
-    let c = hash_to_challenge(
-        &u.compressed,
-        &v.compressed,
-        &w.compressed,
-        &v_t.compress(),
-        &w_t.compress(),
-    );
+/// Generates a single proof that every `w[i]` equals `u[i] * beta`.
+///
+/// The pairs are combined into one composite pair using coefficients derived
+/// from a hash of all of them, so a proof for the composite pair is
+/// overwhelmingly unlikely to verify unless every pair is correct.
+pub(crate) fn generate_batch_proof(
+    rng: &mut impl CryptoRngCore,
+    beta: &Scalar,             // OPRF private key
+    u: &[&PrecompressedPoint], // OPRF blinded inputs
+    v: &CompressedPoint,       // OPRF public key
+    w: &[&PrecompressedPoint], // OPRF blinded outputs
+) -> Proof {
+    let (u, w) = composites(u, v, w);
+    generate_proof(rng, beta, &u, v, &w)
+}
 
-    if bool::from(c.ct_eq(&proof.c)) {
-        Ok(())
-    } else {
-        Err("invalid proof")
+/// Verifies a proof from [`generate_batch_proof`].
+pub(crate) fn verify_batch_proof(
+    u: &[&PrecompressedPoint], // OPRF blinded inputs
+    v: &PrecompressedPoint,    // OPRF public key
+    w: &[&PrecompressedPoint], // OPRF blinded outputs
+    proof: &Proof,
+) -> Result<(), OprfError> {
+    if u.len() != w.len() {
+        return Err(OprfError::BatchSizeMismatch);
+    }
+    let (u, w) = composites(u, &v.compressed, w);
+    verify_proof(&u, v, &w, proof)
+}
+
+fn composites(
+    u: &[&PrecompressedPoint],
+    v: &CompressedPoint,
+    w: &[&PrecompressedPoint],
+) -> (PrecompressedPoint, PrecompressedPoint) {
+    assert_eq!(u.len(), w.len());
+    let mut seed = Sha512::new()
+        .chain_update(b"Juicebox_DLEQ_Batch_2023_1;")
+        .chain_update(v.as_bytes())
+        .chain_update(to_be4(u.len()));
+    for (u, w) in u.iter().zip(w) {
+        seed.update(u.compressed.as_bytes());
+        seed.update(w.compressed.as_bytes());
     }
+    let seed = seed.finalize();
+
+    let d: Vec<Scalar> = (0..u.len())
+        .map(|i| Scalar::from_hash(Sha512::new().chain_update(seed).chain_update(to_be4(i))))
+        .collect();
+    (
+        PrecompressedPoint::from(Point::multiscalar_mul(&d, u.iter().map(|u| u.uncompressed))),
+        PrecompressedPoint::from(Point::multiscalar_mul(&d, w.iter().map(|w| w.uncompressed))),
+    )
 }
 
 fn hash_to_challenge(
@@ -105,19 +215,46 @@ fn hash_to_challenge(
     v_t: &CompressedPoint,
     w_t: &CompressedPoint,
 ) -> Scalar {
-    Scalar::from_hash(
-        Sha512::new()
-            // These values are all constant-size, so we don't need to include
-            // their lengths.
-            .chain_update(b"Juicebox_DLEQ_2023_1;")
-            // `g` is omitted since it's a well-known constant
-            // (`curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT`).
-            .chain_update(u.as_bytes())
-            .chain_update(v.as_bytes())
-            .chain_update(w.as_bytes())
-            .chain_update(v_t.as_bytes())
-            .chain_update(w_t.as_bytes()),
-    )
+    let mut hash = Sha512::new();
+    for part in challenge_transcript_parts(u, v, w, v_t, w_t) {
+        hash.update(part);
+    }
+    Scalar::from_hash(hash)
+}
+
+/// Returns the byte strings that [`hash_to_challenge`] hashes, in order.
+fn challenge_transcript_parts<'a>(
+    u: &'a CompressedPoint,
+    v: &'a CompressedPoint,
+    w: &'a CompressedPoint,
+    v_t: &'a CompressedPoint,
+    w_t: &'a CompressedPoint,
+) -> [&'a [u8]; 6] {
+    [
+        // These values are all constant-size, so we don't need to include
+        // their lengths.
+        b"Juicebox_DLEQ_2023_1;",
+        // `g` is omitted since it's a well-known constant
+        // (`curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT`).
+        u.as_bytes(),
+        v.as_bytes(),
+        w.as_bytes(),
+        v_t.as_bytes(),
+        w_t.as_bytes(),
+    ]
+}
+
+/// Returns the input to the challenge hash as [`verify_proof`] computes it.
+/// For a valid proof, this is also what [`generate_proof`] hashed.
+#[cfg(any(test, feature = "testing"))]
+pub(crate) fn challenge_transcript(
+    u: &PrecompressedPoint,
+    v: &PrecompressedPoint,
+    w: &PrecompressedPoint,
+    proof: &Proof,
+) -> Vec<u8> {
+    let (v_t, w_t) = verifier_commitments(u, v, w, proof);
+    challenge_transcript_parts(&u.compressed, &v.compressed, &w.compressed, &v_t, &w_t).concat()
 }
 
 #[cfg(test)]
@@ -202,4 +339,29 @@ mod tests {
         assert_eq!(proof.c, unserialized.c,);
         assert_eq!(proof.beta_z, unserialized.beta_z);
     }
+
+    #[test]
+    fn test_proof_bytes() {
+        let proof = Proof {
+            c: Scalar::from(1u8),
+            beta_z: -Scalar::ONE,
+        };
+        let bytes = proof.to_bytes();
+        assert_eq!(&bytes[..32], Scalar::from(1u8).as_bytes());
+        assert_eq!(&bytes[32..], (-Scalar::ONE).as_bytes());
+        assert_eq!(Proof::from_bytes(&bytes).unwrap(), proof);
+
+        let mut bad = bytes;
+        bad[..32].copy_from_slice(&[0xff; 32]);
+        assert_eq!(
+            Proof::from_bytes(&bad).unwrap_err(),
+            OprfError::NonCanonicalScalar
+        );
+        let mut bad = bytes;
+        bad[32..].copy_from_slice(&[0xff; 32]);
+        assert_eq!(
+            Proof::from_bytes(&bad).unwrap_err(),
+            OprfError::NonCanonicalScalar
+        );
+    }
 }
@@ -0,0 +1,48 @@
+use core::fmt;
+
+/// Error type for the fallible OPRF operations.
+///
+/// The variants separate malformed input, such as bytes that do not encode a
+/// point, from a server that produced an incorrect result.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum OprfError {
+    /// The bytes are not the canonical encoding of a Ristretto point.
+    NonCanonicalPoint,
+    /// The bytes are not the canonical encoding of a scalar.
+    NonCanonicalScalar,
+    /// A blinding factor was zero.
+    ZeroBlindingFactor,
+    /// A private key was zero.
+    ZeroPrivateKey,
+    /// The public key does not decompress to a valid point.
+    InvalidPublicKey,
+    /// The point is the identity, which is never a valid key.
+    IdentityPoint,
+    /// The proof does not show that the server computed the output with the
+    /// private key matching the public key.
+    ProofVerificationFailed,
+    /// A batch had different numbers of inputs and outputs.
+    BatchSizeMismatch,
+    /// The server's public key is not the one the caller expected.
+    UnexpectedPublicKey,
+}
+
+impl fmt::Display for OprfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NonCanonicalPoint => "decompression failed: not canonical point encoding",
+            Self::NonCanonicalScalar => "not canonical scalar encoding",
+            Self::ZeroBlindingFactor => "blinding factor must be nonzero",
+            Self::ZeroPrivateKey => "private key must be nonzero",
+            Self::InvalidPublicKey => "invalid public key",
+            Self::IdentityPoint => "identity public key",
+            Self::ProofVerificationFailed => "invalid proof",
+            Self::BatchSizeMismatch => "batch size mismatch",
+            Self::UnexpectedPublicKey => "unexpected public key",
+        })
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl std::error::Error for OprfError {}
@@ -1,22 +1,34 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 #![doc = include_str!("../README.md")]
 
+extern crate alloc;
+
+use alloc::vec::Vec;
 use core::fmt;
 use curve25519_dalek::ristretto::{
     CompressedRistretto as CompressedPoint, RistrettoPoint as Point,
 };
+use curve25519_dalek::traits::Identity;
 use curve25519_dalek::Scalar;
-use digest::Digest;
-use juicebox_marshalling::bytes;
+use digest::{consts::U64, Digest};
+use juicebox_marshalling::{bytes, to_be4};
 use rand_core::CryptoRngCore;
 use serde::{Deserialize, Serialize};
 use sha2::Sha512;
-use subtle::ConstantTimeEq;
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
 use zeroize::ZeroizeOnDrop;
 
 mod dleq;
+mod error;
+#[cfg(feature = "otel")]
+pub mod otel;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub use dleq::Proof;
+pub use error::OprfError;
 
 /// A Ristretto [`Point`] in both uncompressed and compressed forms.
 ///
@@ -47,7 +59,7 @@ impl From<Point> for PrecompressedPoint {
 }
 
 impl TryFrom<CompressedPoint> for PrecompressedPoint {
-    type Error = &'static str;
+    type Error = OprfError;
 
     fn try_from(compressed: CompressedPoint) -> Result<Self, Self::Error> {
         match compressed.decompress() {
@@ -55,7 +67,7 @@ impl TryFrom<CompressedPoint> for PrecompressedPoint {
                 uncompressed,
                 compressed,
             }),
-            None => Err("decompression failed: not canonical point encoding"),
+            None => Err(OprfError::NonCanonicalPoint),
         }
     }
 }
@@ -92,6 +104,36 @@ impl fmt::Debug for BlindedInput {
     }
 }
 
+impl BlindedInput {
+    /// Parses a blinded input from its 32-byte compressed encoding, as a
+    /// server would receive it, and rejects the identity point.
+    ///
+    /// An honest client's blinded input is never the identity, because the
+    /// blinding factor is nonzero and inputs hash to non-identity points. A
+    /// client that sends the identity learns nothing from the evaluation, but
+    /// it still consumes server work and could distort accounting, so servers
+    /// should use this to reject it. Ristretto has no small subgroup, so the
+    /// identity is the only degenerate point to check for.
+    ///
+    /// Deserializing a `BlindedInput` with serde only checks that the
+    /// encoding is canonical. That permissive path stays available for
+    /// low-level JKKX17 usage.
+    pub fn from_compressed_checked(bytes: &[u8; 32]) -> Result<Self, OprfError> {
+        let compressed = CompressedPoint(*bytes);
+        let point = PrecompressedPoint::try_from(compressed)?;
+        if compressed == CompressedPoint::identity() {
+            return Err(OprfError::IdentityPoint);
+        }
+        Ok(Self { point })
+    }
+
+    /// Returns the 32-byte compressed encoding of the point, without the CBOR
+    /// framing that serde adds.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        self.point.compressed.as_bytes()
+    }
+}
+
 /// The server's result.
 #[derive(Clone, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(transparent)]
@@ -110,6 +152,12 @@ impl BlindedOutput {
     pub fn to_point(self) -> Point {
         self.point.uncompressed
     }
+
+    /// Returns the 32-byte compressed encoding of the point, without the CBOR
+    /// framing that serde adds.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        self.point.compressed.as_bytes()
+    }
 }
 
 impl From<Point> for BlindedOutput {
@@ -135,9 +183,15 @@ impl fmt::Debug for Output {
     }
 }
 
+impl ConstantTimeEq for Output {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
 impl PartialEq for Output {
     fn eq(&self, other: &Self) -> bool {
-        bool::from(self.0.ct_eq(&other.0))
+        bool::from(self.ct_eq(other))
     }
 }
 
@@ -145,6 +199,28 @@ impl Output {
     pub fn expose_secret(&self) -> &[u8; 64] {
         &self.0
     }
+
+    /// Returns the first `N` bytes of the output, for deriving keys shorter
+    /// than 64 bytes.
+    ///
+    /// The output is a SHA-512 hash, so any prefix of it is as uniformly
+    /// random as the whole, and truncating it is safe. Using more than
+    /// `N = 64` bytes fails to compile.
+    ///
+    /// Unlike the output itself, the returned array is not zeroized when
+    /// dropped.
+    pub fn expose_secret_truncated<const N: usize>(&self) -> [u8; N] {
+        #[allow(clippy::let_unit_value)]
+        let () = AssertAtMost64::<N>::OK;
+        self.0[..N].try_into().unwrap()
+    }
+}
+
+/// Fails to compile [`Output::expose_secret_truncated`] for `N > 64`.
+struct AssertAtMost64<const N: usize>;
+
+impl<const N: usize> AssertAtMost64<N> {
+    const OK: () = assert!(N <= 64, "Output has only 64 bytes");
 }
 
 /// The key used by the server to compute its result.
@@ -175,6 +251,28 @@ impl PrivateKey {
         }
     }
 
+    /// Parses a private key from its canonical 32-byte scalar encoding, such
+    /// as a key exported from an HSM.
+    ///
+    /// Unlike `From<Scalar>` with [`Scalar::from_bytes_mod_order`], this
+    /// rejects encodings that aren't reduced rather than silently reducing
+    /// them. It also rejects zero, whose public key would be the identity.
+    /// The checks run in constant time; only whether the key is valid can
+    /// be observed.
+    pub fn from_bytes(bytes: &[u8; 32]) -> Result<Self, OprfError> {
+        let scalar = Scalar::from_canonical_bytes(*bytes);
+        let is_canonical = scalar.is_some();
+        let scalar = scalar.unwrap_or(Scalar::ZERO);
+        let is_zero = scalar.ct_eq(&Scalar::ZERO);
+        if !bool::from(is_canonical) {
+            return Err(OprfError::NonCanonicalScalar);
+        }
+        if bool::from(is_zero) {
+            return Err(OprfError::ZeroPrivateKey);
+        }
+        Ok(Self { scalar })
+    }
+
     /// Low-level interface exposed for JKKX17 usage.
     pub fn expose_secret(&self) -> &Scalar {
         &self.scalar
@@ -223,37 +321,242 @@ impl fmt::Debug for PublicKey {
 }
 
 impl PublicKey {
+    /// Constructs a public key from the 32 bytes returned by
+    /// [`PublicKey::as_bytes`].
+    ///
+    /// Returns an error if the bytes are not a canonical encoding of a
+    /// Ristretto point, or if they encode the identity point.
+    pub fn from_bytes(bytes: &[u8; 32]) -> Result<Self, OprfError> {
+        let point = CompressedPoint(*bytes);
+        if point.decompress().is_none() {
+            return Err(OprfError::InvalidPublicKey);
+        }
+        if point == CompressedPoint::identity() {
+            return Err(OprfError::IdentityPoint);
+        }
+        Ok(Self { point })
+    }
+
     pub fn as_bytes(&self) -> &[u8; 32] {
         self.point.as_bytes()
     }
 }
 
+/// The domain separator prepended to the input when hashing it to a
+/// Ristretto point.
+///
+/// This is empty, so the input point is
+/// `RistrettoPoint::hash_from_bytes::<Sha512>(input)`. It is fixed for
+/// production use. The `test_domain_override` feature exposes variants of
+/// [`start`] and [`unoblivious_evaluate`] that take a different domain, to
+/// validate test vectors from other implementations.
+pub const HASH_TO_GROUP_DOMAIN: &[u8] = b"";
+
+/// A hash function that the OPRF can be instantiated with, in place of the
+/// default SHA-512.
+///
+/// The hash is used to map inputs to points and to derive outputs. The DLEQ
+/// proofs always use SHA-512, independent of this choice.
+pub trait OprfHash: Digest<OutputSize = U64> + Default {
+    /// Identifies the hash function in the output's domain separator, so
+    /// that outputs computed with different hashes are independent. This
+    /// must be unique among the hashes a deployment uses.
+    ///
+    /// SHA-512 uses the empty identifier, which gives the same outputs as
+    /// [`start`], [`finalize`], and [`unoblivious_evaluate`].
+    const ID: &'static [u8];
+}
+
+impl OprfHash for Sha512 {
+    const ID: &'static [u8] = b"";
+}
+
+fn hash_to_group<H: OprfHash>(domain: &[u8], input: &[u8]) -> Point {
+    Point::from_hash(H::new().chain_update(domain).chain_update(input))
+}
+
 /// Evaluates an OPRF locally, directly using the private key and the input.
 ///
 /// This gives the same result as a full client-server OPRF interaction, but
 /// it is much cheaper computationally.
 pub fn unoblivious_evaluate(private_key: &PrivateKey, input: &[u8]) -> Output {
-    let input_point = Point::hash_from_bytes::<Sha512>(input);
+    unoblivious_evaluate_with_hash::<Sha512>(private_key, input)
+}
+
+/// Like [`unoblivious_evaluate`], but with the hash function `H` instead of
+/// SHA-512.
+pub fn unoblivious_evaluate_with_hash<H: OprfHash>(
+    private_key: &PrivateKey,
+    input: &[u8],
+) -> Output {
+    unoblivious_evaluate_in_domain::<H>(private_key, HASH_TO_GROUP_DOMAIN, input)
+}
+
+/// Like [`unoblivious_evaluate`], but hashes the input to a point using
+/// `domain` instead of [`HASH_TO_GROUP_DOMAIN`].
+///
+/// This is only for validating test vectors and must not be used in
+/// production.
+#[cfg(any(test, feature = "test_domain_override"))]
+pub fn unoblivious_evaluate_with_domain(
+    private_key: &PrivateKey,
+    domain: &[u8],
+    input: &[u8],
+) -> Output {
+    unoblivious_evaluate_in_domain::<Sha512>(private_key, domain, input)
+}
+
+fn unoblivious_evaluate_in_domain<H: OprfHash>(
+    private_key: &PrivateKey,
+    domain: &[u8],
+    input: &[u8],
+) -> Output {
+    let input_point = hash_to_group::<H>(domain, input);
+    let result = private_key.scalar * input_point;
+    hash_to_output::<H>(DEFAULT_OUTPUT_DOMAIN, input, &result)
+}
+
+/// The domain separator at the start of the hash that derives the OPRF's
+/// output, used by the public Juicebox network.
+///
+/// A separate deployment can use its own domain with
+/// [`finalize_with_output_domain`] and
+/// [`unoblivious_evaluate_with_output_domain`], so that its outputs are
+/// independent of the public network's even for the same key and input.
+pub const DEFAULT_OUTPUT_DOMAIN: &str = "Juicebox_OPRF_2023_1;";
+
+/// Like [`unoblivious_evaluate`], but derives the output under `domain`
+/// instead of [`DEFAULT_OUTPUT_DOMAIN`].
+///
+/// Everything that computes outputs for a key, including any server that
+/// evaluates unobliviously and every client that calls
+/// [`finalize_with_output_domain`], must use the same domain. A mismatch
+/// isn't detected: it silently gives a different output.
+pub fn unoblivious_evaluate_with_output_domain(
+    private_key: &PrivateKey,
+    domain: &'static str,
+    input: &[u8],
+) -> Output {
+    let input_point = hash_to_group::<Sha512>(HASH_TO_GROUP_DOMAIN, input);
     let result = private_key.scalar * input_point;
-    hash_to_output(input, &result)
+    hash_to_output::<Sha512>(domain, input, &result)
+}
+
+fn hash_to_output<H: OprfHash>(domain: &str, input: &[u8], result: &Point) -> Output {
+    finish_output(output_hasher::<H>(domain, input), result)
+}
+
+/// Returns a hasher that has absorbed everything in the output hash that
+/// precedes the unblinded result.
+fn output_hasher<H: OprfHash>(domain: &str, input: &[u8]) -> H {
+    let mut hasher = H::new();
+    // Other domains are length-prefixed so that none is a prefix of
+    // another. The default is not, which keeps its outputs unchanged.
+    if domain != DEFAULT_OUTPUT_DOMAIN {
+        hasher.update(to_be4(domain.len()));
+    }
+    hasher.update(domain);
+    // The SHA-512 identifier is empty, which keeps its outputs unchanged
+    // from before the hash was configurable.
+    if !H::ID.is_empty() {
+        hasher.update(to_be4(H::ID.len()));
+        hasher.update(H::ID);
+    }
+    // JKK14 includes the public key in the hash. This does not do so,
+    // because there is no obvious single public key in JKKX17.
+    //
+    // The input is the only variable-length field in this hash,
+    // so its length is omitted.
+    hasher.chain_update(input)
 }
 
-fn hash_to_output(input: &[u8], result: &Point) -> Output {
+fn finish_output<H: OprfHash>(hasher: H, result: &Point) -> Output {
     Output(
-        Sha512::new()
-            .chain_update("Juicebox_OPRF_2023_1;")
-            // JKK14 includes the public key in the hash. This does not do so,
-            // because there is no obvious single public key in JKKX17.
-            //
-            // The input is the only variable-length field in this hash,
-            // so its length is omitted.
-            .chain_update(input)
+        hasher
             .chain_update(result.compress().as_bytes())
             .finalize()
             .into(),
     )
 }
 
+/// An OPRF input hashed once for both [`start_prehashed`] and
+/// [`finalize_prehashed`], which avoids hashing a large input again when
+/// finalizing.
+///
+/// This holds the input's point and a SHA-512 state that has absorbed the
+/// input, so it must be kept as secret as the input itself.
+///
+/// There's no way to build one from a plain SHA-512 digest of the input.
+/// Since [`HASH_TO_GROUP_DOMAIN`] is empty, the point could be computed from
+/// that digest, but the output hash absorbs [`DEFAULT_OUTPUT_DOMAIN`] before
+/// the input, so its state can't be. Use [`InputHasher`] to hash an input
+/// that is too large to hold in memory.
+#[derive(Clone)]
+pub struct InputHash {
+    point: Point,
+    output_hasher: Sha512,
+}
+
+impl InputHash {
+    pub fn new(input: &[u8]) -> Self {
+        let mut hasher = InputHasher::new();
+        hasher.update(input);
+        hasher.finalize()
+    }
+}
+
+impl fmt::Debug for InputHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("InputHash(REDACTED)")
+    }
+}
+
+/// Computes an [`InputHash`] from an input given in pieces, such as one
+/// streamed from a file.
+///
+/// Feeding the input's bytes to [`InputHasher::update`], in order and split
+/// up in any way, gives the same [`InputHash`] as [`InputHash::new`] with
+/// the whole input.
+#[derive(Clone)]
+pub struct InputHasher {
+    point_hasher: Sha512,
+    output_hasher: Sha512,
+}
+
+impl InputHasher {
+    pub fn new() -> Self {
+        Self {
+            point_hasher: Sha512::new().chain_update(HASH_TO_GROUP_DOMAIN),
+            output_hasher: output_hasher::<Sha512>(DEFAULT_OUTPUT_DOMAIN, &[]),
+        }
+    }
+
+    /// Absorbs the next piece of the input.
+    pub fn update(&mut self, input: &[u8]) {
+        self.point_hasher.update(input);
+        self.output_hasher.update(input);
+    }
+
+    pub fn finalize(self) -> InputHash {
+        InputHash {
+            point: Point::from_hash(self.point_hasher),
+            output_hasher: self.output_hasher,
+        }
+    }
+}
+
+impl Default for InputHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for InputHasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("InputHasher(REDACTED)")
+    }
+}
+
 /// A random values produced by [`start`] that is needed to complete the OPRF
 /// on the client.
 #[derive(ZeroizeOnDrop)]
@@ -267,23 +570,109 @@ impl fmt::Debug for BlindingFactor {
     }
 }
 
+impl BlindingFactor {
+    /// Returns the canonical 32-byte encoding of the blinding factor.
+    ///
+    /// This allows a client to persist the blinding factor, for example to
+    /// resume an OPRF after a restart. The bytes must be kept secret.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.scalar.to_bytes()
+    }
+
+    /// Parses a blinding factor previously produced by
+    /// [`BlindingFactor::to_bytes`].
+    ///
+    /// Returns an error if the bytes are not a canonical scalar encoding or
+    /// encode zero, which can never be a valid blinding factor.
+    pub fn from_bytes(bytes: &[u8; 32]) -> Result<Self, OprfError> {
+        let scalar = Option::<Scalar>::from(Scalar::from_canonical_bytes(*bytes))
+            .ok_or(OprfError::NonCanonicalScalar)?;
+        if scalar == Scalar::ZERO {
+            return Err(OprfError::ZeroBlindingFactor);
+        }
+        Ok(Self { scalar })
+    }
+}
+
 /// Starts the OPRF protocol on the client.
 ///
 /// The client should send the returned [`BlindedInput`] to the server and
 /// should keep the returned [`BlindingFactor`] secret. The blinding factor
 /// must be provided to [`finalize`] later to complete the OPRF.
 pub fn start(input: &[u8], rng: &mut impl CryptoRngCore) -> (BlindingFactor, BlindedInput) {
-    let input_point = Point::hash_from_bytes::<Sha512>(input);
-    let blinding_factor = Scalar::random(rng);
-    let blinded_input = BlindedInput {
-        point: PrecompressedPoint::from(input_point * blinding_factor),
+    start_with_hash::<Sha512>(input, rng)
+}
+
+/// Like [`start`], but with the hash function `H` instead of SHA-512. The
+/// OPRF must be completed with [`finalize_with_hash`] using the same hash.
+pub fn start_with_hash<H: OprfHash>(
+    input: &[u8],
+    rng: &mut impl CryptoRngCore,
+) -> (BlindingFactor, BlindedInput) {
+    start_in_domain::<H>(HASH_TO_GROUP_DOMAIN, input, rng)
+}
+
+/// Like [`start`], but hashes the input to a point using `domain` instead of
+/// [`HASH_TO_GROUP_DOMAIN`].
+///
+/// This is only for validating test vectors and must not be used in
+/// production.
+#[cfg(any(test, feature = "test_domain_override"))]
+pub fn start_with_domain(
+    domain: &[u8],
+    input: &[u8],
+    rng: &mut impl CryptoRngCore,
+) -> (BlindingFactor, BlindedInput) {
+    start_in_domain::<Sha512>(domain, input, rng)
+}
+
+/// Like [`start`], but blinds the input with the given `blinding_factor`
+/// instead of a random one.
+///
+/// This is meant for generating and checking test vectors. Outside of tests,
+/// the blinding factor must be random and used only once, as [`start`]
+/// ensures.
+pub fn start_with_blind(input: &[u8], blinding_factor: &BlindingFactor) -> BlindedInput {
+    blind_in_domain::<Sha512>(HASH_TO_GROUP_DOMAIN, input, blinding_factor)
+}
+
+fn start_in_domain<H: OprfHash>(
+    domain: &[u8],
+    input: &[u8],
+    rng: &mut impl CryptoRngCore,
+) -> (BlindingFactor, BlindedInput) {
+    let blinding_factor = BlindingFactor {
+        scalar: Scalar::random(rng),
     };
-    (
-        BlindingFactor {
-            scalar: blinding_factor,
-        },
-        blinded_input,
-    )
+    let blinded_input = blind_in_domain::<H>(domain, input, &blinding_factor);
+    (blinding_factor, blinded_input)
+}
+
+fn blind_in_domain<H: OprfHash>(
+    domain: &[u8],
+    input: &[u8],
+    blinding_factor: &BlindingFactor,
+) -> BlindedInput {
+    blind(&hash_to_group::<H>(domain, input), blinding_factor)
+}
+
+fn blind(input_point: &Point, blinding_factor: &BlindingFactor) -> BlindedInput {
+    BlindedInput {
+        point: PrecompressedPoint::from(input_point * blinding_factor.scalar),
+    }
+}
+
+/// Like [`start`], but with an input that was already hashed into an
+/// [`InputHash`]. Complete the OPRF with [`finalize_prehashed`].
+pub fn start_prehashed(
+    input_hash: &InputHash,
+    rng: &mut impl CryptoRngCore,
+) -> (BlindingFactor, BlindedInput) {
+    let blinding_factor = BlindingFactor {
+        scalar: Scalar::random(rng),
+    };
+    let blinded_input = blind(&input_hash.point, &blinding_factor);
+    (blinding_factor, blinded_input)
 }
 
 /// Completes the OPRF protocol on the client.
@@ -298,9 +687,48 @@ pub fn finalize(
     input: &[u8],
     blinding_factor: &BlindingFactor,
     blinded_output: &BlindedOutput,
+) -> Output {
+    finalize_with_hash::<Sha512>(input, blinding_factor, blinded_output)
+}
+
+/// Like [`finalize`], but with the hash function `H` instead of SHA-512. The
+/// OPRF must have been started with [`start_with_hash`] using the same hash.
+pub fn finalize_with_hash<H: OprfHash>(
+    input: &[u8],
+    blinding_factor: &BlindingFactor,
+    blinded_output: &BlindedOutput,
+) -> Output {
+    let result = blinded_output.point.uncompressed * blinding_factor.scalar.invert();
+    hash_to_output::<H>(DEFAULT_OUTPUT_DOMAIN, input, &result)
+}
+
+/// Like [`finalize`], but derives the output under `domain` instead of
+/// [`DEFAULT_OUTPUT_DOMAIN`]. Start the OPRF with [`start`] as usual, since
+/// the domain only affects the output.
+///
+/// The domain must match the one used by anything else that computes
+/// outputs for the same key, such as
+/// [`unoblivious_evaluate_with_output_domain`]. A mismatch isn't detected:
+/// it silently gives a different output.
+pub fn finalize_with_output_domain(
+    domain: &'static str,
+    input: &[u8],
+    blinding_factor: &BlindingFactor,
+    blinded_output: &BlindedOutput,
+) -> Output {
+    let result = blinded_output.point.uncompressed * blinding_factor.scalar.invert();
+    hash_to_output::<Sha512>(domain, input, &result)
+}
+
+/// Like [`finalize`], but with the [`InputHash`] given to
+/// [`start_prehashed`], so the input is not hashed again.
+pub fn finalize_prehashed(
+    input_hash: &InputHash,
+    blinding_factor: &BlindingFactor,
+    blinded_output: &BlindedOutput,
 ) -> Output {
     let result = blinded_output.point.uncompressed * blinding_factor.scalar.invert();
-    hash_to_output(input, &result)
+    finish_output(input_hash.output_hasher.clone(), &result)
 }
 
 /// The client should call this to ensure that the server did the correct
@@ -316,15 +744,225 @@ pub fn verify_proof(
     blinded_output: &BlindedOutput,
     public_key: &PublicKey,
     proof: &Proof,
-) -> Result<(), &'static str> {
+) -> Result<(), OprfError> {
+    let result = PrecompressedPoint::try_from(public_key.point)
+        .map_err(|_| OprfError::InvalidPublicKey)
+        .and_then(|public_key| {
+            dleq::verify_proof(
+                &blinded_input.point,
+                &public_key,
+                &blinded_output.point,
+                proof,
+            )
+        });
+    #[cfg(feature = "otel")]
+    otel::record_proof_verification(&result);
+    result
+}
+
+/// Verifies several proofs under one public key, reporting the validity of
+/// each `(blinded_input, blinded_output, proof)` item in order.
+///
+/// Unlike calling [`verify_proof`] in a loop, this decompresses the public
+/// key once, and the result identifies which items failed, such as to
+/// exclude a misbehaving server. Every item is fully verified, without
+/// stopping at the first failure. The only error is an invalid public key.
+pub fn verify_proofs(
+    items: &[(BlindedInput, BlindedOutput, Proof)],
+    public_key: &PublicKey,
+) -> Result<Vec<bool>, OprfError> {
     let public_key =
-        PrecompressedPoint::try_from(public_key.point).map_err(|_| "invalid public key")?;
-    dleq::verify_proof(
-        &blinded_input.point,
-        &public_key,
-        &blinded_output.point,
-        proof,
-    )
+        PrecompressedPoint::try_from(public_key.point).map_err(|_| OprfError::InvalidPublicKey)?;
+    Ok(items
+        .iter()
+        .map(|(blinded_input, blinded_output, proof)| {
+            let result = dleq::verify_proof(
+                &blinded_input.point,
+                &public_key,
+                &blinded_output.point,
+                proof,
+            );
+            #[cfg(feature = "otel")]
+            otel::record_proof_verification(&result);
+            result.is_ok()
+        })
+        .collect())
+}
+
+/// Verifies the server's proof like [`verify_proof`], and also checks in
+/// constant time that the server's `public_key` equals the
+/// `expected_public_key` that the caller trusts.
+///
+/// This bundles both checks that a client must perform before using the
+/// output.
+pub fn verify_proof_for_key(
+    blinded_input: &BlindedInput,
+    blinded_output: &BlindedOutput,
+    public_key: &PublicKey,
+    expected_public_key: &PublicKey,
+    proof: &Proof,
+) -> Result<(), OprfError> {
+    let key_matches = public_key.as_bytes().ct_eq(expected_public_key.as_bytes());
+    verify_proof(blinded_input, blinded_output, public_key, proof)?;
+    if bool::from(key_matches) {
+        Ok(())
+    } else {
+        Err(OprfError::UnexpectedPublicKey)
+    }
+}
+
+/// Verifies the server's proof against each of several candidate public
+/// keys, such as the old and new keys while a server rotates its key,
+/// returning the index of the key the proof is valid for.
+///
+/// Every candidate is checked, and the result is selected in constant time,
+/// so the timing doesn't reveal which key matched. Each candidate costs about
+/// as much as a call to [`verify_proof`], so the set should be small.
+///
+/// Returns [`OprfError::InvalidPublicKey`] if any candidate doesn't
+/// decompress, before checking the proof against any of them, and
+/// [`OprfError::ProofVerificationFailed`] if no candidate matches. If the
+/// same key appears more than once, the last index is returned.
+pub fn verify_proof_any(
+    blinded_input: &BlindedInput,
+    blinded_output: &BlindedOutput,
+    public_keys: &[PublicKey],
+    proof: &Proof,
+) -> Result<usize, OprfError> {
+    let public_keys = public_keys
+        .iter()
+        .map(|public_key| {
+            PrecompressedPoint::try_from(public_key.point).map_err(|_| OprfError::InvalidPublicKey)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut matched = Choice::from(0);
+    let mut index = 0u64;
+    for (i, public_key) in public_keys.iter().enumerate() {
+        let matches = dleq::proof_matches(
+            &blinded_input.point,
+            public_key,
+            &blinded_output.point,
+            proof,
+        );
+        index.conditional_assign(&(i as u64), matches);
+        matched |= matches;
+    }
+
+    let result = if bool::from(matched) {
+        Ok(())
+    } else {
+        Err(OprfError::ProofVerificationFailed)
+    };
+    #[cfg(feature = "otel")]
+    otel::record_proof_verification(&result);
+    result.map(|()| index as usize)
+}
+
+/// Runs the OPRF for several inputs at once on the client.
+///
+/// This calls [`start`] for each input, passes all of the blinded inputs to
+/// `evaluate` in one batch, checks the returned batch proof against
+/// `public_key` with [`verify_batch_proof`], and finalizes each output. The
+/// `evaluate` function should send the blinded inputs to the server, which
+/// can answer with [`blind_verifiable_evaluate_many`].
+///
+/// The outputs are in the same order as the inputs.
+pub fn evaluate_many<E>(
+    inputs: &[&[u8]],
+    public_key: &PublicKey,
+    rng: &mut impl CryptoRngCore,
+    evaluate: impl FnOnce(&[BlindedInput]) -> Result<(Vec<BlindedOutput>, Proof), E>,
+) -> Result<Vec<Output>, E>
+where
+    E: From<OprfError>,
+{
+    let (blinding_factors, blinded_inputs): (Vec<BlindingFactor>, Vec<BlindedInput>) =
+        inputs.iter().map(|input| start(input, rng)).unzip();
+    let (blinded_outputs, proof) = evaluate(&blinded_inputs)?;
+    verify_batch_proof(&blinded_inputs, &blinded_outputs, public_key, &proof)?;
+    Ok(inputs
+        .iter()
+        .zip(&blinding_factors)
+        .zip(&blinded_outputs)
+        .map(|((input, blinding_factor), blinded_output)| {
+            finalize(input, blinding_factor, blinded_output)
+        })
+        .collect())
+}
+
+/// The client should call this to ensure that the server did the correct
+/// computation for a batch of inputs, using a proof from
+/// [`blind_verifiable_evaluate_many`].
+///
+/// Like [`verify_proof`], this can only ensure the public key is consistent
+/// with the proof. The caller must somehow ensure the public key is
+/// acceptable.
+///
+/// The combining coefficients are derived by hashing the public key and
+/// every input and output, so no interaction is needed. Verification uses
+/// constant-time scalar multiplication and compares the challenge in
+/// constant time. Its running time depends only on the batch size, which is
+/// public. Whether verification succeeds is not secret, so an invalid public
+/// key or a mismatch between the numbers of inputs and outputs returns early.
+pub fn verify_batch_proof(
+    blinded_inputs: &[BlindedInput],
+    blinded_outputs: &[BlindedOutput],
+    public_key: &PublicKey,
+    proof: &Proof,
+) -> Result<(), OprfError> {
+    let result = PrecompressedPoint::try_from(public_key.point)
+        .map_err(|_| OprfError::InvalidPublicKey)
+        .and_then(|public_key| {
+            dleq::verify_batch_proof(
+                &blinded_inputs.iter().map(|i| &i.point).collect::<Vec<_>>(),
+                &public_key,
+                &blinded_outputs.iter().map(|o| &o.point).collect::<Vec<_>>(),
+                proof,
+            )
+        });
+    #[cfg(feature = "otel")]
+    otel::record_proof_verification(&result);
+    result
+}
+
+/// Like [`verify_batch_proof`], but takes each blinded input alongside its
+/// blinded output.
+///
+/// The result covers the whole batch: a single incorrect output fails
+/// verification no matter how many correct ones surround it, and the error
+/// doesn't say which one was wrong.
+pub fn verify_batched(
+    pairs: &[(BlindedInput, BlindedOutput)],
+    public_key: &PublicKey,
+    proof: &Proof,
+) -> Result<(), OprfError> {
+    let (blinded_inputs, blinded_outputs): (Vec<BlindedInput>, Vec<BlindedOutput>) =
+        pairs.iter().cloned().unzip();
+    verify_batch_proof(&blinded_inputs, &blinded_outputs, public_key, proof)
+}
+
+/// Runs the verifiable OPRF evaluation on the server for a batch of blinded
+/// inputs, producing one output per input and a single proof covering all of
+/// them.
+pub fn blind_verifiable_evaluate_many(
+    private_key: &PrivateKey,
+    public_key: &PublicKey,
+    blinded_inputs: &[BlindedInput],
+    rng: &mut impl CryptoRngCore,
+) -> (Vec<BlindedOutput>, Proof) {
+    let blinded_outputs: Vec<BlindedOutput> = blinded_inputs
+        .iter()
+        .map(|blinded_input| blind_evaluate(private_key, blinded_input))
+        .collect();
+    let proof = dleq::generate_batch_proof(
+        rng,
+        &private_key.scalar,
+        &blinded_inputs.iter().map(|i| &i.point).collect::<Vec<_>>(),
+        &public_key.point,
+        &blinded_outputs.iter().map(|o| &o.point).collect::<Vec<_>>(),
+    );
+    (blinded_outputs, proof)
 }
 
 /// Runs the OPRF evaluation on the server.
@@ -332,10 +970,20 @@ pub fn verify_proof(
 /// To allow the client to verify the server's computation, use
 /// [`blind_verifiable_evaluate`] instead (or call [`generate_proof`]
 /// directly).
+///
+/// Skipping the proof saves two scalar-point multiplications, but then the
+/// client can't detect a server that used the wrong key, so this alone is
+/// only appropriate when the client trusts the server. Such a client calls
+/// [`finalize`] without [`verify_proof`].
 pub fn blind_evaluate(private_key: &PrivateKey, blinded_input: &BlindedInput) -> BlindedOutput {
-    BlindedOutput {
+    #[cfg(feature = "otel")]
+    let start = std::time::Instant::now();
+    let blinded_output = BlindedOutput {
         point: PrecompressedPoint::from(private_key.scalar * blinded_input.point.uncompressed),
-    }
+    };
+    #[cfg(feature = "otel")]
+    otel::record_blind_evaluate(start.elapsed());
+    blinded_output
 }
 
 /// Runs the verifiable OPRF evaluation on the server, including the OPRF
@@ -376,77 +1024,181 @@ pub fn generate_proof(
     )
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Derives the scalar that tweaks the private key for the public `info` in
+/// the partially-oblivious OPRF.
+fn info_tweak(info: &[u8]) -> Scalar {
+    Scalar::from_hash(
+        Sha512::new()
+            .chain_update("Juicebox_POPRF_Tweak_2023_1;")
+            .chain_update(to_be4(info.len()))
+            .chain_update(info),
+    )
+}
 
-    use core::num::NonZeroU32;
-    use rand_core::{OsRng, RngCore};
-    use serde::{de::DeserializeOwned, Deserialize, Serialize};
-    use std::collections::VecDeque;
-    use std::io::Write;
-    use std::path::Path;
+/// Returns the inverse of the private key tweaked for `info`.
+//
+// The tweaked key is zero only if `info` hashes to the negated private key,
+// which is negligibly likely without knowing the private key.
+fn tweaked_inverse(private_key: &PrivateKey, info: &[u8]) -> Scalar {
+    (private_key.scalar + info_tweak(info)).invert()
+}
 
-    #[test]
-    fn test_basic() {
-        for _ in 0..10 {
-            let mut input = [0u8; 8];
-            OsRng.fill_bytes(&mut input);
-            let private_key = PrivateKey::random(&mut OsRng);
-            let public_key = private_key.to_public_key();
-            let expected = unoblivious_evaluate(&private_key, &input);
+fn hash_to_output_with_info(input: &[u8], info: &[u8], result: &Point) -> Output {
+    Output(
+        Sha512::new()
+            .chain_update("Juicebox_POPRF_2023_1;")
+            .chain_update(to_be4(info.len()))
+            .chain_update(info)
+            // The input is the only unprefixed variable-length field.
+            .chain_update(input)
+            .chain_update(result.compress().as_bytes())
+            .finalize()
+            .into(),
+    )
+}
 
-            for _ in 0..3 {
-                // unoblivious
-                assert_eq!(expected, unoblivious_evaluate(&private_key, &input),);
+/// Evaluates the partially-oblivious OPRF locally with the public `info`,
+/// like [`unoblivious_evaluate`].
+///
+/// The same private key yields independent functions for different `info`.
+pub fn unoblivious_evaluate_with_info(
+    private_key: &PrivateKey,
+    info: &[u8],
+    input: &[u8],
+) -> Output {
+    let input_point = hash_to_group::<Sha512>(HASH_TO_GROUP_DOMAIN, input);
+    let result = input_point * tweaked_inverse(private_key, info);
+    hash_to_output_with_info(input, info, &result)
+}
 
-                // oblivious
-                let (blinding_factor, blinded_input) = start(&input, &mut OsRng);
-                let (blinded_output, proof) = blind_verifiable_evaluate(
-                    &private_key,
-                    &public_key,
-                    &blinded_input,
-                    &mut OsRng,
-                );
-                assert!(verify_proof(&blinded_input, &blinded_output, &public_key, &proof).is_ok());
-                assert_eq!(
-                    expected,
-                    finalize(&input, &blinding_factor, &blinded_output)
-                );
-            }
-        }
+/// Runs the partially-oblivious OPRF evaluation on the server, binding in the
+/// public `info`.
+///
+/// The client blinds its input with [`start`] as usual and completes the
+/// OPRF with [`finalize_with_info`]. This is the 2HashDH-POPRF construction:
+/// the server evaluates with the inverse of its private key plus a hash of
+/// `info`.
+pub fn blind_evaluate_with_info(
+    private_key: &PrivateKey,
+    info: &[u8],
+    blinded_input: &BlindedInput,
+) -> BlindedOutput {
+    BlindedOutput {
+        point: PrecompressedPoint::from(
+            blinded_input.point.uncompressed * tweaked_inverse(private_key, info),
+        ),
     }
+}
 
-    struct ManualRng {
-        entropy: VecDeque<u8>,
-    }
+/// Runs [`blind_evaluate_with_info`] and generates a proof, which the client
+/// checks with [`verify_proof_with_info`].
+///
+/// The proof is made for the public key tweaked by `info`, so it fails to
+/// verify if the server used a different `info` than the client expects.
+pub fn blind_verifiable_evaluate_with_info(
+    private_key: &PrivateKey,
+    info: &[u8],
+    blinded_input: &BlindedInput,
+    rng: &mut impl CryptoRngCore,
+) -> (BlindedOutput, Proof) {
+    let tweaked_key = private_key.scalar + info_tweak(info);
+    let blinded_output = BlindedOutput {
+        point: PrecompressedPoint::from(blinded_input.point.uncompressed * tweaked_key.invert()),
+    };
+    // This proves that the blinded input is the blinded output times the
+    // tweaked key, which is the discrete log of the tweaked public key.
+    let proof = dleq::generate_proof(
+        rng,
+        &tweaked_key,
+        &blinded_output.point,
+        &Point::mul_base(&tweaked_key).compress(),
+        &blinded_input.point,
+    );
+    (blinded_output, proof)
+}
 
-    impl rand_core::CryptoRng for ManualRng {}
+/// The client should call this to ensure that the server did the correct
+/// partially-oblivious computation for the public `info`, using a proof from
+/// [`blind_verifiable_evaluate_with_info`].
+///
+/// Like [`verify_proof`], the caller must somehow ensure the public key is
+/// acceptable.
+pub fn verify_proof_with_info(
+    blinded_input: &BlindedInput,
+    blinded_output: &BlindedOutput,
+    public_key: &PublicKey,
+    info: &[u8],
+    proof: &Proof,
+) -> Result<(), OprfError> {
+    let result = public_key
+        .point
+        .decompress()
+        .ok_or(OprfError::InvalidPublicKey)
+        .and_then(|public_key| {
+            let tweaked_public_key =
+                PrecompressedPoint::from(public_key + Point::mul_base(&info_tweak(info)));
+            dleq::verify_proof(
+                &blinded_output.point,
+                &tweaked_public_key,
+                &blinded_input.point,
+                proof,
+            )
+        });
+    #[cfg(feature = "otel")]
+    otel::record_proof_verification(&result);
+    result
+}
 
-    impl rand_core::RngCore for ManualRng {
-        fn next_u32(&mut self) -> u32 {
-            rand_core::impls::next_u32_via_fill(self)
-        }
+/// Completes the partially-oblivious OPRF protocol on the client.
+///
+/// The `input` and `blinding_factor` are as for [`finalize`]. The `info` must
+/// be the same as the server used, and the output differs for every `info`.
+pub fn finalize_with_info(
+    input: &[u8],
+    info: &[u8],
+    blinding_factor: &BlindingFactor,
+    blinded_output: &BlindedOutput,
+) -> Output {
+    let result = blinded_output.point.uncompressed * blinding_factor.scalar.invert();
+    hash_to_output_with_info(input, info, &result)
+}
 
-        fn next_u64(&mut self) -> u64 {
-            rand_core::impls::next_u64_via_fill(self)
-        }
+#[cfg(test)]
+mod tests {
+    use super::testing::{dleq_challenge_transcript, generate_vector};
+    use super::*;
 
-        fn fill_bytes(&mut self, dest: &mut [u8]) {
-            self.try_fill_bytes(dest).unwrap()
-        }
+    use rand_core::{OsRng, RngCore};
+    use serde::{de::DeserializeOwned, Deserialize, Serialize};
+    use std::io::Write;
+    use std::path::Path;
 
-        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
-            if self.entropy.len() >= dest.len() {
-                let drained = self.entropy.drain(0..dest.len());
-                for (byte, pointer) in drained.zip(dest) {
-                    *pointer = byte;
-                }
-                Ok(())
-            } else {
-                Err(rand_core::Error::from(
-                    NonZeroU32::new(rand_core::Error::CUSTOM_START).unwrap(),
-                ))
+    #[test]
+    fn test_basic() {
+        for _ in 0..10 {
+            let mut input = [0u8; 8];
+            OsRng.fill_bytes(&mut input);
+            let private_key = PrivateKey::random(&mut OsRng);
+            let public_key = private_key.to_public_key();
+            let expected = unoblivious_evaluate(&private_key, &input);
+
+            for _ in 0..3 {
+                // unoblivious
+                assert_eq!(expected, unoblivious_evaluate(&private_key, &input),);
+
+                // oblivious
+                let (blinding_factor, blinded_input) = start(&input, &mut OsRng);
+                let (blinded_output, proof) = blind_verifiable_evaluate(
+                    &private_key,
+                    &public_key,
+                    &blinded_input,
+                    &mut OsRng,
+                );
+                assert!(verify_proof(&blinded_input, &blinded_output, &public_key, &proof).is_ok());
+                assert_eq!(
+                    expected,
+                    finalize(&input, &blinding_factor, &blinded_output)
+                );
             }
         }
     }
@@ -479,38 +1231,22 @@ mod tests {
     }
 
     fn run_with_inputs(inputs: &TestInputs) -> TestOutputs {
-        let mut rng = ManualRng {
-            entropy: [
-                hex::decode(&inputs.private_key_seed).unwrap(),
-                hex::decode(&inputs.blinding_factor_seed).unwrap(),
-                hex::decode(&inputs.beta_t_seed).unwrap(),
-            ]
-            .into_iter()
-            .flatten()
-            .collect(),
-        };
-        let private_key = PrivateKey::random(&mut rng);
-        let public_key = private_key.to_public_key();
-
-        let input = hex::decode(&inputs.input).unwrap();
-        let (blinding_factor, blinded_input) = start(&input, &mut rng);
-        let (blinded_output, proof) =
-            blind_verifiable_evaluate(&private_key, &public_key, &blinded_input, &mut rng);
-        assert_eq!(rng.entropy.len(), 0);
-        assert!(verify_proof(&blinded_input, &blinded_output, &public_key, &proof).is_ok());
-        let output = finalize(&input, &blinding_factor, &blinded_output);
-
-        assert_eq!(output, unoblivious_evaluate(&private_key, &input));
-
+        let seed = |hex: &str| -> [u8; 64] { hex::decode(hex).unwrap().try_into().unwrap() };
+        let outputs = generate_vector(
+            &hex::decode(&inputs.input).unwrap(),
+            &seed(&inputs.private_key_seed),
+            &seed(&inputs.blinding_factor_seed),
+            &seed(&inputs.beta_t_seed),
+        );
         TestOutputs {
-            private_key: hex::encode(private_key.scalar.as_bytes()),
-            public_key: hex::encode(public_key.point.as_bytes()),
-            blinding_factor: hex::encode(blinding_factor.scalar.as_bytes()),
-            blinded_input: hex::encode(blinded_input.point.compressed.as_bytes()),
-            blinded_output: hex::encode(blinded_output.point.compressed.as_bytes()),
-            proof_c: hex::encode(proof.c.as_bytes()),
-            proof_beta_z: hex::encode(proof.beta_z.as_bytes()),
-            output: hex::encode(output.expose_secret()),
+            private_key: hex::encode(outputs.private_key),
+            public_key: hex::encode(outputs.public_key),
+            blinding_factor: hex::encode(outputs.blinding_factor),
+            blinded_input: hex::encode(outputs.blinded_input),
+            blinded_output: hex::encode(outputs.blinded_output),
+            proof_c: hex::encode(outputs.proof_c),
+            proof_beta_z: hex::encode(outputs.proof_beta_z),
+            output: hex::encode(outputs.output),
         }
     }
 
@@ -538,6 +1274,39 @@ mod tests {
         test_vectors_from_file(Path::new("src/test_vectors.json"));
     }
 
+    #[test]
+    fn test_dleq_challenge_transcript() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let public_key = private_key.to_public_key();
+        let (_, blinded_input) = start(b"artemis", &mut OsRng);
+        let (blinded_output, proof) =
+            blind_verifiable_evaluate(&private_key, &public_key, &blinded_input, &mut OsRng);
+
+        let transcript =
+            dleq_challenge_transcript(&blinded_input, &blinded_output, &public_key, &proof)
+                .unwrap();
+        assert_eq!(transcript.len(), 21 + 5 * 32);
+        assert_eq!(&transcript[..21], b"Juicebox_DLEQ_2023_1;");
+        assert_eq!(
+            &transcript[21..53],
+            blinded_input.point.compressed.as_bytes()
+        );
+        assert_eq!(&transcript[53..85], public_key.as_bytes());
+        assert_eq!(
+            &transcript[85..117],
+            blinded_output.point.compressed.as_bytes()
+        );
+        let challenge = Scalar::from_hash(Sha512::new().chain_update(&transcript));
+        assert_eq!(challenge.as_bytes(), &proof.to_bytes()[..32]);
+
+        // A proof for a different output hashes to a different challenge.
+        let other_output = blind_evaluate(&PrivateKey::random(&mut OsRng), &blinded_input);
+        let transcript =
+            dleq_challenge_transcript(&blinded_input, &other_output, &public_key, &proof).unwrap();
+        let challenge = Scalar::from_hash(Sha512::new().chain_update(&transcript));
+        assert_ne!(challenge.as_bytes(), &proof.to_bytes()[..32]);
+    }
+
     fn generate_test_inputs() -> TestInputs {
         let mut input = vec![0; OsRng.next_u32() as usize & 0x0f];
         OsRng.fill_bytes(&mut input);
@@ -600,6 +1369,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_blinded_point_bytes() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let (_, blinded_input) = start(b"artemis", &mut OsRng);
+        let blinded_output = blind_evaluate(&private_key, &blinded_input);
+
+        let input_bytes = juicebox_marshalling::to_vec(&blinded_input).unwrap();
+        assert_eq!(&input_bytes[2..], blinded_input.as_bytes());
+        assert_eq!(
+            BlindedInput::from_compressed_checked(blinded_input.as_bytes()).unwrap(),
+            blinded_input
+        );
+        let output_bytes = juicebox_marshalling::to_vec(&blinded_output).unwrap();
+        assert_eq!(&output_bytes[2..], blinded_output.as_bytes());
+    }
+
     #[test]
     fn test_blinded_output_serialize() {
         let blinded_output = BlindedOutput {
@@ -634,6 +1419,319 @@ mod tests {
         assert_eq!(public_key.point, public_key2.point);
     }
 
+    #[test]
+    fn test_blinding_factor_bytes() {
+        let input = b"artemis";
+        let private_key = PrivateKey::random(&mut OsRng);
+        let (blinding_factor, blinded_input) = start(input, &mut OsRng);
+        let bytes = blinding_factor.to_bytes();
+        let blinding_factor2 = BlindingFactor::from_bytes(&bytes).unwrap();
+        assert_eq!(blinding_factor.scalar, blinding_factor2.scalar);
+
+        let blinded_output = blind_evaluate(&private_key, &blinded_input);
+        assert_eq!(
+            unoblivious_evaluate(&private_key, input),
+            finalize(input, &blinding_factor2, &blinded_output)
+        );
+    }
+
+    /// Drops `value` in place and checks that the memory that held its scalar
+    /// reads as zero afterwards. `scalar` must project the raw pointer to the
+    /// scalar's field with `addr_of!`, without creating a reference that
+    /// would be invalidated by the drop.
+    fn assert_scalar_zeroized_on_drop<T>(value: T, scalar: impl Fn(*const T) -> *const Scalar) {
+        let mut slot = core::mem::MaybeUninit::new(value);
+        let value = slot.as_mut_ptr();
+        let bytes = scalar(value).cast::<[u8; 32]>();
+        // SAFETY: `slot` was initialized above and is never read as a `T`
+        // after the drop. `bytes` was derived from the raw place rather than
+        // from a reference, and the scalar's bytes stay owned by `slot`, so
+        // reading them as plain bytes after the drop is in bounds.
+        unsafe {
+            assert_ne!(core::ptr::read(bytes), [0; 32]);
+            core::ptr::drop_in_place(value);
+            assert_eq!(core::ptr::read_volatile(bytes), [0; 32]);
+        }
+    }
+
+    #[test]
+    fn test_blinding_factor_zeroized_on_drop() {
+        let (blinding_factor, _) = start(b"artemis", &mut OsRng);
+        assert_scalar_zeroized_on_drop(blinding_factor, |b| unsafe {
+            core::ptr::addr_of!((*b).scalar)
+        });
+    }
+
+    #[test]
+    fn test_private_key_zeroized_on_drop() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        assert_scalar_zeroized_on_drop(private_key, |k| unsafe {
+            core::ptr::addr_of!((*k).scalar)
+        });
+    }
+
+    #[test]
+    fn test_blinding_factor_from_invalid_bytes() {
+        assert_eq!(
+            BlindingFactor::from_bytes(&[0xff; 32]).unwrap_err(),
+            OprfError::NonCanonicalScalar
+        );
+        // The group order, which would reduce to zero.
+        let mut order = (-Scalar::ONE).to_bytes();
+        order[0] += 1;
+        assert_eq!(
+            BlindingFactor::from_bytes(&order).unwrap_err(),
+            OprfError::NonCanonicalScalar
+        );
+        assert_eq!(
+            BlindingFactor::from_bytes(&[0; 32]).unwrap_err(),
+            OprfError::ZeroBlindingFactor
+        );
+    }
+
+    #[test]
+    fn test_hash_to_group_domain() {
+        let input = b"artemis";
+        assert_eq!(
+            hash_to_group::<Sha512>(HASH_TO_GROUP_DOMAIN, input),
+            Point::hash_from_bytes::<Sha512>(input)
+        );
+        assert_eq!(
+            hash_to_group::<Sha512>(b"Test;", input),
+            Point::hash_from_bytes::<Sha512>(b"Test;artemis")
+        );
+    }
+
+    // A regression snapshot, not an external vector: these values were
+    // produced by this implementation, so they catch changes to how an
+    // overridden domain is applied but don't show agreement with another
+    // implementation.
+    #[test]
+    fn test_overridden_domain_snapshot() {
+        let domain = b"Juicebox_OPRF_Test_Domain;";
+        let input = b"artemis";
+        let private_key = PrivateKey::from(Scalar::from(7u64));
+        let blinding_factor = BlindingFactor {
+            scalar: Scalar::from_bytes_mod_order_wide(&[0x11; 64]),
+        };
+        let blinded_input = blind_in_domain::<Sha512>(domain, input, &blinding_factor);
+        assert_eq!(
+            hex::encode(blinded_input.point.compressed.as_bytes()),
+            "6094031cde336889185e8e9e8393ed08d0e33c1e0a8037918fda51ee6a7e7b74"
+        );
+
+        let expected = unoblivious_evaluate_with_domain(&private_key, domain, input);
+        assert_eq!(hex::encode(expected.expose_secret()), "d3d88d5cf45cde7c923a7a6ab2ad74b63e91ff5c9545e99e4a29093ebe6e32da0619a5bf70877ecce8f576a7f2d372f6d0556c4e94cde1ca172418eae645039a");
+        assert_ne!(expected, unoblivious_evaluate(&private_key, input));
+
+        let blinded_output = blind_evaluate(&private_key, &blinded_input);
+        assert_eq!(expected, finalize(input, &blinding_factor, &blinded_output));
+    }
+
+    #[test]
+    fn test_output_domain() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let input = b"artemis";
+        let (blinding_factor, blinded_input) = start(input, &mut OsRng);
+        let blinded_output = blind_evaluate(&private_key, &blinded_input);
+
+        let default = finalize(input, &blinding_factor, &blinded_output);
+        assert_eq!(
+            finalize_with_output_domain(
+                DEFAULT_OUTPUT_DOMAIN,
+                input,
+                &blinding_factor,
+                &blinded_output
+            ),
+            default
+        );
+        assert_eq!(
+            unoblivious_evaluate_with_output_domain(&private_key, DEFAULT_OUTPUT_DOMAIN, input),
+            default
+        );
+
+        let domain = "Example_Fork_OPRF_2024_1;";
+        let forked = finalize_with_output_domain(domain, input, &blinding_factor, &blinded_output);
+        assert_ne!(forked, default);
+        assert_eq!(
+            unoblivious_evaluate_with_output_domain(&private_key, domain, input),
+            forked
+        );
+    }
+
+    #[test]
+    fn test_evaluate_many() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let public_key = private_key.to_public_key();
+        let inputs: [&[u8]; 3] = [b"apollo", b"artemis", b""];
+
+        let outputs = evaluate_many(&inputs, &public_key, &mut OsRng, |blinded_inputs| {
+            assert_eq!(blinded_inputs.len(), inputs.len());
+            Ok::<_, OprfError>(blind_verifiable_evaluate_many(
+                &private_key,
+                &public_key,
+                blinded_inputs,
+                &mut OsRng,
+            ))
+        })
+        .unwrap();
+        assert_eq!(outputs.len(), inputs.len());
+        for (input, output) in inputs.iter().zip(&outputs) {
+            assert_eq!(output, &unoblivious_evaluate(&private_key, input));
+        }
+
+        assert_eq!(
+            evaluate_many(&[], &public_key, &mut OsRng, |blinded_inputs| {
+                Ok::<_, OprfError>(blind_verifiable_evaluate_many(
+                    &private_key,
+                    &public_key,
+                    blinded_inputs,
+                    &mut OsRng,
+                ))
+            }),
+            Ok(Vec::new())
+        );
+    }
+
+    #[test]
+    fn test_verify_batch_proof() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let public_key = private_key.to_public_key();
+        let (_, blinded_inputs): (Vec<_>, Vec<_>) = [b"apollo".as_slice(), b"artemis"]
+            .iter()
+            .map(|input| start(input, &mut OsRng))
+            .unzip();
+        let (mut blinded_outputs, proof) =
+            blind_verifiable_evaluate_many(&private_key, &public_key, &blinded_inputs, &mut OsRng);
+        assert!(verify_batch_proof(&blinded_inputs, &blinded_outputs, &public_key, &proof).is_ok());
+
+        let other_public_key = PrivateKey::random(&mut OsRng).to_public_key();
+        assert_eq!(
+            verify_batch_proof(&blinded_inputs, &blinded_outputs, &other_public_key, &proof),
+            Err(OprfError::ProofVerificationFailed)
+        );
+        assert_eq!(
+            verify_batch_proof(&blinded_inputs[..1], &blinded_outputs, &public_key, &proof),
+            Err(OprfError::BatchSizeMismatch)
+        );
+
+        blinded_outputs.swap(0, 1);
+        assert_eq!(
+            verify_batch_proof(&blinded_inputs, &blinded_outputs, &public_key, &proof),
+            Err(OprfError::ProofVerificationFailed)
+        );
+    }
+
+    #[test]
+    fn test_verify_batched() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let public_key = private_key.to_public_key();
+        let (_, blinded_inputs): (Vec<_>, Vec<_>) = [b"apollo".as_slice(), b"artemis", b"zeus"]
+            .iter()
+            .map(|input| start(input, &mut OsRng))
+            .unzip();
+        let (blinded_outputs, proof) =
+            blind_verifiable_evaluate_many(&private_key, &public_key, &blinded_inputs, &mut OsRng);
+        let pairs: Vec<(BlindedInput, BlindedOutput)> =
+            blinded_inputs.into_iter().zip(blinded_outputs).collect();
+        assert!(verify_batched(&pairs, &public_key, &proof).is_ok());
+
+        // Flipping any bit of any output either makes it fail to decode or
+        // makes the whole batch fail to verify.
+        let mut checked = 0;
+        for index in 0..pairs.len() {
+            for byte in 0..32 {
+                for bit in 0..8 {
+                    let mut bytes = *pairs[index].1.as_bytes();
+                    bytes[byte] ^= 1 << bit;
+                    let Ok(point) = PrecompressedPoint::try_from(CompressedPoint(bytes)) else {
+                        continue;
+                    };
+                    let mut forged = pairs.clone();
+                    forged[index].1 = BlindedOutput { point };
+                    assert_eq!(
+                        verify_batched(&forged, &public_key, &proof),
+                        Err(OprfError::ProofVerificationFailed),
+                        "output {index}, byte {byte}, bit {bit}"
+                    );
+                    checked += 1;
+                }
+            }
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn test_verify_proof_any() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let public_key = private_key.to_public_key();
+        let old_public_key = PrivateKey::random(&mut OsRng).to_public_key();
+        let (_, blinded_input) = start(b"artemis", &mut OsRng);
+        let (blinded_output, proof) =
+            blind_verifiable_evaluate(&private_key, &public_key, &blinded_input, &mut OsRng);
+
+        let verify = |public_keys: &[PublicKey]| {
+            verify_proof_any(&blinded_input, &blinded_output, public_keys, &proof)
+        };
+        assert_eq!(verify(&[old_public_key.clone(), public_key.clone()]), Ok(1));
+        assert_eq!(verify(&[public_key.clone(), old_public_key.clone()]), Ok(0));
+        assert_eq!(
+            verify(&[old_public_key]),
+            Err(OprfError::ProofVerificationFailed)
+        );
+        assert_eq!(verify(&[]), Err(OprfError::ProofVerificationFailed));
+
+        let invalid = PublicKey {
+            point: CompressedPoint([0xff; 32]),
+        };
+        assert_eq!(
+            verify(&[public_key, invalid]),
+            Err(OprfError::InvalidPublicKey)
+        );
+    }
+
+    #[test]
+    fn test_verify_proof_for_key() {
+        let input = b"artemis";
+        let private_key = PrivateKey::random(&mut OsRng);
+        let public_key = private_key.to_public_key();
+        let other_public_key = PrivateKey::random(&mut OsRng).to_public_key();
+        let (_, blinded_input) = start(input, &mut OsRng);
+        let (blinded_output, proof) =
+            blind_verifiable_evaluate(&private_key, &public_key, &blinded_input, &mut OsRng);
+
+        assert_eq!(
+            verify_proof_for_key(
+                &blinded_input,
+                &blinded_output,
+                &public_key,
+                &public_key,
+                &proof
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            verify_proof_for_key(
+                &blinded_input,
+                &blinded_output,
+                &public_key,
+                &other_public_key,
+                &proof
+            ),
+            Err(OprfError::UnexpectedPublicKey)
+        );
+
+        let (_, other_blinded_input) = start(input, &mut OsRng);
+        assert!(verify_proof_for_key(
+            &other_blinded_input,
+            &blinded_output,
+            &public_key,
+            &public_key,
+            &proof
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_public_key_debug() {
         let public_key = PublicKey {
@@ -648,4 +1746,288 @@ mod tests {
             "PublicKey(5c4bf4acff9c745d2c59c5ed4eb86b607d838b7dcc6a9399484a80ca83cf2634)"
         );
     }
+
+    #[test]
+    fn test_private_key_from_bytes() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        assert_eq!(
+            PrivateKey::from_bytes(&private_key.expose_secret().to_bytes()).unwrap(),
+            private_key
+        );
+
+        assert_eq!(
+            PrivateKey::from_bytes(&[0xff; 32]),
+            Err(OprfError::NonCanonicalScalar)
+        );
+        // The group order, which would reduce to zero.
+        let mut order = (-Scalar::ONE).to_bytes();
+        order[0] += 1;
+        assert_eq!(
+            PrivateKey::from_bytes(&order),
+            Err(OprfError::NonCanonicalScalar)
+        );
+        assert_eq!(
+            PrivateKey::from_bytes(&[0; 32]),
+            Err(OprfError::ZeroPrivateKey)
+        );
+    }
+
+    #[test]
+    fn test_public_key_from_bytes() {
+        let public_key = PrivateKey::random(&mut OsRng).to_public_key();
+        assert_eq!(
+            PublicKey::from_bytes(public_key.as_bytes()).unwrap(),
+            public_key
+        );
+
+        assert_eq!(
+            PublicKey::from_bytes(&[0xff; 32]),
+            Err(OprfError::InvalidPublicKey)
+        );
+        assert_eq!(
+            PublicKey::from_bytes(&[0; 32]),
+            Err(OprfError::IdentityPoint)
+        );
+    }
+
+    impl OprfHash for blake2::Blake2b512 {
+        const ID: &'static [u8] = b"BLAKE2b-512";
+    }
+
+    #[test]
+    fn test_hash_parameter() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let input = b"artemis";
+
+        let (blinding_factor, blinded_input) =
+            start_with_hash::<blake2::Blake2b512>(input, &mut OsRng);
+        let blinded_output = blind_evaluate(&private_key, &blinded_input);
+        let output =
+            finalize_with_hash::<blake2::Blake2b512>(input, &blinding_factor, &blinded_output);
+        assert_eq!(
+            output,
+            unoblivious_evaluate_with_hash::<blake2::Blake2b512>(&private_key, input)
+        );
+        assert_ne!(output, unoblivious_evaluate(&private_key, input));
+
+        // SHA-512 through the generic functions matches the default.
+        assert_eq!(
+            unoblivious_evaluate_with_hash::<Sha512>(&private_key, input),
+            unoblivious_evaluate(&private_key, input)
+        );
+        let (blinding_factor, blinded_input) = start(input, &mut OsRng);
+        let blinded_output = blind_evaluate(&private_key, &blinded_input);
+        assert_eq!(
+            finalize_with_hash::<Sha512>(input, &blinding_factor, &blinded_output),
+            unoblivious_evaluate(&private_key, input)
+        );
+    }
+
+    #[test]
+    fn test_evaluate_with_info() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let public_key = private_key.to_public_key();
+        let input = b"artemis";
+        let info = b"realm 1";
+
+        let (blinding_factor, blinded_input) = start(input, &mut OsRng);
+        let (blinded_output, proof) =
+            blind_verifiable_evaluate_with_info(&private_key, info, &blinded_input, &mut OsRng);
+        assert_eq!(
+            blinded_output,
+            blind_evaluate_with_info(&private_key, info, &blinded_input)
+        );
+        assert_eq!(
+            verify_proof_with_info(&blinded_input, &blinded_output, &public_key, info, &proof),
+            Ok(())
+        );
+        let output = finalize_with_info(input, info, &blinding_factor, &blinded_output);
+        assert_eq!(
+            output,
+            unoblivious_evaluate_with_info(&private_key, info, input)
+        );
+
+        // Different info gives an independent function.
+        assert_ne!(
+            output,
+            unoblivious_evaluate_with_info(&private_key, b"realm 2", input)
+        );
+        assert_ne!(output, unoblivious_evaluate(&private_key, input));
+
+        // The proof authenticates the info.
+        assert_eq!(
+            verify_proof_with_info(
+                &blinded_input,
+                &blinded_output,
+                &public_key,
+                b"realm 2",
+                &proof
+            ),
+            Err(OprfError::ProofVerificationFailed)
+        );
+        let (other_output, other_proof) = blind_verifiable_evaluate_with_info(
+            &private_key,
+            b"realm 2",
+            &blinded_input,
+            &mut OsRng,
+        );
+        assert_eq!(
+            verify_proof_with_info(
+                &blinded_input,
+                &other_output,
+                &public_key,
+                info,
+                &other_proof
+            ),
+            Err(OprfError::ProofVerificationFailed)
+        );
+        assert_eq!(
+            verify_proof_with_info(
+                &blinded_input,
+                &blinded_output,
+                &PrivateKey::random(&mut OsRng).to_public_key(),
+                info,
+                &proof
+            ),
+            Err(OprfError::ProofVerificationFailed)
+        );
+    }
+
+    #[test]
+    fn test_start_with_blind() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let input = b"artemis";
+        let blinding_factor = BlindingFactor::from_bytes(&[7; 32]).unwrap();
+
+        let blinded_input = start_with_blind(input, &blinding_factor);
+        assert_eq!(blinded_input, start_with_blind(input, &blinding_factor));
+        assert_eq!(
+            hex::encode(blinded_input.point.compressed.as_bytes()),
+            "221daa0af7c53d00f2b4a03fb874475c84e389f8b78ba6736bb649417709ba4e"
+        );
+
+        let blinded_output = blind_evaluate(&private_key, &blinded_input);
+        assert_eq!(
+            finalize(input, &blinding_factor, &blinded_output),
+            unoblivious_evaluate(&private_key, input)
+        );
+    }
+
+    #[test]
+    fn test_output_ct_eq() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let output = unoblivious_evaluate(&private_key, b"artemis");
+        let same = unoblivious_evaluate(&private_key, b"artemis");
+        let other = unoblivious_evaluate(&private_key, b"apollo");
+        assert!(bool::from(output.ct_eq(&same)));
+        assert!(!bool::from(output.ct_eq(&other)));
+        assert_eq!(output, same);
+        assert_ne!(output, other);
+    }
+
+    #[test]
+    fn test_output_truncated() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let output = unoblivious_evaluate(&private_key, b"artemis");
+        let truncated: [u8; 32] = output.expose_secret_truncated();
+        assert_eq!(truncated, output.expose_secret()[..32]);
+        assert_eq!(
+            output.expose_secret_truncated::<64>(),
+            *output.expose_secret()
+        );
+        assert_eq!(output.expose_secret_truncated::<0>(), [0u8; 0]);
+    }
+
+    #[test]
+    fn test_prehashed() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let input = b"artemis";
+        let input_hash = InputHash::new(input);
+
+        let (blinding_factor, blinded_input) = start_prehashed(&input_hash, &mut OsRng);
+        let blinded_output = blind_evaluate(&private_key, &blinded_input);
+        let output = finalize_prehashed(&input_hash, &blinding_factor, &blinded_output);
+        assert_eq!(output, unoblivious_evaluate(&private_key, input));
+        assert_eq!(output, finalize(input, &blinding_factor, &blinded_output));
+
+        // The input hash can be reused for another run.
+        let (blinding_factor, blinded_input) = start_prehashed(&input_hash, &mut OsRng);
+        let blinded_output = blind_evaluate(&private_key, &blinded_input);
+        assert_eq!(
+            finalize_prehashed(&input_hash, &blinding_factor, &blinded_output),
+            output
+        );
+
+        // Hashing the input in pieces gives the same result.
+        let mut hasher = InputHasher::new();
+        hasher.update(b"art");
+        hasher.update(b"");
+        hasher.update(b"emis");
+        let streamed_hash = hasher.finalize();
+        let (blinding_factor, blinded_input) = start_prehashed(&streamed_hash, &mut OsRng);
+        let blinded_output = blind_evaluate(&private_key, &blinded_input);
+        assert_eq!(
+            finalize_prehashed(&streamed_hash, &blinding_factor, &blinded_output),
+            output
+        );
+    }
+
+    #[test]
+    fn test_verify_proofs() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let public_key = private_key.to_public_key();
+        let mut items: Vec<_> = [b"apollo".as_slice(), b"artemis", b"ares"]
+            .iter()
+            .map(|input| {
+                let (_, blinded_input) = start(input, &mut OsRng);
+                let (blinded_output, proof) = blind_verifiable_evaluate(
+                    &private_key,
+                    &public_key,
+                    &blinded_input,
+                    &mut OsRng,
+                );
+                (blinded_input, blinded_output, proof)
+            })
+            .collect();
+        assert_eq!(
+            verify_proofs(&items, &public_key),
+            Ok(vec![true, true, true])
+        );
+
+        // A proof for a different output fails only its own item.
+        items[1].1 = items[2].1.clone();
+        assert_eq!(
+            verify_proofs(&items, &public_key),
+            Ok(vec![true, false, true])
+        );
+        assert_eq!(
+            verify_proofs(&items, &PrivateKey::random(&mut OsRng).to_public_key()),
+            Ok(vec![false, false, false])
+        );
+        assert_eq!(verify_proofs(&[], &public_key), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_blinded_input_from_compressed_checked() {
+        let (_, blinded_input) = start(b"artemis", &mut OsRng);
+        let bytes = *blinded_input.point.compressed.as_bytes();
+        assert_eq!(
+            BlindedInput::from_compressed_checked(&bytes).unwrap(),
+            blinded_input
+        );
+
+        assert_eq!(
+            BlindedInput::from_compressed_checked(&[0xff; 32]).unwrap_err(),
+            OprfError::NonCanonicalPoint
+        );
+        assert_eq!(
+            BlindedInput::from_compressed_checked(&[0; 32]).unwrap_err(),
+            OprfError::IdentityPoint
+        );
+
+        // The permissive serde path still accepts the identity.
+        let identity: BlindedInput =
+            serde_json::from_str(&serde_json::to_string(&[0u8; 32]).unwrap()).unwrap();
+        assert_eq!(identity.point.uncompressed, Point::identity());
+    }
 }
@@ -0,0 +1,187 @@
+//! Optional OpenTelemetry metrics for OPRF evaluations and proof
+//! verifications.
+//!
+//! Nothing is recorded until [`init_metrics`] is called with a [`Meter`].
+
+use opentelemetry::metrics::{Counter, Histogram, Meter, Unit};
+use opentelemetry::KeyValue;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Name of the histogram recording [`blind_evaluate`](crate::blind_evaluate)
+/// latency, in seconds.
+pub const BLIND_EVALUATE_DURATION: &str = "juicebox_oprf.blind_evaluate.duration";
+
+/// Name of the counter recording proof verifications. Each verification has a
+/// `result` attribute of either `ok` or `error`.
+pub const PROOF_VERIFICATIONS: &str = "juicebox_oprf.proof_verifications";
+
+struct Instruments {
+    blind_evaluate_duration: Histogram<f64>,
+    proof_verifications: Counter<u64>,
+}
+
+static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+
+/// Creates the OPRF instruments from `meter` and starts recording to them.
+///
+/// This may only be called once per process. Later calls return an error and
+/// leave the original instruments in place.
+pub fn init_metrics(meter: &Meter) -> Result<(), &'static str> {
+    let instruments = Instruments {
+        blind_evaluate_duration: meter
+            .f64_histogram(BLIND_EVALUATE_DURATION)
+            .with_description("Time taken by the OPRF blind evaluation")
+            .with_unit(Unit::new("s"))
+            .init(),
+        proof_verifications: meter
+            .u64_counter(PROOF_VERIFICATIONS)
+            .with_description("Number of OPRF proofs verified")
+            .init(),
+    };
+    INSTRUMENTS
+        .set(instruments)
+        .map_err(|_| "OPRF metrics already initialized")
+}
+
+pub(crate) fn record_blind_evaluate(duration: Duration) {
+    if let Some(instruments) = INSTRUMENTS.get() {
+        instruments
+            .blind_evaluate_duration
+            .record(duration.as_secs_f64(), &[]);
+    }
+}
+
+pub(crate) fn record_proof_verification<T, E>(result: &Result<T, E>) {
+    if let Some(instruments) = INSTRUMENTS.get() {
+        let result = if result.is_ok() { "ok" } else { "error" };
+        instruments
+            .proof_verifications
+            .add(1, &[KeyValue::new("result", result)]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::metrics::{
+        CallbackRegistration, InstrumentProvider, Meter, MetricsError, Observer, Result,
+        SyncCounter, SyncHistogram, Unit,
+    };
+    use opentelemetry::KeyValue;
+    use rand_core::OsRng;
+    use std::any::Any;
+    use std::borrow::Cow;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        blind_evaluate, blind_verifiable_evaluate, start, verify_proof, PrivateKey, Proof,
+    };
+
+    type Recorded = Arc<Mutex<Vec<(String, f64, Vec<KeyValue>)>>>;
+
+    /// Records every measurement along with its instrument name.
+    struct RecordingInstrument {
+        name: Cow<'static, str>,
+        recorded: Recorded,
+    }
+
+    impl SyncCounter<u64> for RecordingInstrument {
+        fn add(&self, value: u64, attributes: &[KeyValue]) {
+            self.recorded.lock().unwrap().push((
+                self.name.to_string(),
+                value as f64,
+                attributes.to_vec(),
+            ));
+        }
+    }
+
+    impl SyncHistogram<f64> for RecordingInstrument {
+        fn record(&self, value: f64, attributes: &[KeyValue]) {
+            self.recorded
+                .lock()
+                .unwrap()
+                .push((self.name.to_string(), value, attributes.to_vec()));
+        }
+    }
+
+    struct RecordingProvider(Recorded);
+
+    impl RecordingProvider {
+        fn instrument(&self, name: Cow<'static, str>) -> Arc<RecordingInstrument> {
+            Arc::new(RecordingInstrument {
+                name,
+                recorded: self.0.clone(),
+            })
+        }
+    }
+
+    impl InstrumentProvider for RecordingProvider {
+        fn u64_counter(
+            &self,
+            name: Cow<'static, str>,
+            _description: Option<Cow<'static, str>>,
+            _unit: Option<Unit>,
+        ) -> Result<Counter<u64>> {
+            Ok(Counter::new(self.instrument(name)))
+        }
+
+        fn f64_histogram(
+            &self,
+            name: Cow<'static, str>,
+            _description: Option<Cow<'static, str>>,
+            _unit: Option<Unit>,
+        ) -> Result<Histogram<f64>> {
+            Ok(Histogram::new(self.instrument(name)))
+        }
+
+        fn register_callback(
+            &self,
+            _instruments: &[Arc<dyn Any>],
+            _callback: Box<dyn Fn(&dyn Observer) + Send + Sync>,
+        ) -> Result<Box<dyn CallbackRegistration>> {
+            Err(MetricsError::Other(String::from("unsupported")))
+        }
+    }
+
+    #[test]
+    fn test_metrics_recorded() {
+        let recorded = Recorded::default();
+        let meter = Meter::new(Arc::new(RecordingProvider(recorded.clone())));
+        init_metrics(&meter).unwrap();
+        assert_eq!(
+            init_metrics(&meter),
+            Err("OPRF metrics already initialized")
+        );
+
+        let private_key = PrivateKey::random(&mut OsRng);
+        let public_key = private_key.to_public_key();
+        let (_, blinded_input) = start(b"artichoke hearts", &mut OsRng);
+        let (blinded_output, proof) =
+            blind_verifiable_evaluate(&private_key, &public_key, &blinded_input, &mut OsRng);
+        verify_proof(&blinded_input, &blinded_output, &public_key, &proof).unwrap();
+        let bad_proof = Proof {
+            c: proof.beta_z,
+            beta_z: proof.c,
+        };
+        verify_proof(&blinded_input, &blinded_output, &public_key, &bad_proof).unwrap_err();
+        blind_evaluate(&private_key, &blinded_input);
+
+        // Other tests in this process may record measurements concurrently
+        // once the metrics are initialized, so these are lower bounds.
+        let recorded = recorded.lock().unwrap();
+        let count = |name: &str, attributes: &[KeyValue]| {
+            recorded
+                .iter()
+                .filter(|(n, _, a)| n == name && a == attributes)
+                .count()
+        };
+        assert!(count(BLIND_EVALUATE_DURATION, &[]) >= 2);
+        assert!(recorded
+            .iter()
+            .filter(|(n, _, _)| n == BLIND_EVALUATE_DURATION)
+            .all(|(_, value, _)| *value >= 0.0));
+        assert!(count(PROOF_VERIFICATIONS, &[KeyValue::new("result", "ok")]) >= 1);
+        assert!(count(PROOF_VERIFICATIONS, &[KeyValue::new("result", "error")]) >= 1);
+    }
+}
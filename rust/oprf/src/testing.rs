@@ -0,0 +1,102 @@
+//! Deterministic test vector generation, for validating other implementations
+//! of this OPRF byte-for-byte.
+//!
+//! The vectors in `src/test_vectors.json` are produced by [`generate_vector`].
+//! When another implementation disagrees on a proof,
+//! [`dleq_challenge_transcript`] shows what this one hashed.
+
+use alloc::vec::Vec;
+use curve25519_dalek::Scalar;
+
+use crate::{
+    blind_evaluate, dleq, finalize, start_with_blind, unoblivious_evaluate, verify_proof,
+    BlindedInput, BlindedOutput, BlindingFactor, OprfError, PrecompressedPoint, PrivateKey, Proof,
+    PublicKey,
+};
+
+/// The values computed by [`generate_vector`], in their canonical byte
+/// encodings.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VectorOutputs {
+    pub private_key: [u8; 32],
+    pub public_key: [u8; 32],
+    pub blinding_factor: [u8; 32],
+    pub blinded_input: [u8; 32],
+    pub blinded_output: [u8; 32],
+    pub proof_c: [u8; 32],
+    pub proof_beta_z: [u8; 32],
+    pub output: [u8; 64],
+}
+
+/// Runs a full verifiable OPRF exchange with deterministic randomness.
+///
+/// Each seed is reduced to a scalar: the first becomes the private key, the
+/// second the blinding factor, and the third the nonce for the DLEQ proof.
+/// These are the scalars the randomized functions would produce from an RNG
+/// that returned the seeds' bytes.
+///
+/// # Panics
+///
+/// Panics if the proof fails to verify or the oblivious output differs from
+/// the unoblivious one, which would indicate a bug in this crate.
+pub fn generate_vector(
+    input: &[u8],
+    private_key_seed: &[u8; 64],
+    blinding_factor_seed: &[u8; 64],
+    beta_t_seed: &[u8; 64],
+) -> VectorOutputs {
+    let private_key = PrivateKey::from(Scalar::from_bytes_mod_order_wide(private_key_seed));
+    let public_key = private_key.to_public_key();
+
+    let blinding_factor = BlindingFactor {
+        scalar: Scalar::from_bytes_mod_order_wide(blinding_factor_seed),
+    };
+    let blinded_input = start_with_blind(input, &blinding_factor);
+    let blinded_output = blind_evaluate(&private_key, &blinded_input);
+    let proof = dleq::generate_proof_with_nonce(
+        &Scalar::from_bytes_mod_order_wide(beta_t_seed),
+        &private_key.scalar,
+        &blinded_input.point,
+        &public_key.point,
+        &blinded_output.point,
+    );
+    assert!(verify_proof(&blinded_input, &blinded_output, &public_key, &proof).is_ok());
+    let output = finalize(input, &blinding_factor, &blinded_output);
+    assert_eq!(output, unoblivious_evaluate(&private_key, input));
+
+    let proof_bytes = proof.to_bytes();
+    VectorOutputs {
+        private_key: private_key.scalar.to_bytes(),
+        public_key: public_key.point.to_bytes(),
+        blinding_factor: blinding_factor.scalar.to_bytes(),
+        blinded_input: blinded_input.point.compressed.to_bytes(),
+        blinded_output: blinded_output.point.compressed.to_bytes(),
+        proof_c: proof_bytes[..32].try_into().unwrap(),
+        proof_beta_z: proof_bytes[32..].try_into().unwrap(),
+        output: *output.expose_secret(),
+    }
+}
+
+/// Returns the exact bytes that [`verify_proof`] feeds into the DLEQ
+/// challenge hash for these values: a domain separator followed by the
+/// compressed blinded input, public key, blinded output, and the two
+/// commitments recomputed from the proof.
+///
+/// The challenge is the SHA-512 hash of these bytes reduced modulo the group
+/// order. For a valid proof, it equals the proof's `c`, and the transcript
+/// is the same one the server hashed when generating the proof.
+pub fn dleq_challenge_transcript(
+    blinded_input: &BlindedInput,
+    blinded_output: &BlindedOutput,
+    public_key: &PublicKey,
+    proof: &Proof,
+) -> Result<Vec<u8>, OprfError> {
+    let public_key =
+        PrecompressedPoint::try_from(public_key.point).map_err(|_| OprfError::InvalidPublicKey)?;
+    Ok(dleq::challenge_transcript(
+        &blinded_input.point,
+        &public_key,
+        &blinded_output.point,
+        proof,
+    ))
+}
@@ -0,0 +1,140 @@
+//! JavaScript bindings for the client side of the OPRF, built with the `wasm`
+//! feature.
+//!
+//! Every value crosses the boundary as a `Uint8Array` holding the same bytes
+//! as the Rust type's `as_bytes` or `to_bytes`, so results can be compared
+//! with those of a server or another client byte-for-byte.
+
+use alloc::vec::Vec;
+use curve25519_dalek::ristretto::CompressedRistretto as CompressedPoint;
+use rand_core::OsRng;
+use wasm_bindgen::prelude::*;
+
+use crate::{BlindedInput, BlindedOutput, BlindingFactor, PrecompressedPoint, Proof, PublicKey};
+
+/// The result of [`oprf_start`].
+#[wasm_bindgen(js_name = OprfStart)]
+pub struct Start {
+    blinding_factor: BlindingFactor,
+    blinded_input: BlindedInput,
+}
+
+#[wasm_bindgen(js_class = OprfStart)]
+impl Start {
+    /// The 32-byte blinding factor, which must be kept secret and passed to
+    /// `oprfFinalize`.
+    #[wasm_bindgen(getter, js_name = blindingFactor)]
+    pub fn blinding_factor(&self) -> Vec<u8> {
+        self.blinding_factor.to_bytes().to_vec()
+    }
+
+    /// The 32-byte blinded input to send to the server.
+    #[wasm_bindgen(getter, js_name = blindedInput)]
+    pub fn blinded_input(&self) -> Vec<u8> {
+        self.blinded_input.as_bytes().to_vec()
+    }
+}
+
+/// Starts the OPRF protocol for `input`, blinding it with randomness from
+/// the browser's `crypto.getRandomValues`. See [`crate::start`].
+#[wasm_bindgen(js_name = oprfStart)]
+pub fn oprf_start(input: &[u8]) -> Start {
+    let (blinding_factor, blinded_input) = crate::start(input, &mut OsRng);
+    Start {
+        blinding_factor,
+        blinded_input,
+    }
+}
+
+/// Completes the OPRF protocol, returning the 64-byte output. See
+/// [`crate::finalize`].
+#[wasm_bindgen(js_name = oprfFinalize)]
+pub fn oprf_finalize(
+    input: &[u8],
+    blinding_factor: &[u8],
+    blinded_output: &[u8],
+) -> Result<Vec<u8>, JsError> {
+    let blinding_factor = BlindingFactor::from_bytes(&array(blinding_factor, "blinding factor")?)?;
+    let blinded_output = blinded_output_from_bytes(blinded_output)?;
+    let output = crate::finalize(input, &blinding_factor, &blinded_output);
+    Ok(output.expose_secret().to_vec())
+}
+
+/// Checks the server's 64-byte `proof` that it computed `blinded_output`
+/// from `blinded_input` with the private key for `public_key`. Throws if it
+/// didn't. See [`crate::verify_proof`].
+#[wasm_bindgen(js_name = oprfVerifyProof)]
+pub fn oprf_verify_proof(
+    blinded_input: &[u8],
+    blinded_output: &[u8],
+    public_key: &[u8],
+    proof: &[u8],
+) -> Result<(), JsError> {
+    let blinded_input =
+        BlindedInput::from_compressed_checked(&array(blinded_input, "blinded input")?)?;
+    let blinded_output = blinded_output_from_bytes(blinded_output)?;
+    let public_key = PublicKey::from_bytes(&array(public_key, "public key")?)?;
+    let proof = Proof::from_bytes(&array(proof, "proof")?)?;
+    crate::verify_proof(&blinded_input, &blinded_output, &public_key, &proof)?;
+    Ok(())
+}
+
+fn blinded_output_from_bytes(bytes: &[u8]) -> Result<BlindedOutput, JsError> {
+    let point = PrecompressedPoint::try_from(CompressedPoint(array(bytes, "blinded output")?))?;
+    Ok(BlindedOutput { point })
+}
+
+fn array<const N: usize>(bytes: &[u8], name: &str) -> Result<[u8; N], JsError> {
+    bytes
+        .try_into()
+        .map_err(|_| JsError::new(&format!("{name} must be {N} bytes, got {}", bytes.len())))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+    use wasm_bindgen_test::*;
+
+    use super::{oprf_finalize, oprf_start, oprf_verify_proof};
+    use crate::{blind_verifiable_evaluate, unoblivious_evaluate, BlindedInput, PrivateKey};
+
+    #[wasm_bindgen_test]
+    fn test_oprf_round_trip() {
+        let private_key = PrivateKey::random(&mut OsRng);
+        let public_key = private_key.to_public_key();
+        let input = b"artemis";
+
+        let start = oprf_start(input);
+        let blinded_input =
+            BlindedInput::from_compressed_checked(&start.blinded_input().try_into().unwrap())
+                .unwrap();
+        let (blinded_output, proof) =
+            blind_verifiable_evaluate(&private_key, &public_key, &blinded_input, &mut OsRng);
+
+        assert!(oprf_verify_proof(
+            &start.blinded_input(),
+            blinded_output.as_bytes(),
+            public_key.as_bytes(),
+            &proof.to_bytes(),
+        )
+        .is_ok());
+        let Ok(output) = oprf_finalize(input, &start.blinding_factor(), blinded_output.as_bytes())
+        else {
+            panic!("oprf_finalize failed");
+        };
+        assert_eq!(
+            output,
+            unoblivious_evaluate(&private_key, input).expose_secret()
+        );
+
+        let other_key = PrivateKey::random(&mut OsRng).to_public_key();
+        assert!(oprf_verify_proof(
+            &start.blinded_input(),
+            blinded_output.as_bytes(),
+            other_key.as_bytes(),
+            &proof.to_bytes(),
+        )
+        .is_err());
+        assert!(oprf_finalize(input, &[0; 31], blinded_output.as_bytes()).is_err());
+    }
+}
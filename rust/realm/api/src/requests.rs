@@ -4,13 +4,14 @@ use alloc::boxed::Box;
 use alloc::vec::Vec;
 use core::fmt;
 use core::time::Duration;
+use ed25519_dalek::{Signature, SignatureError, VerifyingKey};
 use serde::{Deserialize, Serialize};
 
 use crate::signing::OprfSignedPublicKey;
 use crate::types::{
     AuthToken, EncryptedUserSecret, EncryptedUserSecretCommitment, Policy, RealmId,
-    RegistrationVersion, SecretBytesArray, SessionId, UnlockKeyCommitment, UnlockKeyTag,
-    UserSecretEncryptionKeyScalarShare,
+    RegistrationVersion, RequestId, RequestSignature, SecretBytesArray, SecretCipher, SessionId,
+    UnlockKeyCommitment, UnlockKeyTag, UserSecretEncryptionKeyScalarShare,
 };
 use juicebox_marshalling::{self as marshalling, bytes, DeserializationError, SerializationError};
 use juicebox_noise as noise;
@@ -23,6 +24,76 @@ pub struct ClientRequest {
     pub session_id: SessionId,
     pub kind: ClientRequestKind,
     pub encrypted: NoiseRequest,
+    /// Correlates this request with the client's logs. Clients that predate
+    /// this field don't send it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<RequestId>,
+    /// An optional Ed25519 signature over [`ClientRequest::signing_bytes`],
+    /// made with a device key, for deployments that require signed requests.
+    ///
+    /// This is omitted from the encoding when `None`, so unsigned requests
+    /// are encoded exactly as before the field existed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<RequestSignature>,
+}
+
+/// The fields of a [`ClientRequest`] covered by its signature.
+///
+/// This must have the same fields as [`ClientRequest`], minus the
+/// signature, so that the signed bytes are the canonical encoding of an
+/// unsigned request.
+#[derive(Serialize)]
+struct ClientRequestSigningFields<'a> {
+    realm: &'a RealmId,
+    auth_token: &'a AuthToken,
+    session_id: &'a SessionId,
+    kind: &'a ClientRequestKind,
+    encrypted: &'a NoiseRequest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: &'a Option<RequestId>,
+}
+
+impl ClientRequest {
+    /// Returns the exact bytes a device key should sign to produce
+    /// [`ClientRequest::signature`].
+    ///
+    /// These are the canonical CBOR encoding of the request without its
+    /// signature, so they do not change when the request is serialized and
+    /// deserialized.
+    pub fn signing_bytes(&self) -> Result<Vec<u8>, SerializationError> {
+        marshalling::to_vec_canonical(&ClientRequestSigningFields {
+            realm: &self.realm,
+            auth_token: &self.auth_token,
+            session_id: &self.session_id,
+            kind: &self.kind,
+            encrypted: &self.encrypted,
+            request_id: &self.request_id,
+        })
+    }
+
+    /// Verifies the request's signature against the device's Ed25519
+    /// verifying key. Returns an error if the request is unsigned.
+    pub fn verify_signature(&self, verifying_key: &[u8; 32]) -> Result<(), SignatureError> {
+        let signature = self.signature.as_ref().ok_or(SignatureError::default())?;
+        let message = self
+            .signing_bytes()
+            .map_err(|_| SignatureError::default())?;
+        VerifyingKey::from_bytes(verifying_key)?
+            .verify_strict(&message, &Signature::from(&signature.0))
+    }
+}
+
+/// Used in [`ClientResponse::DecodingError`] to say which layer of a request
+/// could not be decoded.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum DecodeStage {
+    /// The [`ClientRequest`] itself. Resending the same request won't help.
+    Outer,
+    /// The [`SecretsRequest`] decrypted from the Noise request, or its
+    /// consistency with the [`ClientRequestKind`]. This can come from a
+    /// session whose state has diverged, so the client may retry on a new
+    /// session.
+    Inner,
 }
 
 /// Used in [`ClientRequest`].
@@ -38,6 +109,30 @@ pub enum ClientRequestKind {
     SecretsRequest,
 }
 
+impl ClientRequestKind {
+    /// Checks on the server that the declared kind matches the decrypted
+    /// content of the request.
+    ///
+    /// A [`ClientRequestKind::HandshakeOnly`] request must not carry a
+    /// [`SecretsRequest`], and a [`ClientRequestKind::SecretsRequest`] request
+    /// must carry one. Otherwise, the request could be routed as the wrong
+    /// kind, so this returns [`ClientResponse::DecodingError`] at the
+    /// [`DecodeStage::Inner`] stage.
+    pub fn check_content(
+        &self,
+        secrets_request: Option<&SecretsRequest>,
+    ) -> Result<(), ClientResponse> {
+        match (self, secrets_request) {
+            (Self::HandshakeOnly, None) | (Self::SecretsRequest, Some(_)) => Ok(()),
+            (Self::HandshakeOnly, Some(_)) | (Self::SecretsRequest, None) => {
+                Err(ClientResponse::DecodingError {
+                    stage: DecodeStage::Inner,
+                })
+            }
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[allow(clippy::large_enum_variant)]
 pub enum ClientResponse {
@@ -54,14 +149,20 @@ pub enum ClientResponse {
     MissingSession,
     /// The server could not decrypt the encapsulated Noise request.
     SessionError,
-    // The server could not deserialize the [`ClientRequest`] or the
-    // encapsulated [`SecretsRequest`].
-    DecodingError,
+    /// The server could not deserialize the request at the given stage.
+    DecodingError {
+        stage: DecodeStage,
+    },
     /// The payload sent to the server was too large to be processed.
     PayloadTooLarge,
     /// The tenant has exceeded their allowed number of operations. Try again
     /// later.
     RateLimitExceeded,
+    /// The realm is shedding load and did not process the request. The
+    /// client should wait at least `retry_after` before sending it again.
+    RateLimited {
+        retry_after: Duration,
+    },
 }
 
 /// A Noise protocol handshake or transport message.
@@ -154,6 +255,22 @@ impl SecretsRequest {
             Self::Delete => false,
         }
     }
+
+    /// Returns true if the realm counts a guess against the user's policy
+    /// when it processes this request.
+    ///
+    /// Clients must not automatically retry these requests after they may
+    /// have reached the realm, since each attempt could use up a guess.
+    pub fn consumes_guess(&self) -> bool {
+        match self {
+            Self::Register1 => false,
+            Self::Register2(_) => false,
+            Self::Recover1 => false,
+            Self::Recover2(_) => true,
+            Self::Recover3(_) => false,
+            Self::Delete => false,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -223,6 +340,10 @@ pub struct Register2Request {
     pub encrypted_secret: EncryptedUserSecret,
     pub encrypted_secret_commitment: EncryptedUserSecretCommitment,
     pub policy: Policy,
+    /// The AEAD used to produce `encrypted_secret`. Realms store this and
+    /// return it from [`Recover3Response::Ok`].
+    #[serde(default, skip_serializing_if = "SecretCipher::is_default")]
+    pub secret_cipher: SecretCipher,
 }
 
 /// Response message for the second phase of registration.
@@ -277,6 +398,8 @@ pub enum Recover3Response {
         encryption_key_scalar_share: UserSecretEncryptionKeyScalarShare,
         encrypted_secret: EncryptedUserSecret,
         encrypted_secret_commitment: EncryptedUserSecretCommitment,
+        #[serde(default, skip_serializing_if = "SecretCipher::is_default")]
+        secret_cipher: SecretCipher,
     },
     VersionMismatch,
     NotRegistered,
@@ -289,7 +412,11 @@ pub enum Recover3Response {
 /// Response message to delete registered secrets.
 #[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum DeleteResponse {
+    /// The realm deleted the user's records, if any.
     Ok,
+    /// The realm deleted `count` records, which is zero if the user wasn't
+    /// registered. Realms that don't report a count send `Ok`.
+    Deleted { count: u32 },
 }
 
 /// The maximum expected request size from the SDK
@@ -298,24 +425,30 @@ pub const BODY_SIZE_LIMIT: usize = 2048;
 #[cfg(test)]
 mod tests {
     use crate::{
-        requests::{Register2Request, SecretsRequest, BODY_SIZE_LIMIT},
+        requests::{
+            ClientRequest, ClientRequestKind, ClientResponse, DecodeStage, DeleteResponse,
+            NoiseRequest, Register2Request, SecretsRequest, BODY_SIZE_LIMIT,
+        },
         signing::{OprfSignedPublicKey, OprfVerifyingKey},
         types::{
-            EncryptedUserSecret, EncryptedUserSecretCommitment, Policy, RegistrationVersion,
-            SecretBytesArray, UnlockKeyCommitment, UnlockKeyTag,
-            UserSecretEncryptionKeyScalarShare,
+            AuthToken, EncryptedUserSecret, EncryptedUserSecretCommitment, Policy, RealmId,
+            RegistrationVersion, RequestId, RequestSignature, SecretBytesArray, SecretCipher,
+            SessionId, UnlockKeyCommitment, UnlockKeyTag, UserSecretEncryptionKeyScalarShare,
         },
     };
     use curve25519_dalek::Scalar;
-    use juicebox_marshalling as marshalling;
+    use ed25519_dalek::{Signer, SigningKey};
+    use juicebox_marshalling::{
+        self as marshalling, CborCodec, Codec, DeserializationError, SerializationError,
+    };
     use juicebox_oprf as oprf;
     use rand_core::OsRng;
+    use serde::{de::DeserializeOwned, Serialize};
 
-    #[test]
-    fn test_request_body_size_limit() {
+    fn register2_request() -> Register2Request {
         let oprf_private_key = oprf::PrivateKey::random(&mut OsRng);
         let oprf_public_key = oprf_private_key.to_public_key();
-        let secrets_request = SecretsRequest::Register2(Box::new(Register2Request {
+        Register2Request {
             version: RegistrationVersion::from([0xff; 16]),
             oprf_private_key,
             oprf_signed_public_key: OprfSignedPublicKey {
@@ -331,8 +464,213 @@ mod tests {
             policy: Policy {
                 num_guesses: u16::MAX,
             },
-        }));
+            secret_cipher: SecretCipher::Aes256Gcm,
+        }
+    }
+
+    #[test]
+    fn test_delete_response_ok_encoding_unchanged() {
+        // `Ok` is what realms sent before `Deleted` existed, so it has to
+        // keep decoding as a unit variant.
+        let ok = marshalling::to_vec(&DeleteResponse::Ok).unwrap();
+        assert_eq!(ok, b"\x62Ok");
+        assert_eq!(
+            marshalling::from_slice::<DeleteResponse>(&ok).unwrap(),
+            DeleteResponse::Ok
+        );
+
+        let deleted = DeleteResponse::Deleted { count: 1 };
+        assert_eq!(
+            marshalling::from_slice::<DeleteResponse>(&marshalling::to_vec(&deleted).unwrap())
+                .unwrap(),
+            deleted
+        );
+    }
+
+    #[test]
+    fn test_request_body_size_limit() {
+        let secrets_request = SecretsRequest::Register2(Box::new(register2_request()));
         let serialized = marshalling::to_vec(&secrets_request).unwrap();
         assert!(serialized.len() < BODY_SIZE_LIMIT);
     }
+
+    /// Frames CBOR with a 4-byte big-endian length, standing in for a
+    /// caller's own format.
+    struct LengthDelimitedCodec;
+
+    impl Codec for LengthDelimitedCodec {
+        fn encode<T: Serialize>(&self, val: &T) -> Result<Vec<u8>, SerializationError> {
+            let body = CborCodec.encode(val)?;
+            let mut bytes = marshalling::to_be4(body.len()).to_vec();
+            bytes.extend(body);
+            Ok(bytes)
+        }
+
+        fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, DeserializationError> {
+            if bytes.len() < 4 {
+                return Err(DeserializationError::new("missing length"));
+            }
+            let (len, body) = bytes.split_at(4);
+            if u32::from_be_bytes(len.try_into().unwrap()) as usize != body.len() {
+                return Err(DeserializationError::new("length mismatch"));
+            }
+            CborCodec.decode(body)
+        }
+    }
+
+    /// A codec chosen at runtime, such as from configuration.
+    #[derive(Clone, Copy)]
+    enum SelectedCodec {
+        Cbor,
+        LengthDelimited,
+    }
+
+    impl Codec for SelectedCodec {
+        fn encode<T: Serialize>(&self, val: &T) -> Result<Vec<u8>, SerializationError> {
+            match self {
+                Self::Cbor => CborCodec.encode(val),
+                Self::LengthDelimited => LengthDelimitedCodec.encode(val),
+            }
+        }
+
+        fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, DeserializationError> {
+            match self {
+                Self::Cbor => CborCodec.decode(bytes),
+                Self::LengthDelimited => LengthDelimitedCodec.decode(bytes),
+            }
+        }
+    }
+
+    #[test]
+    fn test_register2_request_codec_round_trip() {
+        let request = register2_request();
+        let cbor = marshalling::to_vec(&request).unwrap();
+        assert_eq!(CborCodec.encode(&request).unwrap(), cbor);
+
+        fn round_trip(codec: &impl Codec, request: &Register2Request) -> Register2Request {
+            codec.decode(&codec.encode(request).unwrap()).unwrap()
+        }
+        // `Register2Request` isn't `PartialEq`, so compare the encodings.
+        for codec in [SelectedCodec::Cbor, SelectedCodec::LengthDelimited] {
+            let decoded = round_trip(&codec, &request);
+            assert_eq!(marshalling::to_vec(&decoded).unwrap(), cbor);
+        }
+
+        let framed = LengthDelimitedCodec.encode(&request).unwrap();
+        assert_eq!(&framed[4..], cbor);
+        assert!(LengthDelimitedCodec
+            .decode::<Register2Request>(&framed[..framed.len() - 1])
+            .is_err());
+    }
+
+    fn client_request() -> ClientRequest {
+        ClientRequest {
+            realm: RealmId([5; 16]),
+            auth_token: AuthToken::from(String::from("token")),
+            session_id: SessionId(42),
+            kind: ClientRequestKind::SecretsRequest,
+            encrypted: NoiseRequest::Transport {
+                ciphertext: vec![1, 2, 3],
+            },
+            request_id: Some(RequestId([7; 16])),
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn test_signing_bytes_deterministic() {
+        let mut request = client_request();
+        let signing_bytes = request.signing_bytes().unwrap();
+        assert_eq!(
+            signing_bytes,
+            marshalling::to_vec_canonical(&request).unwrap()
+        );
+
+        let signing_key = SigningKey::generate(&mut OsRng);
+        request.signature = Some(RequestSignature(
+            signing_key.sign(&signing_bytes).to_bytes(),
+        ));
+        assert_eq!(request.signing_bytes().unwrap(), signing_bytes);
+
+        let round_tripped: ClientRequest =
+            marshalling::from_slice(&marshalling::to_vec(&request).unwrap()).unwrap();
+        assert_eq!(round_tripped.signing_bytes().unwrap(), signing_bytes);
+        assert!(round_tripped.signature.is_some());
+        assert!(round_tripped
+            .verify_signature(signing_key.verifying_key().as_bytes())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_request_id_optional() {
+        let request = client_request();
+        let round_tripped: ClientRequest =
+            marshalling::from_slice(&marshalling::to_vec(&request).unwrap()).unwrap();
+        assert_eq!(round_tripped.request_id, Some(RequestId([7; 16])));
+        assert_eq!(
+            format!("{:?}", RequestId([0xab; 16])),
+            "abababababababababababababababab"
+        );
+
+        // Requests from older clients don't include the field at all.
+        let without_id = ClientRequest {
+            request_id: None,
+            ..request
+        };
+        let encoded = marshalling::to_vec(&without_id).unwrap();
+        assert!(!encoded.windows(10).any(|w| w == b"request_id"));
+        let decoded: ClientRequest = marshalling::from_slice(&encoded).unwrap();
+        assert_eq!(decoded.request_id, None);
+        assert_eq!(
+            decoded.signing_bytes().unwrap(),
+            marshalling::to_vec_canonical(&without_id).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let verifying_key = signing_key.verifying_key().to_bytes();
+
+        let mut request = client_request();
+        assert!(request.verify_signature(&verifying_key).is_err());
+
+        request.signature = Some(RequestSignature(
+            signing_key
+                .sign(&request.signing_bytes().unwrap())
+                .to_bytes(),
+        ));
+        assert!(request.verify_signature(&verifying_key).is_ok());
+
+        request.session_id = SessionId(43);
+        assert!(request.verify_signature(&verifying_key).is_err());
+    }
+
+    #[test]
+    fn test_check_content() {
+        assert!(ClientRequestKind::HandshakeOnly.check_content(None).is_ok());
+        assert!(ClientRequestKind::SecretsRequest
+            .check_content(Some(&SecretsRequest::Recover1))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_check_content_handshake_only_with_secrets_request() {
+        assert!(matches!(
+            ClientRequestKind::HandshakeOnly.check_content(Some(&SecretsRequest::Recover1)),
+            Err(ClientResponse::DecodingError {
+                stage: DecodeStage::Inner
+            })
+        ));
+    }
+
+    #[test]
+    fn test_check_content_secrets_request_without_secrets_request() {
+        assert!(matches!(
+            ClientRequestKind::SecretsRequest.check_content(None),
+            Err(ClientResponse::DecodingError {
+                stage: DecodeStage::Inner
+            })
+        ));
+    }
 }
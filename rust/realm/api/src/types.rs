@@ -12,7 +12,7 @@ use curve25519_dalek::Scalar;
 use digest::consts::U16;
 use digest::Mac;
 use rand_core::{CryptoRng, RngCore};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use subtle::ConstantTimeEq;
 use zeroize::Zeroize;
 
@@ -152,8 +152,14 @@ impl From<String> for SecretString {
 /// recover their PIN-protected secrets. Clients distribute their trust across
 /// multiple realms, which can run different software and hardware and can be
 /// operated independently.
-#[derive(Copy, Clone, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
-pub struct RealmId(#[serde(with = "bytes")] pub [u8; 16]);
+///
+/// It serializes as a byte string in binary formats such as the CBOR used by
+/// realms, and as a string of 32 lowercase hex digits in human-readable
+/// formats such as JSON. Its [`Display`](fmt::Display) and [`FromStr`]
+/// implementations use the same hex form, though parsing also accepts
+/// dashes, as in a UUID.
+#[derive(Copy, Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct RealmId(pub [u8; 16]);
 
 impl RealmId {
     /// Generates a new id with random data.
@@ -165,6 +171,12 @@ impl RealmId {
 }
 
 impl Debug for RealmId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for RealmId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut buf = [0u8; 32];
         hex::encode_to_slice(self.0, &mut buf).unwrap();
@@ -172,6 +184,27 @@ impl Debug for RealmId {
     }
 }
 
+impl Serialize for RealmId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            bytes::serialize(&self.0, serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RealmId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            Self::from_str(&s).map_err(serde::de::Error::custom)
+        } else {
+            bytes::deserialize(deserializer).map(Self)
+        }
+    }
+}
+
 impl FromStr for RealmId {
     type Err = &'static str;
 
@@ -238,6 +271,39 @@ impl From<[u8; 16]> for RegistrationVersion {
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct SessionId(pub u32);
 
+/// Identifies a single client request across the client's and the realm's
+/// logs.
+///
+/// The client chooses the ID randomly, independent of any secret material,
+/// and reuses it when it retries the same request. Request IDs need not be
+/// confidential.
+#[derive(Copy, Clone, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct RequestId(#[serde(with = "bytes")] pub [u8; 16]);
+
+impl RequestId {
+    /// Generates a new id with random data.
+    pub fn new_random<T: RngCore + CryptoRng>(rng: &mut T) -> Self {
+        let mut id = [0; 16];
+        rng.fill_bytes(&mut id);
+        Self(id)
+    }
+}
+
+impl Debug for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buf = [0u8; 32];
+        hex::encode_to_slice(self.0, &mut buf).unwrap();
+        f.write_str(core::str::from_utf8(&buf).unwrap())
+    }
+}
+
+/// An Ed25519 signature over a [`ClientRequest`](crate::requests::ClientRequest).
+///
+/// Signatures are public, so unlike the secret byte types this is compared
+/// and printed as-is.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RequestSignature(#[serde(with = "bytes")] pub [u8; 64]);
+
 /// A share of the encryption key scalar.
 ///
 /// The client needs a threshold number of such shares, along with the PIN,
@@ -318,6 +384,27 @@ impl From<[u8; 32]> for UserSecretAccessKey {
     }
 }
 
+/// The AEAD used to encrypt an [`EncryptedUserSecret`].
+///
+/// This is recorded with each registration so that recovery decrypts the
+/// secret with the same AEAD that encrypted it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum SecretCipher {
+    /// ChaCha20-Poly1305 ([RFC 8439](https://www.rfc-editor.org/rfc/rfc8439)).
+    #[default]
+    ChaCha20Poly1305,
+    /// AES-256 in Galois/Counter Mode.
+    Aes256Gcm,
+}
+
+impl SecretCipher {
+    /// Returns true for the default cipher, which is omitted when
+    /// serializing registrations for compatibility with older realms.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 /// Defines restrictions on how a secret may be accessed.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Policy {
@@ -397,6 +484,11 @@ impl From<[u8; 16]> for UnlockKeyTag {
 /// before using its [`UserSecretEncryptionKeyScalarShare`]
 /// in recovery and avoid a Denial-of-Service attack by
 /// a misbheaving realm.
+///
+/// The commitment also covers the [`SecretCipher`], so that a realm can't
+/// substitute a different cipher for the one the secret was encrypted with.
+/// The default cipher isn't included, which keeps the commitments of
+/// registrations made before ciphers were selectable unchanged.
 #[derive(Clone, Debug, Deserialize, Eq, Serialize)]
 pub struct EncryptedUserSecretCommitment(SecretBytesArray<16>);
 
@@ -418,9 +510,10 @@ impl EncryptedUserSecretCommitment {
         realm_id: &RealmId,
         encryption_key_scalar_share: &UserSecretEncryptionKeyScalarShare,
         encrypted_secret: &EncryptedUserSecret,
+        secret_cipher: SecretCipher,
     ) -> Self {
         let label = b"Encrypted User Secret Commitment";
-        let mac: [u8; 16] = <Blake2sMac<U16> as Mac>::new(unlock_key.expose_secret().into())
+        let mut mac = <Blake2sMac<U16> as Mac>::new(unlock_key.expose_secret().into())
             .chain_update(to_be4(label.len()))
             .chain_update(label)
             .chain_update(to_be4(realm_id.0.len()))
@@ -428,10 +521,17 @@ impl EncryptedUserSecretCommitment {
             .chain_update(to_be4(encryption_key_scalar_share.as_bytes().len()))
             .chain_update(encryption_key_scalar_share.as_bytes())
             .chain_update(to_be4(encrypted_secret.expose_secret().len()))
-            .chain_update(encrypted_secret.expose_secret())
-            .finalize()
-            .into_bytes()
-            .into();
+            .chain_update(encrypted_secret.expose_secret());
+        let cipher_label: &[u8] = match secret_cipher {
+            SecretCipher::ChaCha20Poly1305 => &[],
+            SecretCipher::Aes256Gcm => b"AES-256-GCM",
+        };
+        if !cipher_label.is_empty() {
+            mac = mac
+                .chain_update(to_be4(cipher_label.len()))
+                .chain_update(cipher_label);
+        }
+        let mac: [u8; 16] = mac.finalize().into_bytes().into();
         Self::from(mac)
     }
 
@@ -484,8 +584,15 @@ impl From<[u8; 32]> for UnlockKeyCommitment {
 
 #[cfg(test)]
 mod tests {
-    use crate::types::{RealmId, SecretBytesArray, SecretBytesVec};
+    use core::str::FromStr;
 
+    use crate::types::{
+        EncryptedUserSecret, EncryptedUserSecretCommitment, RealmId, SecretBytesArray,
+        SecretBytesVec, SecretCipher, UnlockKey, UnlockKeyCommitment, UnlockKeyTag,
+        UserSecretEncryptionKeyScalarShare,
+    };
+
+    use subtle::ConstantTimeEq;
     use zeroize::Zeroize;
 
     #[test]
@@ -497,12 +604,92 @@ mod tests {
         assert_eq!("f0e1d2c3b4a59687ff00112233445566", format!("{r:?}"))
     }
 
+    #[test]
+    fn test_realm_id_display_from_str() {
+        let r = RealmId([
+            0xf0, 0xe1, 0xd2, 0xc3, 0xb4, 0xa5, 0x96, 0x87, 0xff, 0x00, 0x11, 0x22, 0x33, 0x44,
+            0x55, 0x66,
+        ]);
+        assert_eq!(r.to_string(), "f0e1d2c3b4a59687ff00112233445566");
+        assert_eq!(RealmId::from_str(&r.to_string()), Ok(r));
+        assert_eq!(
+            RealmId::from_str("F0E1D2C3-B4A5-9687-FF00-112233445566"),
+            Ok(r)
+        );
+        assert!(RealmId::from_str("f0e1d2c3").is_err());
+    }
+
+    #[test]
+    fn test_realm_id_serde() {
+        let r = RealmId([0xab; 16]);
+
+        // Binary formats keep the byte string that realms expect.
+        let cbor = juicebox_marshalling::to_vec(&r).unwrap();
+        let mut expected = vec![0x50];
+        expected.extend([0xab; 16]);
+        assert_eq!(cbor, expected);
+        assert_eq!(juicebox_marshalling::from_slice::<RealmId>(&cbor), Ok(r));
+
+        let json = serde_json::to_string(&r).unwrap();
+        assert_eq!(json, "\"abababababababababababababababab\"");
+        assert_eq!(serde_json::from_str::<RealmId>(&json).unwrap(), r);
+        assert!(serde_json::from_str::<RealmId>("\"abab\"").is_err());
+    }
+
+    #[test]
+    fn test_tags_and_commitments_compare_in_constant_time() {
+        // These types implement `PartialEq` with `ct_eq` instead of deriving
+        // it. A test can't observe the timing, so this checks that the two
+        // agree, including for differences in only the first or last byte.
+        fn check<T: ConstantTimeEq + PartialEq + From<[u8; N]>, const N: usize>() {
+            let a = T::from([7; N]);
+            let mut first = [7; N];
+            first[0] = 8;
+            let mut last = [7; N];
+            last[N - 1] = 8;
+            for (b, equal) in [
+                (T::from([7; N]), true),
+                (T::from(first), false),
+                (T::from(last), false),
+            ] {
+                assert_eq!(bool::from(a.ct_eq(&b)), equal);
+                assert_eq!(a == b, equal);
+            }
+        }
+        check::<UnlockKeyTag, 16>();
+        check::<EncryptedUserSecretCommitment, 16>();
+        check::<UnlockKeyCommitment, 32>();
+    }
+
     #[test]
     fn test_secret_bytes_vec_redaction() {
         let secret_bytes = SecretBytesVec::from(b"some secret".to_vec());
         assert_eq!(format!("{:?}", secret_bytes), "SecretBytesVec(REDACTED)");
     }
 
+    #[test]
+    fn test_encrypted_secret_commitment_binds_cipher() {
+        let commitment = |cipher| {
+            EncryptedUserSecretCommitment::derive(
+                &UnlockKey::from([1; 32]),
+                &RealmId([2; 16]),
+                &UserSecretEncryptionKeyScalarShare::try_from([3; 32]).unwrap(),
+                &EncryptedUserSecret::from([4; 145]),
+                cipher,
+            )
+        };
+        assert_ne!(
+            commitment(SecretCipher::ChaCha20Poly1305),
+            commitment(SecretCipher::Aes256Gcm)
+        );
+        // The default cipher keeps the commitment from before ciphers were
+        // selectable.
+        assert_eq!(
+            commitment(SecretCipher::ChaCha20Poly1305).expose_secret(),
+            &[155, 208, 227, 251, 96, 62, 234, 101, 54, 163, 242, 80, 133, 112, 136, 204]
+        );
+    }
+
     #[test]
     fn test_secret_bytes_vec_zeroize() {
         let mut secret_bytes = SecretBytesVec::from(b"some secret".to_vec());
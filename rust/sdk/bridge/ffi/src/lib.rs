@@ -4,7 +4,9 @@ pub mod http;
 
 use auth::{AuthTokenGetFn, AuthTokenManager};
 use juicebox_sdk as sdk;
-use juicebox_sdk_bridge::{Client, DeleteError, PinHashingMode, RecoverError, RegisterError};
+use juicebox_sdk_bridge::{
+    Client, DeleteError, PinHashingMode, RealmRole, RecoverError, RegisterError,
+};
 use libc::{c_char, c_void};
 use std::ffi::CString;
 use std::sync::Once;
@@ -20,6 +22,7 @@ pub struct Realm {
     pub id: [u8; 16],
     pub address: *const c_char,
     pub public_key: *const UnmanagedArray<u8>,
+    pub role: RealmRole,
 }
 
 impl From<&Realm> for sdk::Realm {
@@ -40,6 +43,7 @@ impl From<&Realm> for sdk::Realm {
             id: sdk::RealmId(ffi.id),
             address,
             public_key,
+            role: sdk::RealmRole::from(ffi.role),
         }
     }
 }
@@ -52,23 +56,23 @@ pub struct Configuration(sdk::Configuration);
 /// # Arguments
 ///
 /// * `configuration` – Represents the current configuration. The configuration
-/// provided must include at least one `JuiceboxRealm`.
+///   provided must include at least one `JuiceboxRealm`.
 /// * `previous_configurations` – Represents any other configurations you have
-/// previously registered with that you may not yet have migrated the data from.
-/// During `juicebox_client_recover`, they will be tried if the current user has not yet
-/// registered on the current configuration. These should be ordered from most recently
-/// to least recently used.
+///   previously registered with that you may not yet have migrated the data from.
+///   During `juicebox_client_recover`, they will be tried if the current user has not yet
+///   registered on the current configuration. These should be ordered from most recently
+///   to least recently used.
 /// * `auth_token` – Represents the authority to act as a particular user
-/// and should be valid for the lifetime of the `JuiceboxClient`.
+///   and should be valid for the lifetime of the `JuiceboxClient`.
 /// * `http_send` – A function pointer `http_send` that will be called when the client
-/// wishes to make a network request. The appropriate request should be executed by you,
-/// and the the response provided to the response function pointer. This send
-/// should be performed asynchronously. `http_send` should not block on
-/// performing the request, and the response should be returned to the
-/// `response` function pointer argument when the asynchronous work has
-/// completed. The request parameter is only valid for the lifetime of the
-/// `http_send` function and should not be accessed after returning from the
-/// function.
+///   wishes to make a network request. The appropriate request should be executed by you,
+///   and the the response provided to the response function pointer. This send
+///   should be performed asynchronously. `http_send` should not block on
+///   performing the request, and the response should be returned to the
+///   `response` function pointer argument when the asynchronous work has
+///   completed. The request parameter is only valid for the lifetime of the
+///   `http_send` function and should not be accessed after returning from the
+///   function.
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn juicebox_client_create(
@@ -86,14 +90,13 @@ pub unsafe extern "C" fn juicebox_client_create(
             (*(*configuration)).0.to_owned()
         })
         .collect();
-    let sdk = sdk::ClientBuilder::new()
+    let builder = sdk::ClientBuilder::new()
         .tokio_sleeper()
         .configuration((*configuration).0.to_owned())
         .previous_configurations(previous_configurations)
         .auth_token_manager(AuthTokenManager::new(auth_token_get))
-        .http(HttpClient::new(http_send))
-        .build();
-    Box::into_raw(Box::new(Client::new(sdk)))
+        .http(HttpClient::new(http_send));
+    Box::into_raw(Box::new(Client::new(builder)))
 }
 
 #[no_mangle]
@@ -130,6 +133,10 @@ pub unsafe extern "C" fn juicebox_configuration_create(
         register_threshold,
         recover_threshold,
         pin_hashing_mode: sdk::PinHashingMode::from(pin_hashing_mode as u8),
+        secret_cipher: sdk::SecretCipher::ChaCha20Poly1305,
+        retry_policy: sdk::RetryPolicy::default(),
+        max_concurrency: None,
+        require_public_keys: false,
     })))
 }
 
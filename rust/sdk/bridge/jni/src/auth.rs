@@ -6,29 +6,43 @@ use jni::{
     JNIEnv, JavaVM,
 };
 use juicebox_sdk as sdk;
+use sdk::Sleeper;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::Mutex;
+use std::time::Duration;
 
 use crate::{
-    jni_array, jni_object, jni_signature,
+    catch_panic, jni_array, jni_object, jni_signature,
     types::{JNI_BYTE_TYPE, JNI_LONG_TYPE, JNI_VOID_TYPE, JUICEBOX_JNI_REALM_ID_TYPE},
 };
 
+/// How long to wait before asking the app for a token again after it
+/// returned none.
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+
 pub struct AuthTokenManager {
     get_function: GlobalRef,
     jvm: JavaVM,
     await_get_map: Mutex<HashMap<i64, Sender<Option<sdk::AuthToken>>>>,
     next_await_id: AtomicI64,
+    retries: u32,
+    sleeper: sdk::TokioSleeper,
 }
 
 impl AuthTokenManager {
-    pub fn new(get_function: GlobalRef, jvm: JavaVM) -> Self {
+    /// Creates a manager that asks `get_function` for tokens. If it returns
+    /// none, such as while the app refreshes the token asynchronously, the
+    /// manager asks again up to `retries` more times, waiting
+    /// [`RETRY_DELAY`] before each.
+    pub fn new(get_function: GlobalRef, jvm: JavaVM, retries: u32) -> Self {
         AuthTokenManager {
             get_function,
             jvm,
             await_get_map: Mutex::new(HashMap::new()),
             next_await_id: AtomicI64::new(0),
+            retries,
+            sleeper: sdk::TokioSleeper,
         }
     }
 
@@ -46,6 +60,21 @@ impl AuthTokenManager {
 #[async_trait]
 impl sdk::AuthTokenManager for AuthTokenManager {
     async fn get(&self, realm: &sdk::RealmId) -> Option<sdk::AuthToken> {
+        let mut auth_token = self.request(realm).await;
+        for _ in 0..self.retries {
+            if auth_token.is_some() {
+                break;
+            }
+            self.sleeper.sleep(RETRY_DELAY).await;
+            auth_token = self.request(realm).await;
+        }
+        auth_token
+    }
+}
+
+impl AuthTokenManager {
+    /// Asks the app for a token once.
+    async fn request(&self, realm: &sdk::RealmId) -> Option<sdk::AuthToken> {
         let (tx, rx) = channel();
         {
             let mut env = self.jvm.attach_current_thread().unwrap();
@@ -88,22 +117,24 @@ impl sdk::AuthTokenManager for AuthTokenManager {
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_authTokenGetComplete(
-    _env: JNIEnv,
+    mut env: JNIEnv,
     _class: JClass,
     context: jlong,
     context_id: jlong,
     auth_token: jlong,
 ) {
-    let auth_token_manager = context as *const AuthTokenManager;
-    let auth_token = auth_token as *const sdk::AuthToken;
-
-    let auth_token = if auth_token.is_null() {
-        None
-    } else {
-        Some((*auth_token).to_owned())
-    };
+    catch_panic(&mut env, (), |_env| {
+        let auth_token_manager = context as *const AuthTokenManager;
+        let auth_token = auth_token as *const sdk::AuthToken;
+
+        let auth_token = if auth_token.is_null() {
+            None
+        } else {
+            Some((*auth_token).to_owned())
+        };
 
-    (*auth_token_manager).get_callback(context_id, auth_token);
+        (*auth_token_manager).get_callback(context_id, auth_token);
+    })
 }
 
 #[no_mangle]
@@ -113,42 +144,48 @@ pub extern "C" fn Java_xyz_juicebox_sdk_internal_Native_authTokenGeneratorCreate
     _class: JClass,
     json: JString,
 ) -> jlong {
-    let json: String = env.get_string(&json).unwrap().into();
-    Box::into_raw(Box::new(
-        sdk::client_auth::AuthTokenGenerator::from_json(&json).unwrap(),
-    )) as jlong
+    catch_panic(&mut env, 0, |env| {
+        let json: String = env.get_string(&json).unwrap().into();
+        Box::into_raw(Box::new(
+            sdk::client_auth::AuthTokenGenerator::from_json(&json).unwrap(),
+        )) as jlong
+    })
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_authTokenGeneratorDestroy(
-    _env: JNIEnv,
+    mut env: JNIEnv,
     _class: JClass,
     generator: jlong,
 ) {
-    drop(Box::from_raw(
-        generator as *mut sdk::client_auth::AuthTokenGenerator,
-    ));
+    catch_panic(&mut env, (), |_env| {
+        drop(Box::from_raw(
+            generator as *mut sdk::client_auth::AuthTokenGenerator,
+        ));
+    })
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_authTokenGeneratorVend(
-    env: JNIEnv,
+    mut env: JNIEnv,
     _class: JClass,
     generator: jlong,
     realm_id: JByteArray,
     secret_id: JByteArray,
 ) -> jlong {
-    let generator = generator as *mut sdk::client_auth::AuthTokenGenerator;
-    let realm_id =
-        TryInto::<[u8; 16]>::try_into(env.convert_byte_array(realm_id).unwrap()).unwrap();
-    let secret_id =
-        TryInto::<[u8; 16]>::try_into(env.convert_byte_array(secret_id).unwrap()).unwrap();
-    Box::into_raw(Box::new((*generator).vend(
-        &sdk::RealmId(realm_id),
-        &sdk::client_auth::SecretId(secret_id),
-    ))) as jlong
+    catch_panic(&mut env, 0, |env| {
+        let generator = generator as *mut sdk::client_auth::AuthTokenGenerator;
+        let realm_id =
+            TryInto::<[u8; 16]>::try_into(env.convert_byte_array(realm_id).unwrap()).unwrap();
+        let secret_id =
+            TryInto::<[u8; 16]>::try_into(env.convert_byte_array(secret_id).unwrap()).unwrap();
+        Box::into_raw(Box::new((*generator).vend(
+            &sdk::RealmId(realm_id),
+            &sdk::client_auth::SecretId(secret_id),
+        ))) as jlong
+    })
 }
 
 #[no_mangle]
@@ -158,27 +195,33 @@ pub extern "C" fn Java_xyz_juicebox_sdk_internal_Native_authTokenCreate(
     _class: JClass,
     jwt: JString,
 ) -> jlong {
-    let jwt: String = env.get_string(&jwt).unwrap().into();
-    Box::into_raw(Box::new(sdk::AuthToken::from(jwt))) as jlong
+    catch_panic(&mut env, 0, |env| {
+        let jwt: String = env.get_string(&jwt).unwrap().into();
+        Box::into_raw(Box::new(sdk::AuthToken::from(jwt))) as jlong
+    })
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_authTokenDestroy(
-    _env: JNIEnv,
+    mut env: JNIEnv,
     _class: JClass,
     token: jlong,
 ) {
-    drop(Box::from_raw(token as *mut sdk::AuthToken));
+    catch_panic(&mut env, (), |_env| {
+        drop(Box::from_raw(token as *mut sdk::AuthToken));
+    })
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_authTokenString<'a>(
-    env: JNIEnv<'a>,
+    mut env: JNIEnv<'a>,
     _class: JClass<'a>,
     token: jlong,
 ) -> JString<'a> {
-    let token = token as *mut sdk::AuthToken;
-    env.new_string((*token).expose_secret()).unwrap()
+    catch_panic(&mut env, JString::default(), |env| {
+        let token = token as *mut sdk::AuthToken;
+        env.new_string((*token).expose_secret()).unwrap()
+    })
 }
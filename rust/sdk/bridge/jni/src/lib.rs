@@ -1,5 +1,8 @@
 pub mod auth;
 pub mod http;
+pub mod logging;
+#[cfg(debug_assertions)]
+mod seeded;
 
 #[macro_use]
 mod types;
@@ -11,15 +14,20 @@ use jni::{
     JNIEnv,
 };
 use juicebox_sdk as sdk;
-use juicebox_sdk_bridge::{Client, DeleteError, RecoverError, RegisterError};
+use juicebox_sdk_bridge::{Client, DeleteError, RealmRole, RecoverError, RegisterError, Runtime};
+use std::any::Any;
 use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
 use std::str::FromStr;
+use std::sync::Arc;
 use url::Url;
 
 use crate::http::HttpClient;
 use crate::types::{
-    JNI_BYTE_TYPE, JNI_INTEGER_TYPE, JNI_SHORT_OBJECT_TYPE, JNI_SHORT_TYPE, JNI_STRING_TYPE,
-    JNI_VOID_TYPE, JUICEBOX_JNI_HTTP_HEADER_TYPE, JUICEBOX_JNI_REALM_ID_TYPE,
+    JNI_BYTE_TYPE, JNI_ILLEGAL_ARGUMENT_EXCEPTION_TYPE, JNI_INTEGER_TYPE, JNI_SHORT_OBJECT_TYPE,
+    JNI_SHORT_TYPE, JNI_STRING_TYPE, JNI_VOID_TYPE, JUICEBOX_JNI_HTTP_HEADER_TYPE,
+    JUICEBOX_JNI_NATIVE_PANIC_EXCEPTION_TYPE, JUICEBOX_JNI_REALM_ID_TYPE,
+    JUICEBOX_JNI_REALM_ROLE_TYPE, JUICEBOX_JNI_RECOVER_RESULT_TYPE,
 };
 
 #[no_mangle]
@@ -31,6 +39,32 @@ pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_clientCreate(
     previous_configurations: JLongArray,
     auth_token_get: JObject,
     http_send: JObject,
+    runtime: jlong,
+    auth_token_retries: jint,
+) -> jlong {
+    catch_panic(&mut env, 0, |env| {
+        create_client(
+            env,
+            configuration,
+            previous_configurations,
+            auth_token_get,
+            http_send,
+            runtime,
+            auth_token_retries,
+        )
+    })
+}
+
+/// The body of `clientCreate`, shared with the debug-only
+/// `clientCreateWithSeed`.
+pub(crate) unsafe fn create_client(
+    env: &mut JNIEnv,
+    configuration: jlong,
+    previous_configurations: JLongArray,
+    auth_token_get: JObject,
+    http_send: JObject,
+    runtime: jlong,
+    auth_token_retries: jint,
 ) -> jlong {
     let configuration = configuration as *mut sdk::Configuration;
     assert!(!configuration.is_null());
@@ -49,42 +83,81 @@ pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_clientCreate(
         })
         .collect();
 
-    let sdk = sdk::ClientBuilder::new()
+    let builder = sdk::ClientBuilder::new()
         .configuration((*configuration).to_owned())
         .previous_configurations(previous_configurations)
         .auth_token_manager(AuthTokenManager::new(
             env.new_global_ref(auth_token_get).unwrap(),
             env.get_java_vm().unwrap(),
+            auth_token_retries.try_into().unwrap(),
         ))
         .http(HttpClient::new(
             env.new_global_ref(http_send).unwrap(),
             env.get_java_vm().unwrap(),
         ))
-        .tokio_sleeper()
-        .build();
+        .tokio_sleeper();
 
-    Box::into_raw(Box::new(Client::new(sdk))) as jlong
+    let client = if runtime == 0 {
+        Client::new(builder)
+    } else {
+        let runtime = &*(runtime as *const Arc<Runtime>);
+        Client::with_runtime(builder, runtime.clone())
+    };
+    Box::into_raw(Box::new(client)) as jlong
+}
+
+/// Creates a runtime that several clients can share by passing it to
+/// `clientCreate`. Each client keeps the runtime alive, so
+/// `runtimeDestroy` may be called while clients still use it. The runtime
+/// shuts down once it and every client created with it are destroyed.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub extern "C" fn Java_xyz_juicebox_sdk_internal_Native_runtimeCreate(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jlong {
+    catch_panic(&mut env, 0, |_env| {
+        Box::into_raw(Box::new(Arc::new(Runtime::new().unwrap()))) as jlong
+    })
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_runtimeDestroy(
+    mut env: JNIEnv,
+    _class: JClass,
+    runtime: jlong,
+) {
+    catch_panic(&mut env, (), |_env| {
+        drop(Box::from_raw(runtime as *mut Arc<Runtime>));
+    })
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_clientDestroy(
-    _env: JNIEnv,
+    mut env: JNIEnv,
     _class: JClass,
     client: jlong,
 ) {
-    drop(Box::from_raw(
-        client as *mut Client<HttpClient, AuthTokenManager>,
-    ));
+    catch_panic(&mut env, (), |_env| {
+        #[cfg(debug_assertions)]
+        seeded::forget(client);
+        drop(Box::from_raw(
+            client as *mut Client<HttpClient, AuthTokenManager>,
+        ));
+    })
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_sdkVersion<'local>(
-    env: JNIEnv<'local>,
+    mut env: JNIEnv<'local>,
     _class: JClass,
 ) -> JString<'local> {
-    env.new_string(sdk::VERSION).unwrap()
+    catch_panic(&mut env, JString::default(), |env| {
+        env.new_string(sdk::VERSION).unwrap()
+    })
 }
 
 #[no_mangle]
@@ -97,49 +170,77 @@ pub extern "C" fn Java_xyz_juicebox_sdk_internal_Native_configurationCreate(
     recover_threshold: jint,
     pin_hashing_mode: JObject,
 ) -> jlong {
-    let pin_hashing_mode: u8 = env
-        .call_method(
-            &pin_hashing_mode,
-            "ordinal",
-            jni_signature!(() => JNI_INTEGER_TYPE),
-            &[],
-        )
-        .unwrap()
-        .i()
-        .unwrap()
-        .try_into()
-        .unwrap();
+    catch_panic(&mut env, 0, |env| {
+        let pin_hashing_mode: u8 = env
+            .call_method(
+                &pin_hashing_mode,
+                "ordinal",
+                jni_signature!(() => JNI_INTEGER_TYPE),
+                &[],
+            )
+            .unwrap()
+            .i()
+            .unwrap()
+            .try_into()
+            .unwrap();
 
-    let jrealms_length = env.get_array_length(&jrealms).unwrap();
+        let jrealms_length = env.get_array_length(&jrealms).unwrap();
 
-    let mut realms = vec![];
-    for index in 0..jrealms_length {
-        let jrealm = env.get_object_array_element(&jrealms, index).unwrap();
+        let mut builder = sdk::ConfigurationBuilder::new()
+            .register_threshold(register_threshold.try_into().unwrap_or(0))
+            .recover_threshold(recover_threshold.try_into().unwrap_or(0))
+            .pin_hashing_mode(sdk::PinHashingMode::from(pin_hashing_mode));
+        for index in 0..jrealms_length {
+            let jrealm = env.get_object_array_element(&jrealms, index).unwrap();
 
-        let java_id = env
-            .get_field(&jrealm, "id", jni_object!(JUICEBOX_JNI_REALM_ID_TYPE))
-            .unwrap()
-            .l()
-            .unwrap();
-        let id = get_byte_array(&mut env, &java_id, "bytes").unwrap();
+            let java_id = env
+                .get_field(&jrealm, "id", jni_object!(JUICEBOX_JNI_REALM_ID_TYPE))
+                .unwrap()
+                .l()
+                .unwrap();
+            let id = get_byte_array(env, &java_id, "bytes").unwrap();
 
-        let address_string = get_string(&mut env, &jrealm, "address");
-        let address = Url::from_str(&address_string).unwrap();
-        let public_key = get_byte_array(&mut env, &jrealm, "publicKey");
+            let address_string = get_string(env, &jrealm, "address");
+            let Ok(address) = Url::from_str(&address_string) else {
+                throw_illegal_argument(env, &format!("invalid realm address {address_string:?}"));
+                return 0;
+            };
+            let public_key = get_byte_array(env, &jrealm, "publicKey");
 
-        realms.push(sdk::Realm {
-            id: sdk::RealmId(id.try_into().unwrap()),
-            address,
-            public_key,
-        });
-    }
+            let java_role = env
+                .get_field(&jrealm, "role", jni_object!(JUICEBOX_JNI_REALM_ROLE_TYPE))
+                .unwrap()
+                .l()
+                .unwrap();
+            let role: u8 = env
+                .call_method(
+                    &java_role,
+                    "ordinal",
+                    jni_signature!(() => JNI_INTEGER_TYPE),
+                    &[],
+                )
+                .unwrap()
+                .i()
+                .unwrap()
+                .try_into()
+                .unwrap();
 
-    Box::into_raw(Box::new(sdk::Configuration {
-        realms,
-        register_threshold: register_threshold.try_into().unwrap(),
-        recover_threshold: recover_threshold.try_into().unwrap(),
-        pin_hashing_mode: sdk::PinHashingMode::from(pin_hashing_mode),
-    })) as jlong
+            builder = builder.add_realm(sdk::Realm {
+                id: sdk::RealmId(id.try_into().unwrap()),
+                address,
+                public_key,
+                role: sdk::RealmRole::from(RealmRole::from(role)),
+            });
+        }
+
+        match builder.build() {
+            Ok(configuration) => Box::into_raw(Box::new(configuration)) as jlong,
+            Err(err) => {
+                throw_illegal_argument(env, &format!("invalid configuration: {err}"));
+                0
+            }
+        }
+    })
 }
 
 #[no_mangle]
@@ -149,37 +250,43 @@ pub extern "C" fn Java_xyz_juicebox_sdk_internal_Native_configurationCreateFromJ
     _class: JClass,
     json: JString,
 ) -> jlong {
-    let json: String = env.get_string(&json).unwrap().into();
-    Box::into_raw(Box::new(sdk::Configuration::from_json(&json).unwrap())) as jlong
+    catch_panic(&mut env, 0, |env| {
+        let json: String = env.get_string(&json).unwrap().into();
+        Box::into_raw(Box::new(sdk::Configuration::from_json(&json).unwrap())) as jlong
+    })
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_configurationDestroy(
-    _env: JNIEnv,
+    mut env: JNIEnv,
     _class: JClass,
     configuration: jlong,
 ) {
-    drop(Box::from_raw(configuration as *mut sdk::Configuration));
+    catch_panic(&mut env, (), |_env| {
+        drop(Box::from_raw(configuration as *mut sdk::Configuration));
+    })
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_configurationsAreEqual(
-    _env: JNIEnv,
+    mut env: JNIEnv,
     _class: JClass,
     configuration1: jlong,
     configuration2: jlong,
 ) -> jboolean {
-    let configuration1 = configuration1 as *mut sdk::Configuration;
-    let configuration2 = configuration2 as *mut sdk::Configuration;
-    if configuration1.is_null() && configuration2.is_null() {
-        return true as jboolean;
-    }
-    if configuration1.is_null() || configuration2.is_null() {
-        return false as jboolean;
-    }
-    (*configuration1 == *configuration2) as jboolean
+    catch_panic(&mut env, 0, |_env| {
+        let configuration1 = configuration1 as *mut sdk::Configuration;
+        let configuration2 = configuration2 as *mut sdk::Configuration;
+        if configuration1.is_null() && configuration2.is_null() {
+            return true as jboolean;
+        }
+        if configuration1.is_null() || configuration2.is_null() {
+            return false as jboolean;
+        }
+        (*configuration1 == *configuration2) as jboolean
+    })
 }
 
 #[no_mangle]
@@ -193,21 +300,40 @@ pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_clientRegister(
     info: JByteArray,
     num_guesses: jshort,
 ) {
-    let client = &*(client as *const Client<HttpClient, AuthTokenManager>);
-    let pin = env.convert_byte_array(pin).unwrap();
-    let secret = env.convert_byte_array(secret).unwrap();
-    let info = env.convert_byte_array(info).unwrap();
-    let num_guesses = num_guesses.try_into().unwrap();
-
-    if let Err(err) = client.runtime.block_on(client.sdk.register(
-        &sdk::Pin::from(pin),
-        &sdk::UserSecret::from(secret),
-        &sdk::UserInfo::from(info),
-        sdk::Policy { num_guesses },
-    )) {
-        let error = RegisterError::from(err);
-        throw(&mut env, error as i32, "Register");
-    }
+    catch_panic(&mut env, (), |env| {
+        #[cfg(debug_assertions)]
+        let handle = client;
+        let client = &*(client as *const Client<HttpClient, AuthTokenManager>);
+        let Ok(num_guesses) = u16::try_from(num_guesses) else {
+            throw_illegal_argument(
+                env,
+                &format!("numGuesses must not be negative, got {num_guesses}"),
+            );
+            return;
+        };
+        let pin = sdk::Pin::from(env.convert_byte_array(pin).unwrap());
+        let secret = sdk::UserSecret::from(env.convert_byte_array(secret).unwrap());
+        let info = sdk::UserInfo::from(env.convert_byte_array(info).unwrap());
+        let policy = sdk::Policy { num_guesses };
+
+        let result = client.block_on(async {
+            #[cfg(debug_assertions)]
+            if let Some(mut rng) = seeded::take(handle) {
+                let result = client
+                    .sdk
+                    .register_with_rng(&pin, &secret, &info, policy, &mut rng)
+                    .await;
+                seeded::put(handle, rng);
+                return result;
+            }
+            client.sdk.register(&pin, &secret, &info, policy).await
+        });
+        if let Err(err) = result {
+            let description = err.to_string();
+            let error = RegisterError::from(err);
+            throw(env, error as i32, "Register", &description);
+        }
+    })
 }
 
 #[no_mangle]
@@ -219,62 +345,64 @@ pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_clientRecover<'lo
     pin: JByteArray<'local>,
     info: JByteArray<'local>,
 ) -> JByteArray<'local> {
-    let client = &*(client as *const Client<HttpClient, AuthTokenManager>);
-    let pin = env.convert_byte_array(pin).unwrap();
-    let info = env.convert_byte_array(info).unwrap();
-
-    match client.runtime.block_on(
-        client
-            .sdk
-            .recover(&sdk::Pin::from(pin), &sdk::UserInfo::from(info)),
-    ) {
-        Ok(secret) => env.byte_array_from_slice(secret.expose_secret()).unwrap() as JByteArray,
-        Err(err) => {
-            let error = RecoverError::from(err);
-            let java_error_type = "xyz/juicebox/sdk/RecoverError";
-            let java_error_class = env.find_class(java_error_type).unwrap();
-            let java_error_values: JObjectArray = env
-                .call_static_method(
-                    java_error_class,
-                    "values",
-                    jni_signature!(() => jni_array!(jni_object!(java_error_type))),
-                    &[],
-                )
-                .unwrap()
-                .l()
-                .unwrap()
-                .into();
-            let java_error = env
-                .get_object_array_element(&java_error_values, error.reason as i32)
-                .unwrap();
-            let java_exception_class = env.find_class("xyz/juicebox/sdk/RecoverException").unwrap();
+    catch_panic(&mut env, JByteArray::default(), |env| {
+        let client = &*(client as *const Client<HttpClient, AuthTokenManager>);
+        let pin = env.convert_byte_array(pin).unwrap();
+        let info = env.convert_byte_array(info).unwrap();
+
+        match client.block_on(
+            client
+                .sdk
+                .recover(&sdk::Pin::from(pin), &sdk::UserInfo::from(info)),
+        ) {
+            Ok(secret) => env.byte_array_from_slice(secret.expose_secret()).unwrap() as JByteArray,
+            Err(err) => {
+                throw_recover_error(env, err);
+                JByteArray::default()
+            }
+        }
+    })
+}
 
-            let guesses_remaining: JObject = if error.guesses_remaining.is_null() {
-                JObject::null()
-            } else {
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_clientRecoverWithMeta<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass,
+    client: jlong,
+    pin: JByteArray<'local>,
+    info: JByteArray<'local>,
+) -> JObject<'local> {
+    catch_panic(&mut env, JObject::null(), |env| {
+        let client = &*(client as *const Client<HttpClient, AuthTokenManager>);
+        let pin = env.convert_byte_array(pin).unwrap();
+        let info = env.convert_byte_array(info).unwrap();
+
+        match client.block_on(
+            client
+                .sdk
+                .recover_detailed(&sdk::Pin::from(pin), &sdk::UserInfo::from(info)),
+        ) {
+            Ok(outcome) => {
+                let secret = env
+                    .byte_array_from_slice(outcome.secret.expose_secret())
+                    .unwrap();
                 env.new_object(
-                    JNI_SHORT_OBJECT_TYPE,
-                    jni_signature!((JNI_SHORT_TYPE) => JNI_VOID_TYPE),
-                    &[unsafe { *error.guesses_remaining as jshort }.into()],
-                )
-                .unwrap()
-            };
-
-            let java_exception: JThrowable = env
-                .new_object(
-                    java_exception_class,
-                    jni_signature!((jni_object!(java_error_type), jni_object!(JNI_SHORT_OBJECT_TYPE)) => JNI_VOID_TYPE),
+                    JUICEBOX_JNI_RECOVER_RESULT_TYPE,
+                    jni_signature!((jni_array!(JNI_BYTE_TYPE), JNI_SHORT_TYPE) => JNI_VOID_TYPE),
                     &[
-                        JValue::Object(&java_error),
-                        JValue::Object(&guesses_remaining),
+                        JValue::Object(&secret),
+                        JValue::Short(outcome.guesses_remaining as jshort),
                     ],
                 )
                 .unwrap()
-                .into();
-            env.throw(java_exception).unwrap();
-            JByteArray::default()
+            }
+            Err(err) => {
+                throw_recover_error(env, err);
+                JObject::null()
+            }
         }
-    }
+    })
 }
 
 #[no_mangle]
@@ -284,12 +412,29 @@ pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_clientDelete(
     _class: JClass,
     client: jlong,
 ) {
-    let client = &*(client as *const Client<HttpClient, AuthTokenManager>);
+    catch_panic(&mut env, (), |env| {
+        let client = &*(client as *const Client<HttpClient, AuthTokenManager>);
+
+        // The number of deleted records isn't surfaced to Kotlin yet.
+        if let Err(err) = client.block_on(client.sdk.delete()) {
+            let description = err.to_string();
+            let error = DeleteError::from(err);
+            throw(env, error as i32, "Delete", &description);
+        }
+    })
+}
 
-    if let Err(err) = client.runtime.block_on(client.sdk.delete()) {
-        let error = DeleteError::from(err);
-        throw(&mut env, error as i32, "Delete");
-    }
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_clientCancel(
+    mut env: JNIEnv,
+    _class: JClass,
+    client: jlong,
+) {
+    catch_panic(&mut env, (), |_env| {
+        let client = &*(client as *const Client<HttpClient, AuthTokenManager>);
+        client.cancel();
+    })
 }
 
 #[no_mangle]
@@ -300,43 +445,45 @@ pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_httpClientRequest
     http_client: jlong,
     response: JObject,
 ) {
-    let http_client = http_client as *const HttpClient;
-
-    let id = get_byte_array(&mut env, &response, "id").expect("id should not be null");
-    let status_code = get_short(&mut env, &response, "statusCode");
-    let body = get_byte_array(&mut env, &response, "body").expect("body should not be null");
-
-    let java_headers: JObjectArray = env
-        .get_field(
-            &response,
-            "headers",
-            jni_array!(jni_object!(JUICEBOX_JNI_HTTP_HEADER_TYPE)),
-        )
-        .unwrap()
-        .l()
-        .unwrap()
-        .into();
+    catch_panic(&mut env, (), |env| {
+        let http_client = http_client as *const HttpClient;
+
+        let id = get_byte_array(env, &response, "id").expect("id should not be null");
+        let status_code = get_short(env, &response, "statusCode");
+        let body = get_byte_array(env, &response, "body").expect("body should not be null");
+
+        let java_headers: JObjectArray = env
+            .get_field(
+                &response,
+                "headers",
+                jni_array!(jni_object!(JUICEBOX_JNI_HTTP_HEADER_TYPE)),
+            )
+            .unwrap()
+            .l()
+            .unwrap()
+            .into();
 
-    let java_headers_length = env.get_array_length(&java_headers).unwrap();
+        let java_headers_length = env.get_array_length(&java_headers).unwrap();
 
-    let mut headers = HashMap::new();
+        let mut headers = HashMap::new();
 
-    for index in 0..java_headers_length {
-        let java_header = env.get_object_array_element(&java_headers, index).unwrap();
+        for index in 0..java_headers_length {
+            let java_header = env.get_object_array_element(&java_headers, index).unwrap();
 
-        let name_string = get_string(&mut env, &java_header, "name");
-        let value_string = get_string(&mut env, &java_header, "value");
+            let name_string = get_string(env, &java_header, "name");
+            let value_string = get_string(env, &java_header, "value");
 
-        headers.insert(name_string, value_string);
-    }
+            headers.insert(name_string, value_string);
+        }
 
-    let response = sdk::http::Response {
-        status_code,
-        headers,
-        body,
-    };
+        let response = sdk::http::Response {
+            status_code,
+            headers,
+            body,
+        };
 
-    (*http_client).receive(id.try_into().unwrap(), Some(response));
+        (*http_client).receive(id.try_into().unwrap(), Some(response));
+    })
 }
 
 fn get_string(env: &mut JNIEnv, obj: &JObject, name: &str) -> String {
@@ -371,7 +518,15 @@ fn get_short(env: &mut JNIEnv, obj: &JObject, name: &str) -> u16 {
         .unwrap()
 }
 
-fn throw(env: &mut JNIEnv, error_code: i32, name: &str) {
+/// Throws an `IllegalArgumentException` with `message`.
+fn throw_illegal_argument(env: &mut JNIEnv, message: &str) {
+    env.throw_new(JNI_ILLEGAL_ARGUMENT_EXCEPTION_TYPE, message)
+        .unwrap();
+}
+
+/// Throws a `{name}Exception` for the `{name}Error` with ordinal
+/// `error_code`, with a message describing what failed.
+fn throw(env: &mut JNIEnv, error_code: i32, name: &str, description: &str) {
     let java_error_type = format!("xyz/juicebox/sdk/{}Error", name);
     let java_error_class = env.find_class(&java_error_type).unwrap();
     let java_error_values: JObjectArray = env
@@ -391,13 +546,102 @@ fn throw(env: &mut JNIEnv, error_code: i32, name: &str) {
     let java_exception_class = env
         .find_class(format!("xyz/juicebox/sdk/{}Exception", name))
         .unwrap();
+    let message = env
+        .new_string(format!("{name} failed: {description}"))
+        .unwrap();
+    let java_exception: JThrowable = env
+        .new_object(
+            java_exception_class,
+            jni_signature!((jni_object!(java_error_type), jni_object!(JNI_STRING_TYPE)) => JNI_VOID_TYPE),
+            &[JValue::Object(&java_error), JValue::Object(&message)],
+        )
+        .unwrap()
+        .into();
+    env.throw(java_exception).unwrap();
+}
+
+fn throw_recover_error(env: &mut JNIEnv, err: sdk::RecoverError) {
+    let description = err.to_string();
+    let error = RecoverError::from(err);
+    let java_error_type = "xyz/juicebox/sdk/RecoverError";
+    let java_error_class = env.find_class(java_error_type).unwrap();
+    let java_error_values: JObjectArray = env
+        .call_static_method(
+            java_error_class,
+            "values",
+            jni_signature!(() => jni_array!(jni_object!(java_error_type))),
+            &[],
+        )
+        .unwrap()
+        .l()
+        .unwrap()
+        .into();
+    let java_error = env
+        .get_object_array_element(&java_error_values, error.reason as i32)
+        .unwrap();
+    let java_exception_class = env.find_class("xyz/juicebox/sdk/RecoverException").unwrap();
+
+    let guesses_remaining: JObject = if error.guesses_remaining.is_null() {
+        JObject::null()
+    } else {
+        env.new_object(
+            JNI_SHORT_OBJECT_TYPE,
+            jni_signature!((JNI_SHORT_TYPE) => JNI_VOID_TYPE),
+            &[unsafe { *error.guesses_remaining as jshort }.into()],
+        )
+        .unwrap()
+    };
+
+    let message = env
+        .new_string(format!("Recover failed: {description}"))
+        .unwrap();
     let java_exception: JThrowable = env
         .new_object(
             java_exception_class,
-            jni_signature!((jni_object!(java_error_type)) => JNI_VOID_TYPE),
-            &[JValue::Object(&java_error)],
+            jni_signature!((jni_object!(java_error_type), jni_object!(JNI_SHORT_OBJECT_TYPE), jni_object!(JNI_STRING_TYPE)) => JNI_VOID_TYPE),
+            &[
+                JValue::Object(&java_error),
+                JValue::Object(&guesses_remaining),
+                JValue::Object(&message),
+            ],
         )
         .unwrap()
         .into();
     env.throw(java_exception).unwrap();
 }
+
+/// Runs the body of a JNI entry point, catching any panic so that it doesn't
+/// unwind into the JVM, which is undefined behavior.
+///
+/// On panic, this throws a `NativePanicException` with the panic message and
+/// returns `default`. If a Java exception is already pending, such as one
+/// that caused a JNI call to fail and the body to panic, that exception is
+/// left to propagate instead.
+pub(crate) fn catch_panic<'local, R>(
+    env: &mut JNIEnv<'local>,
+    default: R,
+    body: impl FnOnce(&mut JNIEnv<'local>) -> R,
+) -> R {
+    match panic::catch_unwind(AssertUnwindSafe(|| body(env))) {
+        Ok(result) => result,
+        Err(payload) => {
+            if !env.exception_check().unwrap_or(false) {
+                let _ = env.throw_new(
+                    JUICEBOX_JNI_NATIVE_PANIC_EXCEPTION_TYPE,
+                    panic_message(payload.as_ref()),
+                );
+            }
+            default
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "native code panicked"
+    }
+}
@@ -0,0 +1,161 @@
+use jni::{objects::JClass, sys::jint, JNIEnv};
+use std::ffi::CString;
+use std::io;
+use std::sync::OnceLock;
+use tracing::{Level, Metadata};
+use tracing_subscriber::{
+    filter::LevelFilter, fmt, fmt::MakeWriter, layer::SubscriberExt, registry::Registry, reload,
+    util::SubscriberInitExt,
+};
+
+use crate::catch_panic;
+
+/// The logcat tag for all of the SDK's logs.
+const TAG: &str = "JuiceboxSdk";
+
+// Priorities from `android.util.Log`.
+const ANDROID_LOG_VERBOSE: jint = 2;
+const ANDROID_LOG_DEBUG: jint = 3;
+const ANDROID_LOG_INFO: jint = 4;
+const ANDROID_LOG_WARN: jint = 5;
+const ANDROID_LOG_ERROR: jint = 6;
+
+#[cfg(target_os = "android")]
+#[link(name = "log")]
+extern "C" {
+    fn __android_log_write(
+        priority: std::ffi::c_int,
+        tag: *const std::ffi::c_char,
+        text: *const std::ffi::c_char,
+    ) -> std::ffi::c_int;
+}
+
+/// Changes the level of the installed subscriber.
+static LEVEL_HANDLE: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+/// Maps an `android.util.Log` priority to the least severe `tracing` level
+/// logged at that priority. Priorities above `ERROR`, such as `ASSERT`,
+/// turn logging off.
+fn level_filter(priority: jint) -> LevelFilter {
+    match priority {
+        ..=ANDROID_LOG_VERBOSE => LevelFilter::TRACE,
+        ANDROID_LOG_DEBUG => LevelFilter::DEBUG,
+        ANDROID_LOG_INFO => LevelFilter::INFO,
+        ANDROID_LOG_WARN => LevelFilter::WARN,
+        ANDROID_LOG_ERROR => LevelFilter::ERROR,
+        _ => LevelFilter::OFF,
+    }
+}
+
+fn priority(level: &Level) -> jint {
+    match *level {
+        Level::TRACE => ANDROID_LOG_VERBOSE,
+        Level::DEBUG => ANDROID_LOG_DEBUG,
+        Level::INFO => ANDROID_LOG_INFO,
+        Level::WARN => ANDROID_LOG_WARN,
+        Level::ERROR => ANDROID_LOG_ERROR,
+    }
+}
+
+/// Creates a [`LogcatLine`] for each event.
+struct Logcat;
+
+impl<'a> MakeWriter<'a> for Logcat {
+    type Writer = LogcatLine;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogcatLine::new(ANDROID_LOG_INFO)
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        LogcatLine::new(priority(meta.level()))
+    }
+}
+
+/// Buffers one formatted event and writes it to logcat when dropped, so that
+/// each event becomes a single log entry.
+struct LogcatLine {
+    priority: jint,
+    buffer: Vec<u8>,
+}
+
+impl LogcatLine {
+    fn new(priority: jint) -> Self {
+        Self {
+            priority,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl io::Write for LogcatLine {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LogcatLine {
+    fn drop(&mut self) {
+        while self.buffer.last() == Some(&b'\n') {
+            self.buffer.pop();
+        }
+        self.buffer.retain(|byte| *byte != 0);
+        let text = CString::new(std::mem::take(&mut self.buffer)).unwrap();
+        write_log(self.priority, &text);
+    }
+}
+
+#[cfg(target_os = "android")]
+fn write_log(priority: jint, text: &CString) {
+    let tag = CString::new(TAG).unwrap();
+    unsafe { __android_log_write(priority, tag.as_ptr(), text.as_ptr()) };
+}
+
+/// Outside of Android, such as in JVM tests on a desktop, logs go to stderr.
+#[cfg(not(target_os = "android"))]
+fn write_log(_priority: jint, text: &CString) {
+    eprintln!("{TAG}: {}", text.to_string_lossy());
+}
+
+/// Forwards the SDK's `tracing` events at or above `priority`, an
+/// `android.util.Log` priority, to logcat.
+///
+/// The first call installs a global subscriber. Later calls change its level,
+/// so logging can be turned up while diagnosing a problem and back down
+/// after. If the app already installed a different global subscriber, this
+/// does nothing.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub extern "C" fn Java_xyz_juicebox_sdk_internal_Native_enableLogging(
+    mut env: JNIEnv,
+    _class: JClass,
+    priority: jint,
+) {
+    catch_panic(&mut env, (), |_env| {
+        let level = level_filter(priority);
+        if let Some(handle) = LEVEL_HANDLE.get() {
+            _ = handle.modify(|filter| *filter = level);
+            return;
+        }
+
+        let (filter, handle) = reload::Layer::new(level);
+        let installed = tracing_subscriber::registry()
+            .with(filter)
+            .with(
+                fmt::layer()
+                    .with_ansi(false)
+                    .without_time()
+                    .with_writer(Logcat),
+            )
+            .try_init()
+            .is_ok();
+        if installed {
+            _ = LEVEL_HANDLE.set(handle);
+        }
+    })
+}
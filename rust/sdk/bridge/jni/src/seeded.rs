@@ -0,0 +1,73 @@
+//! A deterministic RNG seam for Android instrumentation tests.
+//!
+//! This module is only compiled into debug builds, so release builds of the
+//! library don't export `clientCreateWithSeed` at all and calling it fails
+//! with an `UnsatisfiedLinkError`.
+
+use jni::{
+    objects::{JByteArray, JClass, JLongArray, JObject},
+    sys::{jint, jlong},
+    JNIEnv,
+};
+use rand_chacha::ChaCha20Rng;
+use rand_core::SeedableRng;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::{catch_panic, create_client};
+
+/// The RNGs of seeded clients, keyed by client handle.
+static RNGS: Mutex<BTreeMap<jlong, ChaCha20Rng>> = Mutex::new(BTreeMap::new());
+
+/// Removes the seeded RNG for `client`, if it has one, so that it can be used
+/// across an await. Return it with [`put`] afterwards.
+pub(crate) fn take(client: jlong) -> Option<ChaCha20Rng> {
+    RNGS.lock().unwrap().remove(&client)
+}
+
+pub(crate) fn put(client: jlong, rng: ChaCha20Rng) {
+    RNGS.lock().unwrap().insert(client, rng);
+}
+
+/// Drops the seeded RNG for a destroyed client.
+pub(crate) fn forget(client: jlong) {
+    RNGS.lock().unwrap().remove(&client);
+}
+
+/// Like `clientCreate`, but the client's registrations draw their randomness
+/// from a ChaCha20 generator seeded with the 32-byte `seed`, so that tests
+/// can assert exact shares and ciphertexts. Recoveries still use the
+/// operating system's RNG, which doesn't affect their results.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn Java_xyz_juicebox_sdk_internal_Native_clientCreateWithSeed(
+    mut env: JNIEnv,
+    _class: JClass,
+    configuration: jlong,
+    previous_configurations: JLongArray,
+    auth_token_get: JObject,
+    http_send: JObject,
+    runtime: jlong,
+    auth_token_retries: jint,
+    seed: JByteArray,
+) -> jlong {
+    catch_panic(&mut env, 0, |env| {
+        let seed: [u8; 32] = env
+            .convert_byte_array(seed)
+            .unwrap()
+            .try_into()
+            .expect("seed must be 32 bytes");
+        let client = create_client(
+            env,
+            configuration,
+            previous_configurations,
+            auth_token_get,
+            http_send,
+            runtime,
+            auth_token_retries,
+        );
+        put(client, ChaCha20Rng::from_seed(seed));
+        client
+    })
+}
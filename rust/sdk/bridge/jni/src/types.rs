@@ -1,9 +1,13 @@
 pub const JUICEBOX_JNI_HTTP_HEADER_TYPE: &str = "xyz/juicebox/sdk/internal/Native$HttpHeader";
 pub const JUICEBOX_JNI_HTTP_REQUEST_TYPE: &str = "xyz/juicebox/sdk/internal/Native$HttpRequest";
 pub const JUICEBOX_JNI_REALM_ID_TYPE: &str = "xyz/juicebox/sdk/RealmId";
+pub const JUICEBOX_JNI_REALM_ROLE_TYPE: &str = "xyz/juicebox/sdk/RealmRole";
+pub const JUICEBOX_JNI_RECOVER_RESULT_TYPE: &str = "xyz/juicebox/sdk/RecoverResult";
+pub const JUICEBOX_JNI_NATIVE_PANIC_EXCEPTION_TYPE: &str = "xyz/juicebox/sdk/NativePanicException";
 
 pub const JNI_STRING_TYPE: &str = "java/lang/String";
 pub const JNI_SHORT_OBJECT_TYPE: &str = "java/lang/Short";
+pub const JNI_ILLEGAL_ARGUMENT_EXCEPTION_TYPE: &str = "java/lang/IllegalArgumentException";
 pub const JNI_LONG_TYPE: &str = "J";
 pub const JNI_BYTE_TYPE: &str = "B";
 pub const JNI_SHORT_TYPE: &str = "S";
@@ -4,7 +4,7 @@ use juicebox_sdk as sdk;
 mod tokio;
 
 #[cfg(feature = "tokio")]
-pub use self::tokio::Client;
+pub use self::tokio::{Client, Runtime};
 
 #[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::wasm_bindgen;
@@ -29,6 +29,8 @@ pub enum RegisterError {
     /// A transient error in sending or receiving requests to a realm.
     /// This request may succeed by trying again with the same parameters.
     Transient = 4,
+    /// The operation was stopped by cancelling the `Client`.
+    Cancelled = 5,
 }
 
 impl From<sdk::RegisterError> for RegisterError {
@@ -39,6 +41,7 @@ impl From<sdk::RegisterError> for RegisterError {
             sdk::RegisterError::Assertion => Self::Assertion,
             sdk::RegisterError::Transient => Self::Transient,
             sdk::RegisterError::RateLimitExceeded => Self::RateLimitExceeded,
+            sdk::RegisterError::Cancelled => Self::Cancelled,
         }
     }
 }
@@ -70,6 +73,14 @@ pub enum RecoverErrorReason {
     /// A transient error in sending or receiving requests to a realm.
     /// This request may succeed by trying again with the same parameters.
     Transient = 6,
+    /// The operation was stopped by cancelling the `Client`.
+    Cancelled = 7,
+    /// No guesses remained before this attempt, so the PIN was not checked.
+    /// The secret is locked and inaccessible.
+    Locked = 8,
+    /// The secret was registered with a different set of realms than the
+    /// configuration lists. No guess was used.
+    RealmSetMismatch = 9,
 }
 
 #[repr(C)]
@@ -111,6 +122,18 @@ impl From<sdk::RecoverError> for RecoverError {
                 reason: RecoverErrorReason::RateLimitExceeded,
                 guesses_remaining: std::ptr::null(),
             },
+            sdk::RecoverError::Cancelled => Self {
+                reason: RecoverErrorReason::Cancelled,
+                guesses_remaining: std::ptr::null(),
+            },
+            sdk::RecoverError::Locked => Self {
+                reason: RecoverErrorReason::Locked,
+                guesses_remaining: std::ptr::null(),
+            },
+            sdk::RecoverError::RealmSetMismatch => Self {
+                reason: RecoverErrorReason::RealmSetMismatch,
+                guesses_remaining: std::ptr::null(),
+            },
         }
     }
 }
@@ -143,6 +166,8 @@ pub enum DeleteError {
     /// A transient error in sending or receiving requests to a realm.
     /// This request may succeed by trying again with the same parameters.
     Transient = 4,
+    /// The operation was stopped by cancelling the `Client`.
+    Cancelled = 5,
 }
 
 impl From<sdk::DeleteError> for DeleteError {
@@ -153,6 +178,7 @@ impl From<sdk::DeleteError> for DeleteError {
             sdk::DeleteError::Assertion => DeleteError::Assertion,
             sdk::DeleteError::Transient => DeleteError::Transient,
             sdk::DeleteError::RateLimitExceeded => DeleteError::RateLimitExceeded,
+            sdk::DeleteError::Cancelled => DeleteError::Cancelled,
         }
     }
 }
@@ -165,3 +191,32 @@ pub enum PinHashingMode {
     /// A fast hash used for testing. Do not use in production.
     FastInsecure = 1,
 }
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub enum RealmRole {
+    /// The realm receives new registrations and participates in recovery.
+    ReadWrite = 0,
+    /// The realm participates in recovery but does not receive new
+    /// registrations, such as while it is being decommissioned.
+    RecoverOnly = 1,
+}
+
+impl From<u8> for RealmRole {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::ReadWrite,
+            1 => Self::RecoverOnly,
+            _ => panic!("unexpected value {:?}", value),
+        }
+    }
+}
+
+impl From<RealmRole> for sdk::RealmRole {
+    fn from(value: RealmRole) -> Self {
+        match value {
+            RealmRole::ReadWrite => Self::ReadWrite,
+            RealmRole::RecoverOnly => Self::RecoverOnly,
+        }
+    }
+}
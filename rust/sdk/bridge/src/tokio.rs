@@ -1,17 +1,50 @@
-use tokio::runtime::Runtime;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+pub use tokio::runtime::Runtime;
 
 use juicebox_sdk as sdk;
 
 pub struct Client<HttpClient: sdk::http::Client, Atm: sdk::AuthTokenManager> {
     pub sdk: sdk::Client<sdk::TokioSleeper, HttpClient, Atm>,
-    pub runtime: Runtime,
+    /// May be shared with other clients. It shuts down once the last client
+    /// and handle using it are dropped.
+    pub runtime: Arc<Runtime>,
+    /// The token for operations started since the last [`Client::cancel`].
+    cancellation_token: Mutex<sdk::CancellationToken>,
 }
 
 impl<HttpClient: sdk::http::Client, Atm: sdk::AuthTokenManager> Client<HttpClient, Atm> {
-    pub fn new(sdk: sdk::Client<sdk::TokioSleeper, HttpClient, Atm>) -> Self {
+    /// Creates a client with its own runtime.
+    pub fn new(builder: sdk::ClientBuilder<sdk::TokioSleeper, HttpClient, Atm>) -> Self {
+        Self::with_runtime(builder, Arc::new(Runtime::new().unwrap()))
+    }
+
+    /// Creates a client that runs its operations on `runtime`, which may be
+    /// shared with other clients.
+    pub fn with_runtime(
+        builder: sdk::ClientBuilder<sdk::TokioSleeper, HttpClient, Atm>,
+        runtime: Arc<Runtime>,
+    ) -> Self {
         Self {
-            sdk,
-            runtime: Runtime::new().unwrap(),
+            sdk: builder.build(),
+            runtime,
+            cancellation_token: Mutex::default(),
         }
     }
+
+    /// Runs one of `sdk`'s operations in `runtime` until it completes or
+    /// [`Client::cancel`] stops it.
+    pub fn block_on<F: Future>(&self, operation: F) -> F::Output {
+        let token = self.cancellation_token.lock().unwrap().clone();
+        self.runtime.block_on(token.scope(operation))
+    }
+
+    /// Cancels the operations in progress. Operations started afterwards get
+    /// a new token, so they aren't affected.
+    pub fn cancel(&self) {
+        let mut token = self.cancellation_token.lock().unwrap();
+        token.cancel();
+        *token = sdk::CancellationToken::new();
+    }
 }
@@ -68,6 +68,18 @@ impl From<sdk::RecoverError> for RecoverError {
                 reason: RecoverErrorReason::RateLimitExceeded,
                 guesses_remaining: None,
             },
+            sdk::RecoverError::Cancelled => Self {
+                reason: RecoverErrorReason::Cancelled,
+                guesses_remaining: None,
+            },
+            sdk::RecoverError::Locked => Self {
+                reason: RecoverErrorReason::Locked,
+                guesses_remaining: None,
+            },
+            sdk::RecoverError::RealmSetMismatch => Self {
+                reason: RecoverErrorReason::RealmSetMismatch,
+                guesses_remaining: None,
+            },
         }
     }
 }
@@ -80,7 +92,8 @@ impl Configuration {
     ///
     /// - `realms`: An array of remote services that the client interacts with.
     ///
-    /// Each `realm` is itself an Object containing an: id, address, and optionally a public_key.
+    /// Each `realm` is itself an Object containing an: id, address, and optionally a public_key
+    /// and a role.
     ///
     /// There must be between `registerThreshold` and 255
     /// realms, inclusive.
@@ -100,6 +113,11 @@ impl Configuration {
     /// - `Standard2019` - A tuned hash, secure for use on modern devices as of 2019 with low-entropy PINs.
     /// - `FastInsecure` - A fast hash used for testing. Do not use in production.
     ///
+    /// Possible realm roles are:
+    /// - `ReadWrite` - The default. The realm receives new registrations and participates in recovery.
+    /// - `RecoverOnly` - The realm participates in recovery but does not receive new registrations,
+    /// such as while it is being decommissioned.
+    ///
     /// An example configuration looks like:
     /// ```js
     /// const configuration = new Configuration({
@@ -249,7 +267,11 @@ impl Client {
     /// be rejected with a {@link DeleteError}.
     #[wasm_bindgen(skip_jsdoc)]
     pub async fn delete(&self) -> Result<(), DeleteError> {
-        self.0.delete().await.map_err(DeleteError::from)
+        self.0
+            .delete()
+            .await
+            .map(|_deleted| ())
+            .map_err(DeleteError::from)
     }
 }
 
@@ -546,10 +568,15 @@ mod tests {
                     id: sdk::RealmId([0; 16]),
                     address: url.parse().unwrap(),
                     public_key: None,
+                    role: sdk::RealmRole::ReadWrite,
                 }],
                 register_threshold: 1,
                 recover_threshold: 1,
                 pin_hashing_mode: sdk::PinHashingMode::FastInsecure,
+                secret_cipher: sdk::SecretCipher::ChaCha20Poly1305,
+                retry_policy: sdk::RetryPolicy::default(),
+                max_concurrency: None,
+                require_public_keys: false,
             }),
             to_value::<Vec<sdk::Configuration>>(&vec![]).unwrap().into(),
         )
@@ -0,0 +1,98 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Lets another thread or task stop a [`Client`](crate::Client)'s
+/// operations early.
+///
+/// Set it with
+/// [`ClientBuilder::cancellation_token`](crate::ClientBuilder::cancellation_token)
+/// and keep a clone. Once [`cancel`](Self::cancel) is called, the client's
+/// operations fail with a `Cancelled` error before their next request to a
+/// realm. Requests that are already in flight are allowed to finish, so a
+/// cancelled recovery may still have consumed a guess. A recovery whose PIN
+/// was right still makes the final request that gives that guess back.
+///
+/// The token stays cancelled, failing every later operation, until it is
+/// [`reset`](Self::reset). To cancel one operation without affecting others
+/// on the same client, give it its own token with [`scope`](Self::scope).
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the operations of every client using this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Allows new operations to run again after [`cancel`](Self::cancel).
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Runs a client operation so that it also stops once this token is
+    /// cancelled, in addition to the client's own token.
+    ///
+    /// This relies on the client never spawning tasks: everything the
+    /// operation does happens while the returned future is polled.
+    pub fn scope<F: Future>(&self, operation: F) -> impl Future<Output = F::Output> {
+        Scoped {
+            token: self.clone(),
+            operation: Box::pin(operation),
+        }
+    }
+}
+
+thread_local! {
+    /// The token of the [`CancellationToken::scope`] being polled on this
+    /// thread, if any.
+    static SCOPED_TOKEN: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+/// Returns true if the operation being polled on this thread was scoped to
+/// a token that has been cancelled.
+pub(crate) fn is_scope_cancelled() -> bool {
+    SCOPED_TOKEN.with(|token| {
+        token
+            .borrow()
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    })
+}
+
+struct Scoped<F> {
+    token: CancellationToken,
+    operation: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let outer = SCOPED_TOKEN.with(|token| token.replace(Some(self.token.clone())));
+        // Restores the outer scope even if the operation panics.
+        let _restore = RestoreScope(outer);
+        self.operation.as_mut().poll(cx)
+    }
+}
+
+struct RestoreScope(Option<CancellationToken>);
+
+impl Drop for RestoreScope {
+    fn drop(&mut self) {
+        SCOPED_TOKEN.with(|token| *token.borrow_mut() = self.0.take());
+    }
+}
@@ -0,0 +1,160 @@
+use futures::future::join_all;
+use std::error::Error;
+use std::fmt::{Debug, Display};
+
+use crate::{auth, http, request::RequestError, Client, Sleeper};
+use juicebox_realm_api::types::RealmId;
+
+/// Error type for a realm in [`Client::check_realms`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RealmCheckError {
+    /// The realm rejected the `Client`'s auth token.
+    InvalidAuth,
+
+    /// The SDK software is too old to communicate with this realm
+    /// and must be upgraded.
+    UpgradeRequired,
+
+    /// The tenant has exceeded their allowed number of operations. Try again
+    /// later.
+    RateLimitExceeded,
+
+    /// A software error has occurred. Verify your inputs, check for software
+    /// updates and try again.
+    Assertion,
+
+    /// The realm could not be reached, or failed to respond.
+    Transient,
+
+    /// The realm could not complete a handshake with its configured public
+    /// key. Either the configuration has the wrong key, or something other
+    /// than the realm answered.
+    KeyMismatch,
+}
+
+impl Display for RealmCheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl Error for RealmCheckError {}
+
+impl From<RequestError> for RealmCheckError {
+    fn from(e: RequestError) -> Self {
+        match e {
+            RequestError::InvalidAuth => Self::InvalidAuth,
+            RequestError::UpgradeRequired => Self::UpgradeRequired,
+            RequestError::RateLimitExceeded => Self::RateLimitExceeded,
+            RequestError::Transient => Self::Transient,
+            RequestError::Assertion => Self::Assertion,
+            RequestError::KeyMismatch => Self::KeyMismatch,
+            // Checks don't use the cancellation token, so this can't happen.
+            RequestError::Cancelled => Self::Assertion,
+        }
+    }
+}
+
+impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http, Atm> {
+    pub(crate) async fn perform_check_realms(&self) -> Vec<(RealmId, Result<(), RealmCheckError>)> {
+        let requests = self.configuration.realms.iter().map(|realm| async move {
+            let result = self
+                .check_realm_connection(realm)
+                .await
+                .map_err(RealmCheckError::from);
+            (realm.id, result)
+        });
+        join_all(requests).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::RealmCheckError;
+    use crate::testing::{MockRealmCluster, NoopSleeper};
+    use crate::{http, AuthToken, ClientBuilder, Configuration, RealmId};
+    use juicebox_marshalling as marshalling;
+    use juicebox_realm_api::requests::{ClientRequest, ClientRequestKind, ClientResponse};
+
+    #[tokio::test]
+    async fn test_check_software_realms() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(3, 2);
+        let realms: Vec<RealmId> = configuration.realms.iter().map(|realm| realm.id).collect();
+        let client = cluster.client(configuration, "apollo");
+
+        cluster.set_available(&realms[1], false);
+        cluster.set_hung(&realms[2], true);
+        let client_with_timeout = cluster
+            .client_builder(cluster.configuration(3, 2), "apollo")
+            .request_timeout(Duration::ZERO)
+            .build();
+        assert_eq!(
+            client_with_timeout.check_realms().await[2],
+            (realms[2], Err(RealmCheckError::Transient))
+        );
+
+        cluster.set_hung(&realms[2], false);
+        assert_eq!(
+            client.check_realms().await,
+            vec![
+                (realms[0], Ok(())),
+                (realms[1], Err(RealmCheckError::Transient)),
+                (realms[2], Ok(())),
+            ]
+        );
+        for realm in &realms {
+            assert_eq!(cluster.request_count(realm, "Recover1"), 0);
+        }
+    }
+
+    /// A hardware realm that rejects every auth token, recording the kind of
+    /// each request.
+    #[derive(Clone, Default)]
+    struct RejectingRealm {
+        kinds: Arc<Mutex<Vec<ClientRequestKind>>>,
+    }
+
+    #[async_trait]
+    impl http::Client for RejectingRealm {
+        async fn send(&self, request: http::Request) -> Option<http::Response> {
+            let request: ClientRequest = marshalling::from_slice(&request.body?).ok()?;
+            self.kinds.lock().unwrap().push(request.kind);
+            Some(http::Response {
+                status_code: 200,
+                headers: HashMap::new(),
+                body: marshalling::to_vec(&ClientResponse::InvalidAuth).unwrap(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_hardware_realm_auth() {
+        let configuration = Configuration::test_config(1, 1, 1);
+        let realm = configuration.realms[0].id;
+        let http = RejectingRealm::default();
+        let client = ClientBuilder::new()
+            .configuration(configuration)
+            .http(http.clone())
+            .sleeper(NoopSleeper)
+            .auth_token_manager(HashMap::<RealmId, AuthToken>::from([(
+                realm,
+                AuthToken::from(String::from("expired")),
+            )]))
+            .build();
+
+        assert_eq!(
+            client.check_realms().await,
+            vec![(realm, Err(RealmCheckError::InvalidAuth))]
+        );
+        assert_eq!(
+            *http.kinds.lock().unwrap(),
+            vec![ClientRequestKind::HandshakeOnly]
+        );
+    }
+}
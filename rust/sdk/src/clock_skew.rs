@@ -0,0 +1,200 @@
+use futures::future::join_all;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::instrument;
+
+use crate::{auth, http, Client, Realm, Sleeper};
+
+/// How long to wait for each realm's response when the client has no
+/// [`request_timeout`](crate::ClientBuilder::request_timeout).
+const DEFAULT_CLOCK_SKEW_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http, Atm> {
+    pub(crate) async fn perform_clock_skew(&self) -> Option<i64> {
+        let requests = self
+            .configuration
+            .realms
+            .iter()
+            .map(|realm| self.clock_skew_on_realm(realm));
+
+        let mut skews: Vec<i64> = join_all(requests).await.into_iter().flatten().collect();
+        if skews.is_empty() {
+            return None;
+        }
+        skews.sort_unstable();
+        Some(skews[(skews.len() - 1) / 2])
+    }
+
+    /// Returns the realm's clock minus the local clock, in seconds, based on
+    /// the `Date` header of a response from the realm.
+    #[instrument(level = "trace", skip(self))]
+    async fn clock_skew_on_realm(&self, realm: &Realm) -> Option<i64> {
+        let sent = unix_time(SystemTime::now())?;
+        let response = self
+            .http
+            .send(http::Request {
+                method: http::Method::Get,
+                url: realm.address.to_string(),
+                headers: Default::default(),
+                body: None,
+                timeout: Some(self.request_timeout.unwrap_or(DEFAULT_CLOCK_SKEW_TIMEOUT)),
+            })
+            .await?;
+        let received = unix_time(SystemTime::now())?;
+
+        let date = response
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("Date"))
+            .and_then(|(_, value)| parse_http_date(value))?;
+
+        // Assume the realm generated its response halfway through the round
+        // trip.
+        let local = sent + (received - sent) / 2;
+        Some(date - local)
+    }
+}
+
+fn unix_time(time: SystemTime) -> Option<i64> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs()
+        .try_into()
+        .ok()
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Parses an HTTP date in the preferred IMF-fixdate format (for example,
+/// `Sun, 06 Nov 1994 08:49:37 GMT`) into seconds since the Unix epoch.
+fn parse_http_date(value: &str) -> Option<i64> {
+    let (_weekday, rest) = value.split_once(", ")?;
+    let mut fields = rest.split(' ');
+    let day: i64 = fields.next()?.parse().ok()?;
+    let month_name = fields.next()?;
+    let month = MONTHS.iter().position(|m| *m == month_name)? as i64 + 1;
+    let year: i64 = fields.next()?.parse().ok()?;
+    let mut time = fields.next()?.split(':');
+    let hour: i64 = time.next()?.parse().ok()?;
+    let minute: i64 = time.next()?.parse().ok()?;
+    let second: i64 = time.next()?.parse().ok()?;
+    if fields.next()? != "GMT"
+        || fields.next().is_some()
+        || time.next().is_some()
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Formats seconds since the Unix epoch as an HTTP IMF-fixdate.
+#[cfg(any(test, feature = "testing"))]
+pub(crate) fn format_http_date(time: i64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    let days = time.div_euclid(86400);
+    let seconds = time.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Returns the number of days since 1970-01-01 for a proleptic Gregorian
+/// calendar date.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The inverse of [`days_from_civil`].
+#[cfg(any(test, feature = "testing"))]
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_http_date, parse_http_date};
+    use crate::testing::MockRealmCluster;
+
+    #[test]
+    fn test_http_date() {
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784111777)
+        );
+        assert_eq!(
+            parse_http_date("Thu, 29 Feb 2024 23:59:59 GMT"),
+            Some(1709251199)
+        );
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 PST"), None);
+
+        for time in [0, 784111777, 1709251199, 4102444800] {
+            assert_eq!(parse_http_date(&format_http_date(time)), Some(time));
+        }
+        assert_eq!(format_http_date(784111777), "Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+
+    #[tokio::test]
+    async fn test_clock_skew() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(3, 2);
+        cluster.set_clock_offset(&configuration.realms[0].id, 300);
+        cluster.set_clock_offset(&configuration.realms[1].id, 320);
+        cluster.set_clock_offset(&configuration.realms[2].id, -5);
+        let client = cluster.client(configuration, "apollo");
+
+        let skew = client.clock_skew().await.unwrap();
+        assert!((299..=301).contains(&skew), "{skew}");
+    }
+
+    #[tokio::test]
+    async fn test_clock_skew_behind() {
+        let cluster = MockRealmCluster::new(1);
+        let configuration = cluster.configuration(1, 1);
+        cluster.set_clock_offset(&configuration.realms[0].id, -300);
+        let client = cluster.client(configuration, "apollo");
+
+        let skew = client.clock_skew().await.unwrap();
+        assert!((-301..=-299).contains(&skew), "{skew}");
+    }
+
+    #[tokio::test]
+    async fn test_clock_skew_unavailable() {
+        let cluster = MockRealmCluster::new(1);
+        let configuration = cluster.configuration(1, 1);
+        cluster.set_available(&configuration.realms[0].id, false);
+        let client = cluster.client(configuration, "apollo");
+
+        assert_eq!(client.clock_skew().await, None);
+    }
+}
@@ -1,22 +1,33 @@
+use rand::{rngs::OsRng, Rng};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, ops::Deref};
+use std::error::Error;
+use std::fmt::Display;
+use std::{collections::HashSet, ops::Deref, time::Duration};
 
-use crate::{PinHashingMode, Realm};
-use juicebox_realm_api::types::RealmId;
+use crate::{types::RealmSetHash, PinHashingMode, Realm};
+use juicebox_realm_api::types::{RealmId, SecretCipher};
 use juicebox_secret_sharing::Index;
 
 /// The parameters used to configure a [`Client`](crate::Client).
+///
+/// Prefer building one with [`ConfigurationBuilder`], which checks the
+/// result. A configuration filled in directly isn't checked until it's
+/// passed to a [`ClientBuilder`](crate::ClientBuilder) or
+/// [`Configuration::validate`].
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Configuration {
     /// The remote services that the client interacts with.
     ///
     /// There must be between `register_threshold` and 255 realms, inclusive.
+    /// Realms with the [`RealmRole::RecoverOnly`](crate::RealmRole::RecoverOnly)
+    /// role are included in recovery but do not receive new registrations.
     pub realms: Vec<Realm>,
 
     /// A registration will be considered successful if it's successful on at
     /// least this many realms.
     ///
-    /// Must be between `recover_threshold` and `realms.len()`, inclusive.
+    /// Must be between `recover_threshold` and the number of
+    /// [`RealmRole::ReadWrite`](crate::RealmRole::ReadWrite) realms, inclusive.
     pub register_threshold: u32,
 
     /// A recovery (or an adversary) will need the cooperation of this many
@@ -30,6 +41,109 @@ pub struct Configuration {
     /// inaccessible with the same PIN and should not be done without re-registering
     /// secrets.
     pub pin_hashing_mode: PinHashingMode,
+
+    /// The AEAD used to encrypt secrets on registration. Defaults to
+    /// ChaCha20-Poly1305.
+    ///
+    /// The choice is recorded with each registration, so changing it does
+    /// not affect recovery of secrets registered earlier.
+    #[serde(default, skip_serializing_if = "SecretCipher::is_default")]
+    pub secret_cipher: SecretCipher,
+
+    /// Controls how requests to a realm are retried after transient errors.
+    ///
+    /// The policy of the client's current configuration applies to every
+    /// request, including those to realms of previous configurations.
+    #[serde(default, skip_serializing_if = "RetryPolicy::is_default")]
+    pub retry_policy: RetryPolicy,
+
+    /// The most requests an operation sends to realms at once. By default,
+    /// requests go to all the realms at once. Must be at least 1 if set.
+    ///
+    /// Like `retry_policy`, the current configuration's limit applies to
+    /// operations on previous configurations too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+
+    /// When set, every realm must have a
+    /// [`public_key`](crate::Realm::public_key), so that every request is
+    /// encrypted to a pinned key and a realm without one can't be added by
+    /// mistake. Off by default, since software realms have no public key.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_public_keys: bool,
+}
+
+/// How a [`Client`](crate::Client) retries a request to a realm after a
+/// transient error, such as a network failure.
+///
+/// Errors that retrying won't fix, such as an invalid auth token, are never
+/// retried. Requests that consume a guess are not retried once they may have
+/// reached the realm.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RetryPolicy {
+    /// The most times a single request is attempted, including the first
+    /// attempt. Must be at least 1.
+    pub max_attempts: u32,
+
+    /// The delay before the first retry. Each later retry waits twice as
+    /// long as the previous one, up to `max_delay`.
+    pub base_delay: Duration,
+
+    /// The longest delay between two attempts. Must be at least
+    /// `base_delay`.
+    pub max_delay: Duration,
+
+    /// When set, each delay is picked at random between half its value and
+    /// its full value, so that clients that failed together don't retry in
+    /// lockstep. After a realm reports that it's unavailable, the delay is
+    /// instead picked between zero and its full value, spreading out the
+    /// clients returning to a recovering realm as much as possible.
+    pub jitter: bool,
+}
+
+impl RetryPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns how long to wait before the given retry, counting from 1.
+    pub(crate) fn delay(&self, retry: u32) -> Duration {
+        let delay = self.exponential_delay(retry);
+        if self.jitter {
+            delay.mul_f64(OsRng.gen_range(0.5..=1.0))
+        } else {
+            delay
+        }
+    }
+
+    /// Like [`delay`](Self::delay), but for retrying after a realm answered
+    /// `Unavailable`, with "full jitter" drawn from `rng`.
+    pub(crate) fn unavailable_delay(&self, retry: u32, rng: &mut impl Rng) -> Duration {
+        let delay = self.exponential_delay(retry);
+        if self.jitter {
+            delay.mul_f64(rng.gen_range(0.0..=1.0))
+        } else {
+            delay
+        }
+    }
+
+    fn exponential_delay(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1);
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.max_delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(5),
+            max_delay: Duration::from_secs(1),
+            jitter: true,
+        }
+    }
 }
 
 impl Configuration {
@@ -40,74 +154,349 @@ impl Configuration {
     pub fn to_json(&self) -> String {
         serde_json::to_string_pretty(self).expect("failed to convert configuration to json")
     }
+
+    /// Checks that the realms, thresholds, and retry policy are consistent.
+    ///
+    /// A [`Client`](crate::Client) can't be constructed with an invalid
+    /// configuration, so apps that load configurations at runtime can use
+    /// this to report the problem instead.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.validate_without_addresses()?;
+        for realm in &self.realms {
+            if !matches!(realm.address.scheme(), "http" | "https") || !realm.address.has_host() {
+                return Err(ConfigError::InvalidAddress(realm.id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the checks in [`Configuration::validate`] other than the one on
+    /// realm addresses. Previous configurations are only checked this way,
+    /// so that ones saved before addresses were checked keep working.
+    fn validate_without_addresses(&self) -> Result<(), ConfigError> {
+        if self.realms.is_empty() {
+            return Err(ConfigError::NoRealms);
+        }
+        let Ok(realm_count) = u32::try_from(self.realms.len()) else {
+            return Err(ConfigError::TooManyRealms);
+        };
+
+        let mut ids = HashSet::new();
+        for realm in &self.realms {
+            if !ids.insert(realm.id) {
+                return Err(ConfigError::DuplicateRealmId(realm.id));
+            }
+            // x25519 for now
+            match &realm.public_key {
+                Some(key) if key.len() != 32 => {
+                    return Err(ConfigError::InvalidPublicKey(realm.id));
+                }
+                None if self.require_public_keys => {
+                    return Err(ConfigError::MissingPublicKey(realm.id));
+                }
+                _ => {}
+            }
+        }
+
+        if self.recover_threshold == 0 {
+            return Err(ConfigError::RecoverThresholdZero);
+        }
+        if self.recover_threshold > realm_count {
+            return Err(ConfigError::ThresholdExceedsRealms {
+                threshold: self.recover_threshold,
+                realms: realm_count,
+            });
+        }
+        if self.recover_threshold <= realm_count / 2 {
+            return Err(ConfigError::RecoverThresholdNotMajority {
+                recover_threshold: self.recover_threshold,
+                realms: realm_count,
+            });
+        }
+        if self.register_threshold < self.recover_threshold {
+            return Err(ConfigError::RegisterThresholdBelowRecoverThreshold {
+                register_threshold: self.register_threshold,
+                recover_threshold: self.recover_threshold,
+            });
+        }
+        if self.register_threshold > realm_count {
+            return Err(ConfigError::ThresholdExceedsRealms {
+                threshold: self.register_threshold,
+                realms: realm_count,
+            });
+        }
+
+        let read_write_realms = self
+            .realms
+            .iter()
+            .filter(|realm| realm.role.is_read_write())
+            .count() as u32;
+        if self.register_threshold > read_write_realms {
+            return Err(ConfigError::ThresholdExceedsReadWriteRealms {
+                threshold: self.register_threshold,
+                read_write_realms,
+            });
+        }
+
+        if self.retry_policy.max_attempts == 0 {
+            return Err(ConfigError::InvalidRetryPolicy(
+                "max_attempts must be at least 1",
+            ));
+        }
+        if self.retry_policy.base_delay > self.retry_policy.max_delay {
+            return Err(ConfigError::InvalidRetryPolicy(
+                "base_delay cannot exceed max_delay",
+            ));
+        }
+        if self.max_concurrency == Some(0) {
+            return Err(ConfigError::MaxConcurrencyZero);
+        }
+        if self.pin_hashing_mode.argon2_params().is_none() {
+            return Err(ConfigError::InvalidArgon2Params);
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug)]
-pub(crate) struct CheckedConfiguration(Configuration);
+/// Used to build a [`Configuration`].
+///
+/// Realms and both thresholds must be provided. The PIN hashing mode
+/// defaults to [`PinHashingMode::Standard2019`], and the other settings
+/// default as they do when omitted from JSON.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigurationBuilder {
+    realms: Vec<Realm>,
+    register_threshold: Option<u32>,
+    recover_threshold: Option<u32>,
+    pin_hashing_mode: Option<PinHashingMode>,
+    secret_cipher: SecretCipher,
+    retry_policy: RetryPolicy,
+    max_concurrency: Option<usize>,
+    require_public_keys: bool,
+}
 
-impl CheckedConfiguration {
-    pub fn from(c: Configuration) -> Self {
-        assert!(
-            !c.realms.is_empty(),
-            "Client needs at least one realm in Configuration"
-        );
+impl ConfigurationBuilder {
+    /// Constructs a new `ConfigurationBuilder` with no realms.
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        assert_eq!(
-            c.realms
-                .iter()
-                .map(|realm| realm.id)
-                .collect::<HashSet<_>>()
-                .len(),
-            c.realms.len(),
-            "realm IDs must be unique in Configuration"
-        );
+    /// Adds a realm to the configuration.
+    pub fn add_realm(mut self, realm: Realm) -> Self {
+        self.realms.push(realm);
+        self
+    }
+
+    /// Sets [`Configuration::register_threshold`].
+    pub fn register_threshold(mut self, threshold: u32) -> Self {
+        self.register_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets [`Configuration::recover_threshold`].
+    pub fn recover_threshold(mut self, threshold: u32) -> Self {
+        self.recover_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets [`Configuration::pin_hashing_mode`].
+    pub fn pin_hashing_mode(mut self, mode: PinHashingMode) -> Self {
+        self.pin_hashing_mode = Some(mode);
+        self
+    }
+
+    /// Sets [`Configuration::secret_cipher`].
+    pub fn secret_cipher(mut self, cipher: SecretCipher) -> Self {
+        self.secret_cipher = cipher;
+        self
+    }
+
+    /// Sets [`Configuration::retry_policy`].
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Sets [`Configuration::max_concurrency`].
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    /// Sets [`Configuration::require_public_keys`].
+    pub fn require_public_keys(mut self, require: bool) -> Self {
+        self.require_public_keys = require;
+        self
+    }
 
-        let Ok(realm_count) = u32::try_from(c.realms.len()) else {
-            panic!("too many realms in Client configuration");
+    /// Constructs the [`Configuration`], returning an error if a threshold
+    /// is missing or the result fails [`Configuration::validate`].
+    pub fn build(self) -> Result<Configuration, ConfigError> {
+        let configuration = Configuration {
+            realms: self.realms,
+            register_threshold: self
+                .register_threshold
+                .ok_or(ConfigError::MissingThreshold("register_threshold"))?,
+            recover_threshold: self
+                .recover_threshold
+                .ok_or(ConfigError::MissingThreshold("recover_threshold"))?,
+            pin_hashing_mode: self
+                .pin_hashing_mode
+                .unwrap_or(PinHashingMode::Standard2019),
+            secret_cipher: self.secret_cipher,
+            retry_policy: self.retry_policy,
+            max_concurrency: self.max_concurrency,
+            require_public_keys: self.require_public_keys,
         };
+        configuration.validate()?;
+        Ok(configuration)
+    }
+}
+
+/// Error return type for [`Configuration::validate`] and
+/// [`ConfigurationBuilder::build`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ConfigError {
+    /// The configuration has no realms.
+    NoRealms,
+
+    /// The configuration has more realms than can be counted.
+    TooManyRealms,
+
+    /// More than one realm has this ID.
+    DuplicateRealmId(RealmId),
+
+    /// This realm's public key is not 32 bytes long.
+    InvalidPublicKey(RealmId),
+
+    /// This realm's address is not an HTTP or HTTPS URL with a host.
+    InvalidAddress(RealmId),
+
+    /// This realm has no public key, but
+    /// [`Configuration::require_public_keys`] is set.
+    MissingPublicKey(RealmId),
+
+    /// [`ConfigurationBuilder::build`] was called without setting the named
+    /// threshold.
+    MissingThreshold(&'static str),
+
+    /// The recover threshold is 0.
+    RecoverThresholdZero,
+
+    /// The recover threshold is not more than half the realms, so two
+    /// disjoint groups of realms could each recover the secret.
+    RecoverThresholdNotMajority { recover_threshold: u32, realms: u32 },
+
+    /// The register threshold is less than the recover threshold, so a
+    /// successful registration might not be recoverable.
+    RegisterThresholdBelowRecoverThreshold {
+        register_threshold: u32,
+        recover_threshold: u32,
+    },
+
+    /// A threshold is greater than the number of realms.
+    ThresholdExceedsRealms { threshold: u32, realms: u32 },
+
+    /// The register threshold is greater than the number of
+    /// [`RealmRole::ReadWrite`](crate::RealmRole::ReadWrite) realms.
+    ThresholdExceedsReadWriteRealms {
+        threshold: u32,
+        read_write_realms: u32,
+    },
 
-        for realm in &c.realms {
-            if let Some(public_key) = realm.public_key.as_ref() {
-                assert_eq!(
-                    public_key.len(),
-                    32,
-                    "realm public keys must be 32 bytes" // (x25519 for now)
-                );
+    /// The [`RetryPolicy`] is inconsistent, for the given reason.
+    InvalidRetryPolicy(&'static str),
+
+    /// `max_concurrency` is set to 0, which would allow no requests.
+    MaxConcurrencyZero,
+
+    /// The [`Argon2Params`](crate::Argon2Params) of the PIN hashing mode
+    /// are out of range.
+    InvalidArgon2Params,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoRealms => write!(f, "at least one realm is required"),
+            Self::TooManyRealms => write!(f, "too many realms"),
+            Self::DuplicateRealmId(id) => write!(f, "realm ID {id:?} is not unique"),
+            Self::InvalidPublicKey(id) => {
+                write!(f, "public key for realm {id:?} must be 32 bytes")
+            }
+            Self::InvalidAddress(id) => {
+                write!(f, "address for realm {id:?} must be an HTTP or HTTPS URL")
+            }
+            Self::MissingPublicKey(id) => write!(f, "realm {id:?} requires a public key"),
+            Self::MissingThreshold(name) => write!(f, "{name} is required"),
+            Self::RecoverThresholdZero => write!(f, "recover_threshold must be at least 1"),
+            Self::RecoverThresholdNotMajority {
+                recover_threshold,
+                realms,
+            } => write!(
+                f,
+                "recover_threshold ({recover_threshold}) must contain a majority of the {realms} realms"
+            ),
+            Self::RegisterThresholdBelowRecoverThreshold {
+                register_threshold,
+                recover_threshold,
+            } => write!(
+                f,
+                "register_threshold ({register_threshold}) must be at least recover_threshold ({recover_threshold})"
+            ),
+            Self::ThresholdExceedsRealms { threshold, realms } => write!(
+                f,
+                "threshold ({threshold}) cannot exceed the number of realms ({realms})"
+            ),
+            Self::ThresholdExceedsReadWriteRealms {
+                threshold,
+                read_write_realms,
+            } => write!(
+                f,
+                "register_threshold ({threshold}) cannot exceed the number of read-write realms ({read_write_realms})"
+            ),
+            Self::InvalidRetryPolicy(reason) => write!(f, "retry_policy: {reason}"),
+            Self::MaxConcurrencyZero => write!(f, "max_concurrency must be at least 1"),
+            Self::InvalidArgon2Params => {
+                write!(f, "pin_hashing_mode: Argon2id parameters are out of range")
             }
         }
+    }
+}
 
-        assert!(
-            1 <= c.recover_threshold,
-            "Configuration recover_threshold must be at least 1"
-        );
-        assert!(
-            c.recover_threshold <= realm_count,
-            "Configuration recover_threshold cannot exceed number of realms"
-        );
-        assert!(
-            c.recover_threshold > realm_count / 2,
-            "Configuration recover_threshold must contain a majority of realms"
-        );
+impl Error for ConfigError {}
 
-        assert!(
-            c.recover_threshold <= realm_count,
-            "Configuration register_threshold must be at least recover_threshold"
-        );
-        assert!(
-            c.register_threshold <= realm_count,
-            "Configuration register_threshold cannot exceed number of realms"
-        );
+#[derive(Debug)]
+pub(crate) struct CheckedConfiguration(Configuration);
 
+impl CheckedConfiguration {
+    pub fn new(c: Configuration) -> Result<Self, ConfigError> {
+        c.validate()?;
+        Ok(Self::sorted(c))
+    }
+
+    /// Checks a previous configuration, which may have realm addresses that
+    /// [`Configuration::validate`] would reject.
+    pub fn new_previous(c: Configuration) -> Result<Self, ConfigError> {
+        c.validate_without_addresses()?;
+        Ok(Self::sorted(c))
+    }
+
+    fn sorted(c: Configuration) -> Self {
         // perform a fixed sorting of realms based on their id, so that shares
         // are produced in a consistent ordering for a given configuration.
         let mut sorted_realms = c.realms.clone();
-        sorted_realms.sort_by(|lhs, rhs| lhs.id.cmp(&rhs.id));
+        sorted_realms.sort_by_key(|realm| realm.id);
 
         Self(Configuration {
             realms: sorted_realms,
             register_threshold: c.register_threshold,
             recover_threshold: c.recover_threshold,
             pin_hashing_mode: c.pin_hashing_mode,
+            secret_cipher: c.secret_cipher,
+            retry_policy: c.retry_policy,
+            max_concurrency: c.max_concurrency,
+            require_public_keys: c.require_public_keys,
         })
     }
 }
@@ -124,6 +513,10 @@ impl CheckedConfiguration {
     pub fn share_count(&self) -> u32 {
         self.realms.len().try_into().unwrap()
     }
+
+    pub fn realm_set_hash(&self) -> RealmSetHash {
+        RealmSetHash::new(self.realms.iter().map(|realm| &realm.id))
+    }
 }
 
 impl Deref for CheckedConfiguration {
@@ -136,7 +529,19 @@ impl Deref for CheckedConfiguration {
 
 #[cfg(test)]
 mod tests {
-    use super::Configuration;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use super::{ConfigError, Configuration, ConfigurationBuilder, RetryPolicy};
+    use crate::testing::{MockRealmCluster, NoopSleeper};
+    use crate::{
+        Argon2Params, AuthToken, ClientBuilder, PinHashingMode, Realm, RealmRole, SecretCipher,
+    };
+    use juicebox_marshalling as marshalling;
+    use juicebox_realm_api::types::RealmId;
+    use rand::{rngs::OsRng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use url::Url;
 
     #[test]
     fn test_configuration_json() {
@@ -173,4 +578,307 @@ mod tests {
 
         assert_eq!(input, serialized);
     }
+
+    #[test]
+    fn test_validate() {
+        let valid = Configuration::test_config(3, 3, 2);
+        assert_eq!(valid.validate(), Ok(()));
+
+        let check = |modify: &dyn Fn(&mut Configuration)| {
+            let mut configuration = valid.clone();
+            modify(&mut configuration);
+            configuration.validate().unwrap_err()
+        };
+        assert_eq!(check(&|c| c.realms.clear()), ConfigError::NoRealms);
+        assert_eq!(
+            check(&|c| c.realms[2].id = c.realms[0].id),
+            ConfigError::DuplicateRealmId(valid.realms[0].id)
+        );
+        assert_eq!(
+            check(&|c| c.realms[1].public_key = Some(vec![1; 31])),
+            ConfigError::InvalidPublicKey(valid.realms[1].id)
+        );
+        assert_eq!(
+            check(&|c| {
+                c.require_public_keys = true;
+                c.realms[1].public_key = None;
+            }),
+            ConfigError::MissingPublicKey(valid.realms[1].id)
+        );
+        assert_eq!(
+            check(&|c| c.realms[1].address = Url::parse("mailto:realm@test").unwrap()),
+            ConfigError::InvalidAddress(valid.realms[1].id)
+        );
+        assert_eq!(
+            check(&|c| c.recover_threshold = 0),
+            ConfigError::RecoverThresholdZero
+        );
+        assert_eq!(
+            check(&|c| c.recover_threshold = 1),
+            ConfigError::RecoverThresholdNotMajority {
+                recover_threshold: 1,
+                realms: 3
+            }
+        );
+        assert_eq!(
+            check(&|c| c.recover_threshold = 4),
+            ConfigError::ThresholdExceedsRealms {
+                threshold: 4,
+                realms: 3
+            }
+        );
+        assert_eq!(
+            check(&|c| c.register_threshold = 1),
+            ConfigError::RegisterThresholdBelowRecoverThreshold {
+                register_threshold: 1,
+                recover_threshold: 2
+            }
+        );
+        assert_eq!(
+            check(&|c| c.register_threshold = 4),
+            ConfigError::ThresholdExceedsRealms {
+                threshold: 4,
+                realms: 3
+            }
+        );
+        assert_eq!(
+            check(&|c| c.realms[0].role = RealmRole::RecoverOnly),
+            ConfigError::ThresholdExceedsReadWriteRealms {
+                threshold: 3,
+                read_write_realms: 2
+            }
+        );
+        assert_eq!(
+            check(&|c| c.retry_policy.max_attempts = 0),
+            ConfigError::InvalidRetryPolicy("max_attempts must be at least 1")
+        );
+        assert_eq!(
+            check(&|c| c.max_concurrency = Some(0)),
+            ConfigError::MaxConcurrencyZero
+        );
+        assert_eq!(
+            check(&|c| {
+                c.pin_hashing_mode = PinHashingMode::Argon2id(Argon2Params {
+                    memory_kib: 8,
+                    iterations: 1,
+                    parallelism: 2,
+                })
+            }),
+            ConfigError::InvalidArgon2Params
+        );
+        assert_eq!(
+            check(&|c| c.register_threshold = 1).to_string(),
+            "register_threshold (1) must be at least recover_threshold (2)"
+        );
+    }
+
+    #[test]
+    fn test_configuration_builder() {
+        let expected = Configuration::test_config(3, 3, 2);
+        let builder = expected
+            .realms
+            .iter()
+            .fold(ConfigurationBuilder::new(), |builder, realm| {
+                builder.add_realm(realm.clone())
+            });
+
+        assert_eq!(
+            builder.clone().recover_threshold(2).build(),
+            Err(ConfigError::MissingThreshold("register_threshold"))
+        );
+        assert_eq!(
+            builder
+                .clone()
+                .register_threshold(3)
+                .recover_threshold(1)
+                .build(),
+            Err(ConfigError::RecoverThresholdNotMajority {
+                recover_threshold: 1,
+                realms: 3
+            })
+        );
+        assert_eq!(
+            builder
+                .clone()
+                .add_realm(expected.realms[0].clone())
+                .register_threshold(3)
+                .recover_threshold(2)
+                .build(),
+            Err(ConfigError::DuplicateRealmId(expected.realms[0].id))
+        );
+
+        let configuration = builder
+            .register_threshold(3)
+            .recover_threshold(2)
+            .pin_hashing_mode(expected.pin_hashing_mode)
+            .build()
+            .unwrap();
+        assert_eq!(configuration, expected);
+    }
+
+    #[test]
+    fn test_try_build_rejects_invalid_configuration() {
+        let mut configuration = Configuration::test_config(3, 3, 2);
+        configuration.recover_threshold = 1;
+        let result = ClientBuilder::new()
+            .configuration(Configuration::test_config(1, 1, 1))
+            .previous_configurations(vec![configuration])
+            .http(MockRealmCluster::new(1))
+            .sleeper(NoopSleeper)
+            .auth_token_manager(HashMap::<RealmId, AuthToken>::new())
+            .try_build();
+        assert!(matches!(
+            result,
+            Err(ConfigError::RecoverThresholdNotMajority { .. })
+        ));
+    }
+
+    #[test]
+    fn test_addresses_only_checked_on_current_configuration() {
+        let mut configuration = Configuration::test_config(1, 1, 1);
+        configuration.realms[0].address = Url::parse("mailto:realm@test").unwrap();
+        let builder = || {
+            ClientBuilder::new()
+                .http(MockRealmCluster::new(1))
+                .sleeper(NoopSleeper)
+                .auth_token_manager(HashMap::<RealmId, AuthToken>::new())
+        };
+
+        assert!(builder()
+            .configuration(Configuration::test_config(1, 1, 1))
+            .previous_configurations(vec![configuration.clone()])
+            .try_build()
+            .is_ok());
+        assert!(matches!(
+            builder().configuration(configuration).try_build(),
+            Err(ConfigError::InvalidAddress(_))
+        ));
+    }
+
+    #[test]
+    fn test_retry_policy_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            jitter: false,
+        };
+        let delays: Vec<Duration> = (1..=5).map(|retry| policy.delay(retry)).collect();
+        assert_eq!(
+            delays,
+            [10, 20, 40, 50, 50].map(Duration::from_millis).to_vec()
+        );
+        assert_eq!(policy.delay(u32::MAX), Duration::from_millis(50));
+
+        let policy = RetryPolicy {
+            jitter: true,
+            ..policy
+        };
+        for retry in 1..=5 {
+            let delay = policy.delay(retry);
+            assert!(delays[retry as usize - 1] / 2 <= delay);
+            assert!(delay <= delays[retry as usize - 1]);
+        }
+    }
+
+    #[test]
+    fn test_retry_policy_unavailable_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            jitter: false,
+        };
+        let delays: Vec<Duration> = (1..=5)
+            .map(|retry| policy.unavailable_delay(retry, &mut OsRng))
+            .collect();
+        assert_eq!(
+            delays,
+            [10, 20, 40, 50, 50].map(Duration::from_millis).to_vec()
+        );
+
+        let policy = RetryPolicy {
+            jitter: true,
+            ..policy
+        };
+        let jittered = |seed| {
+            let mut rng = ChaCha20Rng::from_seed(seed);
+            (1..=5)
+                .map(|retry| policy.unavailable_delay(retry, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        let jittered_delays = jittered([1; 32]);
+        assert_eq!(jittered_delays, jittered([1; 32]));
+        assert_ne!(jittered_delays, jittered([2; 32]));
+        for (delay, max) in jittered_delays.iter().zip(&delays) {
+            assert!(delay <= max);
+        }
+        // Unlike other retries, delays can go all the way down to zero.
+        assert!((0..100)
+            .flat_map(|seed| jittered([seed; 32]))
+            .any(|delay| delay < Duration::from_millis(5)));
+    }
+
+    #[test]
+    fn test_configuration_round_trip() {
+        let configuration = Configuration {
+            realms: vec![
+                Realm {
+                    id: RealmId([1; 16]),
+                    address: Url::parse("https://hsm.realm.test/").unwrap(),
+                    public_key: Some(vec![2; 32]),
+                    role: RealmRole::ReadWrite,
+                },
+                Realm {
+                    id: RealmId([3; 16]),
+                    address: Url::parse("https://software.realm.test/path/").unwrap(),
+                    public_key: None,
+                    role: RealmRole::RecoverOnly,
+                },
+            ],
+            register_threshold: 1,
+            recover_threshold: 2,
+            pin_hashing_mode: PinHashingMode::Argon2id(Argon2Params {
+                memory_kib: 64 * 1024,
+                iterations: 3,
+                parallelism: 4,
+            }),
+            secret_cipher: SecretCipher::Aes256Gcm,
+            retry_policy: RetryPolicy {
+                max_attempts: 3,
+                base_delay: Duration::from_millis(100),
+                max_delay: Duration::from_secs(2),
+                jitter: false,
+            },
+            max_concurrency: Some(2),
+            require_public_keys: false,
+        };
+
+        let cbor = marshalling::to_vec(&configuration).unwrap();
+        assert_eq!(
+            marshalling::from_slice::<Configuration>(&cbor).unwrap(),
+            configuration
+        );
+
+        let json = serde_json::to_value(&configuration).unwrap();
+        assert_eq!(json["realms"][0]["id"], "01010101010101010101010101010101");
+        assert_eq!(json["realms"][0]["address"], "https://hsm.realm.test/");
+        assert_eq!(json["realms"][0]["public_key"], hex::encode([2; 32]));
+        assert!(json["realms"][1].get("public_key").is_none());
+        assert!(json.get("require_public_keys").is_none());
+        assert_eq!(
+            json["pin_hashing_mode"],
+            serde_json::json!({
+                "Argon2id": {"memory_kib": 65536, "iterations": 3, "parallelism": 4}
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<Configuration>(json).unwrap(),
+            configuration
+        );
+        assert_eq!(
+            Configuration::from_json(&configuration.to_json()).unwrap(),
+            configuration
+        );
+    }
 }
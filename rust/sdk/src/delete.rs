@@ -4,7 +4,10 @@ use std::fmt::{Debug, Display};
 use tracing::instrument;
 
 use crate::{auth, http, request::RequestError, Client, Realm, Sleeper};
-use juicebox_realm_api::requests::{DeleteResponse, SecretsRequest, SecretsResponse};
+use juicebox_realm_api::requests::{
+    DeleteResponse, Recover1Response, SecretsRequest, SecretsResponse,
+};
+use juicebox_realm_api::types::RealmId;
 
 /// Error return type for [`Client::delete`].
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -28,18 +31,45 @@ pub enum DeleteError {
     /// A transient error in sending or receiving requests to a realm.
     /// This request may succeed by trying again with the same parameters.
     Transient,
+
+    /// The operation was stopped with the `Client`'s
+    /// [`CancellationToken`](crate::CancellationToken).
+    Cancelled,
 }
 
 impl Display for DeleteError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Debug::fmt(self, f)
+        match self {
+            Self::InvalidAuth => write!(f, "realm rejected auth token"),
+            Self::UpgradeRequired => write!(f, "realm requires a newer SDK version"),
+            Self::RateLimitExceeded => write!(f, "tenant exceeded its rate limit"),
+            Self::Assertion => write!(f, "unexpected error in SDK or realm"),
+            Self::Transient => write!(f, "transient error communicating with realm"),
+            Self::Cancelled => write!(f, "operation was cancelled"),
+        }
     }
 }
 
 impl Error for DeleteError {}
 
+/// What [`Client::delete`] would remove from a realm, as reported by
+/// [`Client::delete_dry_run`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DeletePreview {
+    /// The realm has no registration for this user, so nothing would be
+    /// deleted.
+    NotRegistered,
+
+    /// The realm has a registration for this user, which would be deleted.
+    Registered,
+
+    /// The realm has a registration for this user with no guesses remaining,
+    /// which would be deleted.
+    Locked,
+}
+
 impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http, Atm> {
-    pub(crate) async fn perform_delete(&self) -> Result<(), DeleteError> {
+    pub(crate) async fn perform_delete(&self) -> Result<Option<usize>, DeleteError> {
         let requests = self
             .configuration
             .realms
@@ -49,24 +79,138 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
         // Use `join_all` instead of `try_join_all` so that a failed delete
         // request does not short-circuit other requests (which may still
         // succeed).
+        let mut total = Some(0);
+        for count in join_all(requests).await {
+            total = total.zip(count?).map(|(total, count)| total + count);
+        }
+        Ok(total)
+    }
+
+    pub(crate) async fn perform_delete_dry_run(
+        &self,
+    ) -> Result<Vec<(RealmId, DeletePreview)>, DeleteError> {
+        let requests = self
+            .configuration
+            .realms
+            .iter()
+            .map(|realm| self.delete_dry_run_on_realm(realm));
+
         join_all(requests).await.into_iter().collect()
     }
 
+    /// Previews a delete on a realm using a Recover1 request, which reports
+    /// whether the user is registered without consuming a guess.
     #[instrument(level = "trace", skip(self), err(level = "trace", Debug))]
-    async fn delete_on_realm(&self, realm: &Realm) -> Result<(), DeleteError> {
+    async fn delete_dry_run_on_realm(
+        &self,
+        realm: &Realm,
+    ) -> Result<(RealmId, DeletePreview), DeleteError> {
+        let recover1_result = self.make_request(realm, SecretsRequest::Recover1).await;
+
+        let preview = match recover1_result {
+            Err(RequestError::UpgradeRequired) => return Err(DeleteError::UpgradeRequired),
+            Err(RequestError::Transient) => return Err(DeleteError::Transient),
+            Err(RequestError::Assertion | RequestError::KeyMismatch) => {
+                return Err(DeleteError::Assertion)
+            }
+            Err(RequestError::InvalidAuth) => return Err(DeleteError::InvalidAuth),
+            Err(RequestError::RateLimitExceeded) => return Err(DeleteError::RateLimitExceeded),
+            Err(RequestError::Cancelled) => return Err(DeleteError::Cancelled),
+
+            Ok(SecretsResponse::Recover1(response)) => match response {
+                Recover1Response::Ok { .. } => DeletePreview::Registered,
+                Recover1Response::NotRegistered => DeletePreview::NotRegistered,
+                Recover1Response::NoGuesses => DeletePreview::Locked,
+            },
+            Ok(_) => return Err(DeleteError::Assertion),
+        };
+        Ok((realm.id, preview))
+    }
+
+    #[instrument(level = "trace", skip(self), err(level = "trace", Debug))]
+    async fn delete_on_realm(&self, realm: &Realm) -> Result<Option<usize>, DeleteError> {
         let delete_result = self.make_request(realm, SecretsRequest::Delete).await;
 
         match delete_result {
             Err(RequestError::UpgradeRequired) => Err(DeleteError::UpgradeRequired),
             Err(RequestError::Transient) => Err(DeleteError::Transient),
-            Err(RequestError::Assertion) => Err(DeleteError::Assertion),
+            Err(RequestError::Assertion | RequestError::KeyMismatch) => Err(DeleteError::Assertion),
             Err(RequestError::InvalidAuth) => Err(DeleteError::InvalidAuth),
             Err(RequestError::RateLimitExceeded) => Err(DeleteError::RateLimitExceeded),
+            Err(RequestError::Cancelled) => Err(DeleteError::Cancelled),
 
             Ok(SecretsResponse::Delete(dr)) => match dr {
-                DeleteResponse::Ok => Ok(()),
+                DeleteResponse::Ok => Ok(None),
+                DeleteResponse::Deleted { count } => usize::try_from(count)
+                    .map(Some)
+                    .map_err(|_| DeleteError::Assertion),
             },
             Ok(_) => Err(DeleteError::Assertion),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::DeletePreview;
+    use crate::testing::MockRealmCluster;
+    use crate::{Pin, Policy, RecoverError, UserInfo, UserSecret};
+
+    #[tokio::test]
+    async fn test_delete_dry_run() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(2, 2);
+        let realms: Vec<_> = configuration.realms.iter().map(|realm| realm.id).collect();
+        let client = cluster.client(configuration, "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        cluster.set_available(&realms[2], false);
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 1 })
+            .await
+            .unwrap();
+        cluster.set_available(&realms[2], true);
+
+        assert_eq!(
+            client.delete_dry_run().await.unwrap(),
+            vec![
+                (realms[0], DeletePreview::Registered),
+                (realms[1], DeletePreview::Registered),
+                (realms[2], DeletePreview::NotRegistered),
+            ]
+        );
+
+        assert_eq!(
+            client
+                .recover(&Pin::from(b"0000".to_vec()), &user_info)
+                .await
+                .unwrap_err(),
+            RecoverError::InvalidPin {
+                guesses_remaining: 0
+            }
+        );
+        assert_eq!(
+            client.delete_dry_run().await.unwrap(),
+            vec![
+                (realms[0], DeletePreview::Locked),
+                (realms[1], DeletePreview::Locked),
+                (realms[2], DeletePreview::NotRegistered),
+            ]
+        );
+        for realm in &realms {
+            assert_eq!(cluster.request_count(realm, "Delete"), 0);
+        }
+
+        assert_eq!(client.delete().await.unwrap(), Some(2));
+        assert_eq!(client.delete().await.unwrap(), Some(0));
+        assert!(client
+            .delete_dry_run()
+            .await
+            .unwrap()
+            .iter()
+            .all(|(_, preview)| *preview == DeletePreview::NotRegistered));
+    }
+}
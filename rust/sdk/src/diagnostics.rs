@@ -0,0 +1,149 @@
+//! Local re-derivation of a registration's keys, for debugging recoveries
+//! without contacting any realm or consuming a guess.
+//!
+//! For example, if recovery works on one device but not another, comparing
+//! [`DerivedKeys`] from both devices shows whether they hash the PIN and
+//! user info the same way.
+
+use curve25519_dalek::Scalar;
+
+use crate::types::derive_unlock_key_and_commitment;
+use crate::{auth, http, Client, Pin, Sleeper, UserInfo};
+use juicebox_oprf as oprf;
+use juicebox_realm_api::types::RegistrationVersion;
+
+/// The keys derived from a PIN for a registration, as computed by
+/// [`Client::simulate_derivation`].
+///
+/// These are secrets. Anyone who has them can recover the user's secret
+/// without knowing the PIN.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DerivedKeys {
+    /// The PIN hash input to the OPRF.
+    pub access_key: [u8; 32],
+    /// The PIN hash input to the secret's encryption key.
+    pub encryption_key_seed: [u8; 32],
+    /// The OPRF of `access_key` under the registration's root key.
+    pub oprf_result: [u8; 64],
+    /// The key that derives the tag the realms check on recovery.
+    pub unlock_key: [u8; 32],
+    /// The commitment the realms return to check `unlock_key` against.
+    pub unlock_key_commitment: [u8; 32],
+}
+
+impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http, Atm> {
+    /// Derives the keys a recovery with `pin` and `info` would use for a
+    /// registration, without contacting any realm.
+    ///
+    /// Registration picks a random `version` and OPRF `oprf_root_key`, which
+    /// realms never reveal. This recomputes everything else from them: the
+    /// PIN hash with the configuration's [`PinHashingMode`](crate::PinHashingMode),
+    /// the unoblivious OPRF, and the unlock key and commitment over the
+    /// configuration's realms.
+    ///
+    /// Returns `None` if `oprf_root_key` is not a canonical scalar encoding,
+    /// `version` records a different set of realms than the configuration's,
+    /// or the PIN could not be hashed.
+    pub fn simulate_derivation(
+        &self,
+        pin: &Pin,
+        info: &UserInfo,
+        version: [u8; 16],
+        oprf_root_key: [u8; 32],
+    ) -> Option<DerivedKeys> {
+        let scalar: Option<Scalar> = Scalar::from_canonical_bytes(oprf_root_key).into();
+        let oprf_root_key = oprf::PrivateKey::from(scalar?);
+        let version = RegistrationVersion::from(version);
+        let realm_set_hash = self.configuration.realm_set_hash();
+        let binding = realm_set_hash.binding(&version)?;
+
+        let (access_key, encryption_key_seed) =
+            pin.hash(self.configuration.pin_hashing_mode, &version, info)?;
+        let oprf_result = oprf::unoblivious_evaluate(&oprf_root_key, access_key.expose_secret());
+        let (unlock_key, unlock_key_commitment) =
+            derive_unlock_key_and_commitment(&oprf_result, binding);
+
+        Some(DerivedKeys {
+            access_key: *access_key.expose_secret(),
+            encryption_key_seed: *encryption_key_seed.expose_secret(),
+            oprf_result: *oprf_result.expose_secret(),
+            unlock_key: *unlock_key.expose_secret(),
+            unlock_key_commitment: *unlock_key_commitment.expose_secret(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use crate::testing::MockRealmCluster;
+    use crate::types::RealmSetHash;
+    use crate::{Pin, Policy, UserInfo, UserSecret};
+    use juicebox_marshalling as marshalling;
+    use juicebox_oprf as oprf;
+    use juicebox_realm_api::requests::Register2Request;
+    use juicebox_realm_api::types::{UnlockKey, UnlockKeyTag};
+
+    #[tokio::test]
+    async fn test_simulate_derivation_matches_registration() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(3, 2);
+        let realm = configuration.realms[0].id;
+        let client = cluster.client(configuration.clone(), "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let info = UserInfo::new(b"apollo".to_vec()).unwrap();
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        client
+            .register_with_rng(
+                &pin,
+                &UserSecret::from(b"artemis".to_vec()),
+                &info,
+                Policy { num_guesses: 2 },
+                &mut rng.clone(),
+            )
+            .await
+            .unwrap();
+
+        // Registration draws the version and then the OPRF root key.
+        let version = RealmSetHash::new(configuration.realms.iter().map(|realm| &realm.id))
+            .new_version(&mut rng);
+        let oprf_root_key = oprf::PrivateKey::random(&mut rng);
+        let registration: Register2Request =
+            marshalling::from_slice(&cluster.registration(&realm, "apollo").unwrap()).unwrap();
+        assert_eq!(registration.version, version);
+
+        let keys = client
+            .simulate_derivation(
+                &pin,
+                &info,
+                *version.expose_secret(),
+                oprf_root_key.expose_secret().to_bytes(),
+            )
+            .unwrap();
+        assert_eq!(
+            &keys.unlock_key_commitment,
+            registration.unlock_key_commitment.expose_secret()
+        );
+        assert_eq!(
+            registration.unlock_key_tag,
+            UnlockKeyTag::derive(&UnlockKey::from(keys.unlock_key), &realm)
+        );
+
+        let wrong_pin = client
+            .simulate_derivation(
+                &Pin::from(b"4321".to_vec()),
+                &info,
+                *version.expose_secret(),
+                oprf_root_key.expose_secret().to_bytes(),
+            )
+            .unwrap();
+        assert_ne!(wrong_pin.unlock_key_commitment, keys.unlock_key_commitment);
+
+        assert!(client
+            .simulate_derivation(&pin, &info, *version.expose_secret(), [0xff; 32])
+            .is_none());
+    }
+}
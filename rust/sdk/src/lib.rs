@@ -1,38 +1,57 @@
 #![doc = include_str!("../README.md")]
 
+use rand::{rngs::OsRng, CryptoRng, RngCore};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 use tracing::instrument;
 
 mod auth;
+mod cancellation;
+mod check;
+#[cfg(not(target_arch = "wasm32"))]
+mod clock_skew;
 mod configuration;
 mod delete;
+mod metrics;
 mod pin;
 mod recover;
 mod register;
 mod request;
+mod session_store;
 mod sleeper;
+#[cfg(any(test, feature = "test_util"))]
+mod test_util;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod types;
 
 #[cfg(feature = "client_auth")]
 pub mod client_auth;
 
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
+
 use configuration::CheckedConfiguration;
-use types::Session;
 
 pub use auth::AuthTokenManager;
-pub use configuration::Configuration;
-pub use delete::DeleteError;
+pub use cancellation::CancellationToken;
+pub use check::RealmCheckError;
+pub use configuration::{ConfigError, Configuration, ConfigurationBuilder, RetryPolicy};
+pub use delete::{DeleteError, DeletePreview};
 pub use juicebox_networking::http;
 /// A unique identifier for a [`Realm`].
 #[doc = "\n"] // add paragraph break before core crate comment
 pub use juicebox_realm_api::types::RealmId;
-pub use juicebox_realm_api::types::{AuthToken, Policy, JUICEBOX_VERSION_HEADER};
-pub use pin::{Pin, PinHashingMode};
-pub use recover::RecoverError;
-pub use register::RegisterError;
+pub use juicebox_realm_api::types::{AuthToken, Policy, SecretCipher, JUICEBOX_VERSION_HEADER};
+pub use metrics::{ClientMetrics, Operation, Outcome};
+pub use pin::{Argon2Params, HashedPin, Pin, PinHashingMode};
+pub use recover::{RealmOutcome, RecoverError, RecoverOutcome};
+pub use register::{RegisterError, RepairOutcome, ReshareError};
+pub use session_store::{MemorySessionStore, SessionState, SessionStore};
 pub use sleeper::Sleeper;
-pub use types::{Realm, UserInfo, UserSecret};
+pub use types::{Realm, RealmRole, UserInfo, UserInfoError, UserSecret};
 
 #[cfg(feature = "tokio")]
 pub use sleeper::TokioSleeper;
@@ -42,13 +61,21 @@ pub use juicebox_networking::reqwest;
 
 pub static VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// The default for [`ClientBuilder::recover_retry_budget`].
+pub const DEFAULT_RECOVER_RETRY_BUDGET: u32 = 8;
+
 /// Used to build a [`Client`].
 pub struct ClientBuilder<S, Http, Atm> {
-    configuration: Option<CheckedConfiguration>,
-    previous_configurations: Vec<CheckedConfiguration>,
+    configuration: Option<Configuration>,
+    previous_configurations: Vec<Configuration>,
     auth_token_manager: Option<Atm>,
     http: Option<Http>,
     sleeper: Option<S>,
+    recover_retry_budget: u32,
+    request_timeout: Option<Duration>,
+    metrics: Option<Arc<dyn ClientMetrics>>,
+    cancellation_token: Option<CancellationToken>,
+    session_store: Option<Arc<dyn SessionStore>>,
 }
 
 impl<S, Http, Atm> Default for ClientBuilder<S, Http, Atm>
@@ -76,12 +103,18 @@ where
             auth_token_manager: None,
             http: None,
             sleeper: None,
+            recover_retry_budget: DEFAULT_RECOVER_RETRY_BUDGET,
+            request_timeout: None,
+            metrics: None,
+            cancellation_token: None,
+            session_store: None,
         }
     }
 
-    /// Sets the current configuration. The configuration provided must include at least one [`Realm`]
+    /// Sets the current configuration. The configuration provided must include at least one
+    /// [`Realm`] and pass [`Configuration::validate`].
     pub fn configuration(mut self, configuration: Configuration) -> Self {
-        self.configuration = Some(CheckedConfiguration::from(configuration));
+        self.configuration = Some(configuration);
         self
     }
 
@@ -90,10 +123,7 @@ where
     /// will be tried if the current user has not yet registered on the current configuration.
     /// These should be ordered from most recently to least recently used.
     pub fn previous_configurations(mut self, previous_configurations: Vec<Configuration>) -> Self {
-        self.previous_configurations = previous_configurations
-            .into_iter()
-            .map(CheckedConfiguration::from)
-            .collect();
+        self.previous_configurations = previous_configurations;
         self
     }
 
@@ -115,28 +145,105 @@ where
         self
     }
 
+    /// Sets the total number of times a [`Client::recover`] may
+    /// automatically retry requests after transient errors, across all
+    /// realms. Defaults to [`DEFAULT_RECOVER_RETRY_BUDGET`].
+    ///
+    /// Requests that consume a guess are never retried automatically once
+    /// they may have reached a realm, regardless of the budget.
+    pub fn recover_retry_budget(mut self, retries: u32) -> Self {
+        self.recover_retry_budget = retries;
+        self
+    }
+
+    /// Sets how long to wait for each HTTP request to a realm before treating
+    /// it as a transient network error. The timeout is measured with the
+    /// [`Sleeper`]. By default, requests wait for as long as the
+    /// [`http::Client`] does.
+    ///
+    /// Timed out requests are retried like other transient errors, except
+    /// requests that consume a guess, since the realm may have processed
+    /// them.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets a [`ClientMetrics`] to receive the latency and outcome of each
+    /// request to a realm. By default, no metrics are recorded.
+    pub fn metrics(mut self, metrics: Arc<dyn ClientMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Sets a [`CancellationToken`] that stops the [`Client`]'s operations
+    /// before their next request to a realm once it's cancelled. By default,
+    /// operations can't be cancelled.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Sets a [`SessionStore`] to hold the [`Client`]'s Noise sessions with
+    /// hardware realms, such as one that persists them so they survive
+    /// process restarts. Defaults to a new [`MemorySessionStore`].
+    pub fn session_store(mut self, store: Arc<dyn SessionStore>) -> Self {
+        self.session_store = Some(store);
+        self
+    }
+
     /// Constructs a new [`Client`].
+    ///
+    /// Panics if a required component is missing or any configuration is
+    /// invalid. Use [`ClientBuilder::try_build`] to handle invalid
+    /// configurations.
     pub fn build(self) -> Client<S, Http, Atm> {
-        let configuration = self.configuration.expect("configuration is required");
+        match self.try_build() {
+            Ok(client) => client,
+            Err(e) => panic!("invalid Configuration: {e}"),
+        }
+    }
+
+    /// Constructs a new [`Client`], returning an error if the current
+    /// configuration fails [`Configuration::validate`]. Previous
+    /// configurations get the same checks, except that their realm addresses
+    /// aren't checked.
+    ///
+    /// Panics if a required component is missing.
+    pub fn try_build(self) -> Result<Client<S, Http, Atm>, ConfigError> {
+        let configuration =
+            CheckedConfiguration::new(self.configuration.expect("configuration is required"))?;
+        let previous_configurations = self
+            .previous_configurations
+            .into_iter()
+            .map(CheckedConfiguration::new_previous)
+            .collect::<Result<_, _>>()?;
         let auth_token_manager = self
             .auth_token_manager
             .expect("auth_token_manager is required");
         let http = self.http.expect("http_client is required");
         let sleeper = self.sleeper.expect("sleeper is required");
-        let sessions = configuration
+        let session_locks = configuration
             .realms
             .iter()
-            .map(|realm| (realm.id, Mutex::new(None)))
+            .map(|realm| (realm.id, Mutex::new(())))
             .collect();
 
-        Client {
+        Ok(Client {
             configuration,
-            previous_configurations: self.previous_configurations,
+            previous_configurations,
             auth_token_manager,
             http,
             sleeper,
-            sessions,
-        }
+            session_locks,
+            session_store: self
+                .session_store
+                .unwrap_or_else(|| Arc::new(MemorySessionStore::default())),
+            recover_retry_budget: self.recover_retry_budget,
+            request_timeout: self.request_timeout,
+            metrics: self.metrics,
+            cancellation_token: self.cancellation_token,
+        })
     }
 }
 
@@ -186,7 +293,17 @@ pub struct Client<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> {
     auth_token_manager: Atm,
     http: Http,
     sleeper: S,
-    sessions: HashMap<RealmId, Mutex<Option<Session>>>,
+    /// Serializes the requests to each hardware realm, so that only one at
+    /// a time uses its session.
+    session_locks: HashMap<RealmId, Mutex<()>>,
+    /// The latest Noise session with each hardware realm. Requests reuse it,
+    /// even across operations, until it has been idle for the realm's
+    /// session lifetime or the realm reports it missing.
+    session_store: Arc<dyn SessionStore>,
+    recover_retry_budget: u32,
+    request_timeout: Option<Duration>,
+    metrics: Option<Arc<dyn ClientMetrics>>,
+    cancellation_token: Option<CancellationToken>,
 }
 
 impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http, Atm> {
@@ -199,20 +316,202 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
         info: &UserInfo,
         policy: Policy,
     ) -> Result<(), RegisterError> {
-        self.perform_register(pin, secret, info, policy).await
+        self.perform_register(pin, secret, info, policy, &mut OsRng)
+            .await
+    }
+
+    /// Like [`Client::register`], but draws the registration's randomness
+    /// (its version, OPRF and signing keys, and secret shares) from `rng`
+    /// instead of the operating system.
+    ///
+    /// The security of the registration depends on this randomness, so `rng`
+    /// must be a cryptographically secure generator seeded with enough
+    /// entropy. A seeded generator is only appropriate for deterministic
+    /// tests.
+    #[instrument(level = "trace", skip_all, err(level = "trace", Debug))]
+    pub async fn register_with_rng(
+        &self,
+        pin: &Pin,
+        secret: &UserSecret,
+        info: &UserInfo,
+        policy: Policy,
+        rng: &mut (impl CryptoRng + RngCore + Send),
+    ) -> Result<(), RegisterError> {
+        self.perform_register(pin, secret, info, policy, rng).await
     }
 
     /// Retrieves a PIN-protected secret from the configured realms, or falls
     /// back to the previous realms if the current realms do not have a secret
-    /// registered.
+    /// registered, or have one registered with a different set of realms.
     #[instrument(level = "trace", skip_all, err(level = "trace", Debug))]
     pub async fn recover(&self, pin: &Pin, info: &UserInfo) -> Result<UserSecret, RecoverError> {
+        self.perform_recover(pin, info)
+            .await
+            .map(|outcome| outcome.secret)
+    }
+
+    /// Like [`Client::recover`], but also reports which realms failed to
+    /// contribute to a successful recovery.
+    #[instrument(level = "trace", skip_all, err(level = "trace", Debug))]
+    pub async fn recover_detailed(
+        &self,
+        pin: &Pin,
+        info: &UserInfo,
+    ) -> Result<RecoverOutcome, RecoverError> {
         self.perform_recover(pin, info).await
     }
 
+    /// Like [`Client::register`], but with keys that were already derived
+    /// from the PIN outside of the SDK, such as in a secure enclave.
+    #[instrument(level = "trace", skip_all, err(level = "trace", Debug))]
+    pub async fn register_with_hashed_pin(
+        &self,
+        hashed_pin: &HashedPin,
+        secret: &UserSecret,
+        info: &UserInfo,
+        policy: Policy,
+    ) -> Result<(), RegisterError> {
+        self.perform_register(hashed_pin, secret, info, policy, &mut OsRng)
+            .await
+    }
+
+    /// Like [`Client::recover`], but with keys that were already derived from
+    /// the PIN outside of the SDK. The keys must match those passed to
+    /// [`Client::register_with_hashed_pin`].
+    #[instrument(level = "trace", skip_all, err(level = "trace", Debug))]
+    pub async fn recover_with_hashed_pin(
+        &self,
+        hashed_pin: &HashedPin,
+        info: &UserInfo,
+    ) -> Result<UserSecret, RecoverError> {
+        self.perform_recover(hashed_pin, info)
+            .await
+            .map(|outcome| outcome.secret)
+    }
+
+    /// Re-registers the secret if any read-write realm is missing the
+    /// current registration, such as after [`Client::recover_detailed`]
+    /// reports a degraded recovery.
+    ///
+    /// The `pin`, `secret`, `info`, and `policy` should be the same as the
+    /// current registration, with the secret freshly recovered. The repair
+    /// replaces the registration on every read-write realm, including healthy
+    /// ones, because the client never learns the OPRF key needed to recreate
+    /// the share for a single realm. For that reason, nothing is registered if
+    /// any read-write realm's registration has no guesses remaining, which
+    /// returns [`RepairOutcome::Locked`]; a repair never resets a locked
+    /// registration. Other clients for the same user should not register or
+    /// delete concurrently.
+    #[instrument(level = "trace", skip_all, err(level = "trace", Debug))]
+    pub async fn repair(
+        &self,
+        pin: &Pin,
+        secret: &UserSecret,
+        info: &UserInfo,
+        policy: Policy,
+    ) -> Result<RepairOutcome, RegisterError> {
+        self.perform_repair(pin, secret, info, policy).await
+    }
+
+    /// Moves the secret onto the current configuration's realms, such as
+    /// after realms were added to or removed from the configuration.
+    ///
+    /// This recovers the secret from the first of the previous
+    /// configurations that has it registered, then registers it on the
+    /// current configuration with the same `pin` and `info` and the given
+    /// `policy`. The PIN is still needed, because every realm's registration
+    /// is derived from it, but the app doesn't need to handle the secret.
+    ///
+    /// Realms in both configurations are overwritten with the new registration,
+    /// so afterwards the previous configuration may no longer recover the
+    /// secret, and the app should drop it rather than reshare again. The same
+    /// applies if registering fails after reaching some of those realms.
+    /// Resharing from a configuration whose realms hold different registrations
+    /// fails with [`ReshareError::InconsistentRegistrations`] before any guess
+    /// is used. If the configurations share no realms, the previous one
+    /// recovers the secret until the app deletes it with a client built for
+    /// that configuration, so both hold it in the meantime, and a failed
+    /// reshare can always be retried.
+    #[instrument(level = "trace", skip_all, err(level = "trace", Debug))]
+    pub async fn reshare(
+        &self,
+        pin: &Pin,
+        info: &UserInfo,
+        policy: Policy,
+    ) -> Result<(), ReshareError> {
+        self.perform_reshare(pin, info, policy).await
+    }
+
+    /// Replaces the registration's OPRF key, without changing the PIN or the
+    /// secret.
+    ///
+    /// The client generates the OPRF key and splits it among the realms when
+    /// registering, so this recovers the secret from the current
+    /// configuration and registers it again with the same `pin` and `info`
+    /// and the given `policy`. Each registration has a random version, and a
+    /// realm keeps only the latest one for a user, so the old key's shares
+    /// are gone from every realm the new registration reaches. A realm it
+    /// misses keeps the old version, which later recoveries report as
+    /// degraded until [`Client::repair`] replaces it.
+    ///
+    /// If registering fails partway, the realms hold a mix of the old and new
+    /// versions. Recovery uses whichever version at least the recover
+    /// threshold of realms hold, so the rotation can be retried as long as
+    /// one does.
+    #[instrument(level = "trace", skip_all, err(level = "trace", Debug))]
+    pub async fn rotate_oprf_keys(
+        &self,
+        pin: &Pin,
+        info: &UserInfo,
+        policy: Policy,
+    ) -> Result<(), ReshareError> {
+        self.perform_rotate_oprf_keys(pin, info, policy).await
+    }
+
     /// Deletes the registered secret for this user, if any.
+    ///
+    /// Returns the total number of records the realms removed, which is zero
+    /// if the user wasn't registered anywhere, or `None` if any realm didn't
+    /// report a count.
     #[instrument(level = "trace", skip_all, err(level = "trace", Debug))]
-    pub async fn delete(&self) -> Result<(), DeleteError> {
+    pub async fn delete(&self) -> Result<Option<usize>, DeleteError> {
         self.perform_delete().await
     }
+
+    /// Reports what [`Client::delete`] would remove from each configured
+    /// realm, without deleting anything or consuming a guess.
+    #[instrument(level = "trace", skip_all, err(level = "trace", Debug))]
+    pub async fn delete_dry_run(&self) -> Result<Vec<(RealmId, DeletePreview)>, DeleteError> {
+        self.perform_delete_dry_run().await
+    }
+
+    /// Checks which configured realms are reachable, without accessing the
+    /// user's records or consuming a guess.
+    ///
+    /// Apps can use this to show connectivity before asking for a PIN.
+    /// Hardware realms also check the auth token. Software realms can't do
+    /// that without reading the user's record, so for them this only checks
+    /// that the realm responds. Failed checks are not retried.
+    #[instrument(level = "trace", skip_all)]
+    pub async fn check_realms(&self) -> Vec<(RealmId, Result<(), RealmCheckError>)> {
+        self.perform_check_realms().await
+    }
+
+    /// Estimates how far the local clock is from the realms' clocks, as the
+    /// median over the realms that report their time.
+    ///
+    /// Realms reject auth tokens that are expired or not yet valid, so a large
+    /// skew can surface as [`RecoverError::InvalidAuth`]. Apps can use this
+    /// to warn the user that their clock is wrong. The result is the realms'
+    /// time minus the local time, in seconds, so it's positive when the local
+    /// clock is behind. It's `None` if no realm reported its time.
+    ///
+    /// Each realm gets the client's
+    /// [`request_timeout`](ClientBuilder::request_timeout) to respond, or
+    /// 10 seconds if none is set.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[instrument(level = "trace", skip_all)]
+    pub async fn clock_skew(&self) -> Option<i64> {
+        self.perform_clock_skew().await
+    }
 }
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use crate::request::RequestError;
+use juicebox_realm_api::requests::SecretsRequest;
+use juicebox_realm_api::types::RealmId;
+
+/// Receives a measurement for every request a [`Client`](crate::Client)
+/// makes to a realm, for example to export latency histograms and error
+/// counters.
+///
+/// Set it with [`ClientBuilder::metrics`](crate::ClientBuilder::metrics).
+/// `record` is called inline on the request path, so it should return
+/// quickly.
+pub trait ClientMetrics: Send + Sync {
+    /// Records that a request to `realm` finished with `outcome` after
+    /// `elapsed`. The time includes any automatic retries.
+    fn record(&self, realm: RealmId, op: Operation, outcome: Outcome, elapsed: Duration);
+}
+
+/// The kind of request reported to [`ClientMetrics`].
+///
+/// [`Client::register`](crate::Client::register),
+/// [`Client::recover`](crate::Client::recover), and
+/// [`Client::delete`](crate::Client::delete) each make one or more of these
+/// requests to every realm involved.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Operation {
+    Register1,
+    Register2,
+    Recover1,
+    Recover2,
+    Recover3,
+    Delete,
+}
+
+impl From<&SecretsRequest> for Operation {
+    fn from(request: &SecretsRequest) -> Self {
+        match request {
+            SecretsRequest::Register1 => Self::Register1,
+            SecretsRequest::Register2(_) => Self::Register2,
+            SecretsRequest::Recover1 => Self::Recover1,
+            SecretsRequest::Recover2(_) => Self::Recover2,
+            SecretsRequest::Recover3(_) => Self::Recover3,
+            SecretsRequest::Delete => Self::Delete,
+        }
+    }
+}
+
+/// How a request reported to [`ClientMetrics`] ended.
+///
+/// A request that reached the realm and got a response is a `Success`, even
+/// if the response reports a problem like an incorrect PIN.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Outcome {
+    Success,
+    InvalidAuth,
+    UpgradeRequired,
+    RateLimitExceeded,
+    Transient,
+    Assertion,
+    /// The request was stopped by a
+    /// [`CancellationToken`](crate::CancellationToken) before a retry.
+    Cancelled,
+}
+
+impl<T> From<&Result<T, RequestError>> for Outcome {
+    fn from(result: &Result<T, RequestError>) -> Self {
+        match result {
+            Ok(_) => Self::Success,
+            Err(RequestError::InvalidAuth) => Self::InvalidAuth,
+            Err(RequestError::UpgradeRequired) => Self::UpgradeRequired,
+            Err(RequestError::RateLimitExceeded) => Self::RateLimitExceeded,
+            Err(RequestError::Transient) => Self::Transient,
+            Err(RequestError::Assertion | RequestError::KeyMismatch) => Self::Assertion,
+            Err(RequestError::Cancelled) => Self::Cancelled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::{ClientMetrics, Operation, Outcome};
+    use crate::testing::MockRealmCluster;
+    use crate::{Pin, Policy, RealmId, UserInfo, UserSecret};
+
+    #[derive(Default)]
+    struct RecordingMetrics(Mutex<Vec<(RealmId, Operation, Outcome)>>);
+
+    impl ClientMetrics for RecordingMetrics {
+        fn record(&self, realm: RealmId, op: Operation, outcome: Outcome, _elapsed: Duration) {
+            self.0.lock().unwrap().push((realm, op, outcome));
+        }
+    }
+
+    impl RecordingMetrics {
+        fn records(&self, op: Operation) -> Vec<(RealmId, Outcome)> {
+            let mut records: Vec<_> = self
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|record| record.1 == op)
+                .map(|(realm, _, outcome)| (*realm, *outcome))
+                .collect();
+            records.sort_by_key(|(realm, _)| *realm);
+            records
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metrics_recorded_per_request() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(3, 2);
+        let realms: Vec<RealmId> = configuration.realms.iter().map(|realm| realm.id).collect();
+        let metrics = Arc::new(RecordingMetrics::default());
+        let client = cluster
+            .client_builder(configuration, "apollo")
+            .metrics(metrics.clone())
+            .build();
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+        let all_succeeded: Vec<_> = realms
+            .iter()
+            .map(|realm| (*realm, Outcome::Success))
+            .collect();
+        assert_eq!(metrics.records(Operation::Register2), all_succeeded);
+
+        cluster.set_available(&realms[0], false);
+        client.recover(&pin, &user_info).await.unwrap();
+        assert_eq!(
+            metrics.records(Operation::Recover1),
+            vec![
+                (realms[0], Outcome::Transient),
+                (realms[1], Outcome::Success),
+                (realms[2], Outcome::Success),
+            ]
+        );
+        assert_eq!(metrics.records(Operation::Recover3), all_succeeded[1..]);
+
+        client.delete().await.unwrap_err();
+        assert_eq!(
+            metrics.records(Operation::Delete)[0],
+            (realms[0], Outcome::Transient)
+        );
+    }
+}
@@ -3,6 +3,7 @@ use argon2::{Algorithm, Argon2, Params, ParamsBuilder, Version};
 use juicebox_marshalling::to_be4;
 use juicebox_realm_api::types::{RegistrationVersion, SecretBytesVec, UserSecretAccessKey};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use zeroize::Zeroize;
 
 /// A strategy for hashing the user provided [`Pin`]
@@ -12,6 +13,63 @@ pub enum PinHashingMode {
     Standard2019,
     /// A fast hash used for testing. Do not use in production.
     FastInsecure,
+    /// Argon2id with the given cost, so that it can be tuned to the class of
+    /// device doing the hashing.
+    ///
+    /// The parameters aren't stored with registrations. Recovery uses the
+    /// parameters of the configuration it's given, so changing them has the
+    /// same effect as changing modes.
+    Argon2id(Argon2Params),
+}
+
+/// The cost parameters for [`PinHashingMode::Argon2id`].
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Argon2Params {
+    /// The memory size in KiB. Must be at least 8 times `parallelism` and
+    /// at most [`Argon2Params::MAX_MEMORY_KIB`].
+    pub memory_kib: u32,
+    /// The number of passes over the memory. Must be at least 1.
+    pub iterations: u32,
+    /// The degree of parallelism. Must be between 1 and 2^24 - 1, inclusive.
+    pub parallelism: u32,
+}
+
+impl Argon2Params {
+    /// The largest `memory_kib` accepted, 1 GiB.
+    ///
+    /// Argon2 itself allows up to 4 TiB, but every recovery has to allocate
+    /// this much, so a larger value is almost certainly a mistake that would
+    /// make recovery fail on the devices that need it.
+    pub const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+}
+
+impl PinHashingMode {
+    /// Returns the Argon2id parameters for this mode, or `None` if they're
+    /// out of range.
+    pub(crate) fn argon2_params(&self) -> Option<Params> {
+        let params = match self {
+            Self::Standard2019 => Argon2Params {
+                memory_kib: 1024 * 16,
+                iterations: 32,
+                parallelism: 1,
+            },
+            Self::FastInsecure => Argon2Params {
+                memory_kib: Params::MIN_M_COST,
+                iterations: Params::MIN_T_COST,
+                parallelism: Params::MIN_P_COST,
+            },
+            Self::Argon2id(params) => *params,
+        };
+        if params.memory_kib > Argon2Params::MAX_MEMORY_KIB {
+            return None;
+        }
+        ParamsBuilder::new()
+            .m_cost(params.memory_kib)
+            .t_cost(params.iterations)
+            .p_cost(params.parallelism)
+            .build()
+            .ok()
+    }
 }
 
 impl From<u8> for PinHashingMode {
@@ -41,6 +99,73 @@ impl From<Vec<u8>> for Pin {
     }
 }
 
+/// The keys that the SDK would otherwise derive from a [`Pin`], computed
+/// elsewhere, such as in a secure enclave that never reveals the PIN.
+///
+/// The same keys are used for every registration, whereas keys hashed from a
+/// [`Pin`] are also bound to the registration version and [`UserInfo`]. The
+/// caller is responsible for deriving them with an appropriately hard hash.
+///
+/// That includes any context set with [`UserInfo::with_context`], which the
+/// SDK ignores for a `HashedPin`. Tenants relying on contexts to keep their
+/// keys apart must hash [`UserInfo::context`] into the keys themselves.
+pub struct HashedPin {
+    access_key: UserSecretAccessKey,
+    encryption_key_seed: UserSecretEncryptionKeySeed,
+}
+
+impl HashedPin {
+    pub fn new(access_key: [u8; 32], encryption_key_seed: [u8; 32]) -> Self {
+        Self {
+            access_key: UserSecretAccessKey::from(access_key),
+            encryption_key_seed: UserSecretEncryptionKeySeed::from(encryption_key_seed),
+        }
+    }
+}
+
+impl fmt::Debug for HashedPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashedPin").finish_non_exhaustive()
+    }
+}
+
+/// Produces the access key and encryption key seed for a registration
+/// version. This is implemented by [`Pin`], which hashes the PIN, and by
+/// [`HashedPin`], which returns its pre-computed keys.
+pub(crate) trait PinKeys {
+    fn keys(
+        &self,
+        mode: PinHashingMode,
+        version: &RegistrationVersion,
+        info: &UserInfo,
+    ) -> Option<(UserSecretAccessKey, UserSecretEncryptionKeySeed)>;
+}
+
+impl PinKeys for Pin {
+    fn keys(
+        &self,
+        mode: PinHashingMode,
+        version: &RegistrationVersion,
+        info: &UserInfo,
+    ) -> Option<(UserSecretAccessKey, UserSecretEncryptionKeySeed)> {
+        self.hash(mode, version, info)
+    }
+}
+
+impl PinKeys for HashedPin {
+    fn keys(
+        &self,
+        _mode: PinHashingMode,
+        _version: &RegistrationVersion,
+        _info: &UserInfo,
+    ) -> Option<(UserSecretAccessKey, UserSecretEncryptionKeySeed)> {
+        Some((
+            self.access_key.clone(),
+            UserSecretEncryptionKeySeed::from(*self.encryption_key_seed.expose_secret()),
+        ))
+    }
+}
+
 impl Pin {
     pub(crate) fn hash(
         &self,
@@ -48,26 +173,7 @@ impl Pin {
         version: &RegistrationVersion,
         info: &UserInfo,
     ) -> Option<(UserSecretAccessKey, UserSecretEncryptionKeySeed)> {
-        match mode {
-            PinHashingMode::Standard2019 => {
-                let params = ParamsBuilder::new()
-                    .m_cost(1024 * 16)
-                    .t_cost(32)
-                    .p_cost(1)
-                    .build()
-                    .ok()?;
-                self.argon2(params, version, info)
-            }
-            PinHashingMode::FastInsecure => {
-                let params = ParamsBuilder::new()
-                    .m_cost(Params::MIN_M_COST)
-                    .t_cost(Params::MIN_T_COST)
-                    .p_cost(Params::MIN_P_COST)
-                    .build()
-                    .ok()?;
-                self.argon2(params, version, info)
-            }
-        }
+        self.argon2(mode.argon2_params()?, version, info)
     }
 
     fn argon2(
@@ -78,19 +184,22 @@ impl Pin {
     ) -> Option<(UserSecretAccessKey, UserSecretEncryptionKeySeed)> {
         let mut hashed_pin = vec![0u8; 64];
 
+        let mut salt = [
+            &to_be4(version.expose_secret().len()),
+            version.expose_secret().as_slice(),
+            &to_be4(info.expose_secret().len()),
+            info.expose_secret(),
+        ]
+        .concat();
+        // The context is only appended when present, so that info without
+        // one keeps its original salt.
+        if let Some(context) = info.context() {
+            salt.extend(to_be4(context.len()));
+            salt.extend(context);
+        }
+
         Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
-            .hash_password_into(
-                self.expose_secret(),
-                [
-                    &to_be4(version.expose_secret().len()),
-                    version.expose_secret().as_slice(),
-                    &to_be4(info.expose_secret().len()),
-                    info.expose_secret(),
-                ]
-                .concat()
-                .as_slice(),
-                &mut hashed_pin,
-            )
+            .hash_password_into(self.expose_secret(), &salt, &mut hashed_pin)
             .ok()?;
 
         let access_key_bytes: [u8; 32] = hashed_pin[..32].try_into().unwrap();
@@ -112,8 +221,9 @@ mod tests {
     use juicebox_realm_api::types::RegistrationVersion;
 
     use crate::{
-        pin::{Pin, PinHashingMode},
-        UserInfo,
+        pin::{Argon2Params, Pin, PinHashingMode},
+        types::MAX_USER_INFO_LENGTH,
+        UserInfo, UserInfoError,
     };
 
     #[test]
@@ -138,4 +248,102 @@ mod tests {
             expected_encryption_key_seed
         );
     }
+
+    #[test]
+    fn test_argon2id_pin_hashing() {
+        let salt = RegistrationVersion::from([5; 16]);
+        let pin = Pin::from(b"1234".to_vec());
+        let info = UserInfo::from(b"artemis".to_vec());
+        let mode = PinHashingMode::Argon2id(Argon2Params {
+            memory_kib: 256,
+            iterations: 2,
+            parallelism: 2,
+        });
+        let (access_key, encryption_key_seed) = pin.hash(mode, &salt, &info).unwrap();
+        let expected_access_key: [u8; 32] = [
+            164, 239, 32, 101, 150, 159, 194, 31, 179, 254, 62, 108, 172, 13, 104, 62, 207, 34, 6,
+            76, 248, 57, 188, 139, 120, 106, 198, 91, 251, 237, 99, 234,
+        ];
+        let expected_encryption_key_seed: [u8; 32] = [
+            58, 248, 68, 102, 211, 149, 62, 112, 162, 171, 174, 2, 40, 102, 30, 240, 31, 188, 54,
+            84, 146, 152, 112, 7, 89, 128, 230, 97, 65, 71, 121, 50,
+        ];
+        assert_eq!(*access_key.expose_secret(), expected_access_key);
+        assert_eq!(
+            *encryption_key_seed.expose_secret(),
+            expected_encryption_key_seed
+        );
+
+        // The fixed modes are Argon2id with particular parameters.
+        let fast = PinHashingMode::Argon2id(Argon2Params {
+            memory_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+        });
+        let expected = pin
+            .hash(PinHashingMode::FastInsecure, &salt, &info)
+            .unwrap();
+        let actual = pin.hash(fast, &salt, &info).unwrap();
+        assert_eq!(actual.0.expose_secret(), expected.0.expose_secret());
+        assert_eq!(actual.1.expose_secret(), expected.1.expose_secret());
+
+        let invalid = PinHashingMode::Argon2id(Argon2Params {
+            memory_kib: 8,
+            iterations: 0,
+            parallelism: 1,
+        });
+        assert!(invalid.argon2_params().is_none());
+        assert!(pin.hash(invalid, &salt, &info).is_none());
+
+        let too_large = PinHashingMode::Argon2id(Argon2Params {
+            memory_kib: Argon2Params::MAX_MEMORY_KIB + 1,
+            iterations: 1,
+            parallelism: 1,
+        });
+        assert!(too_large.argon2_params().is_none());
+    }
+
+    #[test]
+    fn test_pin_hashing_with_context() {
+        let salt = RegistrationVersion::from([5; 16]);
+        let pin = Pin::from(b"1234".to_vec());
+        let mode = PinHashingMode::FastInsecure;
+        let access_key = |info: &UserInfo| *pin.hash(mode, &salt, info).unwrap().0.expose_secret();
+
+        let info = UserInfo::new(b"artemis".to_vec()).unwrap();
+        let tenant_a = info.clone().with_context(b"tenant-a".to_vec()).unwrap();
+        let tenant_b = info.clone().with_context(b"tenant-b".to_vec()).unwrap();
+        assert_eq!(tenant_a.context(), Some(b"tenant-a".as_slice()));
+        assert_eq!(info.context(), None);
+
+        assert_ne!(access_key(&tenant_a), access_key(&info));
+        assert_ne!(access_key(&tenant_a), access_key(&tenant_b));
+        assert_eq!(
+            access_key(&tenant_a),
+            access_key(
+                &UserInfo::new(b"artemis".to_vec())
+                    .unwrap()
+                    .with_context(b"tenant-a".to_vec())
+                    .unwrap()
+            )
+        );
+
+        // The context is length-prefixed, so moving bytes between the info
+        // and the context changes the keys.
+        let shifted = UserInfo::new(b"artemist".to_vec())
+            .unwrap()
+            .with_context(b"enant-a".to_vec())
+            .unwrap();
+        assert_ne!(access_key(&shifted), access_key(&tenant_a));
+
+        assert_eq!(
+            info.clone().with_context(Vec::new()).unwrap_err(),
+            UserInfoError::Empty
+        );
+        assert_eq!(
+            info.with_context(vec![0; MAX_USER_INFO_LENGTH + 1])
+                .unwrap_err(),
+            UserInfoError::TooLong
+        );
+    }
 }
@@ -3,8 +3,9 @@ use rand::rngs::OsRng;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Display};
+use std::sync::Mutex;
 use subtle::ConstantTimeEq;
-use tracing::instrument;
+use tracing::{debug, instrument, warn};
 
 use juicebox_oprf as oprf;
 use juicebox_realm_api::{
@@ -14,21 +15,22 @@ use juicebox_realm_api::{
     },
     signing::OprfVerifyingKey,
     types::{
-        EncryptedUserSecret, EncryptedUserSecretCommitment, RegistrationVersion,
-        UnlockKeyCommitment, UnlockKeyTag, UserSecretEncryptionKeyScalarShare,
+        EncryptedUserSecret, EncryptedUserSecretCommitment, RealmId, RegistrationVersion,
+        SecretCipher, UnlockKeyCommitment, UnlockKeyTag, UserSecretEncryptionKeyScalarShare,
     },
 };
-use juicebox_secret_sharing::{recover_secret, RecoverSecretError, Share};
+use juicebox_secret_sharing::{combine_shares, RecoverSecretError, Share};
 
 use crate::{
     auth,
     configuration::CheckedConfiguration,
     http,
-    request::{join_at_least_threshold, RequestError},
+    pin::PinKeys,
+    request::{join_at_least_threshold, RequestError, RetryBudget},
     types::{
         derive_unlock_key_and_commitment, UserSecretEncryptionKey, UserSecretEncryptionKeyScalar,
     },
-    Client, Pin, Realm, Sleeper, UserInfo, UserSecret,
+    Client, Realm, Sleeper, UserInfo, UserSecret,
 };
 
 /// Error return type for [`Client::recover`].
@@ -39,10 +41,24 @@ pub enum RecoverError {
     /// guesses remain, this secret is locked and inaccessible.
     InvalidPin { guesses_remaining: u16 },
 
+    /// No guesses remained before this attempt, so the PIN was not checked.
+    /// The secret is locked and inaccessible until it is registered again.
+    ///
+    /// A wrong guess that uses up the last guess is reported as
+    /// [`RecoverError::InvalidPin`] with zero guesses remaining instead.
+    Locked,
+
     /// The secret was not registered or not fully registered with the
     /// provided realms.
     NotRegistered,
 
+    /// The secret was registered with a different set of realms than the
+    /// configuration lists, for example because a realm was added or
+    /// removed since. This is detected before any guess is used. Recover
+    /// with the configuration the secret was registered with, such as by
+    /// passing it in the `Client`'s previous configurations.
+    RealmSetMismatch,
+
     /// A realm rejected the `Client`'s auth token.
     InvalidAuth,
 
@@ -62,41 +78,179 @@ pub enum RecoverError {
     /// A transient error in sending or receiving requests to a realm.
     /// This request may succeed by trying again with the same parameters.
     Transient,
+
+    /// The operation was stopped with the `Client`'s
+    /// [`CancellationToken`](crate::CancellationToken).
+    Cancelled,
 }
 
 impl Display for RecoverError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Debug::fmt(self, f)
+        match self {
+            Self::InvalidPin { guesses_remaining } => {
+                write!(f, "invalid PIN ({guesses_remaining} guesses remaining)")
+            }
+            Self::Locked => write!(f, "secret is locked, with no guesses remaining"),
+            Self::NotRegistered => write!(f, "secret is not registered"),
+            Self::RealmSetMismatch => {
+                write!(f, "secret was registered with a different set of realms")
+            }
+            Self::InvalidAuth => write!(f, "realm rejected auth token"),
+            Self::UpgradeRequired => write!(f, "realm requires a newer SDK version"),
+            Self::RateLimitExceeded => write!(f, "tenant exceeded its rate limit"),
+            Self::Assertion => write!(f, "unexpected error in SDK or realm"),
+            Self::Transient => write!(f, "transient error communicating with realm"),
+            Self::Cancelled => write!(f, "operation was cancelled"),
+        }
     }
 }
 
 impl Error for RecoverError {}
 
+/// The result of a successful [`Client::recover_detailed`].
+#[derive(Clone, Debug)]
+pub struct RecoverOutcome {
+    /// The recovered secret.
+    pub secret: UserSecret,
+
+    /// True if fewer than all of the realms contributed to the recovery.
+    /// The secret remains recoverable while enough realms do, but the app
+    /// may want to prompt the user to register again to restore full
+    /// redundancy.
+    pub degraded: bool,
+
+    /// The realms that did not contribute to the recovery, for example
+    /// because they returned an error or were unreachable.
+    pub failed_realms: Vec<RealmId>,
+
+    /// What happened on each configured realm, in the configuration's order.
+    pub realms: Vec<(RealmId, RealmOutcome)>,
+
+    /// The fewest guesses any realm had remaining after counting this
+    /// recovery's guess. This is lower than the policy's `num_guesses` when
+    /// earlier attempts used the wrong PIN, which the app may want to tell
+    /// the user about.
+    ///
+    /// Realms reset the count after a successful recovery, so the next
+    /// recovery starts with the policy's full number of guesses again.
+    pub guesses_remaining: u16,
+}
+
+/// What happened on one realm during a successful
+/// [`Client::recover_detailed`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RealmOutcome {
+    /// The realm's share was used to recover the secret.
+    Contributed,
+
+    /// The realm returned an error, such as [`RecoverError::Locked`] when it
+    /// had no guesses left or [`RecoverError::Transient`] when it was
+    /// unreachable. This is the first error the realm returned.
+    Failed(RecoverError),
+
+    /// The realm responded, but its registration version, commitment, or
+    /// encrypted secret disagreed with those of the realms that recovered
+    /// the secret.
+    Diverged,
+}
+
+/// Why a guess that a realm counted during recovery was not restored.
+///
+/// A realm counts a guess when it answers phase 2 and restores it only when
+/// phase 3 succeeds. These causes are logged to help diagnose reports of
+/// guesses lost to something other than a wrong PIN.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum GuessConsumedCause {
+    /// Phase 2 failed after the realm may have counted the guess, for
+    /// example because the response was lost or could not be verified.
+    Recover2Failed(RecoverError),
+
+    /// The PIN did not match the unlock key commitment, so phase 3 was not
+    /// attempted.
+    WrongPin,
+
+    /// The realm rejected the unlock key tag in phase 3.
+    BadUnlockKeyTag,
+
+    /// Phase 3 failed for a reason other than the unlock key tag.
+    Recover3Failed(RecoverError),
+}
+
+impl GuessConsumedCause {
+    /// Returns the cause to record when phase 2 fails on a realm with `err`,
+    /// or `None` if the realm could not have counted a guess.
+    pub(crate) fn from_recover2_error(err: RecoverError) -> Option<Self> {
+        match err {
+            RecoverError::Transient | RecoverError::Assertion => Some(Self::Recover2Failed(err)),
+            RecoverError::InvalidPin { .. }
+            | RecoverError::Locked
+            | RecoverError::NotRegistered
+            | RecoverError::RealmSetMismatch
+            | RecoverError::InvalidAuth
+            | RecoverError::UpgradeRequired
+            | RecoverError::RateLimitExceeded
+            | RecoverError::Cancelled => None,
+        }
+    }
+
+    /// Returns the cause to record when phase 3 fails on a realm with `err`.
+    /// The realm counted a guess in phase 2, so every failure leaves it
+    /// consumed.
+    pub(crate) fn from_recover3_error(err: RecoverError) -> Self {
+        match err {
+            RecoverError::InvalidPin { .. } => Self::BadUnlockKeyTag,
+            _ => Self::Recover3Failed(err),
+        }
+    }
+
+    fn record(self, realm: &Realm) {
+        debug!(realm = ?realm.id, cause = ?self, "recover consumed a guess");
+    }
+}
+
 impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http, Atm> {
     pub(crate) async fn perform_recover(
         &self,
-        pin: &Pin,
+        pin: &impl PinKeys,
+        info: &UserInfo,
+    ) -> Result<RecoverOutcome, RecoverError> {
+        self.perform_recover_from(
+            pin,
+            info,
+            std::iter::once(&self.configuration).chain(&self.previous_configurations),
+        )
+        .await
+    }
+
+    /// Recovers from the first of `configurations` that has the secret
+    /// registered, moving on to the next only when a configuration reports
+    /// [`RecoverError::NotRegistered`] or
+    /// [`RecoverError::RealmSetMismatch`]. Neither uses a guess.
+    pub(crate) async fn perform_recover_from<'a>(
+        &self,
+        pin: &impl PinKeys,
         info: &UserInfo,
-    ) -> Result<UserSecret, RecoverError> {
-        let mut configuration = &self.configuration;
-        let mut iter = self.previous_configurations.iter();
-        loop {
-            return match self
-                .perform_recover_with_configuration(pin, info, configuration)
+        configurations: impl IntoIterator<Item = &'a CheckedConfiguration>,
+    ) -> Result<RecoverOutcome, RecoverError> {
+        // The retry budget is shared across every configuration tried.
+        let retry_budget = RetryBudget::new(self.recover_retry_budget);
+        let mut error = RecoverError::NotRegistered;
+        for configuration in configurations {
+            match self
+                .perform_recover_with_configuration(pin, info, configuration, &retry_budget)
                 .await
             {
-                Ok(secret) => Ok(secret),
-                Err(RecoverError::NotRegistered) => {
-                    if let Some(next_configuration) = iter.next() {
-                        configuration = next_configuration;
-                        continue;
-                    }
-
-                    Err(RecoverError::NotRegistered)
+                Err(RecoverError::NotRegistered) => continue,
+                // A mismatch says more than a configuration without the
+                // secret, so it's reported if no configuration has it.
+                Err(RecoverError::RealmSetMismatch) => {
+                    error = RecoverError::RealmSetMismatch;
+                    continue;
                 }
-                Err(err) => Err(err),
-            };
+                result => return result,
+            }
         }
+        Err(error)
     }
 
     /// Performs phase 1 of recovery for the parameters specified in a given
@@ -105,18 +259,32 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
     #[instrument(level = "trace", skip_all, err(level = "trace", Debug))]
     async fn perform_recover_with_configuration(
         &self,
-        pin: &Pin,
+        pin: &impl PinKeys,
         info: &UserInfo,
         configuration: &CheckedConfiguration,
-    ) -> Result<UserSecret, RecoverError> {
-        let recover1_requests = configuration
-            .realms
-            .iter()
-            .map(|realm| self.recover1_on_realm(realm));
+        retry_budget: &RetryBudget,
+    ) -> Result<RecoverOutcome, RecoverError> {
+        // The first error from each realm, for `RecoverOutcome::realms`.
+        let realm_errors: Mutex<HashMap<RealmId, RecoverError>> = Mutex::new(HashMap::new());
+        let record_error = |realm: &Realm, err: RecoverError| {
+            realm_errors.lock().unwrap().entry(realm.id).or_insert(err);
+        };
+
+        let recover1_requests = configuration.realms.iter().map(|realm| async {
+            let result = self.recover1_on_realm(realm, retry_budget).await;
+            if let Err(err) = result {
+                record_error(realm, err);
+            }
+            result
+        });
 
         let mut realms_per_version: HashMap<RegistrationVersion, Vec<Realm>> = HashMap::new();
-        for (version, realm) in
-            join_at_least_threshold(recover1_requests, configuration.recover_threshold).await?
+        for (version, realm) in join_at_least_threshold(
+            recover1_requests,
+            configuration.recover_threshold,
+            self.configuration.max_concurrency,
+        )
+        .await?
         {
             realms_per_version.entry(version).or_default().push(realm);
         }
@@ -132,15 +300,41 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
             return Err(RecoverError::NotRegistered);
         };
 
+        // The version records the realm set the secret was registered with,
+        // which is checked here before Recover2 uses a guess.
+        let realm_set_hash = configuration.realm_set_hash();
+        let Some(binding) = realm_set_hash.binding(&version) else {
+            return Err(RecoverError::RealmSetMismatch);
+        };
+
         let (access_key, encryption_key_seed) = pin
-            .hash(configuration.pin_hashing_mode, &version, info)
+            .keys(configuration.pin_hashing_mode, &version, info)
             .expect("pin hashing failed");
 
         let (oprf_blinding_factor, oprf_blinded_input) =
             oprf::start(access_key.expose_secret(), &mut OsRng);
 
-        let recover2_requests = realms.iter().map(|realm| {
-            self.recover2_on_realm(realm, configuration, &version, &oprf_blinded_input)
+        let recover2_requests = realms.iter().map(|realm| async {
+            let result = self
+                .recover2_on_realm(
+                    realm,
+                    configuration,
+                    &version,
+                    &oprf_blinded_input,
+                    retry_budget,
+                )
+                .await;
+            if let Some(cause) = result
+                .as_ref()
+                .err()
+                .and_then(|err| GuessConsumedCause::from_recover2_error(*err))
+            {
+                cause.record(realm);
+            }
+            if let Err(err) = result {
+                record_error(realm, err);
+            }
+            result
         });
 
         let mut oprf_blinded_result_shares_by_commitment_and_verifying_key: HashMap<_, Vec<_>> =
@@ -148,8 +342,12 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
 
         // TODO: this should stop after finding threshold realms that agree on
         // commitment and verifying key
-        for (oprf_verifying_key, share, commitment, guesses_remaining) in
-            join_at_least_threshold(recover2_requests, configuration.recover_threshold).await?
+        for (oprf_verifying_key, share, commitment, guesses_remaining) in join_at_least_threshold(
+            recover2_requests,
+            configuration.recover_threshold,
+            self.configuration.max_concurrency,
+        )
+        .await?
         {
             oprf_blinded_result_shares_by_commitment_and_verifying_key
                 .entry((commitment, oprf_verifying_key))
@@ -180,44 +378,71 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
             .into_iter()
             .unzip();
 
-        let oprf_blinded_result = match recover_secret(&oprf_blinded_result_shares) {
-            Ok(blinded_result) => oprf::BlindedOutput::from(blinded_result),
-            Err(RecoverSecretError::DuplicateShares) => return Err(RecoverError::Assertion),
-        };
+        // The realms' responses are collected in the order they arrived.
+        // `combine_shares` uses the shares with the lowest indices instead,
+        // so that neither the result nor its timing depends on that order.
+        let oprf_blinded_result =
+            match combine_shares(&oprf_blinded_result_shares, configuration.recover_threshold) {
+                Ok(blinded_result) => oprf::BlindedOutput::from(blinded_result),
+                Err(
+                    RecoverSecretError::DuplicateShares
+                    | RecoverSecretError::InsufficientShares
+                    | RecoverSecretError::InconsistentShares { .. },
+                ) => return Err(RecoverError::Assertion),
+            };
         let oprf_result = oprf::finalize(
             access_key.expose_secret(),
             &oprf_blinding_factor,
             &oprf_blinded_result,
         );
 
-        let (unlock_key, our_commitment) = derive_unlock_key_and_commitment(&oprf_result);
+        let (unlock_key, our_commitment) = derive_unlock_key_and_commitment(&oprf_result, binding);
+        let guesses_remaining = all_guesses_remaining.into_iter().min().unwrap();
         if !bool::from(unlock_key_commitment.ct_eq(&our_commitment)) {
-            let guesses_remaining = all_guesses_remaining.into_iter().min().unwrap();
+            // Realms that failed phase 2 were already logged above.
+            let realm_errors = realm_errors.lock().unwrap();
+            for realm in realms
+                .iter()
+                .filter(|realm| !realm_errors.contains_key(&realm.id))
+            {
+                GuessConsumedCause::WrongPin.record(realm);
+            }
             return Err(RecoverError::InvalidPin { guesses_remaining });
         }
 
-        let recover3_requests = realms.iter().map(|realm| {
-            self.recover3_on_realm(
-                realm,
-                configuration,
-                &version,
-                UnlockKeyTag::derive(&unlock_key, &realm.id),
-            )
+        let recover3_requests = realms.iter().map(|realm| async {
+            let result = self
+                .recover3_on_realm(
+                    realm,
+                    configuration,
+                    &version,
+                    UnlockKeyTag::derive(&unlock_key, &realm.id),
+                    retry_budget,
+                )
+                .await;
+            if let Err(err) = result {
+                GuessConsumedCause::from_recover3_error(err).record(realm);
+                record_error(realm, err);
+            }
+            result
         });
 
-        let mut encryption_key_scalar_shares_by_encrypted_secret: HashMap<
-            EncryptedUserSecret,
-            Vec<Share<Scalar>>,
-        > = HashMap::new();
+        let mut encryption_key_scalar_shares_by_encrypted_secret: HashMap<_, Vec<_>> =
+            HashMap::new();
 
-        for (share, encrypted_secret, commitment, realm) in
-            join_at_least_threshold(recover3_requests, configuration.recover_threshold).await?
+        for (share, encrypted_secret, commitment, secret_cipher, realm) in join_at_least_threshold(
+            recover3_requests,
+            configuration.recover_threshold,
+            self.configuration.max_concurrency,
+        )
+        .await?
         {
             let our_commitment = EncryptedUserSecretCommitment::derive(
                 &unlock_key,
                 &realm.id,
                 &UserSecretEncryptionKeyScalarShare::from(share.secret),
                 &encrypted_secret,
+                secret_cipher,
             );
 
             // We can't use the share from this realm, but we continue
@@ -227,9 +452,9 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
             }
 
             encryption_key_scalar_shares_by_encrypted_secret
-                .entry(encrypted_secret)
+                .entry((encrypted_secret, secret_cipher))
                 .or_default()
-                .push(share);
+                .push((share, realm.id));
         }
 
         encryption_key_scalar_shares_by_encrypted_secret
@@ -239,7 +464,7 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
         // be one or none realms with consensus on an encrypted secret to recover from.
         assert!(encryption_key_scalar_shares_by_encrypted_secret.len() <= 1);
 
-        let Some((encrypted_secret, encryption_key_scalar_shares)) =
+        let Some(((encrypted_secret, secret_cipher), encryption_key_scalar_shares_and_realms)) =
             encryption_key_scalar_shares_by_encrypted_secret
                 .into_iter()
                 .next()
@@ -247,36 +472,97 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
             return Err(RecoverError::Assertion);
         };
 
-        match recover_secret(&encryption_key_scalar_shares) {
+        let (encryption_key_scalar_shares, contributing_realms): (
+            Vec<Share<Scalar>>,
+            Vec<RealmId>,
+        ) = encryption_key_scalar_shares_and_realms.into_iter().unzip();
+
+        match combine_shares(
+            &encryption_key_scalar_shares,
+            configuration.recover_threshold,
+        ) {
             Ok(secret) => {
                 let scalar = UserSecretEncryptionKeyScalar::new(secret);
                 let encryption_key = UserSecretEncryptionKey::derive(&encryption_key_seed, &scalar);
 
-                Ok(UserSecret::decrypt(&encrypted_secret, &encryption_key))
+                let failed_realms: Vec<RealmId> = configuration
+                    .realms
+                    .iter()
+                    .map(|realm| realm.id)
+                    .filter(|id| !contributing_realms.contains(id))
+                    .collect();
+                // Every request ran to completion, so a realm that didn't
+                // contribute and didn't fail must have disagreed.
+                let realm_errors = realm_errors.into_inner().unwrap();
+                let realms = configuration
+                    .realms
+                    .iter()
+                    .map(|realm| {
+                        let outcome = if contributing_realms.contains(&realm.id) {
+                            RealmOutcome::Contributed
+                        } else if let Some(err) = realm_errors.get(&realm.id) {
+                            RealmOutcome::Failed(*err)
+                        } else {
+                            RealmOutcome::Diverged
+                        };
+                        (realm.id, outcome)
+                    })
+                    .collect();
+                if !failed_realms.is_empty() {
+                    // The secret is still recoverable, but closer to not
+                    // being, so operators should look into the failed realms.
+                    warn!(
+                        contributing = contributing_realms.len(),
+                        realms = configuration.realms.len(),
+                        threshold = configuration.recover_threshold,
+                        failed_realms = ?failed_realms,
+                        "recovered with degraded realms"
+                    );
+                }
+                // The commitments bind the cipher, so this only fails if
+                // the realms that agreed on the secret are misbehaving.
+                let secret = UserSecret::decrypt(secret_cipher, &encrypted_secret, &encryption_key)
+                    .map_err(|_| RecoverError::Assertion)?;
+                Ok(RecoverOutcome {
+                    secret,
+                    degraded: !failed_realms.is_empty(),
+                    failed_realms,
+                    realms,
+                    guesses_remaining,
+                })
             }
-            Err(_) => Err(RecoverError::Assertion),
+            Err(
+                RecoverSecretError::DuplicateShares
+                | RecoverSecretError::InsufficientShares
+                | RecoverSecretError::InconsistentShares { .. },
+            ) => Err(RecoverError::Assertion),
         }
     }
 
     /// Performs phase 1 of recovery on a particular realm.
-    #[instrument(level = "trace", skip(self), err(level = "trace", Debug))]
-    async fn recover1_on_realm(
+    #[instrument(level = "trace", skip(self, retry_budget), err(level = "trace", Debug))]
+    pub(crate) async fn recover1_on_realm(
         &self,
         realm: &Realm,
+        retry_budget: &RetryBudget,
     ) -> Result<(RegistrationVersion, Realm), RecoverError> {
-        match self.make_request(realm, SecretsRequest::Recover1).await {
+        match self
+            .make_request_with_retry_budget(realm, SecretsRequest::Recover1, retry_budget)
+            .await
+        {
             Err(RequestError::UpgradeRequired) => Err(RecoverError::UpgradeRequired),
             Err(RequestError::InvalidAuth) => Err(RecoverError::InvalidAuth),
-            Err(RequestError::Assertion) => Err(RecoverError::Assertion),
+            Err(RequestError::Assertion | RequestError::KeyMismatch) => {
+                Err(RecoverError::Assertion)
+            }
             Err(RequestError::Transient) => Err(RecoverError::Transient),
             Err(RequestError::RateLimitExceeded) => Err(RecoverError::RateLimitExceeded),
+            Err(RequestError::Cancelled) => Err(RecoverError::Cancelled),
 
             Ok(SecretsResponse::Recover1(response)) => match response {
                 Recover1Response::Ok { version } => Ok((version, realm.to_owned())),
                 Recover1Response::NotRegistered => Err(RecoverError::NotRegistered),
-                Recover1Response::NoGuesses => Err(RecoverError::InvalidPin {
-                    guesses_remaining: 0,
-                }),
+                Recover1Response::NoGuesses => Err(RecoverError::Locked),
             },
             Ok(_) => Err(RecoverError::Assertion),
         }
@@ -290,6 +576,7 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
         configuration: &CheckedConfiguration,
         version: &RegistrationVersion,
         oprf_blinded_input: &oprf::BlindedInput,
+        retry_budget: &RetryBudget,
     ) -> Result<
         (
             OprfVerifyingKey,
@@ -299,12 +586,16 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
         ),
         RecoverError,
     > {
-        let recover2_request = self.make_request(
+        // Recover2 consumes a guess, so it's never retried automatically
+        // after it may have reached the realm. See
+        // `SecretsRequest::consumes_guess`.
+        let recover2_request = self.make_request_with_retry_budget(
             realm,
             SecretsRequest::Recover2(Recover2Request {
                 version: version.to_owned(),
                 oprf_blinded_input: oprf_blinded_input.to_owned(),
             }),
+            retry_budget,
         );
 
         let (
@@ -316,9 +607,12 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
         ) = match recover2_request.await {
             Err(RequestError::UpgradeRequired) => return Err(RecoverError::UpgradeRequired),
             Err(RequestError::Transient) => return Err(RecoverError::Transient),
-            Err(RequestError::Assertion) => return Err(RecoverError::Assertion),
+            Err(RequestError::Assertion | RequestError::KeyMismatch) => {
+                return Err(RecoverError::Assertion)
+            }
             Err(RequestError::InvalidAuth) => return Err(RecoverError::InvalidAuth),
             Err(RequestError::RateLimitExceeded) => return Err(RecoverError::RateLimitExceeded),
+            Err(RequestError::Cancelled) => return Err(RecoverError::Cancelled),
 
             Ok(SecretsResponse::Recover2(rr)) => match rr {
                 Recover2Response::Ok {
@@ -345,9 +639,7 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
                 }
 
                 Recover2Response::NoGuesses => {
-                    return Err(RecoverError::InvalidPin {
-                        guesses_remaining: 0,
-                    });
+                    return Err(RecoverError::Locked);
                 }
             },
 
@@ -389,35 +681,42 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
         configuration: &CheckedConfiguration,
         version: &RegistrationVersion,
         unlock_key_tag: UnlockKeyTag,
+        retry_budget: &RetryBudget,
     ) -> Result<
         (
             Share<Scalar>,
             EncryptedUserSecret,
             EncryptedUserSecretCommitment,
+            SecretCipher,
             Realm,
         ),
         RecoverError,
     > {
-        let recover3_request = self.make_request(
+        let recover3_request = self.make_request_with_retry_budget(
             realm,
             SecretsRequest::Recover3(Recover3Request {
                 version: version.to_owned(),
                 unlock_key_tag,
             }),
+            retry_budget,
         );
 
         match recover3_request.await {
             Err(RequestError::UpgradeRequired) => Err(RecoverError::UpgradeRequired),
             Err(RequestError::Transient) => Err(RecoverError::Transient),
-            Err(RequestError::Assertion) => Err(RecoverError::Assertion),
+            Err(RequestError::Assertion | RequestError::KeyMismatch) => {
+                Err(RecoverError::Assertion)
+            }
             Err(RequestError::InvalidAuth) => Err(RecoverError::InvalidAuth),
             Err(RequestError::RateLimitExceeded) => Err(RecoverError::RateLimitExceeded),
+            Err(RequestError::Cancelled) => Err(RecoverError::Cancelled),
 
             Ok(SecretsResponse::Recover3(rr)) => match rr {
                 Recover3Response::Ok {
                     encryption_key_scalar_share,
                     encrypted_secret,
                     encrypted_secret_commitment,
+                    secret_cipher,
                 } => {
                     let secret_share = Share {
                         index: configuration
@@ -429,13 +728,12 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
                         secret_share,
                         encrypted_secret,
                         encrypted_secret_commitment,
+                        secret_cipher,
                         realm.to_owned(),
                     ))
                 }
                 Recover3Response::NotRegistered => Err(RecoverError::NotRegistered),
-                Recover3Response::NoGuesses => Err(RecoverError::InvalidPin {
-                    guesses_remaining: 0,
-                }),
+                Recover3Response::NoGuesses => Err(RecoverError::Locked),
                 Recover3Response::BadUnlockKeyTag { guesses_remaining } => {
                     Err(RecoverError::InvalidPin { guesses_remaining })
                 }
@@ -445,3 +743,541 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+    use std::time::Duration;
+
+    use super::GuessConsumedCause;
+    use crate::testing::{MockRealmCluster, RecordingSubscriber};
+    use crate::{
+        HashedPin, Pin, Policy, RealmId, RealmOutcome, RecoverError, SecretCipher, UserInfo,
+        UserSecret,
+    };
+
+    #[test]
+    fn test_register_and_recover_without_tokio() {
+        // The client doesn't need a tokio runtime, so any executor can drive
+        // it.
+        let cluster = MockRealmCluster::new(3);
+        let client = cluster.client(cluster.configuration(3, 2), "apollo");
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        futures::executor::block_on(async {
+            client
+                .register(&pin, &secret, &user_info, Policy { num_guesses: 2 })
+                .await
+                .unwrap();
+            let recovered_secret = client.recover(&pin, &user_info).await.unwrap();
+            assert_eq!(secret.expose_secret(), recovered_secret.expose_secret());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_recover_with_mismatched_user_info() {
+        let cluster = MockRealmCluster::new(3);
+        let client = cluster.client(cluster.configuration(3, 2), "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            client
+                .recover(&pin, &UserInfo::new(b"nope".to_vec()).unwrap())
+                .await
+                .unwrap_err(),
+            RecoverError::InvalidPin {
+                guesses_remaining: 1
+            }
+        );
+
+        let recovered_secret = client.recover(&pin, &user_info).await.unwrap();
+        assert_eq!(secret.expose_secret(), recovered_secret.expose_secret());
+    }
+
+    #[tokio::test]
+    async fn test_recover_with_mismatched_context() {
+        let cluster = MockRealmCluster::new(3);
+        let client = cluster.client(cluster.configuration(3, 2), "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+        let tenant_a = user_info
+            .clone()
+            .with_context(b"tenant-a".to_vec())
+            .unwrap();
+
+        client
+            .register(&pin, &secret, &tenant_a, Policy { num_guesses: 3 })
+            .await
+            .unwrap();
+
+        let tenant_b = user_info
+            .clone()
+            .with_context(b"tenant-b".to_vec())
+            .unwrap();
+        assert_eq!(
+            client.recover(&pin, &tenant_b).await.unwrap_err(),
+            RecoverError::InvalidPin {
+                guesses_remaining: 2
+            }
+        );
+        assert_eq!(
+            client.recover(&pin, &user_info).await.unwrap_err(),
+            RecoverError::InvalidPin {
+                guesses_remaining: 1
+            }
+        );
+
+        let recovered_secret = client.recover(&pin, &tenant_a).await.unwrap();
+        assert_eq!(secret.expose_secret(), recovered_secret.expose_secret());
+    }
+
+    #[tokio::test]
+    async fn test_guess_consumed_by_bad_unlock_key_tag() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(3, 2);
+        let bad_realm = configuration.realms[0].id;
+        let client = cluster.client(configuration, "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+        cluster.corrupt_unlock_key_tag(&bad_realm, "apollo");
+
+        let subscriber = RecordingSubscriber::default();
+        let recovered_secret = {
+            let _guard = tracing::subscriber::set_default(subscriber.clone());
+            client.recover(&pin, &user_info).await.unwrap()
+        };
+        assert_eq!(secret.expose_secret(), recovered_secret.expose_secret());
+
+        let events = subscriber.events("recover consumed a guess");
+        assert_eq!(events.len(), 1, "{events:?}");
+        assert_eq!(events[0]["realm"], format!("{bad_realm:?}"));
+        assert_eq!(events[0]["cause"], "BadUnlockKeyTag");
+    }
+
+    #[tokio::test]
+    async fn test_guess_consumed_logged_once_per_realm() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(3, 2);
+        let flaky_realm = configuration.realms[0].id;
+        let client = cluster.client(configuration, "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+        cluster.lose_responses(&flaky_realm, "Recover2");
+
+        let subscriber = RecordingSubscriber::default();
+        let err = {
+            let _guard = tracing::subscriber::set_default(subscriber.clone());
+            let wrong_pin = Pin::from(b"4321".to_vec());
+            client.recover(&wrong_pin, &user_info).await.unwrap_err()
+        };
+        assert!(matches!(err, RecoverError::InvalidPin { .. }), "{err:?}");
+
+        let events = subscriber.events("recover consumed a guess");
+        assert_eq!(events.len(), 3, "{events:?}");
+        let flaky_events: Vec<_> = events
+            .iter()
+            .filter(|event| event["realm"] == format!("{flaky_realm:?}"))
+            .collect();
+        assert_eq!(flaky_events.len(), 1, "{events:?}");
+        assert_eq!(flaky_events[0]["cause"], "Recover2Failed(Transient)");
+    }
+
+    #[tokio::test]
+    async fn test_recover_with_hung_realm() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(3, 2);
+        let realms: Vec<RealmId> = configuration.realms.iter().map(|realm| realm.id).collect();
+        let client = cluster
+            .client_builder(configuration, "apollo")
+            .request_timeout(Duration::from_secs(1))
+            .build();
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+
+        cluster.set_hung(&realms[0], true);
+        let recovered_secret = client.recover(&pin, &user_info).await.unwrap();
+        assert_eq!(secret.expose_secret(), recovered_secret.expose_secret());
+
+        cluster.set_hung(&realms[1], true);
+        assert_eq!(
+            client.recover(&pin, &user_info).await.unwrap_err(),
+            RecoverError::Transient
+        );
+    }
+
+    #[test]
+    fn test_guess_consumed_cause() {
+        assert_eq!(
+            GuessConsumedCause::from_recover2_error(RecoverError::Transient),
+            Some(GuessConsumedCause::Recover2Failed(RecoverError::Transient))
+        );
+        assert_eq!(
+            GuessConsumedCause::from_recover2_error(RecoverError::NotRegistered),
+            None
+        );
+        assert_eq!(
+            GuessConsumedCause::from_recover3_error(RecoverError::InvalidPin {
+                guesses_remaining: 1
+            }),
+            GuessConsumedCause::BadUnlockKeyTag
+        );
+        assert_eq!(
+            GuessConsumedCause::from_recover3_error(RecoverError::Transient),
+            GuessConsumedCause::Recover3Failed(RecoverError::Transient)
+        );
+    }
+
+    #[test]
+    fn test_recover_error_display() {
+        assert_eq!(
+            RecoverError::InvalidPin {
+                guesses_remaining: 2
+            }
+            .to_string(),
+            "invalid PIN (2 guesses remaining)"
+        );
+        assert_eq!(
+            RecoverError::InvalidAuth.to_string(),
+            "realm rejected auth token"
+        );
+
+        let boxed: Box<dyn std::error::Error> = Box::new(RecoverError::NotRegistered);
+        assert_eq!(boxed.to_string(), "secret is not registered");
+    }
+
+    #[tokio::test]
+    async fn test_recover_detailed_degraded() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(3, 2);
+        let realm_ids: Vec<RealmId> = configuration.realms.iter().map(|realm| realm.id).collect();
+        let failed_realm = realm_ids[2];
+        let client = cluster.client(configuration, "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+
+        let subscriber = RecordingSubscriber::default();
+        let outcome = {
+            let _guard = tracing::subscriber::set_default(subscriber.clone());
+            client.recover_detailed(&pin, &user_info).await.unwrap()
+        };
+        assert_eq!(secret.expose_secret(), outcome.secret.expose_secret());
+        assert!(!outcome.degraded);
+        assert!(outcome.failed_realms.is_empty());
+        assert!(subscriber
+            .events("recovered with degraded realms")
+            .is_empty());
+        assert_eq!(
+            outcome.realms,
+            realm_ids
+                .iter()
+                .map(|id| (*id, RealmOutcome::Contributed))
+                .collect::<Vec<_>>()
+        );
+
+        cluster.set_available(&failed_realm, false);
+        let subscriber = RecordingSubscriber::default();
+        let outcome = {
+            let _guard = tracing::subscriber::set_default(subscriber.clone());
+            client.recover_detailed(&pin, &user_info).await.unwrap()
+        };
+        assert_eq!(secret.expose_secret(), outcome.secret.expose_secret());
+        assert!(outcome.degraded);
+        assert_eq!(outcome.failed_realms, vec![failed_realm]);
+        let events = subscriber.events("recovered with degraded realms");
+        assert_eq!(events.len(), 1, "{events:?}");
+        assert_eq!(events[0]["contributing"], "2");
+        assert_eq!(events[0]["realms"], "3");
+        assert_eq!(events[0]["failed_realms"], format!("{:?}", [failed_realm]));
+        assert_eq!(
+            outcome.realms,
+            vec![
+                (realm_ids[0], RealmOutcome::Contributed),
+                (realm_ids[1], RealmOutcome::Contributed),
+                (failed_realm, RealmOutcome::Failed(RecoverError::Transient)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recover_detailed_guesses_remaining() {
+        let cluster = MockRealmCluster::new(3);
+        let client = cluster.client(cluster.configuration(3, 2), "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 3 })
+            .await
+            .unwrap();
+
+        let outcome = client.recover_detailed(&pin, &user_info).await.unwrap();
+        assert_eq!(outcome.guesses_remaining, 2);
+
+        assert!(matches!(
+            client
+                .recover(&Pin::from(b"4321".to_vec()), &user_info)
+                .await,
+            Err(RecoverError::InvalidPin {
+                guesses_remaining: 2
+            })
+        ));
+        let outcome = client.recover_detailed(&pin, &user_info).await.unwrap();
+        assert_eq!(secret.expose_secret(), outcome.secret.expose_secret());
+        assert_eq!(outcome.guesses_remaining, 1);
+    }
+
+    #[tokio::test]
+    async fn test_recover_with_non_default_cipher() {
+        let cluster = MockRealmCluster::new(3);
+        let mut configuration = cluster.configuration(3, 2);
+        configuration.secret_cipher = SecretCipher::Aes256Gcm;
+        let client = cluster.client(configuration, "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+
+        let recovered_secret = client.recover(&pin, &user_info).await.unwrap();
+        assert_eq!(secret.expose_secret(), recovered_secret.expose_secret());
+
+        // Recovery uses the cipher recorded with the registration, not the
+        // one in the recovering client's configuration.
+        let client = cluster.client(cluster.configuration(3, 2), "apollo");
+        let recovered_secret = client.recover(&pin, &user_info).await.unwrap();
+        assert_eq!(secret.expose_secret(), recovered_secret.expose_secret());
+    }
+
+    #[tokio::test]
+    async fn test_recover2_not_retried_after_transient_error() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(3, 2);
+        let flaky_realm = configuration.realms[0].id;
+        let client = cluster.client(configuration, "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+
+        cluster.lose_responses(&flaky_realm, "Recover2");
+        let recovered_secret = client.recover(&pin, &user_info).await.unwrap();
+        assert_eq!(secret.expose_secret(), recovered_secret.expose_secret());
+        assert_eq!(cluster.request_count(&flaky_realm, "Recover2"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_recover_retry_budget() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(3, 2);
+        let flaky_realm = configuration.realms[0].id;
+        let client = cluster.client(configuration.clone(), "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+
+        // Recover1 is retried after a transient error while the budget
+        // allows.
+        cluster.fail_requests(&flaky_realm, 1);
+        let outcome = client.recover_detailed(&pin, &user_info).await.unwrap();
+        assert!(!outcome.degraded);
+
+        cluster.fail_requests(&flaky_realm, 1);
+        let client = cluster
+            .client_builder(configuration, "apollo")
+            .recover_retry_budget(0)
+            .build();
+        let outcome = client.recover_detailed(&pin, &user_info).await.unwrap();
+        assert_eq!(outcome.failed_realms, vec![flaky_realm]);
+    }
+
+    #[tokio::test]
+    async fn test_recover_after_lockout() {
+        let cluster = MockRealmCluster::new(3);
+        let client = cluster.client(cluster.configuration(3, 2), "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 1 })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            client
+                .recover(&Pin::from(b"nope".to_vec()), &user_info)
+                .await
+                .unwrap_err(),
+            RecoverError::InvalidPin {
+                guesses_remaining: 0
+            }
+        );
+        assert_eq!(
+            client.recover(&pin, &user_info).await.unwrap_err(),
+            RecoverError::Locked
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recover_with_hashed_pin() {
+        let cluster = MockRealmCluster::new(3);
+        let client = cluster.client(cluster.configuration(3, 2), "apollo");
+
+        let hashed_pin = HashedPin::new([1; 32], [2; 32]);
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register_with_hashed_pin(&hashed_pin, &secret, &user_info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+
+        let recovered_secret = client
+            .recover_with_hashed_pin(&hashed_pin, &user_info)
+            .await
+            .unwrap();
+        assert_eq!(secret.expose_secret(), recovered_secret.expose_secret());
+
+        assert_eq!(
+            client
+                .recover_with_hashed_pin(&HashedPin::new([3; 32], [2; 32]), &user_info)
+                .await
+                .unwrap_err(),
+            RecoverError::InvalidPin {
+                guesses_remaining: 1
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recover_with_modified_realm_set() {
+        let cluster = MockRealmCluster::new(4);
+        let mut configuration = cluster.configuration(3, 2);
+        configuration.realms.truncate(3);
+        let client = cluster.client(configuration.clone(), "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &user_info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+
+        // The three original realms still form a recover threshold, but the
+        // added realm changes the realm set recorded in the version. That's
+        // reported before Recover2, so no guess is used.
+        let modified = cluster.client(cluster.configuration(3, 3), "apollo");
+        assert_eq!(
+            modified.recover(&pin, &user_info).await.unwrap_err(),
+            RecoverError::RealmSetMismatch
+        );
+        for realm in &configuration.realms {
+            assert_eq!(cluster.request_count(&realm.id, "Recover2"), 0);
+        }
+
+        // Recovering with the original realms as a previous configuration
+        // works.
+        let migrating = cluster
+            .client_builder(cluster.configuration(3, 3), "apollo")
+            .previous_configurations(vec![configuration])
+            .build();
+        let outcome = migrating.recover_detailed(&pin, &user_info).await.unwrap();
+        assert_eq!(secret.expose_secret(), outcome.secret.expose_secret());
+        assert_eq!(outcome.guesses_remaining, 1);
+
+        let recovered_secret = client.recover(&pin, &user_info).await.unwrap();
+        assert_eq!(secret.expose_secret(), recovered_secret.expose_secret());
+    }
+
+    #[tokio::test]
+    async fn test_recover_unbound_registration() {
+        let cluster = MockRealmCluster::new(4);
+        let mut configuration = cluster.configuration(3, 2);
+        configuration.realms.truncate(3);
+        let client = cluster.client(configuration, "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        // Registrations from before realm set binding keep recovering, even
+        // with a changed realm set.
+        client
+            .perform_register_with_binding(
+                &pin,
+                &secret,
+                &user_info,
+                Policy { num_guesses: 2 },
+                &mut OsRng,
+                false,
+            )
+            .await
+            .unwrap();
+        let recovered_secret = client.recover(&pin, &user_info).await.unwrap();
+        assert_eq!(secret.expose_secret(), recovered_secret.expose_secret());
+
+        let modified = cluster.client(cluster.configuration(3, 3), "apollo");
+        let recovered_secret = modified.recover(&pin, &user_info).await.unwrap();
+        assert_eq!(secret.expose_secret(), recovered_secret.expose_secret());
+    }
+}
@@ -1,4 +1,6 @@
-use rand::rngs::OsRng;
+use futures::future::join_all;
+use rand::{rngs::OsRng, CryptoRng, RngCore};
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt::{Debug, Display};
 use std::iter::zip;
@@ -7,7 +9,8 @@ use tracing::instrument;
 use juicebox_oprf as oprf;
 use juicebox_realm_api::{
     requests::{
-        Register1Response, Register2Request, Register2Response, SecretsRequest, SecretsResponse,
+        Recover1Response, Register1Response, Register2Request, Register2Response, SecretsRequest,
+        SecretsResponse,
     },
     signing::{sign_public_key, OprfSignedPublicKey, OprfSigningKey},
     types::{
@@ -19,13 +22,31 @@ use juicebox_secret_sharing::create_shares;
 
 use crate::{
     auth, http,
-    request::{join_at_least_threshold, RequestError},
+    pin::PinKeys,
+    request::{join_at_least_threshold, join_until_threshold, RequestError, RetryBudget},
     types::{
-        derive_unlock_key_and_commitment, UserSecretEncryptionKey, UserSecretEncryptionKeyScalar,
+        derive_unlock_key_and_commitment, UnlockKeyBinding, UserSecretEncryptionKey,
+        UserSecretEncryptionKeyScalar,
     },
-    Client, Pin, Policy, Realm, Sleeper, UserInfo, UserSecret,
+    Client, Policy, Realm, RecoverError, Sleeper, UserInfo, UserSecret,
 };
 
+/// The result of a [`Client::repair`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RepairOutcome {
+    /// Every read-write realm has the same registration, so nothing was
+    /// registered.
+    Healthy,
+
+    /// The secret was registered again on every read-write realm.
+    Repaired,
+
+    /// At least one read-write realm has a registration with no guesses
+    /// remaining, so nothing was registered. Registering would reset that
+    /// realm's guess count.
+    Locked,
+}
+
 /// Error return type for [`Client::register`].
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub enum RegisterError {
@@ -48,48 +69,139 @@ pub enum RegisterError {
     /// A transient error in sending or receiving requests to a realm.
     /// This request may succeed by trying again with the same parameters.
     Transient,
+
+    /// The operation was stopped with the `Client`'s
+    /// [`CancellationToken`](crate::CancellationToken).
+    Cancelled,
 }
 
 impl Display for RegisterError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Debug::fmt(self, f)
+        match self {
+            Self::InvalidAuth => write!(f, "realm rejected auth token"),
+            Self::UpgradeRequired => write!(f, "realm requires a newer SDK version"),
+            Self::RateLimitExceeded => write!(f, "tenant exceeded its rate limit"),
+            Self::Assertion => write!(f, "unexpected error in SDK or realm"),
+            Self::Transient => write!(f, "transient error communicating with realm"),
+            Self::Cancelled => write!(f, "operation was cancelled"),
+        }
     }
 }
 
 impl Error for RegisterError {}
 
+/// Error return type for [`Client::reshare`] and
+/// [`Client::rotate_oprf_keys`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ReshareError {
+    /// The secret could not be recovered, so nothing was registered.
+    Recover(RecoverError),
+    /// The secret was recovered, but registering it on the current
+    /// configuration failed. See [`Client::reshare`] for when it can be
+    /// retried.
+    Register(RegisterError),
+    /// The realms of a previous configuration hold different registrations,
+    /// such as after an earlier reshare failed partway or completed, so
+    /// nothing was recovered or registered. No guess was used.
+    InconsistentRegistrations,
+}
+
+impl Display for ReshareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Recover(e) => write!(f, "recover failed: {e}"),
+            Self::Register(e) => write!(f, "register failed: {e}"),
+            Self::InconsistentRegistrations => {
+                write!(
+                    f,
+                    "previous configuration's realms hold different registrations"
+                )
+            }
+        }
+    }
+}
+
+impl Error for ReshareError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Recover(e) => Some(e),
+            Self::Register(e) => Some(e),
+            Self::InconsistentRegistrations => None,
+        }
+    }
+}
+
 impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http, Atm> {
     pub(crate) async fn perform_register(
         &self,
-        pin: &Pin,
+        pin: &impl PinKeys,
         secret: &UserSecret,
         info: &UserInfo,
         policy: Policy,
+        rng: &mut (impl CryptoRng + RngCore + Send),
     ) -> Result<(), RegisterError> {
+        self.perform_register_with_binding(pin, secret, info, policy, rng, true)
+            .await
+    }
+
+    /// Registers the secret, binding its unlock key to the configuration's
+    /// realm set if `bind_realm_set` is true. Otherwise, the registration is
+    /// like those made before realm set binding, which tests use to check
+    /// that such registrations still recover.
+    pub(crate) async fn perform_register_with_binding(
+        &self,
+        pin: &impl PinKeys,
+        secret: &UserSecret,
+        info: &UserInfo,
+        policy: Policy,
+        rng: &mut (impl CryptoRng + RngCore + Send),
+        bind_realm_set: bool,
+    ) -> Result<(), RegisterError> {
+        // Shares are created for every realm so that share indices stay the
+        // same across operations, but only read-write realms are sent one.
         let register1_requests = self
             .configuration
             .realms
             .iter()
+            .filter(|realm| realm.role.is_read_write())
             .map(|realm| self.register1_on_realm(realm));
-        join_at_least_threshold(register1_requests, self.configuration.register_threshold).await?;
+        // Register1 only checks that enough realms are available before
+        // doing the expensive work below, so the stragglers aren't needed.
+        join_until_threshold(
+            register1_requests,
+            self.configuration.register_threshold,
+            self.configuration.max_concurrency,
+        )
+        .await?;
 
-        let version = RegistrationVersion::new_random(&mut OsRng);
+        let realm_set_hash = self.configuration.realm_set_hash();
+        let (version, binding) = if bind_realm_set {
+            (
+                realm_set_hash.new_version(rng),
+                UnlockKeyBinding::RealmSet(&realm_set_hash),
+            )
+        } else {
+            (
+                RegistrationVersion::new_random(rng),
+                UnlockKeyBinding::Unbound,
+            )
+        };
 
         let (access_key, encryption_key_seed) = pin
-            .hash(self.configuration.pin_hashing_mode, &version, info)
+            .keys(self.configuration.pin_hashing_mode, &version, info)
             .expect("pin hashing failed");
 
-        let oprf_private_key = oprf::PrivateKey::random(&mut OsRng);
+        let oprf_private_key = oprf::PrivateKey::random(rng);
         let oprf_private_key_shares: Vec<oprf::PrivateKey> = create_shares(
             oprf_private_key.expose_secret(),
             self.configuration.recover_threshold,
             self.configuration.share_count(),
-            &mut OsRng,
+            rng,
         )
         .map(|share| oprf::PrivateKey::from(share.secret))
         .collect();
 
-        let signing_key = OprfSigningKey::new_random(&mut OsRng);
+        let signing_key = OprfSigningKey::new_random(rng);
 
         let oprf_signed_public_keys: Vec<OprfSignedPublicKey> =
             zip(&oprf_private_key_shares, &self.configuration.realms)
@@ -100,21 +212,22 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
 
         let oprf_result = oprf::unoblivious_evaluate(&oprf_private_key, access_key.expose_secret());
 
-        let (unlock_key, unlock_key_commitment) = derive_unlock_key_and_commitment(&oprf_result);
+        let (unlock_key, unlock_key_commitment) =
+            derive_unlock_key_and_commitment(&oprf_result, binding);
 
-        let encryption_key_scalar = UserSecretEncryptionKeyScalar::new_random();
+        let encryption_key_scalar = UserSecretEncryptionKeyScalar::new_random(rng);
         let encryption_key_scalar_shares: Vec<UserSecretEncryptionKeyScalarShare> = create_shares(
             encryption_key_scalar.expose_secret(),
             self.configuration.recover_threshold,
             self.configuration.share_count(),
-            &mut OsRng,
+            rng,
         )
         .map(|share| UserSecretEncryptionKeyScalarShare::from(share.secret))
         .collect();
 
         let encryption_key =
             UserSecretEncryptionKey::derive(&encryption_key_seed, &encryption_key_scalar);
-        let encrypted_secret = secret.encrypt(&encryption_key);
+        let encrypted_secret = secret.encrypt(self.configuration.secret_cipher, &encryption_key);
 
         let register2_requests = zip4(
             &self.configuration.realms,
@@ -122,6 +235,7 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
             oprf_signed_public_keys,
             encryption_key_scalar_shares,
         )
+        .filter(|(realm, ..)| realm.role.is_read_write())
         .map(
             |(
                 realm,
@@ -144,27 +258,162 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
                             &realm.id,
                             &encryption_key_scalar_share,
                             &encrypted_secret,
+                            self.configuration.secret_cipher,
                         ),
                         policy: policy.to_owned(),
+                        secret_cipher: self.configuration.secret_cipher,
                     },
                 )
             },
         );
 
-        join_at_least_threshold(register2_requests, self.configuration.register_threshold).await?;
+        join_at_least_threshold(
+            register2_requests,
+            self.configuration.register_threshold,
+            self.configuration.max_concurrency,
+        )
+        .await?;
 
         Ok(())
     }
 
+    pub(crate) async fn perform_repair(
+        &self,
+        pin: &impl PinKeys,
+        secret: &UserSecret,
+        info: &UserInfo,
+        policy: Policy,
+    ) -> Result<RepairOutcome, RegisterError> {
+        let status_requests = self
+            .configuration
+            .realms
+            .iter()
+            .filter(|realm| realm.role.is_read_write())
+            .map(|realm| self.registration_status_on_realm(realm));
+        let statuses = join_all(status_requests)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut versions = HashSet::new();
+        let mut missing = false;
+        for status in statuses {
+            match status {
+                Recover1Response::Ok { version } => {
+                    versions.insert(version);
+                }
+                Recover1Response::NotRegistered => missing = true,
+                // Registering replaces the registration on every realm, so
+                // it would reset this realm's guess count.
+                Recover1Response::NoGuesses => return Ok(RepairOutcome::Locked),
+            }
+        }
+        if !missing && versions.len() <= 1 {
+            return Ok(RepairOutcome::Healthy);
+        }
+
+        self.perform_register(pin, secret, info, policy, &mut OsRng)
+            .await?;
+        Ok(RepairOutcome::Repaired)
+    }
+
+    pub(crate) async fn perform_reshare(
+        &self,
+        pin: &impl PinKeys,
+        info: &UserInfo,
+        policy: Policy,
+    ) -> Result<(), ReshareError> {
+        // A reshare that failed partway, or finished, leaves some realms of
+        // a previous configuration with the new registration. Recovering
+        // from the rest would use a guess to recover a secret that may
+        // already be elsewhere, so that's refused before Recover2.
+        let retry_budget = RetryBudget::new(self.recover_retry_budget);
+        for configuration in &self.previous_configurations {
+            let versions = join_all(
+                configuration
+                    .realms
+                    .iter()
+                    .map(|realm| self.recover1_on_realm(realm, &retry_budget)),
+            )
+            .await;
+            let mut registered = HashSet::new();
+            for result in versions {
+                match result {
+                    Ok((version, _)) => {
+                        registered.insert(version);
+                    }
+                    Err(RecoverError::NotRegistered | RecoverError::Locked) => {}
+                    Err(err) => return Err(ReshareError::Recover(err)),
+                }
+            }
+            if registered.len() > 1 {
+                return Err(ReshareError::InconsistentRegistrations);
+            }
+        }
+
+        // The current configuration is skipped: any of its realms that are
+        // also in a previous configuration hold that configuration's
+        // registration, which can't be recovered with the current realm set.
+        let secret = self
+            .perform_recover_from(pin, info, &self.previous_configurations)
+            .await
+            .map_err(ReshareError::Recover)?
+            .secret;
+        self.perform_register(pin, &secret, info, policy, &mut OsRng)
+            .await
+            .map_err(ReshareError::Register)
+    }
+
+    pub(crate) async fn perform_rotate_oprf_keys(
+        &self,
+        pin: &impl PinKeys,
+        info: &UserInfo,
+        policy: Policy,
+    ) -> Result<(), ReshareError> {
+        let secret = self
+            .perform_recover_from(pin, info, [&self.configuration])
+            .await
+            .map_err(ReshareError::Recover)?
+            .secret;
+        // Registering always generates a new OPRF key and version.
+        self.perform_register(pin, &secret, info, policy, &mut OsRng)
+            .await
+            .map_err(ReshareError::Register)
+    }
+
+    /// Reads whether the user is registered on a realm, and with which
+    /// version, using a Recover1 request.
+    #[instrument(level = "trace", skip(self), err(level = "trace", Debug))]
+    async fn registration_status_on_realm(
+        &self,
+        realm: &Realm,
+    ) -> Result<Recover1Response, RegisterError> {
+        match self.make_request(realm, SecretsRequest::Recover1).await {
+            Err(RequestError::UpgradeRequired) => Err(RegisterError::UpgradeRequired),
+            Err(RequestError::InvalidAuth) => Err(RegisterError::InvalidAuth),
+            Err(RequestError::Assertion | RequestError::KeyMismatch) => {
+                Err(RegisterError::Assertion)
+            }
+            Err(RequestError::Transient) => Err(RegisterError::Transient),
+            Err(RequestError::RateLimitExceeded) => Err(RegisterError::RateLimitExceeded),
+            Err(RequestError::Cancelled) => Err(RegisterError::Cancelled),
+            Ok(SecretsResponse::Recover1(response)) => Ok(response),
+            Ok(_) => Err(RegisterError::Assertion),
+        }
+    }
+
     /// Executes phase 1 of registration on a particular realm.
     #[instrument(level = "trace", skip(self), err(level = "trace", Debug))]
     async fn register1_on_realm(&self, realm: &Realm) -> Result<(), RegisterError> {
         match self.make_request(realm, SecretsRequest::Register1).await {
             Err(RequestError::UpgradeRequired) => Err(RegisterError::UpgradeRequired),
             Err(RequestError::InvalidAuth) => Err(RegisterError::InvalidAuth),
-            Err(RequestError::Assertion) => Err(RegisterError::Assertion),
+            Err(RequestError::Assertion | RequestError::KeyMismatch) => {
+                Err(RegisterError::Assertion)
+            }
             Err(RequestError::Transient) => Err(RegisterError::Transient),
             Err(RequestError::RateLimitExceeded) => Err(RegisterError::RateLimitExceeded),
+            Err(RequestError::Cancelled) => Err(RegisterError::Cancelled),
             Ok(SecretsResponse::Register1(Register1Response::Ok)) => Ok(()),
             Ok(_) => Err(RegisterError::Assertion),
         }
@@ -183,9 +432,12 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
         {
             Err(RequestError::UpgradeRequired) => Err(RegisterError::UpgradeRequired),
             Err(RequestError::InvalidAuth) => Err(RegisterError::InvalidAuth),
-            Err(RequestError::Assertion) => Err(RegisterError::Assertion),
+            Err(RequestError::Assertion | RequestError::KeyMismatch) => {
+                Err(RegisterError::Assertion)
+            }
             Err(RequestError::Transient) => Err(RegisterError::Transient),
             Err(RequestError::RateLimitExceeded) => Err(RegisterError::RateLimitExceeded),
+            Err(RequestError::Cancelled) => Err(RegisterError::Cancelled),
             Ok(SecretsResponse::Register2(Register2Response::Ok)) => Ok(()),
             Ok(_) => Err(RegisterError::Assertion),
         }
@@ -208,7 +460,19 @@ where
     iter.map(|(((a, b), c), d)| (a, b, c, d))
 }
 
+#[cfg(test)]
 mod tests {
+    use rand_chacha::rand_core::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+    use std::iter::zip;
+
+    use super::RepairOutcome;
+    use crate::testing::{MockClient, MockRealmCluster};
+    use crate::{
+        DeletePreview, Pin, Policy, RealmId, RealmRole, RecoverError, ReshareError, UserInfo,
+        UserSecret,
+    };
+
     #[test]
     fn test_zip4() {
         let a = vec![1, 2, 3];
@@ -226,4 +490,298 @@ mod tests {
 
         assert_eq!(zipped, expected);
     }
+
+    /// Registers on a new cluster with an RNG seeded from `seed`, returning
+    /// the client and the registration stored on each realm.
+    async fn register_seeded(seed: u64) -> (MockClient, Vec<Vec<u8>>) {
+        let cluster = MockRealmCluster::new(3);
+        let client = cluster.client(cluster.configuration(3, 2), "apollo");
+        client
+            .register_with_rng(
+                &Pin::from(b"1234".to_vec()),
+                &UserSecret::from(b"artemis".to_vec()),
+                &UserInfo::from(b"apollo".to_vec()),
+                Policy { num_guesses: 2 },
+                &mut ChaCha20Rng::seed_from_u64(seed),
+            )
+            .await
+            .unwrap();
+        let registrations = (1..=3)
+            .map(|i| cluster.registration(&RealmId([i; 16]), "apollo").unwrap())
+            .collect();
+        (client, registrations)
+    }
+
+    #[tokio::test]
+    async fn test_register_with_rng_is_deterministic() {
+        let (client, first) = register_seeded(7).await;
+        assert_eq!(first, register_seeded(7).await.1);
+        assert_ne!(first, register_seeded(8).await.1);
+
+        let recovered = client
+            .recover(
+                &Pin::from(b"1234".to_vec()),
+                &UserInfo::from(b"apollo".to_vec()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(recovered.expose_secret(), b"artemis");
+    }
+
+    #[tokio::test]
+    async fn test_register_skips_recover_only_realms() {
+        let cluster = MockRealmCluster::new(3);
+        let mut configuration = cluster.configuration(2, 2);
+        configuration.realms[2].role = RealmRole::RecoverOnly;
+        let client = cluster.client(configuration.clone(), "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let info = UserInfo::from(b"apollo".to_vec());
+        client
+            .register(&pin, &secret, &info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+
+        assert!(cluster.is_registered(&configuration.realms[0].id, "apollo"));
+        assert!(cluster.is_registered(&configuration.realms[1].id, "apollo"));
+        assert!(!cluster.is_registered(&configuration.realms[2].id, "apollo"));
+
+        let recovered = client.recover(&pin, &info).await.unwrap();
+        assert_eq!(secret.expose_secret(), recovered.expose_secret());
+    }
+
+    #[tokio::test]
+    async fn test_recover_includes_recover_only_realms() {
+        let cluster = MockRealmCluster::new(3);
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let info = UserInfo::from(b"apollo".to_vec());
+        cluster
+            .client(cluster.configuration(3, 2), "apollo")
+            .register(&pin, &secret, &info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+
+        let mut configuration = cluster.configuration(2, 2);
+        configuration.realms[2].role = RealmRole::RecoverOnly;
+        cluster.set_available(&configuration.realms[0].id, false);
+
+        // Only one read-write realm is reachable, so recovery must use the
+        // recover-only realm to meet the threshold.
+        let recovered = cluster
+            .client(configuration, "apollo")
+            .recover(&pin, &info)
+            .await
+            .unwrap();
+        assert_eq!(secret.expose_secret(), recovered.expose_secret());
+    }
+
+    #[tokio::test]
+    async fn test_rotate_oprf_keys() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(3, 2);
+        let realms: Vec<_> = configuration.realms.iter().map(|realm| realm.id).collect();
+        let client = cluster.client(configuration, "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+        let policy = Policy { num_guesses: 2 };
+
+        client
+            .register(&pin, &secret, &user_info, policy.clone())
+            .await
+            .unwrap();
+        let before: Vec<_> = realms
+            .iter()
+            .map(|realm| cluster.registration(realm, "apollo").unwrap())
+            .collect();
+
+        assert_eq!(
+            client
+                .rotate_oprf_keys(&Pin::from(b"4321".to_vec()), &user_info, policy.clone())
+                .await,
+            Err(ReshareError::Recover(RecoverError::InvalidPin {
+                guesses_remaining: 1
+            }))
+        );
+        client
+            .rotate_oprf_keys(&pin, &user_info, policy.clone())
+            .await
+            .unwrap();
+
+        // Every realm has a new registration, which recovers the same secret
+        // with the same PIN.
+        for (realm, before) in zip(&realms, before) {
+            assert_ne!(cluster.registration(realm, "apollo").unwrap(), before);
+        }
+        let outcome = client.recover_detailed(&pin, &user_info).await.unwrap();
+        assert_eq!(secret.expose_secret(), outcome.secret.expose_secret());
+        assert!(!outcome.degraded);
+        assert_eq!(outcome.guesses_remaining, 1);
+    }
+
+    #[tokio::test]
+    async fn test_reshare_to_new_realms() {
+        let cluster = MockRealmCluster::new(4);
+        let all_realms = cluster.configuration(3, 2).realms;
+        let mut old_configuration = cluster.configuration(3, 2);
+        old_configuration.realms.truncate(3);
+        let mut new_configuration = cluster.configuration(3, 2);
+        new_configuration.realms.remove(0);
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+        let policy = Policy { num_guesses: 2 };
+
+        let client = cluster
+            .client_builder(new_configuration.clone(), "apollo")
+            .previous_configurations(vec![old_configuration.clone()])
+            .build();
+        assert_eq!(
+            client.reshare(&pin, &user_info, policy.clone()).await,
+            Err(ReshareError::Recover(RecoverError::NotRegistered))
+        );
+
+        cluster
+            .client(old_configuration, "apollo")
+            .register(&pin, &secret, &user_info, policy.clone())
+            .await
+            .unwrap();
+        client
+            .reshare(&pin, &user_info, policy.clone())
+            .await
+            .unwrap();
+
+        // The new realms can recover on their own, and the removed realm
+        // keeps its registration until it's deleted.
+        let recovered = cluster
+            .client(new_configuration, "apollo")
+            .recover(&pin, &user_info)
+            .await
+            .unwrap();
+        assert_eq!(secret.expose_secret(), recovered.expose_secret());
+        assert!(all_realms
+            .iter()
+            .all(|realm| cluster.is_registered(&realm.id, "apollo")));
+
+        // The shared realms now hold the new registration, so resharing
+        // again is refused before any guess is used.
+        let recover2_counts: Vec<_> = all_realms
+            .iter()
+            .map(|realm| cluster.request_count(&realm.id, "Recover2"))
+            .collect();
+        assert_eq!(
+            client.reshare(&pin, &user_info, policy).await,
+            Err(ReshareError::InconsistentRegistrations)
+        );
+        for (realm, count) in zip(&all_realms, recover2_counts) {
+            assert_eq!(cluster.request_count(&realm.id, "Recover2"), count);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repair_missing_realm() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(2, 2);
+        let realms: Vec<_> = configuration.realms.iter().map(|realm| realm.id).collect();
+        let client = cluster.client(configuration, "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+        let policy = Policy { num_guesses: 2 };
+
+        cluster.set_available(&realms[2], false);
+        client
+            .register(&pin, &secret, &user_info, policy.clone())
+            .await
+            .unwrap();
+        cluster.set_available(&realms[2], true);
+
+        let outcome = client.recover_detailed(&pin, &user_info).await.unwrap();
+        assert_eq!(outcome.failed_realms, vec![realms[2]]);
+
+        assert_eq!(
+            client
+                .repair(&pin, &outcome.secret, &user_info, policy.clone())
+                .await
+                .unwrap(),
+            RepairOutcome::Repaired
+        );
+        assert!(client
+            .delete_dry_run()
+            .await
+            .unwrap()
+            .iter()
+            .all(|(_, preview)| *preview == DeletePreview::Registered));
+
+        // Every realm now contributes, so a client requiring all of them can
+        // recover.
+        let strict = cluster.client(cluster.configuration(3, 3), "apollo");
+        let recovered = strict.recover(&pin, &user_info).await.unwrap();
+        assert_eq!(secret.expose_secret(), recovered.expose_secret());
+
+        // Repairing a healthy registration does nothing.
+        let register2_count = cluster.request_count(&realms[0], "Register2");
+        assert_eq!(
+            client
+                .repair(&pin, &secret, &user_info, policy)
+                .await
+                .unwrap(),
+            RepairOutcome::Healthy
+        );
+        assert_eq!(
+            cluster.request_count(&realms[0], "Register2"),
+            register2_count
+        );
+    }
+
+    #[tokio::test]
+    async fn test_repair_refuses_locked_realm() {
+        let cluster = MockRealmCluster::new(3);
+        let configuration = cluster.configuration(2, 2);
+        let realms: Vec<_> = configuration.realms.iter().map(|realm| realm.id).collect();
+        let client = cluster.client(configuration, "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let user_info = UserInfo::new(b"apollo".to_vec()).unwrap();
+        let policy = Policy { num_guesses: 1 };
+
+        cluster.set_available(&realms[2], false);
+        client
+            .register(&pin, &secret, &user_info, policy.clone())
+            .await
+            .unwrap();
+        cluster.set_available(&realms[2], true);
+
+        // A wrong guess locks the two realms that have the registration.
+        client
+            .recover(&Pin::from(b"4321".to_vec()), &user_info)
+            .await
+            .unwrap_err();
+
+        // Repairing the missing realm would also reset the locked ones.
+        let register2_counts: Vec<_> = realms
+            .iter()
+            .map(|realm| cluster.request_count(realm, "Register2"))
+            .collect();
+        assert_eq!(
+            client
+                .repair(&pin, &secret, &user_info, policy)
+                .await
+                .unwrap(),
+            RepairOutcome::Locked
+        );
+        for (realm, count) in zip(&realms, register2_counts) {
+            assert_eq!(cluster.request_count(realm, "Register2"), count);
+        }
+        assert_eq!(
+            client.recover(&pin, &user_info).await.unwrap_err(),
+            RecoverError::Locked
+        );
+    }
 }
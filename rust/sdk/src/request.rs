@@ -1,24 +1,29 @@
+use futures::future::{select, Either};
 use futures::{stream::FuturesUnordered, StreamExt};
 use instant::Instant;
 use rand::{rngs::OsRng, RngCore};
 use std::error::Error;
 use std::fmt::{Debug, Display};
 use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::{collections::HashMap, time::Duration};
-use tracing::instrument;
+use tracing::{field, instrument, Span};
 use x25519_dalek as x25519;
 
 use crate::auth;
-use crate::{http, types::Session, Client, Realm, Sleeper};
+use crate::cancellation::{self, CancellationToken};
+use crate::metrics::{Operation, Outcome};
+use crate::{http, types::Session, Client, Realm, SessionState, Sleeper};
 use juicebox_marshalling as marshalling;
 use juicebox_networking::rpc::{self, RpcError, SendOptions};
 use juicebox_noise::client as noise;
 use juicebox_realm_api::{
     requests::{
-        ClientRequest, ClientRequestKind, ClientResponse, NoiseRequest, NoiseResponse,
+        ClientRequest, ClientRequestKind, ClientResponse, DecodeStage, NoiseRequest, NoiseResponse,
         PaddedSecretsResponse, SecretsRequest, SecretsResponse,
     },
-    types::SessionId,
+    types::{RequestId, SessionId},
 };
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -42,6 +47,17 @@ pub(crate) enum RequestError {
     /// with the same parameters. Verify your inputs, check for software,
     /// updates and try again.
     Assertion,
+
+    /// The `Client`'s [`CancellationToken`](crate::CancellationToken) was
+    /// cancelled before the request was sent.
+    Cancelled,
+
+    /// A handshake with a hardware realm failed because the realm couldn't
+    /// decrypt the request or its response couldn't be decrypted, so the
+    /// responder doesn't hold the private key for the configured public key.
+    /// Either the configuration has the wrong key, or something is
+    /// impersonating the realm.
+    KeyMismatch,
 }
 
 impl Display for RequestError {
@@ -73,6 +89,48 @@ impl From<RpcError> for RequestError {
 enum RequestErrorOrMissingSession {
     RequestError(RequestError),
     MissingSession,
+    /// A transient error occurred after the request may have reached the
+    /// realm, so the realm may have processed it.
+    TransientAfterSend,
+    /// The realm asked the client to wait this long before retrying. The
+    /// realm did not process the request.
+    RateLimited(Duration),
+    /// The realm answered [`ClientResponse::Unavailable`]. It did not process
+    /// the request.
+    Unavailable,
+}
+
+/// The longest [`ClientResponse::RateLimited`] delay the client will wait
+/// out. Realms asking for longer waits fail the request with
+/// [`RequestError::RateLimitExceeded`] instead.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// A limit on the number of automatic retries after transient errors, shared
+/// by all the requests of an operation.
+#[derive(Debug)]
+pub(crate) struct RetryBudget {
+    remaining: AtomicU32,
+}
+
+impl RetryBudget {
+    pub fn new(retries: u32) -> Self {
+        Self {
+            remaining: AtomicU32::new(retries),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(u32::MAX)
+    }
+
+    /// Uses up one retry, returning false if none remain.
+    pub fn try_spend(&self) -> bool {
+        self.remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| {
+                remaining.checked_sub(1)
+            })
+            .is_ok()
+    }
 }
 
 impl From<RequestError> for RequestErrorOrMissingSession {
@@ -94,9 +152,10 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
     async fn make_handshake_request(
         &self,
         realm: &Realm,
-        public_key: &Vec<u8>,
+        public_key: &[u8],
         request: &[u8],
-    ) -> Result<(Session, Vec<u8>), RequestError> {
+        request_id: RequestId,
+    ) -> Result<(Session, Vec<u8>), RequestErrorOrMissingSession> {
         let realm_public_key = {
             // Whether the public key looks valid is checked with the
             // `Configuration`, so it's OK to panic on that here.
@@ -115,30 +174,37 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
             .await
             .ok_or(RequestError::InvalidAuth)?;
 
-        match rpc::send(
-            &self.http,
-            &realm.address,
-            ClientRequest {
-                realm: realm.id,
-                auth_token,
-                session_id,
-                kind: if request.is_empty() {
-                    ClientRequestKind::HandshakeOnly
-                } else {
-                    ClientRequestKind::SecretsRequest
+        match self
+            .with_timeout(rpc::send(
+                &self.http,
+                &realm.address,
+                ClientRequest {
+                    realm: realm.id,
+                    auth_token,
+                    session_id,
+                    kind: if request.is_empty() {
+                        ClientRequestKind::HandshakeOnly
+                    } else {
+                        ClientRequestKind::SecretsRequest
+                    },
+                    encrypted: NoiseRequest::Handshake { handshake: fields },
+                    request_id: Some(request_id),
+                    signature: None,
                 },
-                encrypted: NoiseRequest::Handshake { handshake: fields },
-            },
-        )
-        .await?
+            ))
+            .await
+            .map_err(RequestError::from)?
         {
             ClientResponse::Ok(NoiseResponse::Handshake {
                 handshake: handshake_response,
                 session_lifetime,
             }) => {
+                // Noise NK encrypts the handshake to the realm's configured
+                // static key, so only its holder can produce a response that
+                // decrypts here.
                 let (transport, response) = handshake
                     .finish(&handshake_response)
-                    .map_err(|_| RequestError::Assertion)?;
+                    .map_err(|_| RequestError::KeyMismatch)?;
                 Ok((
                     Session {
                         session_id,
@@ -149,14 +215,19 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
                     response,
                 ))
             }
+            ClientResponse::SessionError => Err(RequestError::KeyMismatch.into()),
             ClientResponse::Ok(NoiseResponse::Transport { .. })
             | ClientResponse::MissingSession
-            | ClientResponse::SessionError
-            | ClientResponse::PayloadTooLarge => Err(RequestError::Assertion),
-            ClientResponse::DecodingError => Err(RequestError::Assertion),
-            ClientResponse::Unavailable => Err(RequestError::Transient),
-            ClientResponse::InvalidAuth => Err(RequestError::InvalidAuth),
-            ClientResponse::RateLimitExceeded => Err(RequestError::RateLimitExceeded),
+            | ClientResponse::PayloadTooLarge => Err(RequestError::Assertion.into()),
+            // The session is brand new, so a new one wouldn't decode any
+            // better.
+            ClientResponse::DecodingError { .. } => Err(RequestError::Assertion.into()),
+            ClientResponse::Unavailable => Err(RequestErrorOrMissingSession::Unavailable),
+            ClientResponse::InvalidAuth => Err(RequestError::InvalidAuth.into()),
+            ClientResponse::RateLimitExceeded => Err(RequestError::RateLimitExceeded.into()),
+            ClientResponse::RateLimited { retry_after } => {
+                Err(RequestErrorOrMissingSession::RateLimited(retry_after))
+            }
         }
     }
 
@@ -170,6 +241,7 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
         realm: &Realm,
         session: &mut Session,
         request: &[u8],
+        request_id: RequestId,
     ) -> Result<Vec<u8>, RequestErrorOrMissingSession> {
         let auth_token = self
             .auth_token_manager
@@ -177,24 +249,27 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
             .await
             .ok_or(RequestError::InvalidAuth)?;
 
-        match rpc::send(
-            &self.http,
-            &realm.address,
-            ClientRequest {
-                realm: realm.id,
-                auth_token,
-                session_id: session.session_id,
-                kind: ClientRequestKind::SecretsRequest,
-                encrypted: NoiseRequest::Transport {
-                    ciphertext: session
-                        .transport
-                        .encrypt(request)
-                        .map_err(|_| RequestError::Assertion)?,
+        match self
+            .with_timeout(rpc::send(
+                &self.http,
+                &realm.address,
+                ClientRequest {
+                    realm: realm.id,
+                    auth_token,
+                    session_id: session.session_id,
+                    kind: ClientRequestKind::SecretsRequest,
+                    encrypted: NoiseRequest::Transport {
+                        ciphertext: session
+                            .transport
+                            .encrypt(request)
+                            .map_err(|_| RequestError::Assertion)?,
+                    },
+                    request_id: Some(request_id),
+                    signature: None,
                 },
-            },
-        )
-        .await
-        .map_err(RequestError::from)?
+            ))
+            .await
+            .map_err(RequestError::from)?
         {
             ClientResponse::Ok(NoiseResponse::Transport { ciphertext }) => {
                 session.last_used = Instant::now();
@@ -206,52 +281,84 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
             ClientResponse::Ok(NoiseResponse::Handshake { .. }) | ClientResponse::SessionError => {
                 Err(RequestError::Assertion.into())
             }
-            ClientResponse::DecodingError | ClientResponse::PayloadTooLarge => {
-                Err(RequestError::Assertion.into())
+            // The realm decrypted something it couldn't decode, which a
+            // session that's out of step could cause, so it's handled like a
+            // missing session and retried once on a new one.
+            ClientResponse::DecodingError {
+                stage: DecodeStage::Inner,
+            } => Err(RequestErrorOrMissingSession::MissingSession),
+            ClientResponse::DecodingError {
+                stage: DecodeStage::Outer,
             }
-            ClientResponse::Unavailable => Err(RequestError::Transient.into()),
+            | ClientResponse::PayloadTooLarge => Err(RequestError::Assertion.into()),
+            ClientResponse::Unavailable => Err(RequestErrorOrMissingSession::Unavailable),
             ClientResponse::InvalidAuth => Err(RequestError::InvalidAuth.into()),
             ClientResponse::MissingSession => Err(RequestErrorOrMissingSession::MissingSession),
             ClientResponse::RateLimitExceeded => Err(RequestError::RateLimitExceeded.into()),
+            ClientResponse::RateLimited { retry_after } => {
+                Err(RequestErrorOrMissingSession::RateLimited(retry_after))
+            }
         }
     }
 
     async fn try_make_request(
         &self,
         realm: &Realm,
-        public_key: &Vec<u8>,
+        public_key: &[u8],
         session: Option<Session>,
         request: &[u8],
         needs_forward_secrecy: NeedsForwardSecrecy,
+        request_id: RequestId,
     ) -> Result<(Session, Vec<u8>), RequestErrorOrMissingSession> {
         match session {
             None if needs_forward_secrecy.0 => {
-                let (mut session, handshake_response) =
-                    self.make_handshake_request(realm, public_key, &[]).await?;
+                let (mut session, handshake_response) = self
+                    .make_handshake_request(realm, public_key, &[], request_id)
+                    .await?;
                 if !handshake_response.is_empty() {
                     return Err(RequestError::Assertion.into());
                 }
+                // The realm can lose even a brand new session if it
+                // restarts between the handshake and this request.
                 let response = self
-                    .make_transport_request(realm, &mut session, request)
+                    .make_transport_request(realm, &mut session, request, request_id)
                     .await
-                    .map_err(|e| match e {
-                        RequestErrorOrMissingSession::RequestError(e) => e,
-                        RequestErrorOrMissingSession::MissingSession => RequestError::Assertion,
-                    })?;
+                    .map_err(transient_after_send)?;
                 Ok((session, response))
             }
 
             None => {
                 assert!(!needs_forward_secrecy.0);
-                Ok(self
-                    .make_handshake_request(realm, public_key, request)
-                    .await?)
+                self.make_handshake_request(realm, public_key, request, request_id)
+                    .await
+                    .map_err(transient_after_send)
             }
 
             Some(mut session) => self
-                .make_transport_request(realm, &mut session, request)
+                .make_transport_request(realm, &mut session, request, request_id)
                 .await
-                .map(|response| (session, response)),
+                .map(|response| (session, response))
+                .map_err(transient_after_send),
+        }
+    }
+
+    /// Returns [`RequestError::Cancelled`] if the `Client`'s
+    /// [`CancellationToken`](crate::CancellationToken), or the one the
+    /// operation is [scoped](crate::CancellationToken::scope) to, has been
+    /// cancelled.
+    ///
+    /// This is checked before each round trip to a realm, including retries,
+    /// unless the request isn't `cancellable`. See [`is_cancellable`].
+    fn check_cancelled(&self, cancellable: bool) -> Result<(), RequestError> {
+        let cancelled = self
+            .cancellation_token
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+            || cancellation::is_scope_cancelled();
+        if cancellable && cancelled {
+            Err(RequestError::Cancelled)
+        } else {
+            Ok(())
         }
     }
 
@@ -260,19 +367,64 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
         realm: &Realm,
         request: SecretsRequest,
     ) -> Result<SecretsResponse, RequestError> {
-        match &realm.public_key {
+        self.make_request_with_retry_budget(realm, request, &RetryBudget::unlimited())
+            .await
+    }
+
+    /// Makes a request to a realm, retrying transient errors while the
+    /// `retry_budget` allows.
+    ///
+    /// Requests that [consume a guess](SecretsRequest::consumes_guess) are
+    /// never retried once they may have reached the realm, so that a
+    /// transient error cannot cost the user more than one guess.
+    ///
+    /// Each call picks a random [`RequestId`], which is recorded on this
+    /// function's span and sent to hardware realms, so that the realm's logs
+    /// for the request can be found.
+    #[instrument(
+        level = "trace",
+        skip(self, realm, request, retry_budget),
+        fields(realm = ?realm.id, request_id = field::Empty),
+        err(level = "trace", Debug)
+    )]
+    pub(crate) async fn make_request_with_retry_budget(
+        &self,
+        realm: &Realm,
+        request: SecretsRequest,
+        retry_budget: &RetryBudget,
+    ) -> Result<SecretsResponse, RequestError> {
+        self.check_cancelled(is_cancellable(&request))?;
+        let request_id = RequestId::new_random(&mut OsRng);
+        Span::current().record("request_id", field::debug(request_id));
+        let operation = Operation::from(&request);
+        let start = Instant::now();
+        let result = match &realm.public_key {
             Some(public_key) => {
-                self.make_hardware_realm_request(realm, public_key, request)
+                self.make_hardware_realm_request(
+                    realm,
+                    public_key,
+                    request,
+                    retry_budget,
+                    request_id,
+                )
+                .await
+            }
+            None => {
+                self.make_software_realm_request(realm, request, retry_budget)
                     .await
             }
-            None => self.make_software_realm_request(realm, request).await,
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.record(realm.id, operation, Outcome::from(&result), start.elapsed());
         }
+        result
     }
 
     async fn make_software_realm_request(
         &self,
         realm: &Realm,
         request: SecretsRequest,
+        retry_budget: &RetryBudget,
     ) -> Result<SecretsResponse, RequestError> {
         let auth_token = self
             .auth_token_manager
@@ -286,19 +438,29 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
             format!("Bearer {}", auth_token.expose_secret()),
         );
 
-        for _attempt in 0..2 {
-            return match rpc::send_with_options(
-                &self.http,
-                &realm.address,
-                request.clone(),
-                SendOptions::default().with_headers(headers.clone()),
-            )
-            .await
-            .map_err(RequestError::from)
+        let cancellable = is_cancellable(&request);
+        let retry_policy = &self.configuration.retry_policy;
+        for attempt in 1..=retry_policy.max_attempts {
+            return match self
+                .with_timeout(rpc::send_with_options(
+                    &self.http,
+                    &realm.address,
+                    request.clone(),
+                    SendOptions::default().with_headers(headers.clone()),
+                ))
+                .await
+                .map_err(RequestError::from)
             {
                 Ok(response) => Ok(response),
-                Err(RequestError::Transient) => {
-                    self.sleeper.sleep(Duration::from_millis(5)).await;
+                // The request may have reached the realm, so it can't be
+                // retried if it consumes a guess.
+                Err(RequestError::Transient)
+                    if !request.consumes_guess()
+                        && attempt < retry_policy.max_attempts
+                        && retry_budget.try_spend() =>
+                {
+                    self.sleeper.sleep(retry_policy.delay(attempt)).await;
+                    self.check_cancelled(cancellable)?;
                     continue;
                 }
                 Err(e) => Err(e),
@@ -310,29 +472,55 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
     async fn make_hardware_realm_request(
         &self,
         realm: &Realm,
-        public_key: &Vec<u8>,
+        public_key: &[u8],
         request: SecretsRequest,
+        retry_budget: &RetryBudget,
+        request_id: RequestId,
     ) -> Result<SecretsResponse, RequestError> {
         let needs_forward_secrecy = NeedsForwardSecrecy(request.needs_forward_secrecy());
+        let consumes_guess = request.consumes_guess();
+        let cancellable = is_cancellable(&request);
         let request = marshalling::to_vec(&request).map_err(|_| RequestError::Assertion)?;
-        let mut locked = self.sessions.get(&realm.id).unwrap().lock().await;
+        let locked = self.session_locks.get(&realm.id).unwrap().lock().await;
 
         // The first iteration of this loop attempts the request with an
         // existing session, if available. Subsequent iterations always use a
-        // new session. Even using a brand new session can result in a
-        // `MissingSession` error, if the server restarts at an inopportune
-        // time. This loop tries a few times, but beyond that, it's not likely
-        // to succeed.
-        for attempt in 1..6 {
-            let session = locked
-                .take()
-                .filter(|session| session.last_used.elapsed() < session.lifetime);
+        // new session. If the realm no longer has the existing session, such
+        // as after it restarted, the request is tried once more with a new
+        // session without counting as an attempt, so that even a retry policy
+        // without retries gets past a recycled realm. Otherwise, including
+        // when a new session goes missing too, this loop tries up to the
+        // retry policy's `max_attempts`, but beyond that, it's not likely to
+        // succeed.
+        let retry_policy = &self.configuration.retry_policy;
+        let mut last_error = RequestError::Transient;
+        let mut attempt = 1;
+        let mut reopened_session = false;
+        while attempt <= retry_policy.max_attempts {
+            // The session is taken out of the store until the request
+            // succeeds, since the stored copy goes stale once it's used.
+            let session = if attempt == 1 && !reopened_session {
+                self.session_store
+                    .take(&realm.id)
+                    .and_then(SessionState::into_session)
+                    .filter(|session| session.last_used.elapsed() < session.lifetime)
+            } else {
+                None
+            };
             match self
-                .try_make_request(realm, public_key, session, &request, needs_forward_secrecy)
+                .try_make_request(
+                    realm,
+                    public_key,
+                    session,
+                    &request,
+                    needs_forward_secrecy,
+                    request_id,
+                )
                 .await
             {
                 Ok((session, response)) => {
-                    *locked = Some(session);
+                    self.session_store
+                        .save(&realm.id, SessionState::new(&session));
                     std::mem::drop(locked);
                     let padded_response =
                         marshalling::from_slice::<PaddedSecretsResponse>(response.as_slice())
@@ -340,29 +528,179 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
                     return SecretsResponse::try_from(&padded_response)
                         .map_err(|_| RequestError::Assertion);
                 }
-                Err(RequestErrorOrMissingSession::RequestError(RequestError::Transient)) => {
+                Err(RequestErrorOrMissingSession::TransientAfterSend) if consumes_guess => {
+                    // The realm may have processed the request and counted
+                    // a guess, so retrying could cost the user another one.
+                    return Err(RequestError::Transient);
+                }
+                Err(
+                    RequestErrorOrMissingSession::RequestError(RequestError::Transient)
+                    | RequestErrorOrMissingSession::TransientAfterSend,
+                ) => {
+                    last_error = RequestError::Transient;
+                    if attempt == retry_policy.max_attempts || !retry_budget.try_spend() {
+                        return Err(last_error);
+                    }
                     // This could be due to an in progress leadership transfer, or other transitory problem.
                     // We can retry this as it'll likely need a new session anyway.
-                    self.sleeper.sleep(Duration::from_millis(5 * attempt)).await;
+                    self.sleeper.sleep(retry_policy.delay(attempt)).await;
+                    self.check_cancelled(cancellable)?;
+                    attempt += 1;
+                    continue;
+                }
+                Err(RequestErrorOrMissingSession::Unavailable) => {
+                    // The realm didn't process the request, so this is safe
+                    // to retry even if it consumes a guess. The delay is
+                    // fully jittered so that clients don't all come back to
+                    // a recovering realm at once.
+                    last_error = RequestError::Transient;
+                    if attempt == retry_policy.max_attempts || !retry_budget.try_spend() {
+                        return Err(last_error);
+                    }
+                    self.sleeper
+                        .sleep(retry_policy.unavailable_delay(attempt, &mut OsRng))
+                        .await;
+                    self.check_cancelled(cancellable)?;
+                    attempt += 1;
+                    continue;
+                }
+                Err(RequestErrorOrMissingSession::RateLimited(retry_after)) => {
+                    // The realm didn't process the request, so this is safe
+                    // to retry even if it consumes a guess.
+                    last_error = RequestError::RateLimitExceeded;
+                    if retry_after > MAX_RETRY_AFTER || !retry_budget.try_spend() {
+                        return Err(last_error);
+                    }
+                    self.sleeper.sleep(retry_after).await;
+                    self.check_cancelled(cancellable)?;
+                    attempt += 1;
                     continue;
                 }
                 Err(RequestErrorOrMissingSession::RequestError(e)) => return Err(e),
-                Err(RequestErrorOrMissingSession::MissingSession) => {
+                Err(RequestErrorOrMissingSession::MissingSession) if !reopened_session => {
                     // The next iteration will open a new session and
                     // should have a high chance of success.
+                    reopened_session = true;
+                    self.check_cancelled(cancellable)?;
+                    continue;
+                }
+                Err(RequestErrorOrMissingSession::MissingSession) => {
+                    // A new session went missing too, so the realm is likely
+                    // restarting repeatedly. It didn't process the request,
+                    // so this is safe to retry even if it consumes a guess.
+                    last_error = RequestError::Transient;
+                    if attempt == retry_policy.max_attempts || !retry_budget.try_spend() {
+                        return Err(last_error);
+                    }
+                    self.sleeper.sleep(retry_policy.delay(attempt)).await;
+                    self.check_cancelled(cancellable)?;
+                    attempt += 1;
                     continue;
                 }
             }
         }
-        Err(RequestError::Transient)
+        Err(last_error)
+    }
+}
+
+impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http, Atm> {
+    /// Checks that a realm is reachable, without accessing the user's record
+    /// or retrying.
+    ///
+    /// Hardware realms are sent a handshake-only request, which also checks
+    /// the auth token. The new session is kept for later requests. Software
+    /// realms have no such request, so they are only checked for a response.
+    #[instrument(level = "trace", skip(self, realm), fields(realm = ?realm.id), err(level = "trace", Debug))]
+    pub(crate) async fn check_realm_connection(&self, realm: &Realm) -> Result<(), RequestError> {
+        let Some(public_key) = &realm.public_key else {
+            return match self
+                .with_timeout(async {
+                    self.http
+                        .send(http::Request {
+                            method: http::Method::Get,
+                            url: realm.address.to_string(),
+                            headers: Default::default(),
+                            body: None,
+                            timeout: None,
+                        })
+                        .await
+                        .ok_or(RpcError::Network)
+                })
+                .await
+            {
+                Ok(_) => Ok(()),
+                Err(e) => Err(RequestError::from(e)),
+            };
+        };
+
+        let request_id = RequestId::new_random(&mut OsRng);
+        match self
+            .make_handshake_request(realm, public_key, &[], request_id)
+            .await
+        {
+            Ok((session, response)) if response.is_empty() => {
+                let _locked = self.session_locks.get(&realm.id).unwrap().lock().await;
+                self.session_store
+                    .save(&realm.id, SessionState::new(&session));
+                Ok(())
+            }
+            Ok(_) => Err(RequestError::Assertion),
+            Err(RequestErrorOrMissingSession::RequestError(e)) => Err(e),
+            Err(RequestErrorOrMissingSession::RateLimited(_)) => {
+                Err(RequestError::RateLimitExceeded)
+            }
+            Err(
+                RequestErrorOrMissingSession::MissingSession
+                | RequestErrorOrMissingSession::TransientAfterSend
+                | RequestErrorOrMissingSession::Unavailable,
+            ) => Err(RequestError::Transient),
+        }
+    }
+
+    /// Runs an HTTP request to a realm, failing it as a network error if the
+    /// client's request timeout elapses first.
+    async fn with_timeout<T>(
+        &self,
+        request: impl Future<Output = Result<T, RpcError>>,
+    ) -> Result<T, RpcError> {
+        let Some(timeout) = self.request_timeout else {
+            return request.await;
+        };
+        match select(pin!(request), self.sleeper.sleep(timeout)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => Err(RpcError::Network),
+        }
+    }
+}
+
+/// Returns false for a Recover3 request, which is never cancelled.
+///
+/// Recover3 is only sent after Recover2 has used a guess with the right PIN,
+/// and it's what gives that guess back. Cancelling it would leave the user
+/// with one guess fewer for a PIN they got right.
+fn is_cancellable(request: &SecretsRequest) -> bool {
+    !matches!(request, SecretsRequest::Recover3(_))
+}
+
+/// Marks a transient error from a request that may have reached the realm.
+fn transient_after_send(e: RequestErrorOrMissingSession) -> RequestErrorOrMissingSession {
+    match e {
+        RequestErrorOrMissingSession::RequestError(RequestError::Transient) => {
+            RequestErrorOrMissingSession::TransientAfterSend
+        }
+        e => e,
     }
 }
 
 /// Waits for all the futures to complete, unless enough fail that there is no
 /// way for the threshold to be met.
 ///
+/// At most `max_concurrency` futures are polled at once, if set. The rest
+/// start, in order, as earlier ones complete. Futures that are still pending
+/// or not yet started when this returns early are dropped, cancelling them.
+///
 /// Panics if the total number of `futures` given is less than the threshold,
-/// or if the threshold is 0.
+/// or if the threshold or `max_concurrency` is 0.
 ///
 /// The results and errors are returned in no particular order. An `Ok` return
 /// value will contain at least `threshold` results. An `Error` return value
@@ -370,24 +708,75 @@ impl<S: Sleeper, Http: http::Client, Atm: auth::AuthTokenManager> Client<S, Http
 pub(crate) async fn join_at_least_threshold<I, F, T, E>(
     futures: I,
     threshold: u32,
+    max_concurrency: Option<usize>,
+) -> Result<Vec<T>, E>
+where
+    I: IntoIterator<Item = F>,
+    F: Future<Output = Result<T, E>>,
+    E: Ord,
+{
+    join_threshold(futures, threshold, max_concurrency, StopAtThreshold(false)).await
+}
+
+/// Like [`join_at_least_threshold`], but returns as soon as `threshold`
+/// futures succeed, dropping the rest.
+///
+/// Dropping a future cancels it at its next `.await`, before this returns.
+/// Use this only when the results of the remaining futures aren't needed and
+/// abandoning them partway leaves no state to clean up. In particular, the
+/// realm may still receive and process an abandoned request, depending on
+/// the [`http::Client`].
+pub(crate) async fn join_until_threshold<I, F, T, E>(
+    futures: I,
+    threshold: u32,
+    max_concurrency: Option<usize>,
 ) -> Result<Vec<T>, E>
 where
     I: IntoIterator<Item = F>,
     F: Future<Output = Result<T, E>>,
     E: Ord,
 {
-    let mut futures: FuturesUnordered<F> = futures.into_iter().collect();
-    let total = futures.len();
+    join_threshold(futures, threshold, max_concurrency, StopAtThreshold(true)).await
+}
+
+// Named flag.
+#[derive(Clone, Copy, Debug)]
+struct StopAtThreshold(bool);
+
+async fn join_threshold<I, F, T, E>(
+    futures: I,
+    threshold: u32,
+    max_concurrency: Option<usize>,
+    stop_at_threshold: StopAtThreshold,
+) -> Result<Vec<T>, E>
+where
+    I: IntoIterator<Item = F>,
+    F: Future<Output = Result<T, E>>,
+    E: Ord,
+{
+    let mut pending: Vec<F> = futures.into_iter().collect();
+    let total = pending.len();
     let threshold = threshold as usize;
     assert!(total >= threshold);
     assert!(threshold > 0);
+    let max_concurrency = max_concurrency.unwrap_or(total);
+    assert!(max_concurrency > 0);
     let mut oks = Vec::with_capacity(total);
     let mut errors = Vec::new();
 
+    let later = pending.split_off(max_concurrency.min(total));
+    let mut later = later.into_iter();
+    let mut futures: FuturesUnordered<F> = pending.into_iter().collect();
     while let Some(result) = futures.next().await {
+        if let Some(future) = later.next() {
+            futures.push(future);
+        }
         match result {
             Ok(ok) => {
                 oks.push(ok);
+                if stop_at_threshold.0 && oks.len() == threshold {
+                    return Ok(oks);
+                }
             }
 
             Err(error) => {
@@ -412,8 +801,785 @@ fn min<T: Ord>(values: Vec<T>) -> T {
 
 #[cfg(test)]
 mod tests {
-    use crate::request::join_at_least_threshold;
+    use async_trait::async_trait;
     use futures::future::{err, ready};
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::{RequestError, RetryBudget, MAX_RETRY_AFTER};
+    use crate::request::{join_at_least_threshold, join_until_threshold};
+    use crate::testing::MockRealmCluster;
+    use crate::{
+        http, AuthToken, CancellationToken, ClientBuilder, Configuration, Pin, Policy, RealmId,
+        RegisterError, RetryPolicy, SessionState, SessionStore, Sleeper, UserInfo, UserSecret,
+    };
+    use juicebox_marshalling as marshalling;
+    use juicebox_noise::{server as noise, HandshakeResponse, Transport};
+    use juicebox_realm_api::requests::{
+        ClientRequest, ClientResponse, DecodeStage, NoiseRequest, NoiseResponse,
+        PaddedSecretsResponse, Recover1Response, Recover2Request, Recover2Response,
+        Recover3Request, Recover3Response, SecretsRequest, SecretsResponse,
+    };
+    use juicebox_realm_api::types::{RegistrationVersion, RequestId, UnlockKeyTag};
+    use rand::rngs::OsRng;
+
+    /// A hardware realm that answers every request by asking the client to
+    /// wait.
+    #[derive(Clone)]
+    struct RateLimitingRealm {
+        retry_after: Duration,
+        request_ids: Arc<Mutex<Vec<Option<RequestId>>>>,
+    }
+
+    #[async_trait]
+    impl http::Client for RateLimitingRealm {
+        async fn send(&self, request: http::Request) -> Option<http::Response> {
+            let request: ClientRequest = marshalling::from_slice(&request.body?).ok()?;
+            self.request_ids.lock().unwrap().push(request.request_id);
+            Some(http::Response {
+                status_code: 200,
+                headers: HashMap::new(),
+                body: marshalling::to_vec(&ClientResponse::RateLimited {
+                    retry_after: self.retry_after,
+                })
+                .unwrap(),
+            })
+        }
+    }
+
+    /// A hardware realm that answers every `Recover1` with `NotRegistered`
+    /// over Noise, counting the handshakes it completes.
+    #[derive(Clone)]
+    struct NoiseRealm {
+        secret: x25519_dalek::StaticSecret,
+        session_lifetime: Duration,
+        sessions: Arc<Mutex<HashMap<u32, Transport>>>,
+        handshakes: Arc<Mutex<usize>>,
+        garbled: Arc<Mutex<bool>>,
+        restarts: Arc<Mutex<usize>>,
+    }
+
+    impl NoiseRealm {
+        fn new(session_lifetime: Duration) -> Self {
+            Self {
+                secret: x25519_dalek::StaticSecret::random_from_rng(OsRng),
+                session_lifetime,
+                sessions: Arc::default(),
+                handshakes: Arc::default(),
+                garbled: Arc::default(),
+                restarts: Arc::default(),
+            }
+        }
+
+        fn public_key(&self) -> Vec<u8> {
+            x25519_dalek::PublicKey::from(&self.secret)
+                .as_bytes()
+                .to_vec()
+        }
+
+        fn handshakes(&self) -> usize {
+            *self.handshakes.lock().unwrap()
+        }
+
+        /// Forgets every session, as if the realm had restarted.
+        fn restart(&self) {
+            self.sessions.lock().unwrap().clear();
+        }
+
+        /// Forgets every session just before each of the next `count`
+        /// requests on an existing session, as if the realm kept restarting.
+        fn restart_before_transports(&self, count: usize) {
+            *self.restarts.lock().unwrap() = count;
+        }
+
+        /// Answers the next request on an existing session as though its
+        /// decrypted contents couldn't be decoded.
+        fn garble(&self) {
+            *self.garbled.lock().unwrap() = true;
+        }
+
+        fn respond(request: &[u8]) -> Vec<u8> {
+            let request: SecretsRequest = marshalling::from_slice(request).unwrap();
+            let response = match request {
+                SecretsRequest::Recover1 => {
+                    SecretsResponse::Recover1(Recover1Response::NotRegistered)
+                }
+                SecretsRequest::Recover2(_) => {
+                    SecretsResponse::Recover2(Recover2Response::NotRegistered)
+                }
+                _ => panic!("unexpected request {request:?}"),
+            };
+            marshalling::to_vec(&PaddedSecretsResponse::try_from(&response).unwrap()).unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl http::Client for NoiseRealm {
+        async fn send(&self, request: http::Request) -> Option<http::Response> {
+            let request: ClientRequest = marshalling::from_slice(&request.body?).ok()?;
+            let response = match request.encrypted {
+                NoiseRequest::Handshake { handshake } => {
+                    let public_key = x25519_dalek::PublicKey::from(&self.secret);
+                    let (handshake, payload) =
+                        noise::Handshake::start((&self.secret, &public_key), &handshake, OsRng)
+                            .unwrap();
+                    let response = if payload.is_empty() {
+                        Vec::new()
+                    } else {
+                        Self::respond(&payload)
+                    };
+                    let (transport, handshake) = handshake.finish(&response).unwrap();
+                    self.sessions
+                        .lock()
+                        .unwrap()
+                        .insert(request.session_id.0, transport);
+                    *self.handshakes.lock().unwrap() += 1;
+                    ClientResponse::Ok(NoiseResponse::Handshake {
+                        handshake,
+                        session_lifetime: self.session_lifetime,
+                    })
+                }
+                NoiseRequest::Transport { ciphertext } => {
+                    let mut sessions = self.sessions.lock().unwrap();
+                    let mut restarts = self.restarts.lock().unwrap();
+                    if *restarts > 0 {
+                        *restarts -= 1;
+                        sessions.clear();
+                    }
+                    match sessions.get_mut(&request.session_id.0) {
+                        None => ClientResponse::MissingSession,
+                        Some(_) if std::mem::take(&mut *self.garbled.lock().unwrap()) => {
+                            ClientResponse::DecodingError {
+                                stage: DecodeStage::Inner,
+                            }
+                        }
+                        Some(transport) => {
+                            let request = transport.decrypt(&ciphertext).unwrap();
+                            let ciphertext = transport.encrypt(&Self::respond(&request)).unwrap();
+                            ClientResponse::Ok(NoiseResponse::Transport { ciphertext })
+                        }
+                    }
+                }
+            };
+            Some(http::Response {
+                status_code: 200,
+                headers: HashMap::new(),
+                body: marshalling::to_vec(&response).unwrap(),
+            })
+        }
+    }
+
+    /// A realm that answers every request with the same HTTP response.
+    #[derive(Clone)]
+    struct FixedResponseRealm {
+        status_code: u16,
+        body: Vec<u8>,
+        requests: Arc<Mutex<usize>>,
+    }
+
+    #[async_trait]
+    impl http::Client for FixedResponseRealm {
+        async fn send(&self, _request: http::Request) -> Option<http::Response> {
+            *self.requests.lock().unwrap() += 1;
+            Some(http::Response {
+                status_code: self.status_code,
+                headers: HashMap::new(),
+                body: self.body.clone(),
+            })
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct RecordingSleeper(Arc<Mutex<Vec<Duration>>>);
+
+    /// Cancels its token whenever the client sleeps before a retry.
+    #[derive(Clone, Default)]
+    struct CancellingSleeper(CancellationToken);
+
+    #[async_trait]
+    impl Sleeper for CancellingSleeper {
+        async fn sleep(&self, _duration: Duration) {
+            self.0.cancel();
+        }
+    }
+
+    #[async_trait]
+    impl Sleeper for RecordingSleeper {
+        async fn sleep(&self, duration: Duration) {
+            self.0.lock().unwrap().push(duration);
+        }
+    }
+
+    /// Makes a request to a realm that always responds with `retry_after`,
+    /// returning the error, the number of requests the realm received, and
+    /// the client's sleeps.
+    async fn rate_limited_request(
+        retry_after: Duration,
+        retry_budget: u32,
+    ) -> (RequestError, usize, Vec<Duration>) {
+        let (error, request_ids, sleeps) =
+            rate_limited_request_ids(retry_after, retry_budget, 1).await;
+        (error, request_ids.len(), sleeps)
+    }
+
+    /// Like [`rate_limited_request`], but makes `count` requests and returns
+    /// the request ID the realm saw for each attempt.
+    async fn rate_limited_request_ids(
+        retry_after: Duration,
+        retry_budget: u32,
+        count: usize,
+    ) -> (RequestError, Vec<Option<RequestId>>, Vec<Duration>) {
+        let configuration = Configuration::test_config(1, 1, 1);
+        let realm = configuration.realms[0].clone();
+        let http = RateLimitingRealm {
+            retry_after,
+            request_ids: Arc::default(),
+        };
+        let sleeper = RecordingSleeper::default();
+        let client = ClientBuilder::new()
+            .configuration(configuration)
+            .http(http.clone())
+            .sleeper(sleeper.clone())
+            .auth_token_manager(HashMap::<RealmId, AuthToken>::from([(
+                realm.id,
+                AuthToken::from(String::from("user")),
+            )]))
+            .build();
+        let mut error = RequestError::Assertion;
+        for _ in 0..count {
+            error = client
+                .make_request_with_retry_budget(
+                    &realm,
+                    SecretsRequest::Recover1,
+                    &RetryBudget::new(retry_budget),
+                )
+                .await
+                .unwrap_err();
+        }
+        let request_ids = http.request_ids.lock().unwrap().clone();
+        let sleeps = sleeper.0.lock().unwrap().clone();
+        (error, request_ids, sleeps)
+    }
+
+    /// Makes `count` `Recover1` requests to a [`NoiseRealm`], restarting the
+    /// realm before the requests in `restart_before` and garbling the
+    /// requests in `garble`, and returns how many handshakes the realm
+    /// completed.
+    async fn noise_realm_handshakes(
+        session_lifetime: Duration,
+        count: usize,
+        restart_before: &[usize],
+        garble: &[usize],
+    ) -> usize {
+        let realm = NoiseRealm::new(session_lifetime);
+        let mut configuration = Configuration::test_config(1, 1, 1);
+        configuration.realms[0].public_key = Some(realm.public_key());
+        // Reopening a missing session shouldn't need a retry.
+        configuration.retry_policy.max_attempts = 1;
+        let realm_config = configuration.realms[0].clone();
+        let client = ClientBuilder::new()
+            .configuration(configuration)
+            .http(realm.clone())
+            .sleeper(RecordingSleeper::default())
+            .auth_token_manager(HashMap::<RealmId, AuthToken>::from([(
+                realm_config.id,
+                AuthToken::from(String::from("user")),
+            )]))
+            .build();
+        for i in 0..count {
+            if restart_before.contains(&i) {
+                realm.restart();
+            }
+            if garble.contains(&i) {
+                realm.garble();
+            }
+            let response = client
+                .make_request(&realm_config, SecretsRequest::Recover1)
+                .await
+                .unwrap();
+            assert!(matches!(
+                response,
+                SecretsResponse::Recover1(Recover1Response::NotRegistered)
+            ));
+        }
+        realm.handshakes()
+    }
+
+    #[tokio::test]
+    async fn test_sessions_reused_across_requests() {
+        let lifetime = Duration::from_secs(60);
+        // The first request's handshake carries the request, and later
+        // requests reuse its session.
+        assert_eq!(noise_realm_handshakes(lifetime, 3, &[], &[]).await, 1);
+        // A realm that lost the session answers `MissingSession`, and the
+        // client handshakes again.
+        assert_eq!(noise_realm_handshakes(lifetime, 4, &[2], &[]).await, 2);
+        // So does a realm that couldn't decode what it decrypted.
+        assert_eq!(noise_realm_handshakes(lifetime, 4, &[], &[2]).await, 2);
+        // Sessions that have been idle for their lifetime aren't reused.
+        assert_eq!(noise_realm_handshakes(Duration::ZERO, 3, &[], &[]).await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_missing_sessions_retried_up_to_max_attempts() {
+        let realm = NoiseRealm::new(Duration::from_secs(60));
+        let mut configuration = Configuration::test_config(1, 1, 1);
+        configuration.realms[0].public_key = Some(realm.public_key());
+        configuration.retry_policy.max_attempts = 3;
+        let realm_config = configuration.realms[0].clone();
+        let client = ClientBuilder::new()
+            .configuration(configuration)
+            .http(realm.clone())
+            .sleeper(RecordingSleeper::default())
+            .auth_token_manager(HashMap::<RealmId, AuthToken>::from([(
+                realm_config.id,
+                AuthToken::from(String::from("user")),
+            )]))
+            .build();
+        // Recover2 needs forward secrecy, so a new session is opened with a
+        // handshake before the request is sent on it, and can go missing.
+        let recover2 = || {
+            SecretsRequest::Recover2(Recover2Request {
+                version: RegistrationVersion::from([1; 16]),
+                oprf_blinded_input: juicebox_oprf::start(b"1234", &mut OsRng).1,
+            })
+        };
+
+        client
+            .make_request(&realm_config, SecretsRequest::Recover1)
+            .await
+            .unwrap();
+        // The stored session goes missing, which is retried for free, and
+        // then the first new session does too, which uses an attempt.
+        realm.restart_before_transports(2);
+        let response = client
+            .make_request(&realm_config, recover2())
+            .await
+            .unwrap();
+        assert!(matches!(
+            response,
+            SecretsResponse::Recover2(Recover2Response::NotRegistered)
+        ));
+
+        // With every session going missing, the attempts run out.
+        realm.restart_before_transports(4);
+        assert_eq!(
+            client
+                .make_request(&realm_config, recover2())
+                .await
+                .unwrap_err(),
+            RequestError::Transient
+        );
+        assert_eq!(*realm.restarts.lock().unwrap(), 0);
+    }
+
+    /// A [`SessionStore`] that keeps sessions as bytes, like one backed by
+    /// disk would.
+    #[derive(Default)]
+    struct BytesSessionStore(Mutex<HashMap<RealmId, Vec<u8>>>);
+
+    impl SessionStore for BytesSessionStore {
+        fn take(&self, realm: &RealmId) -> Option<SessionState> {
+            SessionState::from_bytes(&self.0.lock().unwrap().remove(realm)?)
+        }
+
+        fn save(&self, realm: &RealmId, state: SessionState) {
+            self.0.lock().unwrap().insert(*realm, state.to_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sessions_survive_client_restart() {
+        let realm = NoiseRealm::new(Duration::from_secs(60));
+        let mut configuration = Configuration::test_config(1, 1, 1);
+        configuration.realms[0].public_key = Some(realm.public_key());
+        let realm_config = configuration.realms[0].clone();
+        let store = Arc::new(BytesSessionStore::default());
+
+        for _ in 0..3 {
+            // Each iteration builds a new client, as a restarted process
+            // would, sharing only the session store.
+            let client = ClientBuilder::new()
+                .configuration(configuration.clone())
+                .http(realm.clone())
+                .sleeper(RecordingSleeper::default())
+                .auth_token_manager(HashMap::<RealmId, AuthToken>::from([(
+                    realm_config.id,
+                    AuthToken::from(String::from("user")),
+                )]))
+                .session_store(store.clone())
+                .build();
+            let response = client
+                .make_request(&realm_config, SecretsRequest::Recover1)
+                .await
+                .unwrap();
+            assert!(matches!(
+                response,
+                SecretsResponse::Recover1(Recover1Response::NotRegistered)
+            ));
+        }
+        assert_eq!(realm.handshakes(), 1);
+        assert_eq!(store.0.lock().unwrap().len(), 1);
+
+        // A session the realm lost is replaced in the store.
+        realm.restart();
+        let client = ClientBuilder::new()
+            .configuration(configuration)
+            .http(realm.clone())
+            .sleeper(RecordingSleeper::default())
+            .auth_token_manager(HashMap::<RealmId, AuthToken>::from([(
+                realm_config.id,
+                AuthToken::from(String::from("user")),
+            )]))
+            .session_store(store.clone())
+            .build();
+        for _ in 0..2 {
+            client
+                .make_request(&realm_config, SecretsRequest::Recover1)
+                .await
+                .unwrap();
+        }
+        assert_eq!(realm.handshakes(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_clients_share_session_store() {
+        let realm = NoiseRealm::new(Duration::from_secs(60));
+        let mut configuration = Configuration::test_config(1, 1, 1);
+        configuration.realms[0].public_key = Some(realm.public_key());
+        let realm_config = configuration.realms[0].clone();
+        let store = Arc::new(BytesSessionStore::default());
+
+        let client = || {
+            ClientBuilder::new()
+                .configuration(configuration.clone())
+                .http(realm.clone())
+                .sleeper(RecordingSleeper::default())
+                .auth_token_manager(HashMap::<RealmId, AuthToken>::from([(
+                    realm_config.id,
+                    AuthToken::from(String::from("user")),
+                )]))
+                .session_store(store.clone())
+                .build()
+        };
+        let clients = [Arc::new(client()), Arc::new(client())];
+
+        // Both clients use the realm at once. The realm can't decrypt a
+        // request if two clients sent it on the same session, so every
+        // request succeeding shows each session went to one client at a
+        // time.
+        let tasks: Vec<_> = clients
+            .iter()
+            .map(|client| {
+                let client = client.clone();
+                let realm_config = realm_config.clone();
+                tokio::spawn(async move {
+                    for _ in 0..100 {
+                        let response = client
+                            .make_request(&realm_config, SecretsRequest::Recover1)
+                            .await
+                            .unwrap();
+                        assert!(matches!(
+                            response,
+                            SecretsResponse::Recover1(Recover1Response::NotRegistered)
+                        ));
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert!(realm.handshakes() <= 200);
+        assert_eq!(store.0.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_honors_retry_after() {
+        let retry_after = Duration::from_secs(2);
+        assert_eq!(
+            rate_limited_request(retry_after, u32::MAX).await,
+            (RequestError::RateLimitExceeded, 5, vec![retry_after; 5])
+        );
+
+        // Each wait spends from the retry budget.
+        assert_eq!(
+            rate_limited_request(retry_after, 2).await,
+            (RequestError::RateLimitExceeded, 3, vec![retry_after; 2])
+        );
+
+        // Unreasonably long waits aren't honored.
+        assert_eq!(
+            rate_limited_request(MAX_RETRY_AFTER + Duration::from_secs(1), u32::MAX).await,
+            (RequestError::RateLimitExceeded, 1, Vec::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_id_reused_across_retries() {
+        let (_, request_ids, _) = rate_limited_request_ids(Duration::from_secs(1), 2, 2).await;
+        let request_ids: Vec<RequestId> = request_ids.into_iter().map(Option::unwrap).collect();
+        assert_eq!(request_ids.len(), 6);
+        assert!(request_ids[..3].iter().all(|id| *id == request_ids[0]));
+        assert!(request_ids[3..].iter().all(|id| *id == request_ids[3]));
+        assert_ne!(request_ids[0], request_ids[3]);
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors_retried_with_backoff() {
+        let cluster = MockRealmCluster::new(1);
+        let mut configuration = cluster.configuration(1, 1);
+        configuration.retry_policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(15),
+            jitter: false,
+        };
+        let realm = configuration.realms[0].clone();
+        let sleeper = RecordingSleeper::default();
+        let client = ClientBuilder::new()
+            .configuration(configuration)
+            .http(cluster.clone())
+            .sleeper(sleeper.clone())
+            .auth_token_manager(HashMap::<RealmId, AuthToken>::from([(
+                realm.id,
+                AuthToken::from(String::from("apollo")),
+            )]))
+            .build();
+
+        cluster.fail_requests(&realm.id, 2);
+        assert!(client
+            .make_request(&realm, SecretsRequest::Recover1)
+            .await
+            .is_ok());
+        assert_eq!(
+            *sleeper.0.lock().unwrap(),
+            [Duration::from_millis(10), Duration::from_millis(15)]
+        );
+        assert_eq!(cluster.request_count(&realm.id, "Recover1"), 1);
+
+        // The request fails once it runs out of attempts.
+        sleeper.0.lock().unwrap().clear();
+        cluster.fail_requests(&realm.id, 3);
+        assert_eq!(
+            client
+                .make_request(&realm, SecretsRequest::Recover1)
+                .await
+                .unwrap_err(),
+            RequestError::Transient
+        );
+        assert_eq!(sleeper.0.lock().unwrap().len(), 2);
+        assert_eq!(cluster.request_count(&realm.id, "Recover1"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_retried_with_backoff() {
+        for jitter in [false, true] {
+            let mut configuration = Configuration::test_config(1, 1, 1);
+            configuration.retry_policy = RetryPolicy {
+                max_attempts: 4,
+                base_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(25),
+                jitter,
+            };
+            let realm = configuration.realms[0].clone();
+            let http = FixedResponseRealm {
+                status_code: 200,
+                body: marshalling::to_vec(&ClientResponse::Unavailable).unwrap(),
+                requests: Arc::default(),
+            };
+            let sleeper = RecordingSleeper::default();
+            let client = ClientBuilder::new()
+                .configuration(configuration)
+                .http(http.clone())
+                .sleeper(sleeper.clone())
+                .auth_token_manager(HashMap::<RealmId, AuthToken>::from([(
+                    realm.id,
+                    AuthToken::from(String::from("user")),
+                )]))
+                .build();
+
+            assert_eq!(
+                client
+                    .make_request(&realm, SecretsRequest::Recover1)
+                    .await
+                    .unwrap_err(),
+                RequestError::Transient
+            );
+            assert_eq!(*http.requests.lock().unwrap(), 4);
+            let sleeps = sleeper.0.lock().unwrap().clone();
+            let backoff = [10, 20, 25].map(Duration::from_millis);
+            if jitter {
+                assert_eq!(sleeps.len(), 3);
+                assert!(sleeps.iter().zip(&backoff).all(|(sleep, max)| sleep <= max));
+            } else {
+                assert_eq!(sleeps, backoff);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_transient_errors_not_retried() {
+        for (status_code, body, expected) in [
+            (401, Vec::new(), RequestError::InvalidAuth),
+            (200, b"garbage".to_vec(), RequestError::Assertion),
+            (
+                200,
+                marshalling::to_vec(&ClientResponse::DecodingError {
+                    stage: DecodeStage::Outer,
+                })
+                .unwrap(),
+                RequestError::Assertion,
+            ),
+            // A realm without the configured key can't decrypt the handshake.
+            (
+                200,
+                marshalling::to_vec(&ClientResponse::SessionError).unwrap(),
+                RequestError::KeyMismatch,
+            ),
+            // An impersonator can't produce a handshake response that
+            // decrypts.
+            (
+                200,
+                marshalling::to_vec(&ClientResponse::Ok(NoiseResponse::Handshake {
+                    handshake: HandshakeResponse {
+                        server_ephemeral_public: vec![7; 32],
+                        payload_ciphertext: vec![0; 16],
+                    },
+                    session_lifetime: Duration::from_secs(60),
+                }))
+                .unwrap(),
+                RequestError::KeyMismatch,
+            ),
+        ] {
+            let configuration = Configuration::test_config(1, 1, 1);
+            let realm = configuration.realms[0].clone();
+            let http = FixedResponseRealm {
+                status_code,
+                body,
+                requests: Arc::default(),
+            };
+            let sleeper = RecordingSleeper::default();
+            let client = ClientBuilder::new()
+                .configuration(configuration)
+                .http(http.clone())
+                .sleeper(sleeper.clone())
+                .auth_token_manager(HashMap::<RealmId, AuthToken>::from([(
+                    realm.id,
+                    AuthToken::from(String::from("user")),
+                )]))
+                .build();
+
+            assert_eq!(
+                client
+                    .make_request(&realm, SecretsRequest::Recover1)
+                    .await
+                    .unwrap_err(),
+                expected
+            );
+            assert_eq!(*http.requests.lock().unwrap(), 1);
+            assert!(sleeper.0.lock().unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_checked_between_round_trips() {
+        let cluster = MockRealmCluster::new(1);
+        let configuration = cluster.configuration(1, 1);
+        let realm = configuration.realms[0].clone();
+        let token = CancellationToken::new();
+        let client = ClientBuilder::new()
+            .configuration(configuration)
+            .http(cluster.clone())
+            .sleeper(CancellingSleeper(token.clone()))
+            .cancellation_token(token.clone())
+            .auth_token_manager(HashMap::<RealmId, AuthToken>::from([(
+                realm.id,
+                AuthToken::from(String::from("apollo")),
+            )]))
+            .build();
+
+        // The token is cancelled while waiting to retry, so the retry isn't
+        // sent.
+        cluster.fail_requests(&realm.id, 1);
+        assert_eq!(
+            client
+                .make_request(&realm, SecretsRequest::Recover1)
+                .await
+                .unwrap_err(),
+            RequestError::Cancelled
+        );
+        assert_eq!(cluster.request_count(&realm.id, "Recover1"), 0);
+
+        // Operations fail without sending anything until the token is reset.
+        assert_eq!(
+            client
+                .register(
+                    &Pin::from(b"1234".to_vec()),
+                    &UserSecret::from(b"artemis".to_vec()),
+                    &UserInfo::new(b"apollo".to_vec()).unwrap(),
+                    Policy { num_guesses: 2 },
+                )
+                .await
+                .unwrap_err(),
+            RegisterError::Cancelled
+        );
+        assert_eq!(cluster.request_count(&realm.id, "Register1"), 0);
+
+        // Recover3 gives back the guess Recover2 used, so it's sent anyway.
+        assert!(matches!(
+            client
+                .make_request(
+                    &realm,
+                    SecretsRequest::Recover3(Recover3Request {
+                        version: RegistrationVersion::from([1; 16]),
+                        unlock_key_tag: UnlockKeyTag::from([2; 16]),
+                    })
+                )
+                .await,
+            Ok(SecretsResponse::Recover3(Recover3Response::NotRegistered))
+        ));
+
+        token.reset();
+        assert!(client
+            .make_request(&realm, SecretsRequest::Recover1)
+            .await
+            .is_ok());
+        assert_eq!(cluster.request_count(&realm.id, "Recover1"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_scoped_cancellation() {
+        let cluster = MockRealmCluster::new(1);
+        let configuration = cluster.configuration(1, 1);
+        let realm = configuration.realms[0].clone();
+        let client = cluster.client(configuration, "apollo");
+
+        let cancelled = CancellationToken::new();
+        cancelled.cancel();
+        assert_eq!(
+            cancelled
+                .scope(client.make_request(&realm, SecretsRequest::Recover1))
+                .await
+                .unwrap_err(),
+            RequestError::Cancelled
+        );
+        assert_eq!(cluster.request_count(&realm.id, "Recover1"), 0);
+
+        // Operations outside the cancelled scope, or in another one, still
+        // run.
+        assert!(client
+            .make_request(&realm, SecretsRequest::Recover1)
+            .await
+            .is_ok());
+        assert!(CancellationToken::new()
+            .scope(client.make_request(&realm, SecretsRequest::Recover1))
+            .await
+            .is_ok());
+        assert_eq!(cluster.request_count(&realm.id, "Recover1"), 2);
+    }
 
     #[tokio::test]
     async fn test_join_at_least_threshold() {
@@ -425,7 +1591,8 @@ mod tests {
             ready(Ok("Result 4")),
         ];
 
-        let result: Result<Vec<&str>, Box<TestError>> = join_at_least_threshold(futures, 3).await;
+        let result: Result<Vec<&str>, Box<TestError>> =
+            join_at_least_threshold(futures, 3, None).await;
 
         assert!(result.is_ok());
         let results = result.unwrap();
@@ -441,11 +1608,80 @@ mod tests {
             err(Box::new(TestError)),
         ];
 
-        let result: Result<Vec<&str>, Box<TestError>> = join_at_least_threshold(futures, 2).await;
+        let result: Result<Vec<&str>, Box<TestError>> =
+            join_at_least_threshold(futures, 2, None).await;
 
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_join_at_least_threshold_max_concurrency() {
+        let in_flight = Cell::new(0);
+        let max_in_flight = Cell::new(0);
+        let started = Cell::new(0);
+        let request = |fail: bool| {
+            let (in_flight, max_in_flight, started) = (&in_flight, &max_in_flight, &started);
+            async move {
+                started.set(started.get() + 1);
+                in_flight.set(in_flight.get() + 1);
+                max_in_flight.set(max_in_flight.get().max(in_flight.get()));
+                tokio::task::yield_now().await;
+                in_flight.set(in_flight.get() - 1);
+                if fail {
+                    Err(TestError)
+                } else {
+                    Ok(())
+                }
+            }
+        };
+
+        let results = join_at_least_threshold((0..9).map(|_| request(false)), 5, Some(2)).await;
+        assert_eq!(results.unwrap().len(), 9);
+        assert_eq!(max_in_flight.get(), 2);
+        assert_eq!(started.get(), 9);
+
+        // Requests that haven't started when the threshold becomes
+        // unreachable are never sent.
+        started.set(0);
+        let result =
+            join_at_least_threshold([true, true, false, false].map(request), 3, Some(1)).await;
+        assert_eq!(result, Err(TestError));
+        assert_eq!(started.get(), 2);
+        assert_eq!(in_flight.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_join_until_threshold_cancels_rest() {
+        let completed = Cell::new(0);
+        let request = |yields: usize| {
+            let completed = &completed;
+            async move {
+                for _ in 0..yields {
+                    tokio::task::yield_now().await;
+                }
+                completed.set(completed.get() + 1);
+                Ok::<usize, TestError>(yields)
+            }
+        };
+
+        let mut results = join_until_threshold([5, 1, 4, 2, 3].map(request), 2, None)
+            .await
+            .unwrap();
+        results.sort_unstable();
+        assert_eq!(results, vec![1, 2]);
+        assert_eq!(completed.get(), 2);
+
+        // The futures are dropped by the time it returns, so they never run
+        // to completion.
+        tokio::task::yield_now().await;
+        assert_eq!(completed.get(), 2);
+
+        completed.set(0);
+        let results = join_at_least_threshold([5, 1, 4, 2, 3].map(request), 2, None).await;
+        assert_eq!(results.unwrap().len(), 5);
+        assert_eq!(completed.get(), 5);
+    }
+
     #[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
     struct TestError;
 }
@@ -0,0 +1,176 @@
+use instant::{Duration, Instant, SystemTime};
+use juicebox_marshalling as marshalling;
+use juicebox_noise::{Transport, TransportState};
+use juicebox_realm_api::types::{RealmId, SessionId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::types::Session;
+
+/// Holds the [`Client`](crate::Client)'s Noise session with each hardware
+/// realm between requests.
+///
+/// The default, [`MemorySessionStore`], loses the sessions when the process
+/// exits, so a restarted process has to handshake with every realm again.
+/// An implementation backed by disk or a shared cache lets sessions survive
+/// restarts. Set it with
+/// [`ClientBuilder::session_store`](crate::ClientBuilder::session_store).
+///
+/// The store holds at most one session per realm. The client calls `take`
+/// before each request to a realm, which removes the session so that no
+/// other request can use it at the same time. Once a request succeeds, the
+/// client calls `save` with the updated session. Clients sharing a store
+/// may use the same realms concurrently: a request that finds no session
+/// opens a new one, and the session saved last replaces the others.
+///
+/// These methods are called inline on the request path, so they should
+/// return quickly. A store that fails to load or save a session can simply
+/// act as if it had none, which costs the client another handshake.
+pub trait SessionStore: Send + Sync {
+    /// Removes and returns the session saved for `realm`, if any.
+    ///
+    /// This must be atomic: a session may be returned to at most one caller,
+    /// since reusing it in two requests would break the Noise nonces.
+    fn take(&self, realm: &RealmId) -> Option<SessionState>;
+
+    /// Saves `state` as the session for `realm`, replacing any earlier one.
+    fn save(&self, realm: &RealmId, state: SessionState);
+}
+
+/// A [`SessionStore`] that keeps sessions in memory. This is the default.
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<HashMap<RealmId, SessionState>>,
+}
+
+impl SessionStore for MemorySessionStore {
+    fn take(&self, realm: &RealmId) -> Option<SessionState> {
+        self.sessions.lock().unwrap().remove(realm)
+    }
+
+    fn save(&self, realm: &RealmId, state: SessionState) {
+        self.sessions.lock().unwrap().insert(*realm, state);
+    }
+}
+
+/// A snapshot of a Noise session with a realm, for a [`SessionStore`].
+///
+/// The state includes the session's encryption keys, so it should be stored
+/// with the same care as the auth tokens used to open it. Persist it with
+/// [`SessionState::to_bytes`] and [`SessionState::from_bytes`].
+///
+/// The session expires after it has been idle for the lifetime the realm
+/// chose. The expiry is kept as wall-clock time so that it carries across
+/// restarts.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct SessionState {
+    session_id: SessionId,
+    transport: TransportState,
+    lifetime: Duration,
+    /// When the session expires, measured from the Unix epoch.
+    expires_at: Duration,
+}
+
+impl fmt::Debug for SessionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionState")
+            .field("session_id", &self.session_id)
+            .field("lifetime", &self.lifetime)
+            .field("expires_at", &self.expires_at)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SessionState {
+    /// Encodes the state for storage.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        marshalling::to_vec(self).expect("failed to serialize SessionState")
+    }
+
+    /// Decodes a state from [`SessionState::to_bytes`]. Returns `None` if
+    /// the bytes are not a valid state, in which case the store should
+    /// behave as though it had no session.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        marshalling::from_slice(bytes).ok()
+    }
+
+    pub(crate) fn new(session: &Session) -> Self {
+        let idle = session.last_used.elapsed();
+        Self {
+            session_id: session.session_id,
+            transport: session.transport.to_state(),
+            lifetime: session.lifetime,
+            expires_at: unix_now() + session.lifetime.saturating_sub(idle),
+        }
+    }
+
+    /// Resumes the session, or returns `None` if it has expired.
+    pub(crate) fn into_session(self) -> Option<Session> {
+        let remaining = self
+            .expires_at
+            .checked_sub(unix_now())
+            .filter(|remaining| !remaining.is_zero())?;
+        let idle = self.lifetime.saturating_sub(remaining);
+        Some(Session {
+            session_id: self.session_id,
+            transport: Transport::from_state(&self.transport),
+            lifetime: self.lifetime,
+            last_used: Instant::now().checked_sub(idle)?,
+        })
+    }
+}
+
+fn unix_now() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use instant::{Duration, Instant};
+    use juicebox_noise::{client, server};
+    use juicebox_realm_api::types::SessionId;
+    use rand::rngs::OsRng;
+
+    use super::SessionState;
+    use crate::types::Session;
+
+    fn session(lifetime: Duration) -> Session {
+        let secret = x25519_dalek::StaticSecret::random_from_rng(OsRng);
+        let public = x25519_dalek::PublicKey::from(&secret);
+        let (handshake, request) = client::Handshake::start(&public, &[], &mut OsRng).unwrap();
+        let (server, _) = server::Handshake::start((&secret, &public), &request, OsRng).unwrap();
+        let (_, response) = server.finish(&[]).unwrap();
+        let (transport, _) = handshake.finish(&response).unwrap();
+        Session {
+            session_id: SessionId(7),
+            transport,
+            lifetime,
+            last_used: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn test_session_state_round_trip() {
+        let state = SessionState::new(&session(Duration::from_secs(60)));
+        let bytes = state.to_bytes();
+        let session = SessionState::from_bytes(&bytes)
+            .unwrap()
+            .into_session()
+            .unwrap();
+        assert_eq!(session.session_id, SessionId(7));
+        assert_eq!(session.lifetime, Duration::from_secs(60));
+        assert!(session.last_used.elapsed() < session.lifetime);
+
+        assert!(SessionState::from_bytes(&bytes[1..]).is_none());
+    }
+
+    #[test]
+    fn test_expired_session_state() {
+        let state = SessionState::new(&session(Duration::ZERO));
+        assert!(state.into_session().is_none());
+    }
+}
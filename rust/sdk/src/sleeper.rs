@@ -7,6 +7,12 @@ use std::time::Duration;
 ///
 /// Most users should simply use `tokio::sleep` by enabling
 /// the `tokio` feature and using [`ClientBuilder::tokio_sleeper`](crate::ClientBuilder::tokio_sleeper)
+///
+/// Sleeping is the only thing the client needs from an async runtime. It
+/// never spawns tasks: an operation's requests to the realms run
+/// concurrently within the operation's own future. So apps on another
+/// runtime, such as async-std or smol, can implement this with that
+/// runtime's timer and await the client's operations directly.
 #[async_trait]
 pub trait Sleeper {
     async fn sleep(&self, duration: Duration);
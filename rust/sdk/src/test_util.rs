@@ -0,0 +1,78 @@
+//! Helpers for writing tests against the SDK.
+
+use url::Url;
+use x25519_dalek as x25519;
+
+use crate::{Configuration, PinHashingMode, Realm, RealmId, RealmRole, RetryPolicy, SecretCipher};
+
+impl Configuration {
+    /// Returns a minimal [`Configuration`] for tests with `num_realms`
+    /// read-write realms.
+    ///
+    /// Realm `i` (counting from 1) has the id `[i; 16]`, listens on
+    /// `http://127.0.0.1:{8080 + i}/`, and has the x25519 public key for the
+    /// static secret `[i; 32]`. PINs are hashed with
+    /// [`PinHashingMode::FastInsecure`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thresholds are invalid for the number of realms, the
+    /// same way constructing a [`Client`](crate::Client) would.
+    pub fn test_config(num_realms: u8, register_threshold: u32, recover_threshold: u32) -> Self {
+        let realms = (1..=num_realms)
+            .map(|i| Realm {
+                id: RealmId([i; 16]),
+                address: Url::parse(&format!("http://127.0.0.1:{}/", 8080 + u16::from(i))).unwrap(),
+                public_key: Some(
+                    x25519::PublicKey::from(&x25519::StaticSecret::from([i; 32]))
+                        .as_bytes()
+                        .to_vec(),
+                ),
+                role: RealmRole::ReadWrite,
+            })
+            .collect();
+        let configuration = Self {
+            realms,
+            register_threshold,
+            recover_threshold,
+            pin_hashing_mode: PinHashingMode::FastInsecure,
+            secret_cipher: SecretCipher::ChaCha20Poly1305,
+            retry_policy: RetryPolicy::default(),
+            max_concurrency: None,
+            require_public_keys: false,
+        };
+        if let Err(e) = configuration.validate() {
+            panic!("invalid Configuration: {e}");
+        }
+        configuration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::CheckedConfiguration;
+    use crate::Configuration;
+
+    #[test]
+    fn test_test_config_is_valid() {
+        for (num_realms, register_threshold, recover_threshold) in
+            [(1, 1, 1), (3, 3, 2), (3, 2, 2), (5, 4, 3)]
+        {
+            let configuration =
+                Configuration::test_config(num_realms, register_threshold, recover_threshold);
+            assert_eq!(configuration.realms.len(), usize::from(num_realms));
+            assert_eq!(
+                configuration,
+                Configuration::test_config(num_realms, register_threshold, recover_threshold)
+            );
+            let checked = CheckedConfiguration::new(configuration).unwrap();
+            assert_eq!(checked.share_count(), u32::from(num_realms));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "majority")]
+    fn test_test_config_rejects_invalid_thresholds() {
+        Configuration::test_config(3, 3, 1);
+    }
+}
@@ -0,0 +1,483 @@
+//! In-process realm emulation for exercising the [`Client`] without a
+//! network.
+//!
+//! This module is public with the `testing` feature, so that apps can write
+//! end-to-end tests of their use of the SDK. [`MockRealmCluster`] emulates
+//! the realms' handling of registration, recovery, and deletion, including
+//! guess counting, while the client runs the real protocol against it.
+//!
+//! ```ignore
+//! let cluster = MockRealmCluster::new(3);
+//! let client = cluster.client(cluster.configuration(3, 2), "user");
+//! client.register(&pin, &secret, &info, Policy { num_guesses: 5 }).await?;
+//! assert_eq!(client.recover(&pin, &info).await?, secret);
+//! ```
+
+use async_trait::async_trait;
+use rand::rngs::OsRng;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+use url::Url;
+#[cfg(test)]
+use {
+    std::fmt::Debug,
+    tracing::field::{Field, Visit},
+    tracing::span::{Attributes, Id, Record},
+    tracing::{Event, Metadata, Subscriber},
+};
+
+use juicebox_marshalling as marshalling;
+use juicebox_oprf as oprf;
+use juicebox_realm_api::{
+    requests::{
+        DeleteResponse, Recover1Response, Recover2Request, Recover2Response, Recover3Request,
+        Recover3Response, Register1Response, Register2Request, Register2Response, SecretsRequest,
+        SecretsResponse,
+    },
+    types::{AuthToken, RealmId, UnlockKeyTag},
+};
+
+use crate::clock_skew::format_http_date;
+use crate::{
+    http, AuthTokenManager, Client, ClientBuilder, Configuration, PinHashingMode, Realm, RealmRole,
+    RetryPolicy, SecretCipher, Sleeper,
+};
+
+/// A [`Sleeper`] that returns immediately, so that retries don't slow down
+/// tests.
+pub struct NoopSleeper;
+
+#[async_trait]
+impl Sleeper for NoopSleeper {
+    async fn sleep(&self, _duration: Duration) {}
+}
+
+/// An [`AuthTokenManager`] that returns the same token for every realm.
+#[derive(Clone, Debug)]
+pub struct StaticAuthTokenManager(pub AuthToken);
+
+#[async_trait]
+impl AuthTokenManager for StaticAuthTokenManager {
+    async fn get(&self, _realm: &RealmId) -> Option<AuthToken> {
+        Some(self.0.clone())
+    }
+}
+
+/// A set of software realms that handle requests in-process.
+///
+/// Implements [`http::Client`] by routing each request to the realm whose
+/// address matches the request URL and running the realm-side handling for
+/// the decoded [`SecretsRequest`].
+///
+/// The realms identify users by their auth token, so a client built with
+/// [`MockRealmCluster::client`] for a given user name sees that user's
+/// records.
+#[derive(Clone)]
+pub struct MockRealmCluster {
+    realms: Vec<Realm>,
+    state: Arc<Mutex<HashMap<RealmId, MockRealm>>>,
+}
+
+/// The realm-side state for a single realm, keyed by user auth token.
+struct MockRealm {
+    available: bool,
+    hung: bool,
+    clock_offset: i64,
+    failing_requests: usize,
+    lost_response_kind: Option<&'static str>,
+    handled: HashMap<&'static str, usize>,
+    records: HashMap<String, UserRecord>,
+}
+
+impl Default for MockRealm {
+    fn default() -> Self {
+        Self {
+            available: true,
+            hung: false,
+            clock_offset: 0,
+            failing_requests: 0,
+            lost_response_kind: None,
+            handled: HashMap::new(),
+            records: HashMap::new(),
+        }
+    }
+}
+
+struct UserRecord {
+    registration: Register2Request,
+    guess_count: u16,
+}
+
+/// A [`Client`] that talks to a [`MockRealmCluster`].
+pub type MockClient = Client<NoopSleeper, MockRealmCluster, StaticAuthTokenManager>;
+
+impl MockRealmCluster {
+    /// Creates `count` software realms with deterministic ids and addresses.
+    pub fn new(count: u8) -> Self {
+        let realms: Vec<Realm> = (1..=count)
+            .map(|i| Realm {
+                id: RealmId([i; 16]),
+                address: Url::parse(&format!("http://realm{i}.test/")).unwrap(),
+                public_key: None,
+                role: RealmRole::ReadWrite,
+            })
+            .collect();
+        let state = realms
+            .iter()
+            .map(|realm| (realm.id, MockRealm::default()))
+            .collect();
+        Self {
+            realms,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Returns a [`Configuration`] including every realm in the cluster.
+    pub fn configuration(&self, register_threshold: u32, recover_threshold: u32) -> Configuration {
+        Configuration {
+            realms: self.realms.clone(),
+            register_threshold,
+            recover_threshold,
+            pin_hashing_mode: PinHashingMode::FastInsecure,
+            secret_cipher: SecretCipher::ChaCha20Poly1305,
+            retry_policy: RetryPolicy::default(),
+            max_concurrency: None,
+            require_public_keys: false,
+        }
+    }
+
+    /// Builds a [`Client`] for the user identified by `user` that talks to
+    /// this cluster.
+    pub fn client(&self, configuration: Configuration, user: &str) -> MockClient {
+        self.client_builder(configuration, user).build()
+    }
+
+    /// Returns a [`ClientBuilder`] for [`MockRealmCluster::client`], for
+    /// tests that need to customize the client further.
+    pub fn client_builder(
+        &self,
+        configuration: Configuration,
+        user: &str,
+    ) -> ClientBuilder<NoopSleeper, MockRealmCluster, StaticAuthTokenManager> {
+        ClientBuilder::new()
+            .configuration(configuration)
+            .http(self.clone())
+            .sleeper(NoopSleeper)
+            .auth_token_manager(StaticAuthTokenManager(AuthToken::from(user.to_owned())))
+    }
+
+    /// Returns whether `user` has a registration stored on `realm`.
+    pub fn is_registered(&self, realm: &RealmId, user: &str) -> bool {
+        self.state.lock().unwrap()[realm].records.contains_key(user)
+    }
+
+    /// Returns the encoded registration stored for `user` on `realm`, if any.
+    pub fn registration(&self, realm: &RealmId, user: &str) -> Option<Vec<u8>> {
+        self.state.lock().unwrap()[realm]
+            .records
+            .get(user)
+            .map(|record| marshalling::to_vec(&record.registration).unwrap())
+    }
+
+    /// Controls whether `realm` responds to requests. An unavailable realm
+    /// fails requests as if there were a network error.
+    pub fn set_available(&self, realm: &RealmId, available: bool) {
+        self.state.lock().unwrap().get_mut(realm).unwrap().available = available;
+    }
+
+    /// Controls whether requests to `realm` hang forever, as if the realm
+    /// stopped responding without closing the connection.
+    pub fn set_hung(&self, realm: &RealmId, hung: bool) {
+        self.state.lock().unwrap().get_mut(realm).unwrap().hung = hung;
+    }
+
+    /// Makes the next `count` requests to `realm` fail as if there were a
+    /// network error, before the realm processes them.
+    pub fn fail_requests(&self, realm: &RealmId, count: usize) {
+        self.state
+            .lock()
+            .unwrap()
+            .get_mut(realm)
+            .unwrap()
+            .failing_requests = count;
+    }
+
+    /// Makes `realm` process requests of the given kind (such as
+    /// `"Recover2"`) but lose the responses, as if the network failed after
+    /// the realm received them.
+    pub fn lose_responses(&self, realm: &RealmId, kind: &'static str) {
+        self.state
+            .lock()
+            .unwrap()
+            .get_mut(realm)
+            .unwrap()
+            .lost_response_kind = Some(kind);
+    }
+
+    /// Returns how many requests of the given kind `realm` has processed.
+    pub fn request_count(&self, realm: &RealmId, kind: &str) -> usize {
+        self.state.lock().unwrap()[realm]
+            .handled
+            .get(kind)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Sets how many seconds `realm`'s clock is ahead of the local clock, as
+    /// reported in the `Date` header of its responses.
+    pub fn set_clock_offset(&self, realm: &RealmId, seconds: i64) {
+        self.state
+            .lock()
+            .unwrap()
+            .get_mut(realm)
+            .unwrap()
+            .clock_offset = seconds;
+    }
+
+    /// Replaces the unlock key tag stored for `user` on `realm`, so that the
+    /// realm rejects the tag the client derives in phase 3 of recovery.
+    pub fn corrupt_unlock_key_tag(&self, realm: &RealmId, user: &str) {
+        let mut state = self.state.lock().unwrap();
+        let record = state.get_mut(realm).unwrap().records.get_mut(user).unwrap();
+        record.registration.unlock_key_tag = UnlockKeyTag::from([0xff; 16]);
+    }
+
+    fn date(&self, realm: &RealmId) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        let realm = &state[realm];
+        if !realm.available {
+            return None;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        Some(format_http_date(now + realm.clock_offset))
+    }
+
+    fn handle(
+        &self,
+        realm: &RealmId,
+        user: String,
+        request: SecretsRequest,
+    ) -> Option<SecretsResponse> {
+        let mut state = self.state.lock().unwrap();
+        let realm = state.get_mut(realm).unwrap();
+        if !realm.available {
+            return None;
+        }
+        if realm.failing_requests > 0 {
+            realm.failing_requests -= 1;
+            return None;
+        }
+        let kind = request_kind(&request);
+        *realm.handled.entry(kind).or_default() += 1;
+        let lose_response = realm.lost_response_kind == Some(kind);
+        let records = &mut realm.records;
+        let response = match request {
+            SecretsRequest::Register1 => SecretsResponse::Register1(Register1Response::Ok),
+            SecretsRequest::Register2(request) => {
+                records.insert(
+                    user,
+                    UserRecord {
+                        registration: *request,
+                        guess_count: 0,
+                    },
+                );
+                SecretsResponse::Register2(Register2Response::Ok)
+            }
+            SecretsRequest::Recover1 => SecretsResponse::Recover1(match records.get(&user) {
+                None => Recover1Response::NotRegistered,
+                Some(record) if record.guesses_exhausted() => Recover1Response::NoGuesses,
+                Some(record) => Recover1Response::Ok {
+                    version: record.registration.version.clone(),
+                },
+            }),
+            SecretsRequest::Recover2(request) => {
+                SecretsResponse::Recover2(recover2(records.get_mut(&user), request))
+            }
+            SecretsRequest::Recover3(request) => {
+                SecretsResponse::Recover3(recover3(records.get_mut(&user), request))
+            }
+            SecretsRequest::Delete => {
+                let deleted = records.remove(&user).is_some();
+                SecretsResponse::Delete(DeleteResponse::Deleted {
+                    count: u32::from(deleted),
+                })
+            }
+        };
+        (!lose_response).then_some(response)
+    }
+}
+
+fn request_kind(request: &SecretsRequest) -> &'static str {
+    match request {
+        SecretsRequest::Register1 => "Register1",
+        SecretsRequest::Register2(_) => "Register2",
+        SecretsRequest::Recover1 => "Recover1",
+        SecretsRequest::Recover2(_) => "Recover2",
+        SecretsRequest::Recover3(_) => "Recover3",
+        SecretsRequest::Delete => "Delete",
+    }
+}
+
+impl UserRecord {
+    fn guesses_exhausted(&self) -> bool {
+        self.guess_count >= self.registration.policy.num_guesses
+    }
+}
+
+fn recover2(record: Option<&mut UserRecord>, request: Recover2Request) -> Recover2Response {
+    let Some(record) = record else {
+        return Recover2Response::NotRegistered;
+    };
+    if record.registration.version != request.version {
+        return Recover2Response::VersionMismatch;
+    }
+    if record.guesses_exhausted() {
+        return Recover2Response::NoGuesses;
+    }
+    record.guess_count += 1;
+
+    let registration = &record.registration;
+    let (oprf_blinded_result, oprf_proof) = oprf::blind_verifiable_evaluate(
+        &registration.oprf_private_key,
+        &registration.oprf_signed_public_key.public_key,
+        &request.oprf_blinded_input,
+        &mut OsRng,
+    );
+    Recover2Response::Ok {
+        oprf_signed_public_key: registration.oprf_signed_public_key.clone(),
+        oprf_blinded_result,
+        oprf_proof,
+        unlock_key_commitment: registration.unlock_key_commitment.clone(),
+        num_guesses: registration.policy.num_guesses,
+        guess_count: record.guess_count,
+    }
+}
+
+fn recover3(record: Option<&mut UserRecord>, request: Recover3Request) -> Recover3Response {
+    let Some(record) = record else {
+        return Recover3Response::NotRegistered;
+    };
+    if record.registration.version != request.version {
+        return Recover3Response::VersionMismatch;
+    }
+    if !bool::from(
+        record
+            .registration
+            .unlock_key_tag
+            .ct_eq(&request.unlock_key_tag),
+    ) {
+        if record.guesses_exhausted() {
+            return Recover3Response::NoGuesses;
+        }
+        return Recover3Response::BadUnlockKeyTag {
+            guesses_remaining: record.registration.policy.num_guesses - record.guess_count,
+        };
+    }
+    record.guess_count = 0;
+
+    let registration = &record.registration;
+    Recover3Response::Ok {
+        encryption_key_scalar_share: registration.encryption_key_scalar_share.clone(),
+        encrypted_secret: registration.encrypted_secret.clone(),
+        encrypted_secret_commitment: registration.encrypted_secret_commitment.clone(),
+        secret_cipher: registration.secret_cipher,
+    }
+}
+
+#[async_trait]
+impl http::Client for MockRealmCluster {
+    async fn send(&self, request: http::Request) -> Option<http::Response> {
+        let realm = self
+            .realms
+            .iter()
+            .find(|realm| request.url.starts_with(realm.address.as_str()))?;
+        let hung = self.state.lock().unwrap()[&realm.id].hung;
+        if hung {
+            return std::future::pending().await;
+        }
+        if request.method == http::Method::Get {
+            return Some(http::Response {
+                status_code: 404,
+                headers: HashMap::from([("date".to_owned(), self.date(&realm.id)?)]),
+                body: Vec::new(),
+            });
+        }
+        let user = request
+            .headers
+            .get("Authorization")?
+            .strip_prefix("Bearer ")?
+            .to_owned();
+        let secrets_request: SecretsRequest =
+            marshalling::from_slice(request.body.as_deref()?).ok()?;
+
+        let response = self.handle(&realm.id, user, secrets_request)?;
+        Some(http::Response {
+            status_code: 200,
+            headers: HashMap::new(),
+            body: marshalling::to_vec(&response).ok()?,
+        })
+    }
+}
+
+/// A [`Subscriber`] that records the fields of every event, formatted with
+/// `Debug`.
+///
+/// Install it with [`tracing::subscriber::set_default`] for the duration of a
+/// single-threaded test.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct RecordingSubscriber {
+    events: Arc<Mutex<Vec<HashMap<String, String>>>>,
+}
+
+#[cfg(test)]
+impl RecordingSubscriber {
+    /// Returns the events recorded so far whose message is `message`.
+    pub fn events(&self, message: &str) -> Vec<HashMap<String, String>> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| event.get("message").map(String::as_str) == Some(message))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl Subscriber for RecordingSubscriber {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        struct Fields(HashMap<String, String>);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                self.0.insert(field.name().to_owned(), format!("{value:?}"));
+            }
+        }
+
+        let mut fields = Fields(HashMap::new());
+        event.record(&mut fields);
+        self.events.lock().unwrap().push(fields.0);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
@@ -1,14 +1,17 @@
+use aes_gcm::Aes256Gcm;
 use blake2::Blake2sMac256;
-use chacha20poly1305::aead::Aead;
+use chacha20poly1305::aead::{self, Aead, AeadCore};
 use chacha20poly1305::ChaCha20Poly1305;
 use curve25519_dalek::Scalar;
+use digest::consts::{U12, U16, U32};
 use digest::{KeyInit, Mac};
 use instant::{Duration, Instant};
-use rand::rngs::OsRng;
+use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha512};
 
-use std::fmt::{self, Debug};
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
 
 use url::Url;
 
@@ -16,8 +19,8 @@ use juicebox_marshalling::to_be4;
 use juicebox_noise::client as noise;
 use juicebox_oprf as oprf;
 use juicebox_realm_api::types::{
-    EncryptedUserSecret, RealmId, SecretBytesArray, SecretBytesVec, SessionId, UnlockKey,
-    UnlockKeyCommitment,
+    EncryptedUserSecret, RealmId, RegistrationVersion, SecretBytesArray, SecretBytesVec,
+    SecretCipher, SessionId, UnlockKey, UnlockKeyCommitment,
 };
 
 /// A remote service that the client interacts with directly.
@@ -43,6 +46,9 @@ pub struct Realm {
         with = "hex_public_key"
     )]
     pub public_key: Option<Vec<u8>>,
+    /// Which operations the client should perform against this realm.
+    #[serde(default, skip_serializing_if = "RealmRole::is_read_write")]
+    pub role: RealmRole,
 }
 
 impl Debug for Realm {
@@ -50,10 +56,29 @@ impl Debug for Realm {
         f.debug_struct("Realm")
             .field("id", &self.id)
             .field("address", &self.address.as_str())
+            .field("role", &self.role)
             .finish_non_exhaustive()
     }
 }
 
+/// Describes how a [`Realm`] participates in client operations.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum RealmRole {
+    /// The realm receives new registrations and participates in recovery.
+    #[default]
+    ReadWrite,
+    /// The realm participates in recovery but does not receive new
+    /// registrations, such as while it is being decommissioned.
+    RecoverOnly,
+}
+
+impl RealmRole {
+    /// Returns whether new registrations should be sent to the realm.
+    pub fn is_read_write(&self) -> bool {
+        matches!(self, Self::ReadWrite)
+    }
+}
+
 mod hex_realm_id {
     use serde::de::Deserializer;
     use serde::ser::Serializer;
@@ -128,8 +153,41 @@ impl UserSecret {
         self.0.expose_secret()
     }
 
-    pub(crate) fn encrypt(&self, encryption_key: &UserSecretEncryptionKey) -> EncryptedUserSecret {
-        let cipher = ChaCha20Poly1305::new(encryption_key.expose_secret().into());
+    pub(crate) fn encrypt(
+        &self,
+        cipher: SecretCipher,
+        encryption_key: &UserSecretEncryptionKey,
+    ) -> EncryptedUserSecret {
+        match cipher {
+            SecretCipher::ChaCha20Poly1305 => self.encrypt_with::<ChaCha20Poly1305>(encryption_key),
+            SecretCipher::Aes256Gcm => self.encrypt_with::<Aes256Gcm>(encryption_key),
+        }
+    }
+
+    /// Decrypts a secret encrypted with `cipher`. This fails if the key or
+    /// cipher doesn't match the one the secret was encrypted with.
+    pub(crate) fn decrypt(
+        cipher: SecretCipher,
+        encrypted_secret: &EncryptedUserSecret,
+        encryption_key: &UserSecretEncryptionKey,
+    ) -> Result<Self, aead::Error> {
+        match cipher {
+            SecretCipher::ChaCha20Poly1305 => {
+                Self::decrypt_with::<ChaCha20Poly1305>(encrypted_secret, encryption_key)
+            }
+            SecretCipher::Aes256Gcm => {
+                Self::decrypt_with::<Aes256Gcm>(encrypted_secret, encryption_key)
+            }
+        }
+    }
+
+    /// Encrypts the padded secret with any AEAD that takes a 32-byte key and
+    /// a 12-byte nonce.
+    fn encrypt_with<A: UserSecretAead>(
+        &self,
+        encryption_key: &UserSecretEncryptionKey,
+    ) -> EncryptedUserSecret {
+        let cipher = A::new(encryption_key.expose_secret().into());
         let padded_secret = PaddedUserSecret::from(self);
         cipher
             .encrypt(
@@ -141,22 +199,31 @@ impl UserSecret {
             .unwrap()
     }
 
-    pub(crate) fn decrypt(
+    fn decrypt_with<A: UserSecretAead>(
         encrypted_secret: &EncryptedUserSecret,
         encryption_key: &UserSecretEncryptionKey,
-    ) -> Self {
-        let cipher = ChaCha20Poly1305::new(encryption_key.expose_secret().into());
-        let padded_secret = cipher
-            .decrypt(
-                &USER_SECRET_ENCRYPTION_NONCE.into(),
-                encrypted_secret.expose_secret().as_slice(),
-            )
-            .map(|s| PaddedUserSecret::try_from(s).expect("incorrectly sized padded secret"))
-            .expect("secret decryption failed");
-        UserSecret::from(&padded_secret)
+    ) -> Result<Self, aead::Error> {
+        let cipher = A::new(encryption_key.expose_secret().into());
+        let padded_secret = cipher.decrypt(
+            &USER_SECRET_ENCRYPTION_NONCE.into(),
+            encrypted_secret.expose_secret().as_slice(),
+        )?;
+        let padded_secret = PaddedUserSecret::try_from(padded_secret).map_err(|_| aead::Error)?;
+        Ok(UserSecret::from(&padded_secret))
     }
 }
 
+/// An AEAD that can encrypt a [`UserSecret`], selected by a [`SecretCipher`].
+///
+/// Implementations must use a 32-byte key, a 12-byte nonce, and a 16-byte
+/// tag, so that every cipher produces an [`EncryptedUserSecret`] of the same
+/// size.
+trait UserSecretAead: Aead + KeyInit<KeySize = U32> + AeadCore<NonceSize = U12, TagSize = U16> {}
+
+impl UserSecretAead for ChaCha20Poly1305 {}
+
+impl UserSecretAead for Aes256Gcm {}
+
 impl From<Vec<u8>> for UserSecret {
     fn from(value: Vec<u8>) -> Self {
         assert!(
@@ -238,8 +305,8 @@ impl UserSecretEncryptionKeyScalar {
         Self(scalar)
     }
 
-    pub fn new_random() -> Self {
-        Self(Scalar::random(&mut OsRng))
+    pub fn new_random(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        Self(Scalar::random(rng))
     }
 
     pub fn expose_secret(&self) -> &Scalar {
@@ -296,19 +363,99 @@ impl From<[u8; 32]> for UserSecretEncryptionKey {
 ///
 /// This data is used to prevent a malicious [`Realm`] from
 /// forcing use of a salt with a precomputed password table.
+///
+/// The bytes are used exactly as provided: they are prefixed with their
+/// 4-byte big-endian length and appended to the salt. Any change to them,
+/// including text normalization or a different string encoding, will
+/// derive a different key and cause recovery to fail with
+/// [`RecoverError::InvalidPin`](crate::RecoverError::InvalidPin).
+///
+/// Recovering with different info than the secret was registered with is
+/// indistinguishable from using the wrong PIN: it fails with `InvalidPin`
+/// and uses up a guess, but never recovers the wrong secret.
 #[derive(Clone, Debug)]
-pub struct UserInfo(SecretBytesVec);
+pub struct UserInfo {
+    info: SecretBytesVec,
+    context: Option<SecretBytesVec>,
+}
+
+/// The maximum allowed bytes for a [`UserInfo`] created with
+/// [`UserInfo::new`].
+pub const MAX_USER_INFO_LENGTH: usize = 256;
+
+/// Error return type for [`UserInfo::new`] and [`UserInfo::with_context`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UserInfoError {
+    /// The provided info or context was empty.
+    Empty,
+
+    /// The provided info or context exceeded [`MAX_USER_INFO_LENGTH`] bytes.
+    TooLong,
+}
+
+impl Display for UserInfoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl Error for UserInfoError {}
 
 impl UserInfo {
+    /// Creates a validated `UserInfo`, rejecting empty values and values
+    /// longer than [`MAX_USER_INFO_LENGTH`] bytes.
+    pub fn new(value: Vec<u8>) -> Result<Self, UserInfoError> {
+        validate_user_info(&value)?;
+        Ok(Self::from(value))
+    }
+
+    /// Adds a context, such as a tenant ID, that is hashed with the PIN
+    /// separately from the info.
+    ///
+    /// Tenants that share a realm set can each use their own context, so
+    /// their keys stay independent even when two users pick the same PIN and
+    /// info. The context is length-prefixed and appended to the salt after
+    /// the info, so a `UserInfo` without one derives the same keys as before
+    /// contexts existed. Recovery needs the same context as registration.
+    ///
+    /// This only applies to a [`Pin`](crate::Pin). A
+    /// [`HashedPin`](crate::HashedPin) is used as given, so its keys must
+    /// already cover the context.
+    ///
+    /// The context is validated like the info given to [`UserInfo::new`].
+    pub fn with_context(mut self, context: Vec<u8>) -> Result<Self, UserInfoError> {
+        validate_user_info(&context)?;
+        self.context = Some(SecretBytesVec::from(context));
+        Ok(self)
+    }
+
     /// Access the underlying secret bytes.
     pub fn expose_secret(&self) -> &[u8] {
-        self.0.expose_secret()
+        self.info.expose_secret()
+    }
+
+    /// Access the context given to [`UserInfo::with_context`], if any.
+    pub fn context(&self) -> Option<&[u8]> {
+        self.context.as_ref().map(SecretBytesVec::expose_secret)
+    }
+}
+
+fn validate_user_info(value: &[u8]) -> Result<(), UserInfoError> {
+    if value.is_empty() {
+        Err(UserInfoError::Empty)
+    } else if value.len() > MAX_USER_INFO_LENGTH {
+        Err(UserInfoError::TooLong)
+    } else {
+        Ok(())
     }
 }
 
 impl From<Vec<u8>> for UserInfo {
     fn from(value: Vec<u8>) -> Self {
-        Self(SecretBytesVec::from(value))
+        Self {
+            info: SecretBytesVec::from(value),
+            context: None,
+        }
     }
 }
 
@@ -324,10 +471,110 @@ pub(crate) struct Session {
     pub last_used: Instant,
 }
 
+/// A hash of the IDs of the realms in a configuration, which binds a
+/// registration to the set of realms it was made with.
+///
+/// The hash is independent of the order of the realms.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct RealmSetHash([u8; 32]);
+
+/// Starts every [`RegistrationVersion`] whose unlock key is bound to its
+/// realm set. Versions made before realm set binding are entirely random, so
+/// they start with this with negligible probability.
+const REALM_SET_VERSION_MARKER: [u8; 6] = *b"JbRs01";
+
+impl RealmSetHash {
+    pub fn new<'a>(realm_ids: impl IntoIterator<Item = &'a RealmId>) -> Self {
+        let mut realm_ids: Vec<&RealmId> = realm_ids.into_iter().collect();
+        realm_ids.sort_unstable();
+        let label = b"Realm Set";
+        let mut hasher = Sha512::new()
+            .chain_update(to_be4(label.len()))
+            .chain_update(label)
+            .chain_update(to_be4(realm_ids.len()));
+        for realm_id in realm_ids {
+            hasher.update(to_be4(realm_id.0.len()));
+            hasher.update(realm_id.0);
+        }
+        let digest: [u8; 64] = hasher.finalize().into();
+        Self(digest[..32].try_into().unwrap())
+    }
+
+    /// Generates a new registration version that records this realm set.
+    ///
+    /// The version is [`REALM_SET_VERSION_MARKER`], 8 random bytes, and a
+    /// 2-byte tag of the realm set and those random bytes. Recovery reads the
+    /// version before it uses a guess, so the tag lets it detect most realm
+    /// set changes up front.
+    pub fn new_version(&self, rng: &mut (impl CryptoRng + RngCore)) -> RegistrationVersion {
+        let mut nonce = [0u8; 8];
+        rng.fill_bytes(&mut nonce);
+        let mut version = [0u8; 16];
+        version[..6].copy_from_slice(&REALM_SET_VERSION_MARKER);
+        version[6..14].copy_from_slice(&nonce);
+        version[14..].copy_from_slice(&self.version_tag(&nonce));
+        RegistrationVersion::from(version)
+    }
+
+    /// Returns how a registration with `version` derives its unlock key when
+    /// recovered with this realm set, or `None` if the version records a
+    /// different realm set.
+    pub fn binding(&self, version: &RegistrationVersion) -> Option<UnlockKeyBinding<'_>> {
+        let version = version.expose_secret();
+        if version[..6] != REALM_SET_VERSION_MARKER {
+            return Some(UnlockKeyBinding::Unbound);
+        }
+        let tag = self.version_tag(version[6..14].try_into().unwrap());
+        if version[14..] == tag {
+            Some(UnlockKeyBinding::RealmSet(self))
+        } else {
+            None
+        }
+    }
+
+    fn version_tag(&self, nonce: &[u8; 8]) -> [u8; 2] {
+        let label = b"Realm Set Version";
+        let digest: [u8; 64] = Sha512::new()
+            .chain_update(to_be4(label.len()))
+            .chain_update(label)
+            .chain_update(self.0)
+            .chain_update(nonce)
+            .finalize()
+            .into();
+        digest[..2].try_into().unwrap()
+    }
+}
+
+/// How a registration derives its unlock key, as recorded in its
+/// [`RegistrationVersion`] by [`RealmSetHash::new_version`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum UnlockKeyBinding<'a> {
+    /// The registration was made before realm set binding, so the unlock
+    /// key depends only on the OPRF result.
+    Unbound,
+    /// The unlock key is bound to this realm set.
+    RealmSet(&'a RealmSetHash),
+}
+
+/// Derives the unlock key and its commitment from the OPRF result.
+///
+/// For bound registrations, the realm set hash is mixed in so that
+/// recovering against a different set of realms than the secret was
+/// registered with fails the commitment check, rather than unlocking the
+/// secret. Recovery normally catches that earlier with
+/// [`RealmSetHash::binding`].
 pub(crate) fn derive_unlock_key_and_commitment(
     oprf_result: &oprf::Output,
+    binding: UnlockKeyBinding,
 ) -> (UnlockKey, UnlockKeyCommitment) {
-    let digest: [u8; 64] = Sha512::digest(oprf_result.expose_secret()).into();
+    let digest: [u8; 64] = match binding {
+        UnlockKeyBinding::Unbound => Sha512::digest(oprf_result.expose_secret()).into(),
+        UnlockKeyBinding::RealmSet(realm_set_hash) => Sha512::new()
+            .chain_update(realm_set_hash.0)
+            .chain_update(oprf_result.expose_secret())
+            .finalize()
+            .into(),
+    };
     let commitment_bytes: [u8; 32] = digest[..32].try_into().unwrap();
     let key_bytes: [u8; 32] = digest[32..].try_into().unwrap();
     (
@@ -338,10 +585,30 @@ pub(crate) fn derive_unlock_key_and_commitment(
 
 #[cfg(test)]
 mod tests {
+    use rand::rngs::OsRng;
+    use sha2::{Digest, Sha512};
+
     use crate::types::{
-        EncryptedUserSecret, PaddedUserSecret, UserSecret, UserSecretEncryptionKey,
-        MAX_USER_SECRET_LENGTH,
+        derive_unlock_key_and_commitment, EncryptedUserSecret, PaddedUserSecret, RealmSetHash,
+        UnlockKeyBinding, UserInfo, UserInfoError, UserSecret, UserSecretEncryptionKey,
+        MAX_USER_INFO_LENGTH, MAX_USER_SECRET_LENGTH,
     };
+    use juicebox_oprf as oprf;
+    use juicebox_realm_api::types::{RealmId, RegistrationVersion, SecretCipher};
+
+    #[test]
+    fn test_user_info_validation() {
+        assert_eq!(
+            UserInfo::new(b"apollo".to_vec()).unwrap().expose_secret(),
+            b"apollo"
+        );
+        assert!(UserInfo::new(vec![1; MAX_USER_INFO_LENGTH]).is_ok());
+        assert_eq!(UserInfo::new(vec![]).unwrap_err(), UserInfoError::Empty);
+        assert_eq!(
+            UserInfo::new(vec![1; MAX_USER_INFO_LENGTH + 1]).unwrap_err(),
+            UserInfoError::TooLong
+        );
+    }
 
     #[test]
     fn test_secret_padding() {
@@ -388,7 +655,7 @@ mod tests {
     fn test_secret_encryption() {
         let secret = UserSecret::from(b"artemis".to_vec());
         let key = UserSecretEncryptionKey::from([8; 32]);
-        let encrypted_secret = secret.encrypt(&key);
+        let encrypted_secret = secret.encrypt(SecretCipher::ChaCha20Poly1305, &key);
         let expected_encrypted_secret = vec![
             1, 134, 178, 251, 18, 193, 244, 162, 122, 194, 0, 239, 255, 128, 253, 39, 199, 249,
             145, 226, 252, 83, 165, 81, 50, 46, 17, 1, 94, 108, 224, 139, 51, 137, 152, 176, 230,
@@ -416,8 +683,80 @@ mod tests {
             162, 103, 164, 76, 121, 87, 140, 147, 118, 109, 107, 35, 7,
         ])
         .unwrap();
-        let secret = UserSecret::decrypt(&encrypted_secret, &key);
+        let secret =
+            UserSecret::decrypt(SecretCipher::ChaCha20Poly1305, &encrypted_secret, &key).unwrap();
         let expected_secret = b"artemis".to_vec();
         assert_eq!(&expected_secret, secret.expose_secret());
     }
+
+    #[test]
+    fn test_secret_encryption_aes_256_gcm() {
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let key = UserSecretEncryptionKey::from([8; 32]);
+        let encrypted_secret = secret.encrypt(SecretCipher::Aes256Gcm, &key);
+        assert_ne!(
+            encrypted_secret.expose_secret(),
+            secret
+                .encrypt(SecretCipher::ChaCha20Poly1305, &key)
+                .expose_secret()
+        );
+        let decrypted_secret =
+            UserSecret::decrypt(SecretCipher::Aes256Gcm, &encrypted_secret, &key).unwrap();
+        assert_eq!(secret.expose_secret(), decrypted_secret.expose_secret());
+        assert!(
+            UserSecret::decrypt(SecretCipher::ChaCha20Poly1305, &encrypted_secret, &key).is_err()
+        );
+    }
+
+    #[test]
+    fn test_realm_set_hash() {
+        let a = RealmId([1; 16]);
+        let b = RealmId([2; 16]);
+        let c = RealmId([3; 16]);
+        assert_eq!(RealmSetHash::new([&a, &b]), RealmSetHash::new([&b, &a]));
+        assert_ne!(RealmSetHash::new([&a, &b]), RealmSetHash::new([&a, &c]));
+        assert_ne!(RealmSetHash::new([&a, &b]), RealmSetHash::new([&a, &b, &c]));
+    }
+
+    #[test]
+    fn test_realm_set_version_binding() {
+        let hash = RealmSetHash::new([&RealmId([1; 16]), &RealmId([2; 16])]);
+        let version = hash.new_version(&mut OsRng);
+        assert_eq!(
+            hash.binding(&version),
+            Some(UnlockKeyBinding::RealmSet(&hash))
+        );
+
+        // A different realm set is detected from the version alone. The tag
+        // is only 2 bytes, so this checks it against several random sets.
+        let mismatches = (0..16u8)
+            .filter(|i| {
+                let other = RealmSetHash::new([&RealmId([1; 16]), &RealmId([*i + 3; 16])]);
+                other.binding(&version).is_none()
+            })
+            .count();
+        assert!(mismatches >= 15);
+
+        // Versions from before realm set binding are random.
+        let legacy = RegistrationVersion::from([0x42; 16]);
+        assert_eq!(hash.binding(&legacy), Some(UnlockKeyBinding::Unbound));
+    }
+
+    #[test]
+    fn test_unbound_unlock_key_derivation_unchanged() {
+        let oprf_result = oprf::unoblivious_evaluate(
+            &oprf::PrivateKey::from_bytes(&[7; 32]).unwrap(),
+            b"artemis",
+        );
+        let (unlock_key, commitment) =
+            derive_unlock_key_and_commitment(&oprf_result, UnlockKeyBinding::Unbound);
+        let digest = Sha512::digest(oprf_result.expose_secret());
+        assert_eq!(commitment.expose_secret(), &digest[..32]);
+        assert_eq!(unlock_key.expose_secret(), &digest[32..]);
+
+        let hash = RealmSetHash::new([&RealmId([1; 16])]);
+        let (_, bound) =
+            derive_unlock_key_and_commitment(&oprf_result, UnlockKeyBinding::RealmSet(&hash));
+        assert_ne!(bound, commitment);
+    }
 }
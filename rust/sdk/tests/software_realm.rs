@@ -73,6 +73,7 @@ mod software_realm {
                 id,
                 address: Url::from_str(&format!("http://0.0.0.0:{}", port)).unwrap(),
                 public_key: None,
+                role: RealmRole::ReadWrite,
             },
         )
     }
@@ -104,6 +105,10 @@ mod software_realm {
             register_threshold: realm_count,
             recover_threshold: realm_count,
             pin_hashing_mode: PinHashingMode::FastInsecure,
+            secret_cipher: SecretCipher::ChaCha20Poly1305,
+            retry_policy: RetryPolicy::default(),
+            max_concurrency: None,
+            require_public_keys: false,
         };
 
         ClientBuilder::new()
@@ -185,6 +190,7 @@ mod software_realm {
             id: fake_realm_id,
             address: Url::from_str("http://0.0.0.0:0").unwrap(),
             public_key: None,
+            role: RealmRole::ReadWrite,
         });
         tokens.insert(fake_realm_id, AuthToken::from("a.b.c".to_string()));
 
@@ -193,6 +199,10 @@ mod software_realm {
             register_threshold: 3,
             recover_threshold: 3,
             pin_hashing_mode: PinHashingMode::FastInsecure,
+            secret_cipher: SecretCipher::ChaCha20Poly1305,
+            retry_policy: RetryPolicy::default(),
+            max_concurrency: None,
+            require_public_keys: false,
         };
         let client = ClientBuilder::new()
             .tokio_sleeper()
@@ -229,6 +239,10 @@ mod software_realm {
             register_threshold: 2,
             recover_threshold: 2,
             pin_hashing_mode: PinHashingMode::FastInsecure,
+            secret_cipher: SecretCipher::ChaCha20Poly1305,
+            retry_policy: RetryPolicy::default(),
+            max_concurrency: None,
+            require_public_keys: false,
         };
         let register_client = ClientBuilder::new()
             .tokio_sleeper()
@@ -242,6 +256,10 @@ mod software_realm {
             register_threshold: 3,
             recover_threshold: 3,
             pin_hashing_mode: PinHashingMode::FastInsecure,
+            secret_cipher: SecretCipher::ChaCha20Poly1305,
+            retry_policy: RetryPolicy::default(),
+            max_concurrency: None,
+            require_public_keys: false,
         };
         let recover_client = ClientBuilder::new()
             .tokio_sleeper()
@@ -341,6 +359,10 @@ mod software_realm {
             register_threshold: 2,
             recover_threshold: 2,
             pin_hashing_mode: PinHashingMode::FastInsecure,
+            secret_cipher: SecretCipher::ChaCha20Poly1305,
+            retry_policy: RetryPolicy::default(),
+            max_concurrency: None,
+            require_public_keys: false,
         };
         let previous_client = ClientBuilder::new()
             .tokio_sleeper()
@@ -354,6 +376,10 @@ mod software_realm {
             register_threshold: 3,
             recover_threshold: 3,
             pin_hashing_mode: PinHashingMode::FastInsecure,
+            secret_cipher: SecretCipher::ChaCha20Poly1305,
+            retry_policy: RetryPolicy::default(),
+            max_concurrency: None,
+            require_public_keys: false,
         };
         let current_client = ClientBuilder::new()
             .tokio_sleeper()
@@ -465,9 +491,7 @@ mod software_realm {
         };
 
         match client.recover(&pin, &user_info).await {
-            Err(RecoverError::InvalidPin { guesses_remaining }) => {
-                assert_eq!(guesses_remaining, 0);
-            }
+            Err(RecoverError::Locked) => {}
             result => panic!("Unexpected result from recover: {result:?}"),
         };
     }
@@ -482,6 +506,7 @@ mod software_realm {
             id: fake_realm_id,
             address: Url::from_str("http://0.0.0.0:0").unwrap(),
             public_key: None,
+            role: RealmRole::ReadWrite,
         });
         tokens.insert(fake_realm_id, AuthToken::from("a.b.c".to_string()));
 
@@ -490,6 +515,10 @@ mod software_realm {
             register_threshold: 5,
             recover_threshold: 5,
             pin_hashing_mode: PinHashingMode::FastInsecure,
+            secret_cipher: SecretCipher::ChaCha20Poly1305,
+            retry_policy: RetryPolicy::default(),
+            max_concurrency: None,
+            require_public_keys: false,
         };
 
         let client = ClientBuilder::new()
@@ -524,6 +553,10 @@ mod software_realm {
             register_threshold: 4,
             recover_threshold: 4,
             pin_hashing_mode: PinHashingMode::FastInsecure,
+            secret_cipher: SecretCipher::ChaCha20Poly1305,
+            retry_policy: RetryPolicy::default(),
+            max_concurrency: None,
+            require_public_keys: false,
         };
 
         let client = ClientBuilder::new()
@@ -597,9 +630,7 @@ mod software_realm {
         };
 
         match client.recover(&pin, &user_info).await {
-            Err(RecoverError::InvalidPin { guesses_remaining }) => {
-                assert_eq!(guesses_remaining, 0);
-            }
+            Err(RecoverError::Locked) => {}
             result => panic!("Unexpected result from recover: {result:?}"),
         };
 
@@ -0,0 +1,35 @@
+#[cfg(feature = "testing")]
+mod testing {
+    use juicebox_sdk::testing::MockRealmCluster;
+    use juicebox_sdk::{Pin, Policy, RecoverError, UserInfo, UserSecret};
+
+    #[tokio::test]
+    async fn test_register_and_recover() {
+        let cluster = MockRealmCluster::new(3);
+        let client = cluster.client(cluster.configuration(3, 2), "apollo");
+
+        let pin = Pin::from(b"1234".to_vec());
+        let secret = UserSecret::from(b"artemis".to_vec());
+        let info = UserInfo::new(b"apollo".to_vec()).unwrap();
+
+        client
+            .register(&pin, &secret, &info, Policy { num_guesses: 2 })
+            .await
+            .unwrap();
+        let recovered = client.recover(&pin, &info).await.unwrap();
+        assert_eq!(recovered.expose_secret(), secret.expose_secret());
+
+        assert!(matches!(
+            client.recover(&Pin::from(b"4321".to_vec()), &info).await,
+            Err(RecoverError::InvalidPin {
+                guesses_remaining: 1
+            })
+        ));
+
+        client.delete().await.unwrap();
+        assert!(matches!(
+            client.recover(&pin, &info).await,
+            Err(RecoverError::NotRegistered)
+        ));
+    }
+}
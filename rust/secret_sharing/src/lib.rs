@@ -81,9 +81,59 @@ pub fn create_shares<'a, Rng: CryptoRng + RngCore + Send, S: Secret>(
     })
 }
 
-#[derive(Debug)]
+/// Like [`create_shares`], but writes the shares into `shares` instead of
+/// allocating, so that it can be used without a heap.
+///
+/// `shares[i]` receives the secret of the share at `Index(i + 1)`. Given the
+/// same RNG state, the shares are the same as those from [`create_shares`].
+pub fn create_shares_into<Rng: CryptoRng + RngCore + Send, S: Secret>(
+    secret: &S,
+    threshold: u32,
+    count: u32,
+    rng: &mut Rng,
+    shares: &mut [S],
+) -> Result<(), CreateSharesError> {
+    assert!(threshold > 0);
+    assert!(count > 0);
+    assert!(threshold <= count);
+
+    if shares.len() != count as usize {
+        return Err(CreateSharesError::LengthMismatch);
+    }
+
+    // Evaluates the polynomial at every index at once with Horner's method,
+    // so each coefficient is only needed while it's being applied.
+    shares.fill(S::default());
+    for _ in 1..threshold {
+        let coefficient = S::random(rng);
+        for (index, share) in (1..=count).map(Index).zip(shares.iter_mut()) {
+            *share = (*share + &coefficient) * &index.as_scalar();
+        }
+    }
+    for share in shares.iter_mut() {
+        *share = *share + secret;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum CreateSharesError {
+    /// The output slice's length is not the requested share count.
+    LengthMismatch,
+}
+
+#[derive(Debug, Eq, PartialEq)]
 pub enum RecoverSecretError {
     DuplicateShares,
+    /// Fewer than `threshold` shares were provided.
+    InsufficientShares,
+    /// The provided shares do not all lie on a common polynomial.
+    ///
+    /// `likely_bad` identifies the single share whose removal makes the
+    /// remaining shares consistent, if exactly one such share exists.
+    InconsistentShares {
+        likely_bad: Option<Index>,
+    },
 }
 
 /// Attempts to recover a secret from a provided set of shares.
@@ -95,6 +145,87 @@ pub enum RecoverSecretError {
 /// from the same `create` operation will result in a `secret` being
 /// recovered that does not match the original.
 pub fn recover_secret<S: Secret>(shares: &[Share<S>]) -> Result<S, RecoverSecretError> {
+    interpolate(shares, Scalar::ZERO)
+}
+
+/// Recovers a secret from the `threshold` shares with the lowest indices.
+///
+/// Unlike [`recover_secret`], the result and the work done don't depend on
+/// the order of `shares` or on how many extra shares there are, so a caller
+/// that collects shares as they arrive doesn't reveal the arrival order
+/// through what it combines or how long that takes.
+pub fn combine_shares<S: Secret>(
+    shares: &[Share<S>],
+    threshold: u32,
+) -> Result<S, RecoverSecretError> {
+    assert!(threshold > 0);
+
+    if shares.len() < threshold as usize {
+        return Err(RecoverSecretError::InsufficientShares);
+    }
+
+    let mut selected = shares.to_vec();
+    selected.sort_unstable_by_key(|share| share.index.0);
+    selected.truncate(threshold as usize);
+    interpolate(&selected, Scalar::ZERO)
+}
+
+/// Attempts to recover a secret from a provided set of shares, verifying
+/// that all of the shares are consistent with each other.
+///
+/// Unlike [`recover_secret`], any shares beyond the first `threshold` are
+/// used to check that every share lies on the same polynomial. If they do
+/// not, an error is returned instead of a silently incorrect `secret`.
+pub fn recover_secret_checked<S: Secret + PartialEq>(
+    shares: &[Share<S>],
+    threshold: u32,
+) -> Result<S, RecoverSecretError> {
+    assert!(threshold > 0);
+
+    if shares.len() < threshold as usize {
+        return Err(RecoverSecretError::InsufficientShares);
+    }
+
+    if !is_consistent(shares, threshold)? {
+        let mut consistent_without = Vec::new();
+        for i in 0..shares.len() {
+            let others: Vec<_> = shares[..i]
+                .iter()
+                .chain(&shares[i + 1..])
+                .cloned()
+                .collect();
+            if others.len() > threshold as usize && is_consistent(&others, threshold)? {
+                consistent_without.push(shares[i].index);
+            }
+        }
+        let likely_bad = match consistent_without.as_slice() {
+            [index] => Some(*index),
+            _ => None,
+        };
+        return Err(RecoverSecretError::InconsistentShares { likely_bad });
+    }
+
+    interpolate(&shares[..threshold as usize], Scalar::ZERO)
+}
+
+/// Checks that the polynomial defined by the first `threshold` shares
+/// passes through every remaining share.
+fn is_consistent<S: Secret + PartialEq>(
+    shares: &[Share<S>],
+    threshold: u32,
+) -> Result<bool, RecoverSecretError> {
+    let (basis, rest) = shares.split_at(threshold as usize);
+    for share in rest {
+        if interpolate(basis, share.index.as_scalar())? != share.secret {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Evaluates the polynomial defined by `shares` at `x` using Lagrange
+/// interpolation.
+fn interpolate<S: Secret>(shares: &[Share<S>], x: Scalar) -> Result<S, RecoverSecretError> {
     shares
         .iter()
         .enumerate()
@@ -102,10 +233,10 @@ pub fn recover_secret<S: Secret>(shares: &[Share<S>]) -> Result<S, RecoverSecret
             let others = shares[..i].iter().chain(&shares[i + 1..]);
             let numerator: Scalar = others
                 .clone()
-                .map(|other_share| other_share.index.as_scalar())
+                .map(|other_share| x - other_share.index.as_scalar())
                 .product();
             let denominator: Scalar = others
-                .map(|other_share| other_share.index.as_scalar() - share.index.as_scalar())
+                .map(|other_share| share.index.as_scalar() - other_share.index.as_scalar())
                 .product();
 
             if denominator == Scalar::ZERO {
@@ -122,7 +253,8 @@ pub fn recover_secret<S: Secret>(shares: &[Share<S>]) -> Result<S, RecoverSecret
 mod tests {
     use super::*;
     use itertools::Itertools;
-    use rand_core::OsRng;
+    use rand_chacha::ChaCha20Rng;
+    use rand_core::{OsRng, SeedableRng};
 
     #[test]
     fn test_all_scalar_shares() {
@@ -219,6 +351,104 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_checked_recreation() {
+        enumerate_counts_and_thresholds(10, |count, threshold| {
+            let secret = Scalar::random(&mut OsRng);
+
+            let generated_shares: Vec<_> =
+                create_shares(&secret, threshold, count, &mut OsRng).collect();
+
+            assert_eq!(
+                recover_secret_checked(&generated_shares, threshold).unwrap(),
+                secret
+            );
+            assert_eq!(
+                recover_secret_checked(&generated_shares[..threshold as usize - 1], threshold)
+                    .unwrap_err(),
+                RecoverSecretError::InsufficientShares
+            );
+        });
+    }
+
+    #[test]
+    fn test_checked_recreation_with_corrupted_share() {
+        let secret = RistrettoPoint::random(&mut OsRng);
+        let threshold = 3;
+        let count = 6;
+
+        for corrupted in 0..count as usize {
+            let mut shares: Vec<_> = create_shares(&secret, threshold, count, &mut OsRng).collect();
+            shares[corrupted].secret = RistrettoPoint::random(&mut OsRng);
+
+            assert_eq!(
+                recover_secret_checked(&shares, threshold).unwrap_err(),
+                RecoverSecretError::InconsistentShares {
+                    likely_bad: Some(shares[corrupted].index)
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_checked_recreation_cannot_identify_with_one_extra_share() {
+        let secret = Scalar::random(&mut OsRng);
+        let mut shares: Vec<_> = create_shares(&secret, 3, 4, &mut OsRng).collect();
+        shares[1].secret = Scalar::random(&mut OsRng);
+
+        assert_eq!(
+            recover_secret_checked(&shares, 3).unwrap_err(),
+            RecoverSecretError::InconsistentShares { likely_bad: None }
+        );
+    }
+
+    #[test]
+    fn test_create_shares_into() {
+        enumerate_counts_and_thresholds(10, |count, threshold| {
+            let secret = Scalar::random(&mut OsRng);
+            let seed = [7; 32];
+
+            let expected: Vec<_> =
+                create_shares(&secret, threshold, count, &mut ChaCha20Rng::from_seed(seed))
+                    .map(|share| share.secret)
+                    .collect();
+            let mut shares = vec![Scalar::ZERO; count as usize];
+            create_shares_into(
+                &secret,
+                threshold,
+                count,
+                &mut ChaCha20Rng::from_seed(seed),
+                &mut shares,
+            )
+            .unwrap();
+            assert_eq!(shares, expected);
+        });
+
+        let mut shares = [RistrettoPoint::default(); 4];
+        assert_eq!(
+            create_shares_into(&RistrettoPoint::default(), 2, 5, &mut OsRng, &mut shares),
+            Err(CreateSharesError::LengthMismatch)
+        );
+    }
+
+    #[test]
+    fn test_combine_shares() {
+        let secret = Scalar::random(&mut OsRng);
+        let mut shares: Vec<_> = create_shares(&secret, 3, 5, &mut OsRng).collect();
+        // Only the three lowest indices are used, so a bad share with a
+        // higher index doesn't matter, wherever it is in the slice.
+        shares[4].secret = Scalar::random(&mut OsRng);
+        shares.swap(0, 4);
+        shares.swap(1, 3);
+
+        assert_eq!(combine_shares(&shares, 3).unwrap(), secret);
+        assert_ne!(recover_secret(&shares).unwrap(), secret);
+        assert_eq!(
+            combine_shares(&shares[..2], 3).unwrap_err(),
+            RecoverSecretError::InsufficientShares
+        );
+    }
+
     fn enumerate_counts_and_thresholds(max_count: u32, test: impl Fn(u32, u32)) {
         assert!(max_count > 1);
         for i in 2..=max_count {
@@ -74,14 +74,26 @@ public final class Configuration {
         /// maintains a matching private key. Software realms do not
         /// require public keys.
         public let publicKey: Data?
+        /// Which operations the client should perform against this realm.
+        public let role: RealmRole
 
-        public init(id: RealmId, address: URL, publicKey: Data? = nil) {
+        public init(id: RealmId, address: URL, publicKey: Data? = nil, role: RealmRole = .readWrite) {
             self.id = id
             self.address = address
             self.publicKey = publicKey
+            self.role = role
         }
     }
 
+    /// Describes how a `Realm` participates in client operations.
+    public enum RealmRole: UInt32 {
+        /// The realm receives new registrations and participates in recovery.
+        case readWrite = 0
+        /// The realm participates in recovery but does not receive new
+        /// registrations, such as while it is being decommissioned.
+        case recoverOnly = 1
+    }
+
     /// A strategy for hashing the user provided pin.
     public enum PinHashingMode: UInt32 {
         /// A tuned hash, secure for use on modern devices as of 2019 with low-entropy PINs.
@@ -119,7 +131,8 @@ extension Configuration.Realm: FfiConvertible {
                             try body(.init(
                                 id: rawId,
                                 address: addressCStr,
-                                public_key: publicKeyArrayPointer
+                                public_key: publicKeyArrayPointer,
+                                role: JuiceboxRealmRole(rawValue: role.rawValue)
                             ))
                         }
                     }
@@ -127,7 +140,8 @@ extension Configuration.Realm: FfiConvertible {
                     return try body(.init(
                         id: rawId,
                         address: addressCStr,
-                        public_key: nil
+                        public_key: nil,
+                        role: JuiceboxRealmRole(rawValue: role.rawValue)
                     ))
                 }
             }
@@ -62,6 +62,12 @@ public enum RecoverError: Error {
     /// A transient error in sending or receiving requests to a realm.
     /// This request may succeed by trying again with the same parameters.
     case transient
+    /// No guesses remained before this attempt, so the PIN was not checked.
+    /// The secret is locked and inaccessible.
+    case locked
+    /// The secret was registered with a different set of realms than the
+    /// configuration lists. No guess was used.
+    case realmSetMismatch
 
     init(_ error: JuiceboxRecoverError) {
         switch error.reason {
@@ -73,6 +79,8 @@ public enum RecoverError: Error {
         case JuiceboxRecoverErrorReasonRateLimitExceeded: self = .rateLimitExceeded
         case JuiceboxRecoverErrorReasonAssertion: self = .assertion
         case JuiceboxRecoverErrorReasonTransient: self = .transient
+        case JuiceboxRecoverErrorReasonLocked: self = .locked
+        case JuiceboxRecoverErrorReasonRealmSetMismatch: self = .realmSetMismatch
         default: fatalError("Unexpected error type \(error)")
         }
     }
@@ -37,6 +37,10 @@ typedef enum {
    * This request may succeed by trying again with the same parameters.
    */
   JuiceboxDeleteErrorTransient = 4,
+  /**
+   * The operation was stopped by cancelling the `Client`.
+   */
+  JuiceboxDeleteErrorCancelled = 5,
 } JuiceboxDeleteError;
 
 typedef enum {
@@ -57,6 +61,18 @@ typedef enum {
   JuiceboxPinHashingModeFastInsecure = 1,
 } JuiceboxPinHashingMode;
 
+typedef enum {
+  /**
+   * The realm receives new registrations and participates in recovery.
+   */
+  JuiceboxRealmRoleReadWrite = 0,
+  /**
+   * The realm participates in recovery but does not receive new
+   * registrations, such as while it is being decommissioned.
+   */
+  JuiceboxRealmRoleRecoverOnly = 1,
+} JuiceboxRealmRole;
+
 /**
  * Error returned during `Client.recover`
  */
@@ -97,6 +113,20 @@ typedef enum {
    * This request may succeed by trying again with the same parameters.
    */
   JuiceboxRecoverErrorReasonTransient = 6,
+  /**
+   * The operation was stopped by cancelling the `Client`.
+   */
+  JuiceboxRecoverErrorReasonCancelled = 7,
+  /**
+   * No guesses remained before this attempt, so the PIN was not checked.
+   * The secret is locked and inaccessible.
+   */
+  JuiceboxRecoverErrorReasonLocked = 8,
+  /**
+   * The secret was registered with a different set of realms than the
+   * configuration lists. No guess was used.
+   */
+  JuiceboxRecoverErrorReasonRealmSetMismatch = 9,
 } JuiceboxRecoverErrorReason;
 
 /**
@@ -128,6 +158,10 @@ typedef enum {
    * This request may succeed by trying again with the same parameters.
    */
   JuiceboxRegisterErrorTransient = 4,
+  /**
+   * The operation was stopped by cancelling the `Client`.
+   */
+  JuiceboxRegisterErrorCancelled = 5,
 } JuiceboxRegisterError;
 
 typedef struct JuiceboxAuthToken JuiceboxAuthToken;
@@ -197,6 +231,7 @@ typedef struct {
   uint8_t id[16];
   const char *address;
   const JuiceboxUnmanagedDataArray *public_key;
+  JuiceboxRealmRole role;
 } JuiceboxRealm;
 
 typedef struct {
@@ -227,23 +262,23 @@ typedef void (*JuiceboxAuthTokenStringCallbackFn)(JuiceboxAuthToken *auth_token,
  * # Arguments
  *
  * * `configuration` – Represents the current configuration. The configuration
- * provided must include at least one `JuiceboxRealm`.
+ *   provided must include at least one `JuiceboxRealm`.
  * * `previous_configurations` – Represents any other configurations you have
- * previously registered with that you may not yet have migrated the data from.
- * During `juicebox_client_recover`, they will be tried if the current user has not yet
- * registered on the current configuration. These should be ordered from most recently
- * to least recently used.
+ *   previously registered with that you may not yet have migrated the data from.
+ *   During `juicebox_client_recover`, they will be tried if the current user has not yet
+ *   registered on the current configuration. These should be ordered from most recently
+ *   to least recently used.
  * * `auth_token` – Represents the authority to act as a particular user
- * and should be valid for the lifetime of the `JuiceboxClient`.
+ *   and should be valid for the lifetime of the `JuiceboxClient`.
  * * `http_send` – A function pointer `http_send` that will be called when the client
- * wishes to make a network request. The appropriate request should be executed by you,
- * and the the response provided to the response function pointer. This send
- * should be performed asynchronously. `http_send` should not block on
- * performing the request, and the response should be returned to the
- * `response` function pointer argument when the asynchronous work has
- * completed. The request parameter is only valid for the lifetime of the
- * `http_send` function and should not be accessed after returning from the
- * function.
+ *   wishes to make a network request. The appropriate request should be executed by you,
+ *   and the the response provided to the response function pointer. This send
+ *   should be performed asynchronously. `http_send` should not block on
+ *   performing the request, and the response should be returned to the
+ *   `response` function pointer argument when the asynchronous work has
+ *   completed. The request parameter is only valid for the lifetime of the
+ *   `http_send` function and should not be accessed after returning from the
+ *   function.
  */
 JuiceboxClient *juicebox_client_create(JuiceboxConfiguration *configuration,
                                        JuiceboxUnmanagedConfigurationArray previous_configurations,
@@ -99,8 +99,7 @@ struct Demo: AsyncParsableCommand {
                 info: Data("artemis".utf8)
             )
             fatalError("[Swift] Unexpected result from recover: \(String(decoding: secret, as: UTF8.self))")
-        } catch RecoverError.invalidPin(let guessesRemaining) {
-            assert(guessesRemaining == 0)
+        } catch RecoverError.locked {
             print("[Swift] Recover expectedly unsuccessful")
         }
 